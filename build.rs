@@ -0,0 +1,12 @@
+fn main() {
+    println!("cargo:rerun-if-changed=proto/gateway.proto");
+    println!("cargo:rerun-if-changed=migrations"); // embedded by sqlx::migrate!
+    #[cfg(feature = "grpc")]
+    {
+        let descriptors = protox::compile(["proto/gateway.proto"], ["proto"]).expect("failed to compile proto/gateway.proto");
+        tonic_build::configure()
+            .build_client(false)
+            .compile_fds(descriptors)
+            .expect("failed to generate the gRPC service");
+    }
+}
@@ -0,0 +1,30 @@
+-- Finished requests, as kept by the request log; times are Unix milliseconds.
+CREATE TABLE requests (
+    id BIGSERIAL PRIMARY KEY,
+    at_ms BIGINT NOT NULL,
+    model TEXT NOT NULL,
+    key_name TEXT,
+    organization TEXT,
+    project TEXT,
+    status BIGINT NOT NULL,
+    prompt_tokens BIGINT NOT NULL,
+    cached_tokens BIGINT,
+    completion_tokens BIGINT NOT NULL,
+    cost DOUBLE PRECISION,
+    latency_ms BIGINT NOT NULL,
+    audio_seconds DOUBLE PRECISION
+);
+CREATE INDEX requests_at ON requests (at_ms);
+CREATE INDEX requests_key_at ON requests (key_name, at_ms);
+
+-- Admin changes; `line` is the entry's JSON exactly as hashed into the chain.
+CREATE TABLE audit_log (
+    seq BIGSERIAL PRIMARY KEY,
+    id TEXT NOT NULL UNIQUE,
+    at_ms BIGINT NOT NULL,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target TEXT NOT NULL,
+    line TEXT NOT NULL
+);
+CREATE INDEX audit_log_at ON audit_log (at_ms);
@@ -0,0 +1,7 @@
+-- Whole documents replaced on every change, such as CREDENTIAL_STORE's keys and
+-- sealed credentials.
+CREATE TABLE documents (
+    name TEXT PRIMARY KEY,
+    body TEXT NOT NULL,
+    updated_at_ms BIGINT NOT NULL
+);
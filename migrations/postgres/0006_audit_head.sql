@@ -0,0 +1,8 @@
+-- The hash of the newest audit entry, which the next one must chain to. An
+-- entry is appended only if this is still the hash it was written against, so
+-- instances sharing the store keep one unbroken chain. Until the first entry
+-- after this migration, the head is the hash of the newest line.
+CREATE TABLE audit_head (
+    id INTEGER PRIMARY KEY,
+    hash TEXT NOT NULL
+);
@@ -0,0 +1,30 @@
+-- Finished requests, as kept by the request log; times are Unix milliseconds.
+CREATE TABLE requests (
+    id INTEGER PRIMARY KEY,
+    at_ms INTEGER NOT NULL,
+    model TEXT NOT NULL,
+    key_name TEXT,
+    organization TEXT,
+    project TEXT,
+    status INTEGER NOT NULL,
+    prompt_tokens INTEGER NOT NULL,
+    cached_tokens INTEGER,
+    completion_tokens INTEGER NOT NULL,
+    cost REAL,
+    latency_ms INTEGER NOT NULL,
+    audio_seconds REAL
+);
+CREATE INDEX requests_at ON requests (at_ms);
+CREATE INDEX requests_key_at ON requests (key_name, at_ms);
+
+-- Admin changes; `line` is the entry's JSON exactly as hashed into the chain.
+CREATE TABLE audit_log (
+    seq INTEGER PRIMARY KEY,
+    id TEXT NOT NULL UNIQUE,
+    at_ms INTEGER NOT NULL,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target TEXT NOT NULL,
+    line TEXT NOT NULL
+);
+CREATE INDEX audit_log_at ON audit_log (at_ms);
@@ -0,0 +1,2 @@
+-- Each request's `metadata` annotations, as a JSON object of strings; NULL without any.
+ALTER TABLE requests ADD COLUMN metadata TEXT;
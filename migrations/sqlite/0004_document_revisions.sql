@@ -0,0 +1,4 @@
+-- Each document's revision, so a change can replace it only if nobody else has
+-- since it was read. Existing documents take the revision in their body.
+ALTER TABLE documents ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+UPDATE documents SET revision = COALESCE(json_extract(body, '$.revision'), 0);
@@ -0,0 +1,9 @@
+-- Async chat jobs, so any instance can answer a poll for a job another one runs.
+-- `finished_at_ms` is NULL while the job is in progress.
+CREATE TABLE jobs (
+    id TEXT PRIMARY KEY,
+    owner TEXT,
+    body TEXT NOT NULL,
+    finished_at_ms INTEGER
+);
+CREATE INDEX jobs_finished_at ON jobs (finished_at_ms);
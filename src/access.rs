@@ -0,0 +1,111 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::{collections::BTreeMap, net::{IpAddr, SocketAddr}, sync::Arc};
+use tracing::info;
+
+use crate::AppError;
+
+// --- IP Access Control ---
+// Loaded from IP_ACCESS. Rule sets are keyed by path prefix ("/v1", "/admin");
+// the "*" set applies to every request and is checked before the most specific
+// matching prefix. Within a set, deny wins, and a non-empty allow list rejects
+// anything not on it.
+#[derive(Debug, Default, Deserialize)]
+pub struct IpAccessConfig {
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpNet>,
+    #[serde(default)]
+    pub rules: BTreeMap<String, IpRules>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct IpRules {
+    #[serde(default)]
+    pub allow: Vec<IpNet>,
+    #[serde(default)]
+    pub deny: Vec<IpNet>,
+}
+
+impl IpRules {
+    fn permits(&self, ip: &IpAddr) -> bool {
+        if self.deny.iter().any(|net| net.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(ip))
+    }
+}
+
+pub struct IpAccessPolicy {
+    config: IpAccessConfig,
+}
+
+impl IpAccessPolicy {
+    pub fn new(config: IpAccessConfig) -> Self {
+        IpAccessPolicy { config }
+    }
+
+    // The peer address, unless it is a trusted proxy: then X-Forwarded-For is read
+    // right to left, skipping further trusted hops, and the first untrusted
+    // address is the client. Entries left of that are client-controlled and ignored.
+    // If every entry is trusted, none names the client, and the peer is used.
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let peer = peer.to_canonical();
+        if !self.is_trusted(&peer) {
+            return peer;
+        }
+
+        let forwarded: Vec<IpAddr> = headers.get_all("x-forwarded-for").iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter_map(|entry| entry.trim().parse::<IpAddr>().ok())
+            .map(|ip| ip.to_canonical())
+            .collect();
+
+        forwarded.iter().rev()
+            .find(|ip| !self.is_trusted(ip))
+            .copied()
+            .unwrap_or(peer)
+    }
+
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.config.trusted_proxies.iter().any(|net| net.contains(ip))
+    }
+
+    fn permits(&self, ip: &IpAddr, path: &str) -> bool {
+        if let Some(global) = self.config.rules.get("*") {
+            if !global.permits(ip) {
+                return false;
+            }
+        }
+
+        let group = self.config.rules.iter()
+            .filter(|(prefix, _)| prefix.as_str() != "*" && path_in_group(path, prefix))
+            .max_by_key(|(prefix, _)| prefix.len());
+        group.is_none_or(|(_, rules)| rules.permits(ip))
+    }
+}
+
+fn path_in_group(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    path == prefix || path.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('/'))
+}
+
+pub async fn enforce(
+    State(policy): State<Arc<IpAccessPolicy>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = policy.client_ip(peer.ip(), request.headers());
+    if !policy.permits(&ip, request.uri().path()) {
+        info!("Denied {} {} from {}", request.method(), request.uri().path(), ip);
+        return AppError::Forbidden("Access from this address is not allowed.".to_string()).into_response();
+    }
+    next.run(request).await
+}
@@ -0,0 +1,184 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use tokio::sync::Notify;
+use tracing::info;
+
+use crate::{admin::Admin, auth::Caller, AppError, AppState};
+
+pub const HEADER: &str = "x-request-id";
+
+// --- In-Flight Requests ---
+// Every chat request is listed here from the moment it is accepted until its
+// stream ends, under the ID the gateway returns in X-Request-ID. Cancelling one
+// closes its backend connection and ends the client's stream (logged as 499).
+// Admins can list and cancel any request under /admin/requests; a key with
+// `cancel_requests` can cancel its own with POST /v1/requests/cancel.
+#[derive(Default)]
+pub struct ActiveRequests {
+    requests: Mutex<HashMap<String, ActiveRequest>>,
+}
+
+struct ActiveRequest {
+    model: String,
+    key: Option<String>,
+    tenant: Option<String>,
+    started_at: DateTime<Utc>,
+    received: Instant,
+    backend: Option<String>, // the replica serving it, once chosen
+    cancel: Arc<Notify>,
+}
+
+#[derive(Serialize)]
+struct ActiveSummary {
+    id: String,
+    model: String,
+    key: Option<String>,
+    tenant: Option<String>,
+    started_at: DateTime<Utc>,
+    elapsed_ms: u64,
+    backend: Option<String>,
+}
+
+// Lists the request until dropped. `cancel` is notified at most once; the
+// notification is kept if nothing is waiting on it yet.
+pub struct Registration {
+    requests: Arc<ActiveRequests>,
+    id: String,
+    pub cancel: Arc<Notify>,
+}
+
+impl Registration {
+    pub fn set_backend(&self, url: &str) {
+        if let Some(request) = self.requests.requests.lock().unwrap().get_mut(&self.id) {
+            request.backend = Some(url.to_string());
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.requests.requests.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl ActiveRequests {
+    pub fn register(
+        self: &Arc<Self>,
+        id: &str,
+        model: &str,
+        key: Option<&str>,
+        tenant: Option<&str>,
+        received: Instant,
+    ) -> Registration {
+        let cancel = Arc::new(Notify::new());
+        self.requests.lock().unwrap().insert(id.to_string(), ActiveRequest {
+            model: model.to_string(),
+            key: key.map(str::to_string),
+            tenant: tenant.map(str::to_string),
+            started_at: Utc::now(),
+            received,
+            backend: None,
+            cancel: cancel.clone(),
+        });
+        Registration { requests: self.clone(), id: id.to_string(), cancel }
+    }
+
+    // With `key` set, only that key's requests can be cancelled. False if no
+    // such request is in flight.
+    pub fn cancel(&self, id: &str, key: Option<&str>) -> bool {
+        let requests = self.requests.lock().unwrap();
+        let Some(request) = requests.get(id).filter(|r| key.is_none() || r.key.as_deref() == key) else { return false };
+        info!("Cancelling request {} for model '{}'", id, request.model);
+        request.cancel.notify_one();
+        true
+    }
+
+    // Requests in flight on a replica, as drain mode waits for them to finish.
+    pub fn on_backend(&self, url: &str) -> usize {
+        self.requests.lock().unwrap().values().filter(|r| r.backend.as_deref() == Some(url)).count()
+    }
+
+    fn list(&self) -> Vec<ActiveSummary> {
+        let mut list: Vec<ActiveSummary> = self.requests.lock().unwrap().iter()
+            .map(|(id, request)| ActiveSummary {
+                id: id.clone(),
+                model: request.model.clone(),
+                key: request.key.clone(),
+                tenant: request.tenant.clone(),
+                started_at: request.started_at,
+                elapsed_ms: request.received.elapsed().as_millis() as u64,
+                backend: request.backend.clone(),
+            })
+            .collect();
+        list.sort_by_key(|request| request.started_at);
+        list
+    }
+}
+
+fn cancelled(id: String) -> Json<Value> {
+    Json(json!({ "id": id, "object": "request", "cancelled": true }))
+}
+
+// GET /admin/requests
+#[utoipa::path(
+    get, path = "/admin/requests", tag = "Admin",
+    responses((status = 200, description = "The requests in flight.", body = Object)),
+)]
+pub async fn list(State(state): State<Arc<AppState>>) -> Json<Value> {
+    Json(json!({ "object": "list", "data": state.active.list() }))
+}
+
+// POST /admin/requests/:id/cancel
+#[utoipa::path(
+    post, path = "/admin/requests/{id}/cancel", tag = "Admin",
+    params(("id" = String, Path, description = "The request's `x-request-id`.")),
+    responses((status = 200, description = "The request was cancelled.", body = Object)),
+)]
+pub async fn admin_cancel(
+    State(state): State<Arc<AppState>>,
+    Extension(admin): Extension<Admin>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let before = state.active.list().into_iter().find(|request| request.id == id);
+    if !state.active.cancel(&id, None) {
+        return Err(AppError::RequestNotFound(id));
+    }
+    state.audit.record(&admin, "request.cancel", &id, json!(before), Value::Null).await;
+    Ok(cancelled(id))
+}
+
+// POST /v1/requests/cancel, naming the request in X-Request-ID.
+#[utoipa::path(
+    post, path = "/v1/requests/cancel", tag = "Chat",
+    params(("X-Request-ID" = String, Header, description = "The request to cancel; it must be the caller's own.")),
+    responses((status = 200, description = "The request was cancelled.", body = Object)),
+)]
+pub async fn client_cancel(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    let key = caller.key();
+    if key.is_some_and(|key| !key.cancel_requests) {
+        return Err(AppError::Forbidden("This API key may not cancel requests.".to_string()));
+    }
+    let id = headers.get(HEADER).and_then(|v| v.to_str().ok()).map(str::trim)
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| AppError::InvalidRequest("Name the request to cancel in the X-Request-ID header.".to_string()))?
+        .to_string();
+    if !state.active.cancel(&id, key.map(|key| key.name.as_str())) {
+        return Err(AppError::RequestNotFound(id));
+    }
+    Ok(cancelled(id))
+}
@@ -0,0 +1,487 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{MatchedPath, Query, Request, State},
+    http::{
+        header::{AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE},
+        Method,
+    },
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    fmt::Write,
+    sync::Arc,
+};
+use tracing::info;
+
+use crate::{active, audit, credentials, drain, metadata::Metadata, request_log::RequestRecord, trace, AppError, AppState};
+
+// --- Admin API ---
+// Mounted under /admin only when ADMIN_API_KEY or ADMIN_KEYS is set; every route
+// requires an admin key as a bearer token, except the dashboard page itself,
+// which holds no data and asks for the key in the browser.
+pub fn router(state: Arc<AppState>, admins: AdminKeys) -> Router {
+    let admins = Arc::new(admins);
+    let mut routes = Router::new()
+        .route("/admin/usage", get(usage))
+        .route("/admin/usage/export", get(export))
+        .route("/admin/dashboard", get(dashboard))
+        .route("/admin/requests", get(active::list))
+        .route("/admin/requests/:id/cancel", post(active::admin_cancel))
+        .route("/admin/requests/:id/trace", get(trace::get))
+        .route("/admin/backends/:id/drain", get(drain::get).post(drain::drain))
+        .route("/admin/backends/:id/undrain", post(drain::undrain))
+        .route("/admin/audit", get(audit::query));
+    if state.credentials.is_some() {
+        routes = routes.merge(credentials::router());
+    }
+    routes
+        .route_layer(axum::middleware::from_fn(move |mut request: Request, next: Next| {
+            let admins = admins.clone();
+            async move {
+                let token = request.headers().get(AUTHORIZATION)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.strip_prefix("Bearer "))
+                    .map(str::trim);
+                let Some(admin) = token.and_then(|token| admins.keys.get(token)).cloned() else {
+                    return AppError::Unauthorized("Invalid or missing admin API key.".to_string()).into_response();
+                };
+                let path = request.extensions().get::<MatchedPath>().map(|path| path.as_str().to_string()).unwrap_or_default();
+                let required = required_role(request.method(), &path);
+                if admin.role < required {
+                    info!("Admin '{}' ({}) may not {} {}", admin.name, admin.role.name(), request.method(), path);
+                    let message = format!("This needs the {} role; this admin key has {}.", required.name(), admin.role.name());
+                    return AppError::Forbidden(message).into_response();
+                }
+                request.extensions_mut().insert(admin);
+                next.run(request).await
+            }
+        }))
+        .route("/admin/ui", get(ui))
+        .with_state(state)
+}
+
+// --- Admin Roles ---
+// ADMIN_KEYS maps each admin key to a name and a role; ADMIN_API_KEY, if set, is
+// one more key with the admin role. Viewers may read everything but stream
+// traces, operators may also read those, cancel requests, and drain backends,
+// and only admins may change anything else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Viewer,
+    Operator,
+    Admin,
+}
+
+impl Role {
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Admin {
+    pub name: String,
+    pub role: Role,
+}
+
+pub struct AdminKeys {
+    keys: HashMap<String, Admin>,
+}
+
+impl AdminKeys {
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let mut keys: HashMap<String, Admin> = crate::config::env_json("ADMIN_KEYS")?.unwrap_or_default();
+        if let Ok(key) = std::env::var("ADMIN_API_KEY").map(|key| key.trim().to_string()) {
+            if !key.is_empty() {
+                keys.insert(key, Admin { name: "admin".to_string(), role: Role::Admin });
+            }
+        }
+        Ok((!keys.is_empty()).then_some(AdminKeys { keys }))
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+}
+
+// Routes not listed here need the admin role, so new ones start out locked down.
+fn required_role(method: &Method, path: &str) -> Role {
+    match (method, path) {
+        (&Method::GET, "/admin/requests/:id/trace") => Role::Operator, // holds prompts and completions
+        (&Method::GET, _) => Role::Viewer,
+        (&Method::POST, "/admin/requests/:id/cancel") => Role::Operator,
+        (&Method::POST, "/admin/backends/:id/drain" | "/admin/backends/:id/undrain") => Role::Operator,
+        _ => Role::Admin,
+    }
+}
+
+// --- Time Windows ---
+// `from`/`to` accept RFC 3339 timestamps or plain dates (midnight UTC). The
+// default window is the last 24 hours.
+#[derive(Debug, Deserialize)]
+pub struct Window {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+impl Window {
+    pub fn resolve(&self) -> Result<(DateTime<Utc>, DateTime<Utc>), AppError> {
+        let to = self.to.as_deref().map(parse_time).transpose()?.unwrap_or_else(Utc::now);
+        let from = self.from.as_deref().map(parse_time).transpose()?.unwrap_or(to - Duration::hours(24));
+        if from >= to {
+            return Err(AppError::InvalidRequest("'from' must be before 'to'.".to_string()));
+        }
+        Ok((from, to))
+    }
+}
+
+fn parse_time(raw: &str) -> Result<DateTime<Utc>, AppError> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(raw) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+        .map_err(|_| AppError::InvalidRequest(format!("Invalid time '{}'; use RFC 3339 or YYYY-MM-DD.", raw)))
+}
+
+// --- Usage Report ---
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    #[default]
+    Model,
+    Key,
+    Organization,
+    Project,
+    Day,
+}
+
+impl GroupBy {
+    pub fn group_of(self, record: &RequestRecord) -> String {
+        match self {
+            GroupBy::Model => record.model.clone(),
+            GroupBy::Key => record.key.clone().unwrap_or_else(|| "(none)".to_string()),
+            GroupBy::Organization => record.organization.clone().unwrap_or_else(|| "(none)".to_string()),
+            GroupBy::Project => record.project.clone().unwrap_or_else(|| "(none)".to_string()),
+            GroupBy::Day => record.at.format("%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct UsageQuery {
+    #[serde(default)]
+    group_by: GroupBy,
+    #[serde(flatten)]
+    window: Window,
+}
+
+#[derive(Debug, Serialize)]
+struct UsageGroup {
+    group: String,
+    requests: u64,
+    errors: u64,
+    error_rate: f64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    audio_minutes: f64,
+    cost: f64,
+    latency_p50_ms: u64,
+    latency_p95_ms: u64,
+}
+
+#[utoipa::path(
+    get, path = "/admin/usage", tag = "Admin",
+    params(
+        ("group_by" = Option<String>, Query, description = "`model` (the default), `key`, `organization`, `project`, or `day`."),
+        ("from" = Option<String>, Query, description = "RFC 3339 time or YYYY-MM-DD; defaults to 24 hours before `to`."),
+        ("to" = Option<String>, Query, description = "RFC 3339 time or YYYY-MM-DD; defaults to now."),
+    ),
+    responses((status = 200, description = "Requests, errors, tokens, cost, and latency per group.", body = Object)),
+)]
+async fn usage(State(state): State<Arc<AppState>>, Query(query): Query<UsageQuery>) -> Result<Json<serde_json::Value>, AppError> {
+    let (from, to) = query.window.resolve()?;
+
+    let mut groups: BTreeMap<String, Vec<RequestRecord>> = BTreeMap::new();
+    for record in state.request_log.range(from, to) {
+        groups.entry(query.group_by.group_of(&record)).or_default().push(record);
+    }
+
+    let data: Vec<UsageGroup> = groups.into_iter()
+        .map(|(group, records)| {
+            let requests = records.len() as u64;
+            // Client disconnects (499) are not the gateway's or the backend's fault.
+            let errors = records.iter().filter(|r| r.status >= 400 && r.status != 499).count() as u64;
+            let mut latencies: Vec<u64> = records.iter().map(|r| r.latency_ms).collect();
+            latencies.sort_unstable();
+            UsageGroup {
+                group,
+                requests,
+                errors,
+                error_rate: errors as f64 / requests as f64,
+                prompt_tokens: records.iter().map(|r| r.prompt_tokens).sum(),
+                completion_tokens: records.iter().map(|r| r.completion_tokens).sum(),
+                audio_minutes: records.iter().filter_map(|r| r.audio_seconds).fold(0.0, |total, s| total + s) / 60.0,
+                cost: records.iter().filter_map(|r| r.cost).fold(0.0, |total, c| total + c),
+                latency_p50_ms: percentile(&latencies, 0.50),
+                latency_p95_ms: percentile(&latencies, 0.95),
+            }
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "object": "usage_report",
+        "group_by": query.group_by,
+        "from": from,
+        "to": to,
+        "data": data,
+    })))
+}
+
+// Nearest-rank percentile over an already sorted slice.
+pub fn percentile<T: Copy + Default>(sorted: &[T], p: f64) -> T {
+    if sorted.is_empty() {
+        return T::default();
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+// --- Billing Export ---
+// Per-request rows are streamed a page at a time straight from the request log.
+// `key_day` rows (one per day, key, and model) are aggregated first; the number
+// of groups is small even when the number of requests is not.
+const EXPORT_PAGE: usize = 1000;
+const REQUEST_COLUMNS: &str = "at,model,key,organization,project,status,prompt_tokens,completion_tokens,cost,latency_ms,metadata\n";
+const KEY_DAY_COLUMNS: &str = "day,key,model,requests,prompt_tokens,completion_tokens,cost\n";
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum ExportFormat {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Granularity {
+    #[default]
+    Request,
+    KeyDay,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+    #[serde(default)]
+    granularity: Granularity,
+    #[serde(flatten)]
+    window: Window,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct KeyDayRow {
+    day: String,
+    key: String,
+    model: String,
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    cost: f64,
+}
+
+#[utoipa::path(
+    get, path = "/admin/usage/export", tag = "Admin",
+    params(
+        ("format" = Option<String>, Query, description = "`csv` (the default) or `jsonl`."),
+        ("granularity" = Option<String>, Query, description = "`request` (the default) or `key_day`."),
+        ("from" = Option<String>, Query, description = "RFC 3339 time or YYYY-MM-DD; defaults to 24 hours before `to`."),
+        ("to" = Option<String>, Query, description = "RFC 3339 time or YYYY-MM-DD; defaults to now."),
+    ),
+    responses((status = 200, description = "The request log or daily per-key totals, streamed.", content_type = "text/csv", body = String)),
+)]
+async fn export(State(state): State<Arc<AppState>>, Query(query): Query<ExportQuery>) -> Result<Response, AppError> {
+    let (from, to) = query.window.resolve()?;
+    let format = query.format;
+
+    let body = match query.granularity {
+        Granularity::Request => {
+            let header = (format == ExportFormat::Csv).then(|| Ok(Bytes::from_static(REQUEST_COLUMNS.as_bytes())));
+            let pages = stream::unfold(None, move |after| {
+                let state = state.clone();
+                async move {
+                    let (records, last) = state.request_log.page(from, to, after, EXPORT_PAGE)?;
+                    let mut out = String::new();
+                    for record in &records {
+                        match format {
+                            ExportFormat::Csv => {
+                                let _ = writeln!(
+                                    out, "{},{},{},{},{},{},{},{},{},{},{}",
+                                    record.at.to_rfc3339(), csv_field(&record.model),
+                                    csv_field(record.key.as_deref().unwrap_or("")),
+                                    csv_field(record.organization.as_deref().unwrap_or("")),
+                                    csv_field(record.project.as_deref().unwrap_or("")), record.status,
+                                    record.prompt_tokens, record.completion_tokens,
+                                    record.cost.map(|c| format!("{:.6}", c)).unwrap_or_default(), record.latency_ms,
+                                    csv_field(&metadata_json(&record.metadata)),
+                                );
+                            }
+                            ExportFormat::Jsonl => {
+                                out.push_str(&serde_json::to_string(record).unwrap_or_default());
+                                out.push('\n');
+                            }
+                        }
+                    }
+                    Some((Ok::<_, Infallible>(Bytes::from(out)), Some(last)))
+                }
+            });
+            Body::from_stream(stream::iter(header).chain(pages))
+        }
+        Granularity::KeyDay => {
+            let mut rows: BTreeMap<(String, String, String), KeyDayRow> = BTreeMap::new();
+            let mut after = None;
+            while let Some((records, last)) = state.request_log.page(from, to, after, EXPORT_PAGE) {
+                for record in records {
+                    let day = GroupBy::Day.group_of(&record);
+                    let key = GroupBy::Key.group_of(&record);
+                    let row = rows.entry((day.clone(), key.clone(), record.model.clone())).or_insert_with(|| KeyDayRow {
+                        day, key, model: record.model.clone(), ..Default::default()
+                    });
+                    row.requests += 1;
+                    row.prompt_tokens += record.prompt_tokens;
+                    row.completion_tokens += record.completion_tokens;
+                    row.cost += record.cost.unwrap_or(0.0);
+                }
+                after = Some(last);
+            }
+
+            let mut out = String::new();
+            if format == ExportFormat::Csv {
+                out.push_str(KEY_DAY_COLUMNS);
+            }
+            for row in rows.values() {
+                match format {
+                    ExportFormat::Csv => {
+                        let _ = writeln!(
+                            out, "{},{},{},{},{},{},{:.6}",
+                            row.day, csv_field(&row.key), csv_field(&row.model), row.requests,
+                            row.prompt_tokens, row.completion_tokens, row.cost
+                        );
+                    }
+                    ExportFormat::Jsonl => {
+                        out.push_str(&serde_json::to_string(row).unwrap_or_default());
+                        out.push('\n');
+                    }
+                }
+            }
+            Body::from(out)
+        }
+    };
+
+    let (content_type, extension) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+        ExportFormat::Jsonl => ("application/x-ndjson", "jsonl"),
+    };
+    let filename = format!("attachment; filename=\"usage-{}-{}.{}\"", from.format("%Y%m%d"), to.format("%Y%m%d"), extension);
+    Ok(([(CONTENT_TYPE, content_type.to_string()), (CONTENT_DISPOSITION, filename)], body).into_response())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// A JSON object, or nothing when there's no metadata.
+fn metadata_json(metadata: &Metadata) -> String {
+    match metadata.is_empty() {
+        true => String::new(),
+        false => serde_json::to_string(metadata).unwrap_or_default(),
+    }
+}
+
+// --- Dashboard ---
+// Everything `/admin/ui` shows, in one payload it polls every few seconds.
+const DASHBOARD_MINUTES: i64 = 60;
+const DASHBOARD_ERRORS: usize = 20;
+
+#[derive(Debug, Serialize)]
+struct Throughput {
+    requests: Vec<u64>,
+    completion_tokens: Vec<u64>,
+}
+
+#[utoipa::path(
+    get, path = "/admin/ui", tag = "Admin",
+    responses((status = 200, description = "The dashboard page; it asks for the admin key in the browser.", content_type = "text/html", body = String)),
+)]
+async fn ui() -> Html<&'static str> {
+    Html(include_str!("admin_ui.html"))
+}
+
+#[utoipa::path(
+    get, path = "/admin/dashboard", tag = "Admin",
+    responses((status = 200, description = "Recent traffic and backend health, for the admin UI.", body = Object)),
+)]
+async fn dashboard(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let now = Utc::now();
+    let since = now - Duration::minutes(DASHBOARD_MINUTES);
+    let records = state.request_log.range(since, now);
+
+    let health = state.health.as_ref().map(|h| h.snapshot());
+    let models = state.vllm_backends.snapshot();
+    let backends: BTreeMap<&String, serde_json::Value> = models.iter()
+        .map(|(model, backend)| {
+            let replicas = match health.as_ref().and_then(|h| h.get(model)) {
+                Some(replicas) => serde_json::json!(replicas),
+                None => serde_json::json!(backend.replicas.get().iter().map(|url| serde_json::json!({ "url": url })).collect::<Vec<_>>()),
+            };
+            (model, replicas)
+        })
+        .collect();
+
+    let recent_errors: Vec<&RequestRecord> = records.iter().rev()
+        .filter(|r| r.status >= 400 && r.status != 499)
+        .take(DASHBOARD_ERRORS)
+        .collect();
+
+    // One bucket per minute, oldest first.
+    let mut throughput: BTreeMap<String, Throughput> = BTreeMap::new();
+    for record in &records {
+        let minute = (record.at - since).num_minutes().clamp(0, DASHBOARD_MINUTES - 1) as usize;
+        let series = throughput.entry(record.model.clone()).or_insert_with(|| Throughput {
+            requests: vec![0; DASHBOARD_MINUTES as usize],
+            completion_tokens: vec![0; DASHBOARD_MINUTES as usize],
+        });
+        series.requests[minute] += 1;
+        series.completion_tokens[minute] += record.completion_tokens;
+    }
+
+    Json(serde_json::json!({
+        "at": now,
+        "health_checks": health.is_some(),
+        "store": state.store.health().await,
+        "backends": backends,
+        "latency": state.latency.snapshot(),
+        "in_flight": state.metrics.in_flight(),
+        "recent_errors": recent_errors,
+        "throughput": { "from": since, "minutes": DASHBOARD_MINUTES, "models": throughput },
+    }))
+}
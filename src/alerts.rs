@@ -0,0 +1,297 @@
+use chrono::{DateTime, Datelike, Utc};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+use crate::{request_log::RequestRecord, store::Store};
+
+const SPEND_SYNC: Duration = Duration::from_secs(60);
+
+// --- Alert Configuration ---
+// Loaded from ALERTS. Every alert is posted as `{"text": ...}` to each webhook,
+// which is what Slack (and most chat tools' Slack-compatible hooks) expect.
+#[derive(Debug, Deserialize)]
+pub struct AlertConfig {
+    pub webhooks: Vec<String>,
+    // The same alert is not sent again within this many seconds.
+    #[serde(default = "default_cooldown")]
+    pub cooldown_secs: u64,
+    #[serde(default)]
+    pub budgets: HashMap<String, KeyBudget>, // API key name -> budget
+    #[serde(default)]
+    pub error_rate: Option<ErrorRateAlert>,
+    // Needs HEALTH_CHECK_INTERVAL_SECS.
+    #[serde(default = "default_true")]
+    pub backends_down: bool,
+}
+
+fn default_cooldown() -> u64 {
+    3600
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// Spend is tracked from request costs, so the key's backends need `pricing`.
+#[derive(Debug, Deserialize)]
+pub struct KeyBudget {
+    pub usd: f64,
+    #[serde(default)]
+    pub period: BudgetPeriod,
+    // Fractions of `usd`; each one alerts once per period.
+    #[serde(default = "default_thresholds")]
+    pub thresholds: Vec<f64>,
+}
+
+fn default_thresholds() -> Vec<f64> {
+    vec![0.8, 1.0]
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPeriod {
+    Day,
+    #[default]
+    Month,
+}
+
+impl BudgetPeriod {
+    fn current(self) -> String {
+        let format = match self {
+            BudgetPeriod::Day => "%Y-%m-%d",
+            BudgetPeriod::Month => "%Y-%m",
+        };
+        Utc::now().format(format).to_string()
+    }
+
+    // When the current period began.
+    fn start(self) -> DateTime<Utc> {
+        let today = Utc::now().date_naive();
+        let first = match self {
+            BudgetPeriod::Day => today,
+            BudgetPeriod::Month => today.with_day(1).unwrap_or(today),
+        };
+        first.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()
+    }
+}
+
+// Fires when more than `threshold` of a model's requests in the last
+// `window_secs` failed with a 5xx.
+#[derive(Debug, Deserialize)]
+pub struct ErrorRateAlert {
+    pub threshold: f64,
+    #[serde(default = "default_window")]
+    pub window_secs: u64,
+    #[serde(default = "default_min_requests")]
+    pub min_requests: usize,
+}
+
+fn default_window() -> u64 {
+    300
+}
+
+fn default_min_requests() -> usize {
+    20
+}
+
+// --- Alerter ---
+pub struct Alerter {
+    config: AlertConfig,
+    client: Client,
+    state: Mutex<AlertState>,
+}
+
+#[derive(Default)]
+struct AlertState {
+    spend: HashMap<String, Spend>,
+    outcomes: HashMap<String, VecDeque<(Instant, bool)>>, // model -> (when, failed)
+    last_sent: HashMap<String, Instant>, // alert id -> when
+    down: HashMap<String, bool>, // model -> a "down" alert went out
+}
+
+struct Spend {
+    period: String,
+    usd: f64,
+    crossed: usize, // thresholds already alerted this period
+}
+
+impl AlertState {
+    // The key's spend in the budget's current period.
+    fn spend(&mut self, key: &str, budget: &KeyBudget) -> &mut Spend {
+        let period = budget.period.current();
+        let spend = self.spend.entry(key.to_string())
+            .or_insert_with(|| Spend { period: period.clone(), usd: 0.0, crossed: 0 });
+        if spend.period != period {
+            *spend = Spend { period, usd: 0.0, crossed: 0 };
+        }
+        spend
+    }
+}
+
+impl Spend {
+    fn alerts(&mut self, key: &str, budget: &KeyBudget, alerts: &mut Vec<(String, String)>) {
+        while let Some(threshold) = budget.thresholds.get(self.crossed).filter(|t| self.usd >= *t * budget.usd) {
+            alerts.push((
+                format!("budget:{}:{}:{}", key, self.period, threshold),
+                format!(
+                    ":moneybag: API key '{}' has spent ${:.2} of its ${:.2} budget for {} ({:.0}%).",
+                    key, self.usd, budget.usd, self.period, threshold * 100.0
+                ),
+            ));
+            self.crossed += 1;
+        }
+    }
+}
+
+impl Alerter {
+    pub fn new(mut config: AlertConfig, client: Client) -> Self {
+        for budget in config.budgets.values_mut() {
+            budget.thresholds.sort_by(f64::total_cmp);
+        }
+        info!(
+            "Alerting to {} webhook(s): {} key budget(s), error rate {}, backends down {}",
+            config.webhooks.len(), config.budgets.len(),
+            config.error_rate.as_ref().map_or("off".to_string(), |e| format!("> {:.0}%", e.threshold * 100.0)),
+            if config.backends_down { "on" } else { "off" }
+        );
+        Alerter { config, client, state: Mutex::new(AlertState::default()) }
+    }
+
+    // --- Shared Spend ---
+    // With a persistent store, budgeted keys' spend is refreshed from it at startup
+    // and then every minute, so it survives restarts and counts the requests of
+    // every instance sharing the store.
+    pub fn sync_spend(self: &Arc<Self>, store: Arc<dyn Store>) {
+        if !store.persistent() || self.config.budgets.is_empty() {
+            return;
+        }
+        let alerter = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SPEND_SYNC);
+            loop {
+                interval.tick().await;
+                for period in [BudgetPeriod::Day, BudgetPeriod::Month] {
+                    if !alerter.config.budgets.values().any(|budget| budget.period == period) {
+                        continue;
+                    }
+                    match store.spend_since(period.start()).await {
+                        Ok(Some(totals)) => alerter.refresh(period, &totals),
+                        Ok(None) => {}
+                        Err(e) => warn!("Failed to refresh budget spend: {:#}", e),
+                    }
+                }
+            }
+        });
+    }
+
+    fn refresh(&self, period: BudgetPeriod, totals: &HashMap<String, f64>) {
+        let mut alerts = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            for (key, budget) in self.config.budgets.iter().filter(|(_, budget)| budget.period == period) {
+                let spend = state.spend(key, budget);
+                // This instance's latest requests may not be written yet.
+                spend.usd = spend.usd.max(totals.get(key).copied().unwrap_or(0.0));
+                spend.alerts(key, budget, &mut alerts);
+            }
+        }
+        for (id, text) in alerts {
+            self.send(id, text);
+        }
+    }
+
+    // Called once for every finished request.
+    pub fn observe(&self, record: &RequestRecord) {
+        let mut alerts = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            if let (Some(key), Some(cost)) = (&record.key, record.cost) {
+                if let Some(budget) = self.config.budgets.get(key) {
+                    let spend = state.spend(key, budget);
+                    spend.usd += cost;
+                    spend.alerts(key, budget, &mut alerts);
+                }
+            }
+
+            // Client disconnects are not counted at all.
+            if let Some(rule) = self.config.error_rate.as_ref().filter(|_| record.status != 499) {
+                let now = Instant::now();
+                let window = Duration::from_secs(rule.window_secs);
+                let outcomes = state.outcomes.entry(record.model.clone()).or_default();
+                outcomes.push_back((now, record.status >= 500));
+                while outcomes.front().is_some_and(|(at, _)| now.duration_since(*at) > window) {
+                    outcomes.pop_front();
+                }
+                let failed = outcomes.iter().filter(|(_, failed)| *failed).count();
+                let rate = failed as f64 / outcomes.len() as f64;
+                if outcomes.len() >= rule.min_requests && rate > rule.threshold {
+                    alerts.push((
+                        format!("error_rate:{}", record.model),
+                        format!(
+                            ":rotating_light: Model '{}' is failing {:.0}% of requests ({} of {} in the last {}s).",
+                            record.model, rate * 100.0, failed, outcomes.len(), rule.window_secs
+                        ),
+                    ));
+                }
+            }
+        }
+        for (id, text) in alerts {
+            self.send(id, text);
+        }
+    }
+
+    // Called by the health monitor whenever a model's replicas change state.
+    pub fn backends_changed(&self, model: &str, healthy: usize, total: usize) {
+        if !self.config.backends_down {
+            return;
+        }
+        let was_down = self.state.lock().unwrap().down.get(model).copied().unwrap_or(false);
+        if healthy == 0 {
+            if self.send(format!("down:{}", model), format!(
+                ":red_circle: All {} backend(s) for model '{}' are unhealthy.", total, model
+            )) {
+                self.state.lock().unwrap().down.insert(model.to_string(), true);
+            }
+        } else if was_down {
+            self.state.lock().unwrap().down.insert(model.to_string(), false);
+            self.send(format!("up:{}", model), format!(
+                ":large_green_circle: Model '{}' has recovered ({} of {} backends healthy).", model, healthy, total
+            ));
+        }
+    }
+
+    // Posts unless the same alert went out within the cooldown. Returns whether it was sent.
+    fn send(&self, id: String, text: String) -> bool {
+        {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let cooldown = Duration::from_secs(self.config.cooldown_secs);
+            if state.last_sent.get(&id).is_some_and(|at| now.duration_since(*at) < cooldown) {
+                return false;
+            }
+            state.last_sent.insert(id, now);
+        }
+
+        warn!("Alert: {}", text);
+        let payload = json!({ "text": text });
+        for url in &self.config.webhooks {
+            let request = self.client.post(url).json(&payload);
+            let url = url.clone();
+            tokio::spawn(async move {
+                match request.send().await {
+                    Ok(response) if response.status().is_success() => {}
+                    Ok(response) => warn!("Alert webhook {} returned {}", url, response.status()),
+                    Err(e) => warn!("Failed to post alert to {}: {}", url, e),
+                }
+            });
+        }
+        true
+    }
+}
@@ -0,0 +1,245 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use std::{io::Write, time::Duration};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::{
+    aws::{uri_encode, AwsCredentials},
+    log_content::LogContent,
+    redaction::{RedactionConfig, Redactor},
+    request_log::RequestRecord,
+    ChatMessage,
+};
+
+// Records waiting for the uploader; beyond this they are dropped rather than
+// slowing requests down.
+const ARCHIVE_BUFFER: usize = 10_000;
+// Failed uploads are retried on the next flush; past this many batches' worth of
+// records the oldest are dropped.
+const RETAINED_BATCHES: usize = 10;
+
+// --- Archive Sink ---
+// With ARCHIVE_SINK set, every completed request (prompt, completion, and the
+// request log fields) is written to S3-compatible storage as gzipped JSONL, one
+// object per batch under `<prefix>YYYY/MM/DD/`.
+#[derive(Debug, Deserialize)]
+pub struct ArchiveConfig {
+    pub endpoint: String, // e.g. https://s3.eu-west-1.amazonaws.com, or a MinIO URL
+    pub bucket: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    #[serde(default)]
+    pub access_key_id: Option<String>, // falls back to AWS_ACCESS_KEY_ID
+    #[serde(default)]
+    pub secret_access_key: Option<String>, // falls back to AWS_SECRET_ACCESS_KEY
+    #[serde(default = "default_flush_interval")]
+    pub flush_interval_secs: u64,
+    #[serde(default = "default_max_batch")]
+    pub max_batch: usize, // records per object; a full batch is uploaded right away
+    #[serde(default)]
+    pub content: LogContent,
+    #[serde(default)]
+    pub pii: Option<RedactionConfig>, // detectors for `redacted`; the built-in ones when omitted
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_prefix() -> String {
+    "llm-gateway/".to_string()
+}
+
+fn default_flush_interval() -> u64 {
+    300
+}
+
+fn default_max_batch() -> usize {
+    10_000
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArchiveRecord {
+    pub request_id: String,
+    #[serde(flatten)]
+    pub record: RequestRecord,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub messages: Option<Vec<ChatMessage>>, // as the client sent them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion: Option<String>, // as the client received it
+    #[serde(skip)]
+    pub content: LogContent, // the archive's mode, or the tenant's if stricter
+}
+
+#[derive(Clone)]
+pub struct Archiver {
+    tx: mpsc::Sender<ArchiveRecord>,
+    content: LogContent,
+}
+
+impl Archiver {
+    pub fn start(mut config: ArchiveConfig, client: Client) -> Result<Self> {
+        // Built whatever the mode, since a tenant may ask for `redacted`.
+        let redactor = Redactor::new(config.pii.take().unwrap_or_default())?;
+        let bucket = Bucket::new(&config, client)?;
+        info!(
+            "Archiving requests to {}/{}/{} every {}s ({:?} content)",
+            config.endpoint.trim_end_matches('/'), config.bucket, config.prefix, config.flush_interval_secs, config.content
+        );
+
+        let (tx, rx) = mpsc::channel(ARCHIVE_BUFFER);
+        tokio::spawn(upload_batches(
+            rx, bucket, redactor, config.prefix, Duration::from_secs(config.flush_interval_secs.max(1)), config.max_batch.max(1),
+        ));
+        Ok(Archiver { tx, content: config.content })
+    }
+
+    pub fn content(&self) -> LogContent {
+        self.content
+    }
+
+    pub fn submit(&self, record: ArchiveRecord) {
+        if let Err(mpsc::error::TrySendError::Full(record)) = self.tx.try_send(record) {
+            warn!("Archive sink is falling behind; dropped record for {}", record.request_id);
+        }
+    }
+}
+
+// --- Uploader ---
+async fn upload_batches(
+    mut rx: mpsc::Receiver<ArchiveRecord>,
+    bucket: Bucket,
+    redactor: Redactor,
+    prefix: String,
+    interval: Duration,
+    max_batch: usize,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    let mut received = Vec::new();
+    let mut lines: Vec<String> = Vec::new();
+    let mut failing = false; // since the last upload failed; retries wait for the ticker
+
+    loop {
+        tokio::select! {
+            count = rx.recv_many(&mut received, max_batch) => {
+                if count == 0 {
+                    break;
+                }
+                for mut record in received.drain(..) {
+                    apply_content(&redactor, &mut record);
+                    lines.push(serde_json::to_string(&record).unwrap_or_default());
+                }
+                if lines.len() < max_batch || failing {
+                    continue;
+                }
+            }
+            _ = ticker.tick() => {}
+        }
+        if lines.is_empty() {
+            continue;
+        }
+
+        if let Err(e) = upload(&bucket, &prefix, &lines).await {
+            let limit = max_batch * RETAINED_BATCHES;
+            let dropped = lines.len().saturating_sub(limit);
+            error!(
+                "Failed to archive {} records: {:#}; retrying on the next flush{}",
+                lines.len(), e,
+                if dropped > 0 { format!(", {} oldest records dropped", dropped) } else { String::new() }
+            );
+            lines.drain(..dropped);
+            failing = true;
+            continue;
+        }
+        failing = false;
+        lines.clear();
+    }
+
+    // Only reached if every sender is gone.
+    if !lines.is_empty() {
+        if let Err(e) = upload(&bucket, &prefix, &lines).await {
+            error!("Failed to archive {} records: {:#}", lines.len(), e);
+        }
+    }
+}
+
+fn apply_content(redactor: &Redactor, record: &mut ArchiveRecord) {
+    if let Some(messages) = &mut record.messages {
+        record.content.apply_messages(redactor, messages);
+    }
+    if let Some(completion) = &mut record.completion {
+        record.content.apply(redactor, completion);
+    }
+}
+
+async fn upload(bucket: &Bucket, prefix: &str, lines: &[String]) -> Result<()> {
+    let now = Utc::now();
+    let key = format!(
+        "{}{}/{}-{}.jsonl.gz",
+        prefix, now.format("%Y/%m/%d"), now.format("%Y%m%dT%H%M%SZ"), uuid::Uuid::new_v4()
+    );
+    let mut jsonl = lines.join("\n");
+    jsonl.push('\n');
+    let body = tokio::task::spawn_blocking(move || {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(jsonl.as_bytes())?;
+        encoder.finish()
+    }).await??;
+
+    let size = body.len();
+    bucket.put(&key, body, now).await?;
+    info!("Archived {} records to {} ({} bytes)", lines.len(), key, size);
+    Ok(())
+}
+
+// --- S3 Client ---
+// Path-style PutObject signed with AWS Signature Version 4, which every
+// S3-compatible store accepts.
+struct Bucket {
+    client: Client,
+    endpoint: Url,
+    bucket: String,
+    credentials: AwsCredentials,
+}
+
+impl Bucket {
+    fn new(config: &ArchiveConfig, client: Client) -> Result<Self> {
+        let endpoint = Url::parse(&config.endpoint)
+            .with_context(|| format!("Invalid ARCHIVE_SINK endpoint '{}'", config.endpoint))?;
+        Ok(Bucket {
+            client,
+            endpoint,
+            bucket: config.bucket.clone(),
+            credentials: AwsCredentials::resolve(&config.access_key_id, &config.secret_access_key, &config.region, "ARCHIVE_SINK")?,
+        })
+    }
+
+    async fn put(&self, key: &str, body: Vec<u8>, now: DateTime<Utc>) -> Result<()> {
+        let path = format!(
+            "{}/{}/{}",
+            uri_encode(self.endpoint.path().trim_end_matches('/')), uri_encode(&self.bucket), uri_encode(key)
+        );
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        let headers = self.credentials.sign("PUT", &url, "s3", &body, Vec::new(), now);
+
+        let mut request = self.client.put(url).header("content-type", "application/gzip");
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        let response = request.body(body).send().await.context("Failed to reach archive storage")?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("Archive storage returned {}: {}", status, text.trim());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,440 @@
+use axum::{
+    body::Body,
+    extract::{multipart::{Field, MultipartError}, DefaultBodyLimit, Multipart, State},
+    http::{header::{CONTENT_TYPE, RETRY_AFTER}, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Extension, Json, Router,
+};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{io, path::PathBuf, sync::Arc};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::{mpsc, oneshot},
+};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{
+    auth::Caller, backend::Backend, headers, mock, request_log::Recorder, stream::{self, UpstreamBody}, tenants, upstream, AppError, AppState,
+    RequestMeta,
+};
+
+// OpenAI's limit for audio uploads.
+pub const DEFAULT_MAX_UPLOAD_BYTES: usize = 25 * 1024 * 1024;
+
+// Mock backends "transcribe" uploads as 16 kHz 16-bit mono PCM.
+const MOCK_BYTES_PER_SECOND: f64 = 32_000.0;
+
+// --- Audio Transcription ---
+// POST /v1/audio/transcriptions takes OpenAI's multipart form and routes it by its
+// `model` field to AUDIO_BACKENDS (vLLM serving Whisper, faster-whisper-server, ...).
+// The form is re-encoded on the way through, so the file is never held in memory:
+// it is streamed upstream as it arrives or, if it comes before `model`, spooled to
+// a temporary file first.
+pub fn router(max_upload_bytes: usize) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/v1/audio/transcriptions", post(transcriptions).layer(DefaultBodyLimit::max(max_upload_bytes)))
+        .route("/v1/audio/speech", post(speech))
+}
+
+// Where the form goes, decided as soon as its `model` field has been read.
+struct Route {
+    model: String,
+    backend: Arc<Backend>,
+}
+
+#[utoipa::path(
+    post, path = "/v1/audio/transcriptions", tag = "Audio",
+    description = "OpenAI's multipart transcription form (`file`, `model`, and any other fields), forwarded as it arrives.",
+    request_body(content_type = "multipart/form-data", description = "`file`, `model`, and optional `language`, `prompt`, `response_format`, ..."),
+    responses((status = 200, description = "The backend's transcription, in the requested `response_format`.", body = Object)),
+)]
+async fn transcriptions(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    form: Multipart,
+) -> Response {
+    let meta = RequestMeta::new();
+    let boundary = format!("gateway-{}", uuid::Uuid::new_v4().simple());
+    let (route_tx, route_rx) = oneshot::channel();
+    let (body_tx, body_rx) = mpsc::channel(8);
+
+    let reading = read_form(&state, &caller, form, &boundary, route_tx, body_tx);
+    let sending = async {
+        let route: Route = route_rx.await.ok()?;
+        let mut recorder = Recorder::new(&state, &meta, route.model.clone(), &caller);
+        recorder.set_tenant(caller.tenant().cloned());
+        recorder.started();
+        let sent = send(&state, &headers, &route, &boundary, body_rx, &mut recorder).await;
+        Some((route, recorder, sent))
+    };
+    let (read, sent) = tokio::join!(reading, sending);
+    let Some((route, recorder, sent)) = sent else {
+        // The form was rejected before it named a usable model.
+        return read.err().map(IntoResponse::into_response).unwrap_or_default();
+    };
+
+    // An upload that fails midway fails the backend request too; report the cause.
+    let (mut response, replica) = match read.and(sent) {
+        Ok((response, replica)) => (response, Some(replica)),
+        Err(e) => (e.into_response(), None),
+    };
+    headers::stamp_metadata(response.headers_mut(), &meta, &route.model, replica.as_deref());
+    let cost = recorder.audio_seconds()
+        .and_then(|seconds| Some(route.backend.config.pricing?.per_audio_minute * seconds / 60.0));
+    recorder.finish(response.status().as_u16(), None, cost);
+    response
+}
+
+// Reads the form, routes it once `model` is known, and writes it out again for the
+// backend. Stops quietly if the backend request has already ended.
+async fn read_form(
+    state: &AppState,
+    caller: &Caller,
+    mut form: Multipart,
+    boundary: &str,
+    route: oneshot::Sender<Route>,
+    body: mpsc::Sender<io::Result<Bytes>>,
+) -> Result<(), AppError> {
+    let mut route = Some(route); // taken once the form is routed
+    let mut model: Option<String> = None;
+    let mut fields: Vec<(String, String)> = Vec::new(); // read before routing
+    let mut spooled: Option<Spooled> = None;
+    let mut has_file = false;
+    let writer = FormWriter { boundary, body };
+    // Err(None): the backend request has ended, so nobody reads the rest.
+    let written: Result<(), Option<AppError>> = async {
+        while let Some(mut field) = form.next_field().await.map_err(bad_form)? {
+            let Some(name) = field.name().map(str::to_string) else { continue };
+            match name.as_str() {
+                "file" if has_file => return Err(Some(duplicate("file"))),
+                "model" if model.is_some() => return Err(Some(duplicate("model"))),
+                "file" => {
+                    has_file = true;
+                    match &model {
+                        Some(requested) => {
+                            start(state, caller, requested, &mut route, &writer, &fields).await?;
+                            writer.field(field).await?;
+                        }
+                        None => spooled = Some(Spooled::write(&mut field).await?),
+                    }
+                }
+                "model" => {
+                    let requested = field.text().await.map_err(bad_form)?;
+                    if let Some(spooled) = &spooled {
+                        start(state, caller, &requested, &mut route, &writer, &fields).await?;
+                        spooled.copy_to(&writer).await?;
+                    }
+                    model = Some(requested);
+                }
+                _ => {
+                    let value = field.text().await.map_err(bad_form)?;
+                    match route {
+                        Some(_) => fields.push((name, value)),
+                        None => writer.text(&name, &value).await?,
+                    }
+                }
+            }
+        }
+        if route.is_some() {
+            let missing = if model.is_none() { "model" } else { "file" };
+            return Err(Some(AppError::InvalidRequest(format!("The upload needs a '{}' field.", missing))));
+        }
+        writer.write(format!("--{}--\r\n", boundary)).await
+    }.await;
+    match written {
+        Ok(()) | Err(None) => Ok(()),
+        Err(Some(e)) => {
+            // Don't let the backend take a truncated form for a complete one.
+            let _ = writer.body.send(Err(io::Error::other("upload failed"))).await;
+            Err(e)
+        }
+    }
+}
+
+// Routes the form by its model and writes out the fields buffered until then.
+async fn start(
+    state: &AppState,
+    caller: &Caller,
+    requested: &str,
+    route: &mut Option<oneshot::Sender<Route>>,
+    writer: &FormWriter<'_>,
+    fields: &[(String, String)],
+) -> Result<(), Option<AppError>> {
+    let (model, backend) = tenants::route_in(caller, &state.audio_backends, requested)?;
+    let Some(route) = route.take() else { return Ok(()) };
+    let served = backend.served_model(&model).to_string();
+    route.send(Route { model, backend }).map_err(|_| None)?;
+    writer.text("model", &served).await?;
+    for (name, value) in fields {
+        writer.text(name, value).await?;
+    }
+    Ok(())
+}
+
+// Forwards the re-encoded form and returns the backend's response.
+async fn send(
+    state: &AppState,
+    headers: &HeaderMap,
+    route: &Route,
+    boundary: &str,
+    body: mpsc::Receiver<io::Result<Bytes>>,
+    recorder: &mut Recorder,
+) -> Result<(Response, String), AppError> {
+    let backend = &route.backend;
+    let replica = backend.pick_replica(&state.latency).ok_or_else(|| AppError::NoBackendAvailable(route.model.clone()))?;
+    recorder.set_backend(&replica);
+    let exchange = async {
+        if mock::is_mock(&replica) {
+            return Ok(mock_transcription(body).await);
+        }
+        let url = backend.endpoint_url(&replica, "audio/transcriptions", backend.served_model(&route.model));
+        info!("Routing transcription for model '{}' to: {}", route.model, url);
+        let upload = futures::stream::unfold(body, |mut body| async move { body.recv().await.map(|chunk| (chunk, body)) });
+        let res = backend.authorize(state.header_policy.forward_request(headers, backend.client_for(&replica).post(&url)))
+            .header(CONTENT_TYPE, format!("multipart/form-data; boundary={}", boundary))
+            .body(reqwest::Body::wrap_stream(upload))
+            .send().await.map_err(AppError::BackendRequestFailed)?;
+        if !res.status().is_success() {
+            let status = res.status();
+            let retry_after = res.headers().get(RETRY_AFTER).cloned();
+            let text = res.text().await.unwrap_or_else(|_| "No response body".to_string());
+            return Err(AppError::BackendRespondedError { status, text, url, retry_after });
+        }
+        let response_headers = upstream::response_headers(&state.header_policy, &res);
+        let text = res.bytes().await.map_err(AppError::BackendRequestFailed)?;
+        Ok((response_headers, text))
+    };
+    let (response_headers, text) = upstream::within_total(backend, &replica, exchange).await?;
+    if let Some(seconds) = audio_seconds(&text) {
+        recorder.set_audio_seconds(seconds);
+    }
+    Ok(((response_headers, text).into_response(), replica))
+}
+
+// OpenAI reports `usage: {"type": "duration", "seconds": ...}`; Whisper servers
+// report `duration` with `response_format: verbose_json`. Plain text formats
+// carry neither.
+fn audio_seconds(text: &[u8]) -> Option<f64> {
+    let value: Value = serde_json::from_slice(text).ok()?;
+    value.pointer("/usage/seconds").or_else(|| value.get("duration")).and_then(Value::as_f64)
+}
+
+async fn mock_transcription(mut body: mpsc::Receiver<io::Result<Bytes>>) -> (HeaderMap, Bytes) {
+    let mut bytes = 0;
+    while let Some(Ok(chunk)) = body.recv().await {
+        bytes += chunk.len();
+    }
+    let seconds = (bytes as f64 / MOCK_BYTES_PER_SECOND).ceil();
+    let text = json!({
+        "text": "Lorem ipsum dolor sit amet.",
+        "usage": { "type": "duration", "seconds": seconds },
+    });
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    (headers, Bytes::from(text.to_string()))
+}
+
+fn bad_form(e: MultipartError) -> AppError {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        return AppError::InvalidRequest("The upload is larger than AUDIO_MAX_UPLOAD_BYTES allows.".to_string());
+    }
+    AppError::InvalidRequest(format!("Invalid upload: {}", e))
+}
+
+fn duplicate(name: &str) -> AppError {
+    AppError::InvalidRequest(format!("The upload has more than one '{}' field.", name))
+}
+
+// --- Speech ---
+// POST /v1/audio/speech forwards OpenAI's text-to-speech request to the model's
+// AUDIO_BACKENDS entry (Kokoro, Orpheus, openedai-speech, ...) and streams the
+// audio back as the backend produces it, with the backend's content type.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+struct SpeechRequest {
+    model: String,
+    input: String,
+    #[serde(flatten)]
+    options: serde_json::Map<String, Value>, // voice, response_format, speed, ...
+}
+
+#[utoipa::path(
+    post, path = "/v1/audio/speech", tag = "Audio",
+    request_body = SpeechRequest,
+    responses((status = 200, description = "The audio, streamed as the backend produces it.", content_type = "application/octet-stream", body = Vec<u8>)),
+)]
+async fn speech(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    Json(mut request): Json<SpeechRequest>,
+) -> Response {
+    let meta = RequestMeta::new();
+    let routed = tenants::route_in(&caller, &state.audio_backends, &request.model);
+    let (model, backend) = match routed {
+        Ok(routed) => routed,
+        Err(e) => {
+            let mut response = e.into_response();
+            headers::stamp_metadata(response.headers_mut(), &meta, &request.model, None);
+            Recorder::new(&state, &meta, request.model, &caller).finish(response.status().as_u16(), None, None);
+            return response;
+        }
+    };
+    request.model = backend.served_model(&model).to_string();
+    let mut recorder = Recorder::new(&state, &meta, model.clone(), &caller);
+    recorder.set_tenant(caller.tenant().cloned());
+    recorder.started();
+
+    let replica = backend.pick_replica(&state.latency);
+    if let Some(replica) = &replica {
+        recorder.set_backend(replica);
+    }
+    let opened = match &replica {
+        Some(replica) => open_speech(&state, &headers, &backend, replica, &request).await,
+        None => Err(AppError::NoBackendAvailable(model.clone())),
+    };
+    let (response_headers, audio) = match opened {
+        Ok(opened) => opened,
+        Err(e) => {
+            let mut response = e.into_response();
+            headers::stamp_metadata(response.headers_mut(), &meta, &model, replica.as_deref());
+            recorder.finish(response.status().as_u16(), None, None);
+            return response;
+        }
+    };
+    let mut response = (response_headers, Body::from_stream(stream::passthrough(audio, &backend.timeouts, recorder))).into_response();
+    headers::stamp_metadata(response.headers_mut(), &meta, &model, replica.as_deref());
+    response
+}
+
+// The backend's response headers and audio, once it has answered.
+async fn open_speech(
+    state: &AppState,
+    headers: &HeaderMap,
+    backend: &Backend,
+    replica: &str,
+    request: &SpeechRequest,
+) -> Result<(HeaderMap, UpstreamBody), AppError> {
+    if mock::is_mock(replica) {
+        return Ok(mock_speech(&request.input));
+    }
+    let url = backend.endpoint_url(replica, "audio/speech", &request.model);
+    info!("Routing speech for model '{}' to: {}", request.model, url);
+    let outbound = backend.authorize(state.header_policy.forward_request(headers, backend.client_for(replica).post(&url))).json(request);
+    let res = upstream::forward(backend, outbound, url).await?;
+    Ok((upstream::response_headers(&state.header_policy, &res), Box::pin(res.bytes_stream())))
+}
+
+// A second of silence per 15 characters of input, as 16 kHz 16-bit mono WAV.
+fn mock_speech(input: &str) -> (HeaderMap, UpstreamBody) {
+    let samples = (input.chars().count().div_ceil(15).max(1) * 16_000) as u32;
+    let data_bytes = samples * 2;
+    let mut wav = Vec::with_capacity(44 + data_bytes as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_bytes).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes()); // fmt chunk size
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&16_000u32.to_le_bytes()); // sample rate
+    wav.extend_from_slice(&32_000u32.to_le_bytes()); // byte rate
+    wav.extend_from_slice(&2u16.to_le_bytes()); // block align
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_bytes.to_le_bytes());
+    wav.resize(44 + data_bytes as usize, 0);
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("audio/wav"));
+    let chunks: Vec<reqwest::Result<Bytes>> = wav.chunks(32_000).map(|chunk| Ok(Bytes::copy_from_slice(chunk))).collect();
+    (headers, Box::pin(futures::stream::iter(chunks)))
+}
+
+// --- Form Encoding ---
+struct FormWriter<'a> {
+    boundary: &'a str,
+    body: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl FormWriter<'_> {
+    async fn write(&self, bytes: impl Into<Bytes>) -> Result<(), Option<AppError>> {
+        self.body.send(Ok(bytes.into())).await.map_err(|_| None)
+    }
+
+    fn part_header(&self, name: &str, file: Option<(&str, &str)>) -> String {
+        let mut header = format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"", self.boundary, quote(name));
+        if let Some((file_name, content_type)) = file {
+            header += &format!("; filename=\"{}\"\r\nContent-Type: {}", quote(file_name), quote(content_type));
+        }
+        header + "\r\n\r\n"
+    }
+
+    async fn text(&self, name: &str, value: &str) -> Result<(), Option<AppError>> {
+        self.write(format!("{}{}\r\n", self.part_header(name, None), value)).await
+    }
+
+    async fn field(&self, mut field: Field<'_>) -> Result<(), Option<AppError>> {
+        let file_name = field.file_name().unwrap_or("audio").to_string();
+        let content_type = field.content_type().unwrap_or("application/octet-stream").to_string();
+        self.write(self.part_header("file", Some((&file_name, &content_type)))).await?;
+        while let Some(chunk) = field.chunk().await.map_err(bad_form)? {
+            self.write(chunk).await?;
+        }
+        self.write("\r\n").await
+    }
+}
+
+// Keeps a name from breaking out of its quoted header parameter.
+fn quote(value: &str) -> String {
+    value.replace('"', "%22").replace(['\r', '\n'], "")
+}
+
+// --- Spooled Uploads ---
+// A file that arrived before the form's `model`; deleted once dropped.
+struct Spooled {
+    path: PathBuf,
+    file_name: String,
+    content_type: String,
+}
+
+impl Drop for Spooled {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl Spooled {
+    async fn write(field: &mut Field<'_>) -> Result<Self, AppError> {
+        let spooled = Spooled {
+            path: std::env::temp_dir().join(format!("gateway-audio-{}", uuid::Uuid::new_v4().simple())),
+            file_name: field.file_name().unwrap_or("audio").to_string(),
+            content_type: field.content_type().unwrap_or("application/octet-stream").to_string(),
+        };
+        let spool_error = |e: io::Error| AppError::Internal(format!("Failed to spool the upload: {}", e));
+        let mut file = tokio::fs::File::create(&spooled.path).await.map_err(spool_error)?;
+        while let Some(chunk) = field.chunk().await.map_err(bad_form)? {
+            file.write_all(&chunk).await.map_err(spool_error)?;
+        }
+        file.flush().await.map_err(spool_error)?;
+        Ok(spooled)
+    }
+
+    async fn copy_to(&self, writer: &FormWriter<'_>) -> Result<(), Option<AppError>> {
+        let spool_error = |e: io::Error| Some(AppError::Internal(format!("Failed to read the spooled upload: {}", e)));
+        writer.write(writer.part_header("file", Some((&self.file_name, &self.content_type)))).await?;
+        let mut file = tokio::fs::File::open(&self.path).await.map_err(spool_error)?;
+        loop {
+            let mut chunk = vec![0; 64 * 1024];
+            let read = file.read(&mut chunk).await.map_err(spool_error)?;
+            if read == 0 {
+                break;
+            }
+            chunk.truncate(read);
+            writer.write(chunk).await?;
+        }
+        writer.write("\r\n").await
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    admin::{Admin, Role, Window},
+    store::Store,
+    AppError, AppState,
+};
+
+// --- Audit Log ---
+// Every admin change (keys created or deleted, credentials stored, requests
+// cancelled) is recorded with who made it, when, and the object before and
+// after, secrets only ever as hints. With AUDIT_LOG set, entries are appended to
+// that JSONL file, which the gateway never rewrites, and read back at startup.
+// Each entry carries the SHA-256 of the line before it, so an edited or deleted
+// line breaks the chain. With a persistent STORE_URL, entries are stored before
+// the change answers, chained to the newest entry of any instance sharing the
+// store, read back from there, and still appended to AUDIT_LOG if that is set
+// too. GET /admin/audit queries the entries, from the store when it has them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: String,
+    pub at: DateTime<Utc>,
+    pub actor: String,
+    pub role: Role,
+    pub action: String,
+    pub target: String,
+    pub before: Value,
+    pub after: Value,
+    pub prev_hash: String,
+}
+
+struct Log {
+    file: Option<File>,
+    store: Arc<dyn Store>,
+    entries: Vec<AuditEntry>,
+    last_hash: String,
+}
+
+pub struct AuditLog {
+    log: Mutex<Log>,
+    writing: tokio::sync::Mutex<()>, // one entry at a time, so the file keeps the chain's order
+}
+
+const MAX_CONFLICTS: u32 = 20;
+
+impl AuditLog {
+    pub async fn from_env(store: Arc<dyn Store>) -> Result<Arc<Self>> {
+        let lines = store.audit_lines().await?;
+        let mut log = Log { file: None, store, entries: Vec::new(), last_hash: String::new() };
+        if log.store.persistent() {
+            log.load("the store", lines)?;
+            info!("Audit log in the store ({} entries)", log.entries.len());
+        }
+        if let Some(path) = std::env::var_os("AUDIT_LOG").map(PathBuf::from) {
+            if path.exists() && !log.store.persistent() {
+                let lines = BufReader::new(File::open(&path).with_context(|| format!("Failed to read '{}'", path.display()))?).lines();
+                log.load(&format!("'{}'", path.display()), lines.collect::<Result<_, _>>()?)?;
+            }
+            let file = std::fs::OpenOptions::new().create(true).append(true).open(&path)
+                .with_context(|| format!("Failed to open AUDIT_LOG '{}'", path.display()))?;
+            info!("Audit log {} ({} entries)", path.display(), log.entries.len());
+            log.file = Some(file);
+        }
+        Ok(Arc::new(AuditLog { log: Mutex::new(log), writing: tokio::sync::Mutex::new(()) }))
+    }
+
+    // Called once the change has been made. `before` and `after` are Null for
+    // objects that didn't exist on that side.
+    pub async fn record(&self, admin: &Admin, action: &str, target: &str, before: Value, after: Value) {
+        let _writing = self.writing.lock().await;
+        let (store, last_hash) = {
+            let log = self.log.lock().unwrap();
+            (log.store.clone(), log.last_hash.clone())
+        };
+        let mut entry = AuditEntry {
+            id: format!("audit_{}", uuid::Uuid::new_v4().simple()),
+            at: Utc::now(),
+            actor: admin.name.clone(),
+            role: admin.role,
+            action: action.to_string(),
+            target: target.to_string(),
+            before,
+            after,
+            prev_hash: last_hash,
+        };
+        let mut line = serde_json::to_string(&entry).unwrap_or_default();
+        info!("Audit: '{}' {} {}", entry.actor, entry.action, entry.target);
+        if store.persistent() {
+            // Another instance may have appended since; the entry is chained again to the new head.
+            let mut outcome = Err(anyhow::anyhow!("the audit log in the store kept changing"));
+            for attempt in 0..MAX_CONFLICTS {
+                if attempt > 0 {
+                    tokio::time::sleep(std::time::Duration::from_millis(5 * attempt as u64)).await;
+                }
+                let appended = match store.audit_head().await {
+                    Ok(head) => {
+                        entry.prev_hash = head;
+                        line = serde_json::to_string(&entry).unwrap_or_default();
+                        store.append_audit(&entry, &line, &hash(&line)).await
+                    }
+                    Err(e) => Err(e),
+                };
+                match appended {
+                    Ok(true) => outcome = Ok(()),
+                    Ok(false) => continue,
+                    Err(e) => outcome = Err(e),
+                }
+                break;
+            }
+            if let Err(e) = outcome {
+                error!("Failed to store audit entry {}: {:#}; it is not persisted: {}", entry.id, e, line);
+            }
+        }
+        let mut log = self.log.lock().unwrap();
+        if let Some(file) = &mut log.file {
+            if let Err(e) = writeln!(file, "{}", line).and_then(|_| file.sync_data()) {
+                error!("Failed to append to AUDIT_LOG: {}; the entry is kept in memory only", e);
+            }
+        }
+        log.last_hash = hash(&line);
+        log.entries.push(entry);
+    }
+}
+
+impl Log {
+    fn load(&mut self, source: &str, lines: Vec<String>) -> Result<()> {
+        for (number, line) in lines.iter().enumerate() {
+            let entry: AuditEntry = serde_json::from_str(line)
+                .with_context(|| format!("Invalid entry on line {} of {}", number + 1, source))?;
+            if entry.prev_hash != self.last_hash {
+                warn!("Audit log in {} was modified before line {}: its hash chain is broken", source, number + 1);
+            }
+            self.last_hash = hash(line);
+            self.entries.push(entry);
+        }
+        Ok(())
+    }
+}
+
+pub fn hash(line: &str) -> String {
+    format!("{:x}", Sha256::digest(line.as_bytes()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    actor: Option<String>,
+    action: Option<String>, // exact, or a prefix ending in `.` such as `key.`
+    target: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: usize,
+    #[serde(flatten)]
+    window: Window,
+}
+
+fn default_limit() -> usize {
+    100
+}
+
+#[utoipa::path(
+    get, path = "/admin/audit", tag = "Admin",
+    params(
+        ("actor" = Option<String>, Query, description = "The admin key's name."),
+        ("action" = Option<String>, Query, description = "An action such as `key.create`, or a prefix such as `key.`."),
+        ("target" = Option<String>, Query),
+        ("from" = Option<String>, Query, description = "RFC 3339 or YYYY-MM-DD; defaults to 24 hours before `to`."),
+        ("to" = Option<String>, Query, description = "RFC 3339 or YYYY-MM-DD; defaults to now."),
+        ("limit" = Option<usize>, Query, description = "At most this many entries, newest first (default 100)."),
+    ),
+    responses((status = 200, description = "Matching audit entries, newest first.", body = Object)),
+)]
+pub async fn query(State(state): State<Arc<AppState>>, Query(query): Query<AuditQuery>) -> Result<Json<Value>, AppError> {
+    let (from, to) = query.window.resolve()?;
+    let filter = AuditFilter { from, to, actor: query.actor, action: query.action, target: query.target, limit: query.limit };
+    // The store has every instance's entries; memory only this one's.
+    let store = state.audit.log.lock().unwrap().store.clone();
+    if store.persistent() {
+        let lines = store.query_audit(&filter).await.map_err(|e| AppError::Internal(format!("{:#}", e)))?;
+        let data = lines.iter().map(|line| serde_json::from_str(line))
+            .collect::<Result<Vec<AuditEntry>, _>>()
+            .map_err(|e| AppError::Internal(format!("Invalid audit entry in the store: {}", e)))?;
+        return Ok(Json(json!({ "object": "list", "data": data })));
+    }
+    let log = state.audit.log.lock().unwrap();
+    let data: Vec<&AuditEntry> = log.entries.iter().rev()
+        .filter(|entry| filter.matches(entry))
+        .take(filter.limit)
+        .collect();
+    Ok(Json(json!({ "object": "list", "data": data })))
+}
+
+// GET /admin/audit's parameters, with the window resolved.
+pub struct AuditFilter {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub actor: Option<String>,
+    pub action: Option<String>, // exact, or a prefix ending in `.`
+    pub target: Option<String>,
+    pub limit: usize,
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        entry.at >= self.from && entry.at < self.to
+            && self.actor.as_ref().is_none_or(|actor| &entry.actor == actor)
+            && self.target.as_ref().is_none_or(|target| &entry.target == target)
+            && self.action.as_ref().is_none_or(|action| {
+                &entry.action == action || (action.ends_with('.') && entry.action.starts_with(action.as_str()))
+            })
+    }
+}
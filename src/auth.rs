@@ -0,0 +1,445 @@
+use anyhow::Result;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header::{AUTHORIZATION, SEC_WEBSOCKET_PROTOCOL}, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tracing::{info, warn};
+
+use crate::{
+    credentials::{self, CredentialStore},
+    output_limit::ResponseLimit,
+    priority::Priority,
+    prompt::SystemPromptConfig,
+    tenants::{Tenant, Tenants},
+    AppError, AppState,
+};
+
+// --- API Keys ---
+// Loaded from GATEWAY_API_KEYS, a JSON object mapping each secret key to its
+// settings. When unset, the /v1 routes stay open as before.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiKey {
+    pub name: String,
+    // Model names or globs (`mistral-*`) this key may use; omitted means all models.
+    #[serde(default)]
+    pub models: Option<Vec<String>>,
+    // Renames requested models (e.g. "default" -> "llama-3-70b") ahead of its tenant's aliases.
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+    // Overrides the model/default system prompt for this key's requests.
+    #[serde(default)]
+    pub system_prompt: Option<SystemPromptConfig>,
+    // May pin requests with X-Gateway-Route / X-Gateway-Backend.
+    #[serde(default)]
+    pub routing_overrides: bool,
+    // May inject faults with X-Gateway-Chaos.
+    #[serde(default)]
+    pub chaos: bool,
+    // May record its streams with X-Gateway-Trace.
+    #[serde(default)]
+    pub trace: bool,
+    // The X-Priority values the key may send; omitted means both.
+    #[serde(default)]
+    pub priorities: Option<Vec<Priority>>,
+    // May cancel its own in-flight requests with POST /v1/requests/cancel.
+    #[serde(default)]
+    pub cancel_requests: bool,
+    // The TENANTS entry whose models and quotas apply to this key.
+    #[serde(default)]
+    pub tenant: Option<String>,
+    // Share of a saturated backend's slots relative to other keys (if no tenant).
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    // Refused from then on, like a revoked key.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    // The OpenAI-Organization / OpenAI-Project values the key may send.
+    #[serde(default)]
+    pub organizations: Option<Vec<String>>,
+    #[serde(default)]
+    pub projects: Option<Vec<String>>,
+    // Lets the key sign requests instead of (or, if required, as well as) sending the token.
+    #[serde(default)]
+    pub signing: Option<SigningConfig>,
+    // Caps what one request may generate, on top of the model's own limit.
+    #[serde(default)]
+    pub response_limit: Option<ResponseLimit>,
+    // Replaces LOG_SAMPLING's rate for this key's requests.
+    #[serde(default)]
+    pub log_sample_rate: Option<f64>,
+}
+
+impl ApiKey {
+    pub fn check_expiry(&self) -> Result<(), AppError> {
+        match self.expires_at {
+            Some(at) if at <= Utc::now() => Err(AppError::Unauthorized(format!("This API key expired at {}.", at.to_rfc3339()))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn status(&self) -> &'static str {
+        if self.check_expiry().is_ok() { "active" } else { "expired" }
+    }
+}
+
+pub fn default_weight() -> f64 {
+    1.0
+}
+
+// Keys created through the admin API live in CREDENTIAL_STORE and are accepted
+// alongside these; GATEWAY_API_KEYS still has to be set (`{}` will do) for /v1
+// to require a key.
+pub struct KeyStore {
+    keys: HashMap<String, Arc<ApiKey>>,
+    stored: Option<Arc<CredentialStore>>,
+    seen: Mutex<HashMap<Vec<u8>, i64>>, // accepted signatures -> when their timestamp leaves the key's window, for replay checks
+}
+
+impl KeyStore {
+    pub fn from_env(stored: Option<Arc<CredentialStore>>) -> Result<Option<Self>> {
+        let keys: Option<HashMap<String, ApiKey>> = crate::config::env_json("GATEWAY_API_KEYS")?;
+        if keys.is_none() && stored.as_ref().is_some_and(|store| !store.keys().is_empty()) {
+            warn!("CREDENTIAL_STORE holds API keys, but they are ignored while GATEWAY_API_KEYS is not set");
+        }
+        Ok(keys.map(|keys| KeyStore {
+            keys: keys.into_iter().map(|(secret, key)| (secret, Arc::new(key))).collect(),
+            stored,
+            seen: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len() + self.stored.as_ref().map_or(0, |store| store.keys().len())
+    }
+
+    fn all(&self) -> Vec<Arc<ApiKey>> {
+        let mut keys: Vec<_> = self.keys.values().cloned().collect();
+        keys.extend(self.stored.iter().flat_map(|store| store.keys()));
+        keys
+    }
+
+    fn find(&self, token: &str) -> Result<Option<Arc<ApiKey>>, AppError> {
+        if let Some(key) = self.keys.get(token) {
+            key.check_expiry()?;
+            return Ok(Some(key.clone()));
+        }
+        match &self.stored {
+            Some(store) => store.find_key(token),
+            None => Ok(None),
+        }
+    }
+
+    fn find_named(&self, name: &str) -> Result<Option<Arc<ApiKey>>, AppError> {
+        if let Some(key) = self.keys.values().find(|key| key.name == name) {
+            key.check_expiry()?;
+            return Ok(Some(key.clone()));
+        }
+        match &self.stored {
+            Some(store) => store.key_named(name),
+            None => Ok(None),
+        }
+    }
+
+    pub fn named(&self, name: &str) -> Option<Arc<ApiKey>> {
+        self.all().into_iter().find(|key| key.name == name)
+    }
+
+    // The GATEWAY_API_KEYS entries, with a hint of each secret.
+    pub fn configured(&self) -> Vec<(String, Arc<ApiKey>)> {
+        let mut keys: Vec<_> = self.keys.iter().map(|(secret, key)| (credentials::hint(secret), key.clone())).collect();
+        keys.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+        keys
+    }
+
+    pub fn check_tenants(&self, tenants: &Tenants) -> Result<()> {
+        for key in self.all() {
+            if let Some(tenant) = key.tenant.as_ref().filter(|tenant| tenants.get(tenant).is_none()) {
+                anyhow::bail!("API key '{}' has unknown tenant '{}'", key.name, tenant);
+            }
+        }
+        Ok(())
+    }
+}
+
+// The authenticated caller, attached to every /v1 request. No key means
+// authentication is disabled.
+#[derive(Clone)]
+pub struct Caller {
+    key: Option<Arc<ApiKey>>,
+    tenant: Option<Arc<Tenant>>,
+    scope: Scope,
+    background: Option<f64>, // batch work: queued apart from interactive requests, at this fraction of the weight
+}
+
+impl Caller {
+    // Checked before model lookup, so a restricted key gets the same answer for a
+    // model it may not use as for one that does not exist.
+    pub fn authorize_model(&self, model: &str) -> Result<(), AppError> {
+        let Some(key) = self.key.as_ref() else { return Ok(()) };
+        let allowed = match &key.models {
+            None => true,
+            Some(patterns) => patterns.iter().any(|pattern| glob_match(pattern, model)),
+        };
+        if allowed {
+            Ok(())
+        } else {
+            info!("Key '{}' is not allowed to use model '{}'", key.name, model);
+            Err(AppError::ModelAccessDenied(model.to_string()))
+        }
+    }
+
+    pub fn key(&self) -> Option<&ApiKey> {
+        self.key.as_deref()
+    }
+
+    // The model a requested name stands for: the key's alias, else its tenant's.
+    // Aliases are not chained.
+    pub fn resolve_alias(&self, model: &str) -> Option<&String> {
+        self.key.as_ref().and_then(|key| key.aliases.get(model))
+            .or_else(|| self.tenant.as_ref().and_then(|tenant| tenant.resolve_alias(model)))
+    }
+
+    pub fn tenant(&self) -> Option<&Arc<Tenant>> {
+        self.tenant.as_ref()
+    }
+
+    pub fn scope(&self) -> &Scope {
+        &self.scope
+    }
+
+    // Who this request queues as when a backend is saturated, and with what weight.
+    pub fn flow(&self) -> (String, f64) {
+        let (flow, weight) = match (&self.tenant, &self.key) {
+            (Some(tenant), _) => (format!("tenant:{}", tenant.name), tenant.weight),
+            (None, Some(key)) => (format!("key:{}", key.name), key.weight),
+            (None, None) => (String::new(), 1.0),
+        };
+        match self.background {
+            Some(scale) => (format!("batch:{}", flow), weight * scale),
+            None => (flow, weight),
+        }
+    }
+
+    // Rebuilds the caller a batch job was submitted by, from the names stored
+    // with it. None if its key or tenant no longer exists.
+    pub fn restore(state: &AppState, key: Option<&str>, tenant: Option<&str>, scope: Scope) -> Option<Caller> {
+        let key = match (key, &state.api_keys) {
+            (Some(name), Some(store)) => Some(store.named(name)?),
+            (None, None) => None,
+            _ => return None,
+        };
+        let tenant = match tenant {
+            Some(name) => Some(state.tenants.get(name)?.clone()),
+            None => None,
+        };
+        Some(Caller { key, tenant, scope, background: None })
+    }
+
+    pub fn in_background(self, weight: f64) -> Caller {
+        Caller { background: Some(weight), ..self }
+    }
+}
+
+pub async fn authenticate(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let result = if state.api_keys.is_some() && request.headers().contains_key(SIGNATURE) {
+        verify_signed(&state, request).await
+    } else {
+        identify(&state, request.headers()).map(|caller| (caller, request))
+    };
+    match result {
+        Ok((caller, mut request)) => {
+            request.extensions_mut().insert(caller);
+            next.run(request).await
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+fn for_key(state: &AppState, key: Arc<ApiKey>, headers: &HeaderMap) -> Result<Caller, AppError> {
+    Ok(Caller {
+        tenant: key.tenant.as_ref().and_then(|tenant| state.tenants.get(tenant)).cloned(),
+        scope: Scope::of(Some(&key), headers)?,
+        key: Some(key),
+        background: None,
+    })
+}
+
+// The caller behind a request's headers (or gRPC metadata).
+pub fn identify(state: &AppState, headers: &HeaderMap) -> Result<Caller, AppError> {
+    let Some(store) = &state.api_keys else {
+        return Ok(Caller { key: None, tenant: state.tenants.named_in(headers)?, scope: Scope::of(None, headers)?, background: None });
+    };
+    let token = headers.get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .or_else(|| websocket_key(headers));
+    let key = match token {
+        Some(token) => store.find(token)?,
+        None => None,
+    };
+    match key {
+        Some(key) if key.signing.as_ref().is_some_and(|signing| signing.required) => {
+            Err(AppError::Unauthorized("This API key must sign its requests (X-Gateway-Signature).".to_string()))
+        }
+        Some(key) => for_key(state, key, headers),
+        None => {
+            let message = if token.is_some() { "Invalid API key." } else { "Missing API key. Send it as 'Authorization: Bearer <key>'." };
+            Err(AppError::Unauthorized(message.to_string()))
+        }
+    }
+}
+
+// --- Organization and Project ---
+// `OpenAI-Organization` and `OpenAI-Project` say what a request should be billed
+// to, as with OpenAI. A key with `organizations` or `projects` may only send those
+// values, and if only one is listed, requests without the header use it. The
+// values are recorded with each request for GET /admin/usage.
+const ORGANIZATION: &str = "openai-organization";
+const PROJECT: &str = "openai-project";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Scope {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+}
+
+impl Scope {
+    fn of(key: Option<&ApiKey>, headers: &HeaderMap) -> Result<Scope, AppError> {
+        Ok(Scope {
+            organization: scoped(headers, ORGANIZATION, key.and_then(|key| key.organizations.as_ref()), "organization")?,
+            project: scoped(headers, PROJECT, key.and_then(|key| key.projects.as_ref()), "project")?,
+        })
+    }
+}
+
+fn scoped(headers: &HeaderMap, header: &str, allowed: Option<&Vec<String>>, what: &str) -> Result<Option<String>, AppError> {
+    let sent = match headers.get(header) {
+        Some(value) => value.to_str().map(|v| v.trim().to_string())
+            .map_err(|_| AppError::InvalidRequest(format!("The {} header must be ASCII.", header)))?,
+        None => String::new(),
+    };
+    match allowed {
+        Some(allowed) if sent.is_empty() && allowed.len() == 1 => Ok(Some(allowed[0].clone())),
+        Some(allowed) if !sent.is_empty() && !allowed.contains(&sent) => {
+            Err(AppError::Forbidden(format!("This API key may not use {} '{}'.", what, sent)))
+        }
+        _ => Ok(Some(sent).filter(|sent| !sent.is_empty())),
+    }
+}
+
+// --- Request Signing ---
+// For callers that can't hold a bearer token, a key with `signing` can instead
+// name itself and sign each request:
+//   X-Gateway-Key: <key name>
+//   X-Gateway-Timestamp: <unix seconds>
+//   X-Gateway-Signature: v1=<hex HMAC-SHA256 of "<timestamp>.<METHOD>.<path and query>.<body>">
+// The timestamp must be within `window_secs` of the gateway's clock, and each
+// signature is accepted only once. With `required`, the key's bearer token alone
+// is refused.
+const KEY_NAME: &str = "x-gateway-key";
+const TIMESTAMP: &str = "x-gateway-timestamp";
+const SIGNATURE: &str = "x-gateway-signature";
+const MAX_SIGNED_BODY: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SigningConfig {
+    pub secret: String,
+    #[serde(default = "default_window")]
+    pub window_secs: u64,
+    #[serde(default)]
+    pub required: bool,
+}
+
+fn default_window() -> u64 {
+    300
+}
+
+async fn verify_signed(state: &AppState, request: Request) -> Result<(Caller, Request), AppError> {
+    let store = state.api_keys.as_ref().expect("only called with GATEWAY_API_KEYS");
+    let headers = request.headers();
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.trim().to_string());
+    let (Some(name), Some(timestamp), Some(signature)) = (header(KEY_NAME), header(TIMESTAMP), header(SIGNATURE)) else {
+        return Err(AppError::Unauthorized("Signed requests need X-Gateway-Key, X-Gateway-Timestamp, and X-Gateway-Signature.".to_string()));
+    };
+    let invalid = || AppError::Unauthorized("Invalid request signature.".to_string());
+    let key = store.find_named(&name)?.ok_or_else(invalid)?;
+    let signing = key.signing.as_ref().ok_or_else(invalid)?;
+    let at: i64 = timestamp.parse().map_err(|_| AppError::Unauthorized("X-Gateway-Timestamp must be Unix seconds.".to_string()))?;
+    let now = Utc::now().timestamp();
+    if now.abs_diff(at) > signing.window_secs {
+        return Err(AppError::Unauthorized(format!("X-Gateway-Timestamp is more than {}s from the gateway's clock.", signing.window_secs)));
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = axum::body::to_bytes(body, MAX_SIGNED_BODY).await
+        .map_err(|_| AppError::InvalidRequest(format!("Signed request bodies are limited to {} bytes.", MAX_SIGNED_BODY)))?;
+    let target = parts.uri.path_and_query().map_or("/", |target| target.as_str());
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(signing.secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}.{}.{}.", timestamp, parts.method, target).as_bytes());
+    mac.update(&body);
+    let provided = signature.strip_prefix("v1=").and_then(decode_hex).ok_or_else(invalid)?;
+    mac.verify_slice(&provided).map_err(|_| invalid())?;
+
+    // A signature only has to be remembered while its timestamp would still be accepted.
+    let mut seen = store.seen.lock().unwrap();
+    seen.retain(|_, expires_at| *expires_at >= now);
+    if seen.insert(provided, at.saturating_add_unsigned(signing.window_secs)).is_some() {
+        info!("Refused a replayed request signature for key '{}'", key.name);
+        return Err(AppError::Unauthorized("This request signature was already used.".to_string()));
+    }
+    drop(seen);
+    let caller = for_key(state, key, &parts.headers)?;
+    Ok((caller, Request::from_parts(parts, Body::from(body))))
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+// Browsers can't set headers on a WebSocket handshake, so Realtime clients send
+// the key as an `openai-insecure-api-key.<key>` subprotocol instead.
+fn websocket_key(headers: &HeaderMap) -> Option<&str> {
+    headers.get_all(SEC_WEBSOCKET_PROTOCOL).iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .find_map(|protocol| protocol.trim().strip_prefix("openai-insecure-api-key."))
+}
+
+// `*` matches any run of characters (including none); everything else is literal.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || !text[first.len()..].ends_with(last) || text.len() < first.len() + last.len() {
+        return false;
+    }
+
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
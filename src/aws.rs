@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+
+// --- AWS Signature Version 4 ---
+// Shared by the archive sink (S3) and CREDENTIAL_STORE's KMS master key, each
+// signing for the region its config names. Keys fall back to AWS_ACCESS_KEY_ID /
+// AWS_SECRET_ACCESS_KEY, and AWS_SESSION_TOKEN is sent when set.
+pub struct AwsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    region: String,
+}
+
+impl AwsCredentials {
+    // `setting` names the config the keys were missing from, for the error.
+    pub fn resolve(access_key_id: &Option<String>, secret_access_key: &Option<String>, region: &str, setting: &str) -> Result<Self> {
+        let credential = |value: &Option<String>, var: &str| {
+            value.clone().or_else(|| std::env::var(var).ok())
+                .with_context(|| format!("{} needs credentials: set {} or the matching field", setting, var))
+        };
+        Ok(AwsCredentials {
+            access_key_id: credential(access_key_id, "AWS_ACCESS_KEY_ID")?,
+            secret_access_key: credential(secret_access_key, "AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+            region: region.to_string(),
+        })
+    }
+
+    // The headers to send with a request to `url` (whose path must already be
+    // encoded) with no query string: `headers`, the x-amz-* ones, and
+    // authorization. `host` is signed but left out, since reqwest derives the same
+    // one from the URL.
+    pub fn sign(
+        &self,
+        method: &str,
+        url: &Url,
+        service: &str,
+        payload: &[u8],
+        mut headers: Vec<(&'static str, String)>,
+        now: DateTime<Utc>,
+    ) -> Vec<(&'static str, String)> {
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = format!("{:x}", Sha256::digest(payload));
+
+        headers.extend([("host", host), ("x-amz-content-sha256", payload_hash.clone()), ("x-amz-date", amz_date.clone())]);
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        headers.sort_by_key(|(name, _)| *name);
+        let canonical_headers: String = headers.iter().map(|(k, v)| format!("{}:{}\n", k, v.trim())).collect();
+        let signed_headers = headers.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(";");
+        let canonical_request = format!("{}\n{}\n\n{}\n{}\n{}", method, url.path(), canonical_headers, signed_headers, payload_hash);
+
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{:x}", amz_date, scope, Sha256::digest(canonical_request.as_bytes())
+        );
+        let mut signing_key = hmac(format!("AWS4{}", self.secret_access_key).as_bytes(), date.as_bytes());
+        for part in [self.region.as_str(), service, "aws4_request"] {
+            signing_key = hmac(&signing_key, part.as_bytes());
+        }
+        let signature = hmac(&signing_key, string_to_sign.as_bytes())
+            .iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id, scope, signed_headers, signature
+        );
+
+        headers.retain(|(name, _)| *name != "host");
+        headers.push(("authorization", authorization));
+        headers
+    }
+}
+
+pub fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+// SigV4 encoding: everything but unreserved characters and `/`.
+pub fn uri_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
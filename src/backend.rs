@@ -0,0 +1,340 @@
+use anyhow::{Context, Result};
+use reqwest::{header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION}, Client, RequestBuilder};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+use crate::{
+    client::{self, ClientSettings, IpFamily, ProxyConfig, Timeouts},
+    config::{self, BackendConfig},
+    drain::Drains,
+    fleet::{Fleet, FleetEvent},
+    mock,
+    queue::{FairQueue, StreamLimit},
+    routing::{self, Balance, LatencyTracker},
+    secrets::{Secret, Secrets},
+    tokenizer::Tokenizer,
+    uds,
+};
+
+// How long a replica whose stream stalled is avoided.
+const SUSPECT_SECS: u64 = 60;
+
+// --- Runtime Backend ---
+// The parsed config plus everything built from it at startup.
+pub struct Backend {
+    pub config: BackendConfig,
+    pub client: Client,
+    pub timeouts: Timeouts, // the backend's own, else UPSTREAM_TIMEOUTS
+    pub tokenizer: Tokenizer,
+    pub replicas: Arc<ReplicaSet>, // `url` first, then `replicas`, then any discovered ones
+    pub created: i64, // when the backend was loaded, as reported by /v1/models
+    pub queue: Option<Arc<FairQueue>>, // with `max_concurrent_requests`
+    pub streams: Option<StreamLimit>, // with `max_concurrent_streams`
+    pub api_key: Option<Secret>, // with `api_key`
+    pub headers: Vec<(HeaderName, Secret)>, // with `headers`
+    pub sockets: HashMap<String, Client>, // `unix:` replica -> a client connecting to its socket
+    pub proxy: Option<ProxyConfig>, // the backend's own, else UPSTREAM_PROXY; for realtime sessions
+    pub fleet: Option<Arc<Fleet>>, // told about replicas marked suspect
+    pub drains: Arc<Drains>, // replicas taking no new requests, shared by every backend
+    suspects: Mutex<HashMap<String, Instant>>, // replica -> when it may be picked again
+    next_replica: AtomicUsize,
+}
+
+impl Backend {
+    pub fn new(model_name: &str, config: BackendConfig, shared_client: &Client, global: &ClientSettings) -> Result<Self> {
+        let tokenizer = Tokenizer::load(config.tokenizer.as_ref())
+            .with_context(|| format!("Invalid tokenizer configuration for model '{}'", model_name))?;
+        let timeouts = config.timeouts.or(global.timeouts);
+        let settings = ClientSettings {
+            tls: config.tls.clone().or_else(|| global.tls.clone()),
+            pool: config.pool.clone().or_else(|| global.pool.clone()),
+            proxy: config.proxy.clone().or_else(|| global.proxy.clone()),
+            timeouts,
+            ip_family: config.ip_family,
+        };
+        let client = if config.tls.is_some() || config.pool.is_some() || config.timeouts.is_some() || config.proxy.is_some()
+            || config.ip_family != IpFamily::Auto
+        {
+            client::build_client(&settings)
+                .with_context(|| format!("Invalid client configuration for model '{}'", model_name))?
+        } else {
+            shared_client.clone()
+        };
+        let replicas = config.static_replicas();
+        let mut sockets = HashMap::new();
+        for replica in &replicas {
+            if let Some(path) = uds::socket_path(replica) {
+                let client = client::socket_client(&path, &settings).with_context(|| format!("Invalid replica '{}' for model '{}'", replica, model_name))?;
+                sockets.insert(replica.clone(), client);
+            }
+        }
+        if replicas.is_empty() && config.discovery.is_none() {
+            anyhow::bail!("Model '{}' needs a `url`, `replicas`, or `discovery`", model_name);
+        }
+        if !config.path.starts_with('/') || !config.path.contains("{endpoint}") {
+            anyhow::bail!("Model '{}' needs a `path` starting with / and containing {{endpoint}}, got '{}'", model_name, config.path);
+        }
+        if config.path.replace("{endpoint}", "").replace("{model}", "").contains(['{', '}']) {
+            anyhow::bail!("Model '{}' has an unknown placeholder in `path` '{}'; use {{endpoint}} and {{model}}", model_name, config.path);
+        }
+        if config.pacing.is_some_and(|pacing| pacing.tokens_per_second <= 0.0 || pacing.burst < 0.0) {
+            anyhow::bail!("Model '{}' needs a positive `pacing.tokens_per_second`", model_name);
+        }
+        for replica in replicas.iter().filter(|url| mock::is_mock(url)) {
+            mock::MockConfig::parse(replica)?;
+        }
+        Ok(Backend {
+            queue: config.max_concurrent_requests.map(FairQueue::new),
+            streams: config.max_concurrent_streams.map(StreamLimit::new),
+            config,
+            client,
+            timeouts: timeouts.unwrap_or_default(),
+            tokenizer,
+            replicas: Arc::new(ReplicaSet::new(replicas)),
+            created: chrono::Utc::now().timestamp(),
+            api_key: None,
+            headers: Vec::new(),
+            sockets,
+            proxy: settings.proxy,
+            fleet: None,
+            drains: Arc::default(),
+            suspects: Mutex::new(HashMap::new()),
+            next_replica: AtomicUsize::new(0),
+        })
+    }
+
+    // Adds the backend's `api_key` and `headers`, replacing any forwarded headers
+    // of the same names.
+    pub fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        let mut outbound = HeaderMap::new();
+        if let Some(key) = self.api_key.as_ref().map(Secret::get).filter(|key| !key.is_empty()) {
+            match HeaderValue::from_str(&format!("Bearer {}", key)) {
+                Ok(bearer) => {
+                    outbound.insert(AUTHORIZATION, bearer);
+                }
+                Err(_) => warn!("The backend's api_key is not a valid header value; sending the request without it"),
+            }
+        }
+        for (name, value) in &self.headers {
+            match HeaderValue::from_str(&value.get()) {
+                Ok(mut value) => {
+                    value.set_sensitive(true);
+                    outbound.insert(name.clone(), value);
+                }
+                Err(_) => warn!("The backend's {} header is not a valid header value; sending the request without it", name),
+            }
+        }
+        match outbound.is_empty() {
+            true => request,
+            false => request.headers(outbound),
+        }
+    }
+
+    // How busy the backend is, from 0 up (past 1 when requests queue). None
+    // without a concurrency limit to measure against.
+    pub fn utilization(&self) -> Option<f64> {
+        match (&self.queue, &self.streams) {
+            (Some(queue), _) => Some(queue.utilization()),
+            (None, Some(streams)) => Some(streams.utilization()),
+            (None, None) => None,
+        }
+    }
+
+    // A replica URL, as chosen by the backend's `balance` policy. None while
+    // discovery has found no replicas.
+    pub fn pick_replica(&self, latency: &LatencyTracker) -> Option<String> {
+        let replicas: Vec<String> = self.replicas.get().iter().filter(|url| !self.is_drained(url)).cloned().collect();
+        if replicas.is_empty() {
+            return None;
+        }
+        let request_number = self.next_replica.fetch_add(1, Ordering::Relaxed);
+        let fastest = match self.config.balance {
+            Balance::RoundRobin => None,
+            Balance::Latency => routing::pick_fastest(self, &replicas, latency, request_number),
+        };
+        let start = fastest.unwrap_or(request_number % replicas.len());
+        // Skip replicas whose streams recently stalled, unless all of them did.
+        let suspects = self.suspects.lock().unwrap();
+        let usable = (0..replicas.len()).map(|offset| (start + offset) % replicas.len())
+            .find(|&i| suspects.get(&replicas[i]).is_none_or(|until| *until <= Instant::now()))
+            .unwrap_or(start);
+        Some(replicas[usable].clone())
+    }
+
+    // Keeps new requests away from a replica whose stream stalled or that refused
+    // the connection, for a while. Other gateway instances are told too.
+    pub fn mark_suspect(&self, url: &str) {
+        warn!("Marking backend {} suspect for {}s", url, SUSPECT_SECS);
+        self.suspect_for(url, SUSPECT_SECS);
+        if let Some(fleet) = &self.fleet {
+            fleet.publish(FleetEvent::Suspect { url: url.to_string(), secs: SUSPECT_SECS });
+        }
+    }
+
+    // Marks the replica suspect here only, as when another instance reports it.
+    pub fn suspect_for(&self, url: &str, secs: u64) {
+        let mut suspects = self.suspects.lock().unwrap();
+        let now = Instant::now();
+        suspects.retain(|_, until| *until > now);
+        suspects.insert(url.to_string(), now + Duration::from_secs(secs));
+    }
+
+    // The model name to send the backend for a request to `model`.
+    pub fn served_model<'a>(&'a self, model: &'a str) -> &'a str {
+        self.config.upstream_model.as_deref().unwrap_or(model)
+    }
+
+    // The URL of an OpenAI endpoint such as `chat/completions` on a replica, from
+    // the backend's `path` template.
+    pub fn endpoint_url(&self, replica: &str, endpoint: &str, model: &str) -> String {
+        let path = self.config.path.replace("{endpoint}", endpoint).replace("{model}", model);
+        format!("{}{}", self.base_url(replica).trim_end_matches('/'), path)
+    }
+
+    // Where to send `replica`'s requests. A Unix socket's client ignores the host,
+    // so its requests go to `localhost`.
+    pub fn base_url<'a>(&'a self, replica: &'a str) -> &'a str {
+        match self.sockets.contains_key(replica) {
+            true => "http://localhost",
+            false => replica,
+        }
+    }
+
+    // The client that reaches `replica`.
+    pub fn client_for(&self, replica: &str) -> &Client {
+        self.sockets.get(replica).unwrap_or(&self.client)
+    }
+
+    pub fn is_drained(&self, url: &str) -> bool {
+        self.drains.contains(url)
+    }
+
+    // The replica after `url` that isn't drained, wrapping around; counting from
+    // the first one if `url` is gone.
+    pub fn replica_after(&self, url: &str) -> Option<String> {
+        let replicas = self.replicas.get();
+        let start = replicas.iter().position(|replica| replica == url).map_or(0, |i| i + 1);
+        (0..replicas.len()).map(|offset| &replicas[(start + offset) % replicas.len()])
+            .find(|replica| !self.is_drained(replica))
+            .cloned()
+    }
+}
+
+// --- Replica Set ---
+// Readers take a snapshot, so a request sees one consistent list even while
+// discovery swaps in a new one.
+pub struct ReplicaSet(RwLock<Arc<Vec<String>>>);
+
+impl ReplicaSet {
+    pub fn new(urls: Vec<String>) -> Self {
+        ReplicaSet(RwLock::new(Arc::new(urls)))
+    }
+
+    pub fn get(&self) -> Arc<Vec<String>> {
+        self.0.read().unwrap().clone()
+    }
+
+    // Returns whether anything changed.
+    pub fn set(&self, urls: Vec<String>) -> bool {
+        let mut current = self.0.write().unwrap();
+        if **current == urls {
+            return false;
+        }
+        *current = Arc::new(urls);
+        true
+    }
+}
+
+// --- Backend Table ---
+// Model name -> backend. Swapped as a whole when the backend registry changes, so
+// like replicas, a request works from one snapshot.
+pub type Backends = HashMap<String, Arc<Backend>>;
+
+pub struct BackendTable(RwLock<Arc<Backends>>);
+
+impl BackendTable {
+    pub fn new(backends: Backends) -> Self {
+        BackendTable(RwLock::new(Arc::new(backends)))
+    }
+
+    pub fn snapshot(&self) -> Arc<Backends> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn get(&self, model: &str) -> Option<Arc<Backend>> {
+        self.0.read().unwrap().get(model).cloned()
+    }
+
+    pub fn contains(&self, model: &str) -> bool {
+        self.0.read().unwrap().contains_key(model)
+    }
+
+    pub fn replace(&self, backends: Backends) {
+        *self.0.write().unwrap() = Arc::new(backends);
+    }
+}
+
+// Builds backends from their configs, at startup and for registry updates.
+#[derive(Clone)]
+pub struct BackendLoader {
+    pub client: Client,
+    pub settings: ClientSettings,
+    pub mock_mode: bool, // `--mock`: keep every model's settings but serve it from mock://lorem
+    pub redaction: bool, // whether PII_REDACTION is configured
+    pub secrets: Arc<Secrets>, // resolves `api_key` and `headers` references
+    pub fleet: Option<Arc<Fleet>>, // FLEET_REDIS_URL
+    pub drains: Arc<Drains>, // replicas drained through /admin
+}
+
+impl BackendLoader {
+    pub fn load(&self, model_name: &str, mut config: BackendConfig) -> Result<Backend> {
+        if self.mock_mode {
+            config.url = "mock://lorem".to_string();
+            config.replicas.clear();
+            config.discovery = None;
+        }
+        if config.redact_pii && !self.redaction {
+            anyhow::bail!("Model '{}' sets redact_pii but PII_REDACTION is not configured", model_name);
+        }
+        info!("  - Model: '{}' -> URL: '{}'", model_name, config.url);
+        for replica in &config.replicas {
+            info!("      replica: '{}'", replica);
+        }
+        let api_key = config.api_key.as_deref()
+            .map(|reference| self.secrets.reference(reference))
+            .transpose()
+            .with_context(|| format!("Invalid api_key for model '{}'", model_name))?;
+        let mut backend = Backend::new(model_name, config, &self.client, &self.settings)?;
+        backend.api_key = api_key;
+        let mut headers: Vec<(HeaderName, Secret)> = Vec::new();
+        for (name, reference) in &backend.config.headers {
+            let header = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("Invalid header name '{}' in headers for model '{}'", name, model_name))?;
+            let value = self.secrets.reference(reference)
+                .with_context(|| format!("Invalid {} header for model '{}'", name, model_name))?;
+            headers.push((header, value));
+        }
+        backend.headers = headers;
+        backend.fleet = self.fleet.clone();
+        backend.drains = self.drains.clone();
+        Ok(backend)
+    }
+
+    // Loads one of the per-endpoint tables (AUDIO_BACKENDS, ...).
+    pub fn load_table(&self, var: &str) -> Result<Backends> {
+        let configs = config::env_backends(var)?;
+        if !configs.is_empty() {
+            info!("Configured {}:", var);
+        }
+        configs.into_iter()
+            .map(|(model_name, config)| Ok((model_name.clone(), Arc::new(self.load(&model_name, config)?))))
+            .collect()
+    }
+}
@@ -0,0 +1,736 @@
+use anyhow::{Context, Result};
+use axum::{
+    body::{to_bytes, Body},
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
+    http::{header::CONTENT_TYPE, HeaderMap},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use bytes::BytesMut;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::Semaphore,
+    task::JoinSet,
+};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::{auth::{Caller, Scope}, metadata, stream, usage::StreamOptions, AppError, AppState, ChatRequest, RequestMeta};
+
+const ENDPOINT: &str = "/v1/chat/completions";
+const COMPLETION_WINDOW_SECS: i64 = 24 * 3600; // "24h", the only window OpenAI offers
+const MAX_LINES: usize = 50_000;
+const MAX_ATTEMPTS: u32 = 4; // per line, for retryable errors
+const READ_CHUNK: usize = 64 * 1024;
+
+// --- Batch API ---
+// With BATCH_API set, /v1/files and /v1/batches emulate OpenAI's Batch API:
+// upload a JSONL file of chat requests, create a batch from it, and fetch the
+// results file when it completes. Batches run in the background through the
+// same pipeline as interactive requests, at most `concurrency` lines at a time
+// across all batches, and queue at a saturated backend as a separate flow with
+// `weight` times their key's weight, so they yield to interactive traffic.
+// Files and batch state live under `dir`; unfinished batches pick up where they
+// left off after a restart.
+#[derive(Debug, Deserialize)]
+pub struct BatchConfig {
+    pub dir: PathBuf,
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    #[serde(default = "default_max_file_bytes")]
+    pub max_file_bytes: usize,
+}
+
+fn default_concurrency() -> usize {
+    4
+}
+
+fn default_weight() -> f64 {
+    0.1
+}
+
+fn default_max_file_bytes() -> usize {
+    200 * 1024 * 1024
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FileObject {
+    pub id: String,
+    pub object: String,
+    pub bytes: u64,
+    pub created_at: i64,
+    pub filename: String,
+    pub purpose: String,
+}
+
+// Metadata as kept on disk, next to the file's content.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredFile {
+    #[serde(flatten)]
+    file: FileObject,
+    owner: Option<String>, // API key name
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct RequestCounts {
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Batch {
+    pub id: String,
+    pub object: String,
+    pub endpoint: String,
+    pub errors: Option<Value>,
+    pub input_file_id: String,
+    pub completion_window: String,
+    pub status: String,
+    pub output_file_id: Option<String>,
+    pub error_file_id: Option<String>,
+    pub created_at: i64,
+    pub in_progress_at: Option<i64>,
+    pub expires_at: i64,
+    pub finalizing_at: Option<i64>,
+    pub completed_at: Option<i64>,
+    pub failed_at: Option<i64>,
+    pub expired_at: Option<i64>,
+    pub cancelling_at: Option<i64>,
+    pub cancelled_at: Option<i64>,
+    pub request_counts: RequestCounts,
+    pub metadata: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredBatch {
+    #[serde(flatten)]
+    batch: Batch,
+    owner: Option<String>,
+    tenant: Option<String>,
+    #[serde(default)]
+    scope: Scope,
+}
+
+impl StoredBatch {
+    fn finished(&self) -> bool {
+        matches!(self.batch.status.as_str(), "completed" | "failed" | "expired" | "cancelled")
+    }
+}
+
+pub struct BatchStore {
+    config: BatchConfig,
+    files: Mutex<HashMap<String, StoredFile>>,
+    batches: Mutex<HashMap<String, StoredBatch>>,
+    slots: Arc<Semaphore>, // lines running, across all batches
+}
+
+impl BatchStore {
+    pub fn open(config: BatchConfig) -> Result<Arc<Self>> {
+        let files_dir = config.dir.join("files");
+        let batches_dir = config.dir.join("batches");
+        for dir in [&files_dir, &batches_dir] {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create batch directory '{}'", dir.display()))?;
+        }
+        let files: HashMap<String, StoredFile> = read_records(&files_dir)?
+            .into_iter().map(|file: StoredFile| (file.file.id.clone(), file)).collect();
+        let batches: HashMap<String, StoredBatch> = read_records(&batches_dir)?
+            .into_iter().map(|batch: StoredBatch| (batch.batch.id.clone(), batch)).collect();
+        info!(
+            "Batch API on {} ({} files, {} batches, {} concurrent requests)",
+            config.dir.display(), files.len(), batches.len(), config.concurrency
+        );
+        Ok(Arc::new(BatchStore {
+            slots: Arc::new(Semaphore::new(config.concurrency.max(1))),
+            config,
+            files: Mutex::new(files),
+            batches: Mutex::new(batches),
+        }))
+    }
+
+    fn content_path(&self, file_id: &str) -> PathBuf {
+        self.config.dir.join("files").join(format!("{}.data", file_id))
+    }
+
+    fn save_file(&self, file: StoredFile) -> Result<FileObject> {
+        let path = self.config.dir.join("files").join(format!("{}.json", file.file.id));
+        std::fs::write(&path, serde_json::to_vec(&file)?).with_context(|| format!("Failed to write '{}'", path.display()))?;
+        let object = file.file.clone();
+        self.files.lock().unwrap().insert(file.file.id.clone(), file);
+        Ok(object)
+    }
+
+    // Applies `change` to the batch and writes it out; returns the updated batch.
+    fn update(&self, id: &str, change: impl FnOnce(&mut StoredBatch)) -> Option<StoredBatch> {
+        let mut batches = self.batches.lock().unwrap();
+        let batch = batches.get_mut(id)?;
+        change(batch);
+        let path = self.config.dir.join("batches").join(format!("{}.json", id));
+        if let Err(e) = serde_json::to_vec(&*batch).map_err(anyhow::Error::from).and_then(|bytes| Ok(std::fs::write(&path, bytes)?)) {
+            warn!("Failed to save batch {}: {}", id, e);
+        }
+        Some(batch.clone())
+    }
+
+    fn status(&self, id: &str) -> Option<String> {
+        self.batches.lock().unwrap().get(id).map(|batch| batch.batch.status.clone())
+    }
+
+    // Owners only see their own files and batches; without API keys, everyone sees everything.
+    fn file_for(&self, caller: &Caller, id: &str) -> Result<StoredFile, AppError> {
+        self.files.lock().unwrap().get(id)
+            .filter(|file| owned_by(caller, &file.owner))
+            .cloned()
+            .ok_or_else(|| AppError::NotFound(format!("No such file: '{}'.", id)))
+    }
+
+    fn batch_for(&self, caller: &Caller, id: &str) -> Result<StoredBatch, AppError> {
+        self.batches.lock().unwrap().get(id)
+            .filter(|batch| owned_by(caller, &batch.owner))
+            .cloned()
+            .ok_or_else(|| AppError::NotFound(format!("No such batch: '{}'.", id)))
+    }
+}
+
+pub fn owned_by(caller: &Caller, owner: &Option<String>) -> bool {
+    caller.key().is_none_or(|key| owner.as_deref() == Some(key.name.as_str()))
+}
+
+fn read_records<T: serde::de::DeserializeOwned>(dir: &std::path::Path) -> Result<Vec<T>> {
+    let mut records = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read '{}'", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            let bytes = std::fs::read(&path)?;
+            records.push(serde_json::from_slice(&bytes).with_context(|| format!("Invalid record '{}'", path.display()))?);
+        }
+    }
+    Ok(records)
+}
+
+fn store(state: &AppState) -> &Arc<BatchStore> {
+    state.batches.as_ref().expect("batch routes are only mounted with BATCH_API")
+}
+
+fn new_id(prefix: &str) -> String {
+    format!("{}{}", prefix, uuid::Uuid::new_v4().simple())
+}
+
+pub fn router(max_file_bytes: usize) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/v1/files", post(upload_file).get(list_files).layer(DefaultBodyLimit::max(max_file_bytes)))
+        .route("/v1/files/:id", get(get_file).delete(delete_file))
+        .route("/v1/files/:id/content", get(file_content))
+        .route("/v1/batches", post(create_batch).get(list_batches))
+        .route("/v1/batches/:id", get(get_batch))
+        .route("/v1/batches/:id/cancel", post(cancel_batch))
+}
+
+// Restarts batches that were running when the gateway stopped.
+pub fn resume(state: &Arc<AppState>) {
+    let Some(store) = &state.batches else { return };
+    let unfinished: Vec<String> = store.batches.lock().unwrap().values()
+        .filter(|batch| !batch.finished())
+        .map(|batch| batch.batch.id.clone())
+        .collect();
+    for id in unfinished {
+        info!("Resuming batch {}", id);
+        tokio::spawn(run(state.clone(), store.clone(), id));
+    }
+}
+
+// --- Files ---
+#[utoipa::path(
+    post, path = "/v1/files", tag = "Batch",
+    request_body(content_type = "multipart/form-data", description = "`purpose` and a JSONL `file`."),
+    responses((status = 200, description = "The stored file.", body = FileObject)),
+)]
+async fn upload_file(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    mut form: Multipart,
+) -> Result<Json<FileObject>, AppError> {
+    let store = store(&state);
+    let id = new_id("file-");
+    let path = store.content_path(&id);
+    let mut purpose = None;
+    let mut upload = None;
+    let bad_form = |e: axum::extract::multipart::MultipartError| AppError::InvalidRequest(format!("Invalid upload: {}", e));
+    while let Some(mut field) = form.next_field().await.map_err(bad_form)? {
+        match field.name() {
+            Some("purpose") => purpose = Some(field.text().await.map_err(bad_form)?),
+            Some("file") => {
+                let filename = field.file_name().unwrap_or("upload.jsonl").to_string();
+                // Written as it arrives, so large files never sit in memory.
+                let mut out = tokio::fs::File::create(&path).await.map_err(internal)?;
+                let mut bytes = 0u64;
+                while let Some(chunk) = field.chunk().await.map_err(bad_form)? {
+                    bytes += chunk.len() as u64;
+                    out.write_all(&chunk).await.map_err(internal)?;
+                }
+                out.flush().await.map_err(internal)?;
+                upload = Some((filename, bytes));
+            }
+            _ => {}
+        }
+    }
+    let (Some(purpose), Some((filename, bytes))) = (purpose, upload) else {
+        let _ = tokio::fs::remove_file(&path).await;
+        return Err(AppError::InvalidRequest("The upload needs a 'purpose' and a 'file' field.".to_string()));
+    };
+    info!("Stored file {} ('{}', {} bytes)", id, filename, bytes);
+    let file = FileObject { id, object: "file".to_string(), bytes, created_at: Utc::now().timestamp(), filename, purpose };
+    let owner = caller.key().map(|key| key.name.clone());
+    Ok(Json(store.save_file(StoredFile { file, owner }).map_err(internal)?))
+}
+
+#[utoipa::path(get, path = "/v1/files", tag = "Batch", responses((status = 200, description = "The caller's files, newest first.", body = Object)))]
+async fn list_files(State(state): State<Arc<AppState>>, Extension(caller): Extension<Caller>) -> Json<Value> {
+    let mut data: Vec<FileObject> = store(&state).files.lock().unwrap().values()
+        .filter(|file| owned_by(&caller, &file.owner))
+        .map(|file| file.file.clone())
+        .collect();
+    data.sort_by_key(|file| std::cmp::Reverse(file.created_at));
+    Json(json!({ "object": "list", "data": data }))
+}
+
+#[utoipa::path(get, path = "/v1/files/{id}", tag = "Batch", params(("id" = String, Path)), responses((status = 200, description = "The file.", body = FileObject)))]
+async fn get_file(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<String>,
+) -> Result<Json<FileObject>, AppError> {
+    Ok(Json(store(&state).file_for(&caller, &id)?.file))
+}
+
+#[utoipa::path(delete, path = "/v1/files/{id}", tag = "Batch", params(("id" = String, Path)), responses((status = 200, description = "The file was deleted.", body = Object)))]
+async fn delete_file(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let store = store(&state);
+    store.file_for(&caller, &id)?;
+    store.files.lock().unwrap().remove(&id);
+    let _ = std::fs::remove_file(store.config.dir.join("files").join(format!("{}.json", id)));
+    let _ = std::fs::remove_file(store.content_path(&id));
+    Ok(Json(json!({ "id": id, "object": "file", "deleted": true })))
+}
+
+#[utoipa::path(
+    get, path = "/v1/files/{id}/content", tag = "Batch",
+    params(("id" = String, Path)),
+    responses((status = 200, description = "The file's content.", content_type = "application/octet-stream", body = Vec<u8>)),
+)]
+async fn file_content(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let store = store(&state);
+    store.file_for(&caller, &id)?;
+    let file = tokio::fs::File::open(store.content_path(&id)).await.map_err(internal)?;
+    let body = futures::stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut buf = BytesMut::with_capacity(READ_CHUNK);
+        match file.read_buf(&mut buf).await {
+            Ok(0) => None,
+            Ok(_) => Some((Ok(buf.freeze()), Some(file))),
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+    Ok(([(CONTENT_TYPE, "application/octet-stream")], Body::from_stream(body)).into_response())
+}
+
+fn internal(e: impl std::fmt::Display) -> AppError {
+    AppError::Internal(e.to_string())
+}
+
+// --- Batches ---
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateBatch {
+    input_file_id: String,
+    endpoint: String,
+    completion_window: String,
+    #[serde(default)]
+    metadata: Option<Value>,
+}
+
+#[utoipa::path(
+    post, path = "/v1/batches", tag = "Batch",
+    request_body = CreateBatch,
+    responses((status = 200, description = "The batch, validating.", body = Batch)),
+)]
+async fn create_batch(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    Json(request): Json<CreateBatch>,
+) -> Result<Json<Batch>, AppError> {
+    let store = store(&state);
+    if request.endpoint != ENDPOINT {
+        return Err(AppError::InvalidRequest(format!("Only '{}' is supported as a batch endpoint.", ENDPOINT)));
+    }
+    if request.completion_window != "24h" {
+        return Err(AppError::InvalidRequest("completion_window must be '24h'.".to_string()));
+    }
+    store.file_for(&caller, &request.input_file_id)?;
+
+    let now = Utc::now().timestamp();
+    let batch = Batch {
+        id: new_id("batch_"),
+        object: "batch".to_string(),
+        endpoint: request.endpoint,
+        errors: None,
+        input_file_id: request.input_file_id,
+        completion_window: request.completion_window,
+        status: "validating".to_string(),
+        output_file_id: None,
+        error_file_id: None,
+        created_at: now,
+        in_progress_at: None,
+        expires_at: now + COMPLETION_WINDOW_SECS,
+        finalizing_at: None,
+        completed_at: None,
+        failed_at: None,
+        expired_at: None,
+        cancelling_at: None,
+        cancelled_at: None,
+        request_counts: RequestCounts::default(),
+        metadata: request.metadata,
+    };
+    let id = batch.id.clone();
+    let stored = StoredBatch {
+        batch,
+        owner: caller.key().map(|key| key.name.clone()),
+        tenant: caller.tenant().map(|tenant| tenant.name.clone()),
+        scope: caller.scope().clone(),
+    };
+    store.batches.lock().unwrap().insert(id.clone(), stored);
+    let created = store.update(&id, |_| {}).expect("just inserted");
+    info!("Created batch {} from file {}", id, created.batch.input_file_id);
+    tokio::spawn(run(state.clone(), store.clone(), id));
+    Ok(Json(created.batch))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    #[serde(default)]
+    after: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_limit() -> usize {
+    20
+}
+
+#[utoipa::path(
+    get, path = "/v1/batches", tag = "Batch",
+    params(
+        ("after" = Option<String>, Query, description = "A batch id; lists the batches created before it."),
+        ("limit" = Option<usize>, Query, description = "Defaults to 20."),
+    ),
+    responses((status = 200, description = "The caller's batches, newest first.", body = Object)),
+)]
+async fn list_batches(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    Query(query): Query<ListQuery>,
+) -> Json<Value> {
+    let mut batches: Vec<Batch> = store(&state).batches.lock().unwrap().values()
+        .filter(|batch| owned_by(&caller, &batch.owner))
+        .map(|batch| batch.batch.clone())
+        .collect();
+    batches.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| b.id.cmp(&a.id)));
+    if let Some(after) = &query.after {
+        let skip = batches.iter().position(|batch| batch.id == *after).map_or(0, |i| i + 1);
+        batches.drain(..skip);
+    }
+    let has_more = batches.len() > query.limit;
+    batches.truncate(query.limit);
+    Json(json!({
+        "object": "list",
+        "first_id": batches.first().map(|b| b.id.clone()),
+        "last_id": batches.last().map(|b| b.id.clone()),
+        "has_more": has_more,
+        "data": batches,
+    }))
+}
+
+#[utoipa::path(get, path = "/v1/batches/{id}", tag = "Batch", params(("id" = String, Path)), responses((status = 200, description = "The batch.", body = Batch)))]
+async fn get_batch(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<String>,
+) -> Result<Json<Batch>, AppError> {
+    Ok(Json(store(&state).batch_for(&caller, &id)?.batch))
+}
+
+// Lines already running finish; the rest are skipped.
+#[utoipa::path(post, path = "/v1/batches/{id}/cancel", tag = "Batch", params(("id" = String, Path)), responses((status = 200, description = "The batch, cancelling.", body = Batch)))]
+async fn cancel_batch(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<String>,
+) -> Result<Json<Batch>, AppError> {
+    let store = store(&state);
+    let batch = store.batch_for(&caller, &id)?;
+    if batch.finished() {
+        return Ok(Json(batch.batch));
+    }
+    info!("Cancelling batch {}", id);
+    let batch = store.update(&id, |batch| {
+        batch.batch.status = "cancelling".to_string();
+        batch.batch.cancelling_at = Some(Utc::now().timestamp());
+    }).expect("batch exists");
+    Ok(Json(batch.batch))
+}
+
+// --- Execution ---
+struct Line {
+    custom_id: String,
+    body: ChatRequest,
+}
+
+// Every problem with the input file, as OpenAI reports them in `errors`.
+fn parse_input(raw: &str) -> Result<Vec<Line>, Vec<Value>> {
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+    let mut seen = HashSet::new();
+    for (number, text) in raw.lines().enumerate().filter(|(_, text)| !text.trim().is_empty()) {
+        let mut error = |code: &str, message: String| errors.push(json!({ "code": code, "message": message, "line": number + 1 }));
+        let entry: Value = match serde_json::from_str(text) {
+            Ok(entry) => entry,
+            Err(e) => {
+                error("invalid_json_line", format!("Line is not valid JSON: {}", e));
+                continue;
+            }
+        };
+        let Some(custom_id) = entry["custom_id"].as_str() else {
+            error("missing_custom_id", "Each line needs a string 'custom_id'.".to_string());
+            continue;
+        };
+        if !seen.insert(custom_id.to_string()) {
+            error("duplicate_custom_id", format!("custom_id '{}' appears more than once.", custom_id));
+            continue;
+        }
+        if entry["url"].as_str() != Some(ENDPOINT) || entry["method"].as_str().is_some_and(|m| m != "POST") {
+            error("invalid_url", format!("Each line must POST to '{}'.", ENDPOINT));
+            continue;
+        }
+        match serde_json::from_value::<ChatRequest>(entry["body"].clone()) {
+            Ok(body) => lines.push(Line { custom_id: custom_id.to_string(), body }),
+            Err(e) => error("invalid_body", format!("Invalid chat request: {}", e)),
+        }
+    }
+    if lines.len() > MAX_LINES {
+        errors.push(json!({ "code": "too_many_requests", "message": format!("A batch may hold at most {} requests.", MAX_LINES) }));
+    }
+    if lines.is_empty() && errors.is_empty() {
+        errors.push(json!({ "code": "empty_file", "message": "The input file has no requests." }));
+    }
+    if errors.is_empty() { Ok(lines) } else { Err(errors) }
+}
+
+async fn run(state: Arc<AppState>, store: Arc<BatchStore>, id: String) {
+    if let Err(e) = execute(&state, &store, &id).await {
+        warn!("Batch {} failed: {:#}", id, e);
+        store.update(&id, |batch| {
+            batch.batch.status = "failed".to_string();
+            batch.batch.failed_at = Some(Utc::now().timestamp());
+            batch.batch.errors = Some(json!({ "object": "list", "data": [{ "code": "batch_failed", "message": format!("{:#}", e) }] }));
+        });
+    }
+}
+
+async fn execute(state: &Arc<AppState>, store: &Arc<BatchStore>, id: &str) -> Result<()> {
+    let batch = store.update(id, |_| {}).context("Batch disappeared")?;
+    let raw = tokio::fs::read_to_string(store.content_path(&batch.batch.input_file_id)).await
+        .context("Failed to read the input file")?;
+    let lines = match parse_input(&raw) {
+        Ok(lines) => lines,
+        Err(errors) => {
+            info!("Batch {} failed validation with {} error(s)", id, errors.len());
+            store.update(id, |batch| {
+                batch.batch.status = "failed".to_string();
+                batch.batch.failed_at = Some(Utc::now().timestamp());
+                batch.batch.errors = Some(json!({ "object": "list", "data": errors }));
+            });
+            return Ok(());
+        }
+    };
+    let caller = Caller::restore(state, batch.owner.as_deref(), batch.tenant.as_deref(), batch.scope.clone())
+        .context("The API key or tenant that created this batch no longer exists")?
+        .in_background(store.config.weight);
+
+    // Results are appended as lines finish; after a restart, lines with a
+    // result already are not run again.
+    let output_path = store.config.dir.join("batches").join(format!("{}.output.jsonl", id));
+    let error_path = store.config.dir.join("batches").join(format!("{}.errors.jsonl", id));
+    let done_ok = finished_ids(&output_path).await;
+    let done_err = finished_ids(&error_path).await;
+    store.update(id, |batch| {
+        if batch.batch.status == "validating" {
+            batch.batch.status = "in_progress".to_string();
+            batch.batch.in_progress_at = Some(Utc::now().timestamp());
+        }
+        batch.batch.request_counts = RequestCounts { total: lines.len(), completed: done_ok.len(), failed: done_err.len() };
+    });
+    let output = Arc::new(tokio::sync::Mutex::new(append(&output_path).await?));
+    let errors = Arc::new(tokio::sync::Mutex::new(append(&error_path).await?));
+
+    let mut expired = false;
+    let mut running = JoinSet::new();
+    for line in lines.into_iter().filter(|line| !done_ok.contains(&line.custom_id) && !done_err.contains(&line.custom_id)) {
+        let slot = store.slots.clone().acquire_owned().await?;
+        if store.status(id).as_deref() != Some("in_progress") {
+            break;
+        }
+        if Utc::now().timestamp() >= batch.batch.expires_at {
+            expired = true;
+            break;
+        }
+        let (state, store, caller, output, errors, id) = (state.clone(), store.clone(), caller.clone(), output.clone(), errors.clone(), id.to_string());
+        running.spawn(async move {
+            let (status, request_id, body) = run_line(&state, caller, &HeaderMap::new(), line.body).await;
+            let result = json!({
+                "id": new_id("batch_req_"),
+                "custom_id": line.custom_id,
+                "response": { "status_code": status, "request_id": request_id, "body": body },
+                "error": null,
+            });
+            let mut text = result.to_string();
+            text.push('\n');
+            let file = if status == 200 { &output } else { &errors };
+            if let Err(e) = file.lock().await.write_all(text.as_bytes()).await {
+                warn!("Failed to write a result for batch {}: {}", id, e);
+            }
+            store.update(&id, |batch| match status {
+                200 => batch.batch.request_counts.completed += 1,
+                _ => batch.batch.request_counts.failed += 1,
+            });
+            drop(slot);
+        });
+    }
+    while running.join_next().await.is_some() {}
+    output.lock().await.flush().await?;
+    errors.lock().await.flush().await?;
+
+    // Finalize: publish the result files and settle the status.
+    let now = Utc::now().timestamp();
+    store.update(id, |batch| {
+        if batch.batch.status == "in_progress" {
+            batch.batch.status = "finalizing".to_string();
+        }
+        batch.batch.finalizing_at = Some(now);
+    });
+    let owner = batch.owner.clone();
+    let output_file_id = publish(store, &output_path, format!("{}_output.jsonl", id), owner.clone()).await?;
+    let error_file_id = publish(store, &error_path, format!("{}_error.jsonl", id), owner).await?;
+    let finished = store.update(id, |batch| {
+        batch.batch.output_file_id = output_file_id;
+        batch.batch.error_file_id = error_file_id;
+        match batch.batch.status.as_str() {
+            "cancelling" => {
+                batch.batch.status = "cancelled".to_string();
+                batch.batch.cancelled_at = Some(now);
+            }
+            _ if expired => {
+                batch.batch.status = "expired".to_string();
+                batch.batch.expired_at = Some(now);
+            }
+            _ => {
+                batch.batch.status = "completed".to_string();
+                batch.batch.completed_at = Some(now);
+            }
+        }
+    }).context("Batch disappeared")?;
+    let counts = &finished.batch.request_counts;
+    info!("Batch {} {}: {} completed, {} failed of {}", id, finished.batch.status, counts.completed, counts.failed, counts.total);
+    Ok(())
+}
+
+// One request, retried with backoff while the error says it is worth it; also
+// runs async jobs, with their request's headers.
+pub async fn run_line(state: &Arc<AppState>, caller: Caller, headers: &HeaderMap, mut body: ChatRequest) -> (u16, String, Value) {
+    body.stream_options = Some(StreamOptions { include_usage: true });
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let mut meta = RequestMeta::new();
+        let model = body.model.clone();
+        let started = match metadata::collect(headers, body.metadata.as_ref()) {
+            Ok(metadata) => {
+                meta.metadata = Arc::new(metadata);
+                crate::start_chat(state.clone(), caller.clone(), headers.clone(), body.clone(), meta.clone()).await
+            }
+            Err(e) => Err(e),
+        };
+        match started {
+            Ok((_, payloads)) => {
+                return match stream::collect(payloads).await {
+                    Ok(completion) => (200, meta.id, completion),
+                    Err(error) => (502, meta.id, json!({ "error": { "message": error, "type": "api_error", "code": null } })),
+                };
+            }
+            Err(e) if e.should_retry() && attempt < MAX_ATTEMPTS => {
+                tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+            }
+            Err(e) => {
+                let response = e.into_response();
+                let status = response.status().as_u16();
+                crate::request_log::Recorder::new(state, &meta, model, &caller).finish(status, None, None);
+                let body = to_bytes(response.into_body(), usize::MAX).await.ok()
+                    .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+                    .unwrap_or(Value::Null);
+                return (status, meta.id, body);
+            }
+        }
+    }
+}
+
+async fn finished_ids(path: &std::path::Path) -> HashSet<String> {
+    let Ok(raw) = tokio::fs::read_to_string(path).await else { return HashSet::new() };
+    raw.lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter_map(|result| result["custom_id"].as_str().map(str::to_string))
+        .collect()
+}
+
+async fn append(path: &std::path::Path) -> Result<tokio::fs::File> {
+    tokio::fs::OpenOptions::new().create(true).append(true).open(path).await
+        .with_context(|| format!("Failed to open '{}'", path.display()))
+}
+
+// Moves a results file into the file store; None if it is empty.
+async fn publish(store: &BatchStore, path: &std::path::Path, filename: String, owner: Option<String>) -> Result<Option<String>> {
+    let bytes = tokio::fs::metadata(path).await.map(|m| m.len()).unwrap_or(0);
+    if bytes == 0 {
+        let _ = tokio::fs::remove_file(path).await;
+        return Ok(None);
+    }
+    let id = new_id("file-");
+    tokio::fs::rename(path, store.content_path(&id)).await?;
+    let file = FileObject {
+        id: id.clone(),
+        object: "file".to_string(),
+        bytes,
+        created_at: Utc::now().timestamp(),
+        filename,
+        purpose: "batch_output".to_string(),
+    };
+    store.save_file(StoredFile { file, owner })?;
+    Ok(Some(id))
+}
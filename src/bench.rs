@@ -0,0 +1,184 @@
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+use crate::{admin::percentile, capture::parse_header};
+
+// --- Load Test ---
+// `llm_gateway bench --model <model> --concurrency <n>` sends streaming chat
+// requests to `<target>/v1/chat/completions` (the gateway, or a vLLM replica
+// directly) and reports the distributions of time to first token, latency, and
+// decode speed, plus the overall throughput. Prompts come from `--prompt-file`,
+// one per line: a plain line is sent as a user message, and a JSON object line
+// supplies request fields such as `messages` or `max_tokens`. They are used in
+// turn, as often as needed.
+#[derive(Debug, clap::Args)]
+pub struct BenchArgs {
+    #[arg(long, help = "Model to request")]
+    model: String,
+    #[arg(long, value_name = "URL", default_value = "http://localhost:3000", help = "Base URL of the gateway or backend")]
+    target: String,
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..), help = "Requests in flight at once")]
+    concurrency: u32,
+    #[arg(long, default_value_t = 100, help = "Requests to send in total")]
+    requests: usize,
+    #[arg(long, value_name = "SECS", help = "Stop starting requests after this long instead")]
+    duration: Option<u64>,
+    #[arg(long, value_name = "FILE", help = "Prompts, one per line (text or a JSON request object)")]
+    prompt_file: Option<PathBuf>,
+    #[arg(long, default_value_t = 128, help = "max_tokens for prompts that don't set it")]
+    max_tokens: u32,
+    #[arg(long = "header", value_name = "NAME: VALUE", value_parser = parse_header, help = "Extra request header; repeatable")]
+    headers: Vec<(String, String)>,
+}
+
+// One request's measurements.
+struct Sample {
+    ok: bool,
+    ttft: Option<Duration>,
+    latency: Duration,
+    tokens: u64, // completion tokens, from usage or else counted as content chunks
+}
+
+impl Sample {
+    // Tokens after the first, over the time since it arrived.
+    fn decode_rate(&self) -> Option<f64> {
+        let decode = self.latency.checked_sub(self.ttft?)?.as_secs_f64();
+        (self.tokens > 1 && decode > 0.0).then(|| (self.tokens - 1) as f64 / decode)
+    }
+}
+
+pub async fn bench(mut args: BenchArgs) -> Result<()> {
+    args.target = args.target.trim_end_matches('/').to_string();
+    let prompts = load_prompts(&args).await?;
+    let deadline = args.duration.map(|secs| Instant::now() + Duration::from_secs(secs));
+    let total = if deadline.is_some() { usize::MAX } else { args.requests };
+    info!(
+        "Benchmarking model '{}' at {} with {} concurrent requests{}",
+        args.model, args.target, args.concurrency,
+        args.duration.map(|secs| format!(" for {}s", secs)).unwrap_or_else(|| format!(", {} in total", total))
+    );
+
+    let client = Client::new();
+    let started = Instant::now();
+    let samples: Vec<Sample> = futures::stream::iter((0..total).map(|i| prompts[i % prompts.len()].clone()))
+        .take_while(|_| std::future::ready(deadline.is_none_or(|deadline| Instant::now() < deadline)))
+        .map(|body| run_one(&client, &args, body))
+        .buffer_unordered(args.concurrency as usize)
+        .collect()
+        .await;
+    report(&samples, started.elapsed());
+    Ok(())
+}
+
+async fn load_prompts(args: &BenchArgs) -> Result<Vec<Value>> {
+    let lines = match &args.prompt_file {
+        Some(path) => tokio::fs::read_to_string(path).await.with_context(|| format!("Failed to read {}", path.display()))?,
+        None => "Write a short story about a lighthouse keeper.".to_string(),
+    };
+    let mut prompts = Vec::new();
+    for line in lines.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let mut body = match serde_json::from_str::<Value>(line) {
+            Ok(Value::Object(fields)) => Value::Object(fields),
+            _ => json!({ "messages": [{ "role": "user", "content": line }] }),
+        };
+        body["model"] = json!(args.model);
+        body["stream"] = json!(true);
+        body["stream_options"] = json!({ "include_usage": true });
+        if body.get("max_tokens").is_none() {
+            body["max_tokens"] = json!(args.max_tokens);
+        }
+        prompts.push(body);
+    }
+    if prompts.is_empty() {
+        anyhow::bail!("The prompt file has no prompts");
+    }
+    Ok(prompts)
+}
+
+async fn run_one(client: &Client, args: &BenchArgs, body: Value) -> Sample {
+    let started = Instant::now();
+    let mut sample = Sample { ok: false, ttft: None, latency: Duration::ZERO, tokens: 0 };
+    let mut request = client.post(format!("{}/v1/chat/completions", args.target)).json(&body);
+    for (name, value) in &args.headers {
+        request = request.header(name, value);
+    }
+    let response = match request.send().await {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            warn!("Request failed with {}", response.status());
+            sample.latency = started.elapsed();
+            return sample;
+        }
+        Err(e) => {
+            warn!("Request failed: {}", e);
+            sample.latency = started.elapsed();
+            return sample;
+        }
+    };
+
+    let (mut chunks, mut usage) = (0, None);
+    let mut body = response.bytes_stream();
+    let mut buffer = Vec::new();
+    while let Some(bytes) = body.next().await {
+        let Ok(bytes) = bytes else { break };
+        buffer.extend_from_slice(&bytes);
+        while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim_end().strip_prefix("data:").map(str::trim) else { continue };
+            if data == "[DONE]" {
+                sample.ok = true;
+                continue;
+            }
+            let Ok(chunk) = serde_json::from_str::<Value>(data) else { continue };
+            if let Some(tokens) = chunk.pointer("/usage/completion_tokens").and_then(Value::as_u64) {
+                usage = Some(tokens);
+            }
+            let content = chunk.pointer("/choices/0/delta/content").and_then(Value::as_str).is_some_and(|c| !c.is_empty());
+            if content {
+                chunks += 1;
+                sample.ttft.get_or_insert_with(|| started.elapsed());
+            }
+        }
+    }
+    sample.latency = started.elapsed();
+    sample.tokens = usage.unwrap_or(chunks);
+    sample
+}
+
+fn report(samples: &[Sample], elapsed: Duration) {
+    let ok: Vec<&Sample> = samples.iter().filter(|s| s.ok).collect();
+    let tokens: u64 = ok.iter().map(|s| s.tokens).sum();
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    println!(
+        "{} requests in {:.1}s: {} ok, {} failed; {:.2} requests/s, {:.1} completion tokens/s",
+        samples.len(), secs, ok.len(), samples.len() - ok.len(), ok.len() as f64 / secs, tokens as f64 / secs
+    );
+
+    let ms = |values: Vec<Duration>| sorted(values.iter().map(|d| d.as_secs_f64() * 1000.0).collect());
+    println!("{:<22}{:>10}{:>10}{:>10}{:>10}{:>10}", "", "min", "p50", "p90", "p99", "max");
+    for (name, values) in [
+        ("ttft (ms)", ms(ok.iter().filter_map(|s| s.ttft).collect())),
+        ("latency (ms)", ms(ok.iter().map(|s| s.latency).collect())),
+        ("decode (tokens/s)", sorted(ok.iter().filter_map(|s| s.decode_rate()).collect())),
+        ("completion tokens", sorted(ok.iter().map(|s| s.tokens as f64).collect())),
+    ] {
+        println!(
+            "{:<22}{:>10.1}{:>10.1}{:>10.1}{:>10.1}{:>10.1}",
+            name, percentile(&values, 0.0), percentile(&values, 0.50), percentile(&values, 0.90), percentile(&values, 0.99),
+            values.last().copied().unwrap_or_default()
+        );
+    }
+}
+
+fn sorted(mut values: Vec<f64>) -> Vec<f64> {
+    values.sort_by(f64::total_cmp);
+    values
+}
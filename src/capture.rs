@@ -0,0 +1,237 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::StreamExt;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{path::PathBuf, time::Instant};
+use tokio::{fs, io::AsyncWriteExt, sync::mpsc};
+use tracing::{error, info, warn};
+
+use crate::{log_content::LogContent, redaction::Redactor, ChatRequest};
+
+// Exchanges waiting for the writer; beyond this they are dropped.
+const CAPTURE_BUFFER: usize = 10_000;
+
+// --- Traffic Capture ---
+// With CAPTURE_DIR set, every chat request is written as it was sent upstream,
+// along with each payload the backend streamed back and when it arrived (in ms
+// since the gateway received the request). One JSON line per exchange goes to
+// `<dir>/capture-YYYY-MM-DD.jsonl`; `llm_gateway replay` re-sends these files.
+// CAPTURE_CONTENT applies a logged-content mode to the messages and streamed
+// deltas; `metadata` leaves a request out altogether.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Exchange {
+    pub request_id: String,
+    pub at: DateTime<Utc>,
+    pub backend: Option<String>,
+    pub status: u16,
+    pub request: ChatRequest,
+    pub chunks: Vec<CapturedChunk>,
+    #[serde(skip)]
+    pub content: LogContent, // the capture's mode, or the tenant's if stricter
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CapturedChunk {
+    pub ms: u64,
+    pub data: String, // the SSE `data:` payload, as sent by the backend
+}
+
+impl Exchange {
+    fn completion(&self) -> String {
+        self.chunks.iter()
+            .filter_map(|chunk| serde_json::from_str::<Value>(&chunk.data).ok())
+            .filter_map(|chunk| chunk.pointer("/choices/0/delta/content").and_then(Value::as_str).map(str::to_string))
+            .collect()
+    }
+
+    fn first_ms(&self) -> Option<u64> {
+        self.chunks.first().map(|chunk| chunk.ms)
+    }
+
+    fn total_ms(&self) -> Option<u64> {
+        self.chunks.last().map(|chunk| chunk.ms)
+    }
+}
+
+#[derive(Clone)]
+pub struct Capturer {
+    tx: mpsc::Sender<Exchange>,
+    content: LogContent,
+}
+
+impl Capturer {
+    pub fn start(dir: PathBuf, content: LogContent) -> Result<Self> {
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create CAPTURE_DIR '{}'", dir.display()))?;
+        info!("Capturing chat traffic to {} ({:?} content)", dir.display(), content);
+        let redactor = Redactor::new(Default::default())?;
+        let (tx, rx) = mpsc::channel(CAPTURE_BUFFER);
+        tokio::spawn(write_exchanges(rx, dir, redactor));
+        Ok(Capturer { tx, content })
+    }
+
+    pub fn content(&self) -> LogContent {
+        self.content
+    }
+
+    pub fn submit(&self, exchange: Exchange) {
+        if let Err(mpsc::error::TrySendError::Full(exchange)) = self.tx.try_send(exchange) {
+            warn!("Capture writer is falling behind; dropped {}", exchange.request_id);
+        }
+    }
+}
+
+async fn write_exchanges(mut rx: mpsc::Receiver<Exchange>, dir: PathBuf, redactor: Redactor) {
+    let mut current: Option<(NaiveDate, fs::File)> = None;
+    while let Some(mut exchange) = rx.recv().await {
+        exchange.content.apply_messages(&redactor, &mut exchange.request.messages);
+        for chunk in &mut exchange.chunks {
+            exchange.content.apply_payload(&redactor, &mut chunk.data);
+        }
+        let today = Utc::now().date_naive();
+        if current.as_ref().is_none_or(|(date, _)| *date != today) {
+            let path = dir.join(format!("capture-{}.jsonl", today.format("%Y-%m-%d")));
+            match fs::OpenOptions::new().create(true).append(true).open(&path).await {
+                Ok(file) => current = Some((today, file)),
+                Err(e) => {
+                    error!("Failed to open capture file {}: {}", path.display(), e);
+                    current = None;
+                    continue;
+                }
+            }
+        }
+        let Some((_, file)) = &mut current else { continue };
+        let mut line = serde_json::to_vec(&exchange).unwrap_or_default();
+        line.push(b'\n');
+        if let Err(e) = file.write_all(&line).await {
+            error!("Failed to write capture for {}: {}", exchange.request_id, e);
+        }
+    }
+}
+
+// --- Replay ---
+// `llm_gateway replay <capture.jsonl>... --target <url>` re-sends captured
+// requests to `<url>/v1/chat/completions` and compares each answer with the
+// captured one.
+#[derive(Debug, clap::Args)]
+pub struct ReplayArgs {
+    #[arg(required = true, value_name = "CAPTURE", help = "Capture files (JSONL)")]
+    files: Vec<PathBuf>,
+    #[arg(long, value_name = "URL", help = "Base URL of the deployment to replay against")]
+    target: String,
+    #[arg(long, help = "Send every request to this model instead")]
+    model: Option<String>,
+    #[arg(long = "header", value_name = "NAME: VALUE", value_parser = parse_header, help = "Extra request header; repeatable")]
+    headers: Vec<(String, String)>,
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..), help = "Requests in flight at once")]
+    concurrency: u32,
+    #[arg(long, value_name = "FILE", help = "Write the replayed exchanges here")]
+    output: Option<PathBuf>,
+}
+
+pub fn parse_header(header: &str) -> Result<(String, String), String> {
+    let (name, value) = header.split_once(':').ok_or_else(|| format!("expected 'Name: value', got '{}'", header))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+pub async fn replay(mut args: ReplayArgs) -> Result<()> {
+    args.target = args.target.trim_end_matches('/').to_string();
+    let mut captured = Vec::new();
+    for path in &args.files {
+        let text = fs::read_to_string(path).await.with_context(|| format!("Failed to read {}", path.display()))?;
+        for (number, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            match serde_json::from_str::<Exchange>(line) {
+                Ok(exchange) => captured.push(exchange),
+                Err(e) => warn!("Skipping {}:{}: {}", path.display(), number + 1, e),
+            }
+        }
+    }
+    info!("Replaying {} requests against {}", captured.len(), args.target);
+
+    let client = Client::new();
+    let results: Vec<(Exchange, Exchange)> = futures::stream::iter(captured)
+        .map(|original| {
+            let (client, args) = (&client, &args);
+            async move {
+                let replayed = replay_one(client, args, &original).await;
+                (original, replayed)
+            }
+        })
+        .buffered(args.concurrency as usize)
+        .collect()
+        .await;
+
+    let ms = |value: Option<u64>| value.map_or("-".to_string(), |ms| ms.to_string());
+    let mut identical = 0;
+    let mut failed = 0;
+    for (original, replayed) in &results {
+        let same = original.completion() == replayed.completion();
+        identical += same as usize;
+        failed += (replayed.status != 200) as usize;
+        println!(
+            "{}\tstatus {}\tfirst chunk {} -> {} ms\ttotal {} -> {} ms\t{}",
+            original.request_id, replayed.status, ms(original.first_ms()), ms(replayed.first_ms()),
+            ms(original.total_ms()), ms(replayed.total_ms()), if same { "identical" } else { "differs" }
+        );
+    }
+    println!("{} replayed, {} failed, {} identical completions", results.len(), failed, identical);
+
+    if let Some(path) = &args.output {
+        let mut out = String::new();
+        for (_, replayed) in &results {
+            out.push_str(&serde_json::to_string(replayed)?);
+            out.push('\n');
+        }
+        fs::write(path, out).await.with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+async fn replay_one(client: &Client, args: &ReplayArgs, original: &Exchange) -> Exchange {
+    let mut request = original.request.clone();
+    if let Some(model) = &args.model {
+        request.model = model.clone();
+    }
+    let mut exchange = Exchange {
+        request_id: original.request_id.clone(),
+        at: Utc::now(),
+        backend: Some(args.target.clone()),
+        status: 502,
+        request,
+        chunks: Vec::new(),
+        content: LogContent::Full,
+    };
+
+    let started = Instant::now();
+    let mut builder = client.post(format!("{}/v1/chat/completions", args.target)).json(&exchange.request);
+    for (name, value) in &args.headers {
+        builder = builder.header(name, value);
+    }
+    let response = match builder.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("Replay of {} failed: {}", original.request_id, e);
+            return exchange;
+        }
+    };
+    exchange.status = response.status().as_u16();
+
+    let mut body = response.bytes_stream();
+    let mut buffer = Vec::new();
+    while let Some(bytes) = body.next().await {
+        let Ok(bytes) = bytes else {
+            exchange.status = 502;
+            break;
+        };
+        buffer.extend_from_slice(&bytes);
+        while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if let Some(data) = line.trim_end().strip_prefix("data:") {
+                exchange.chunks.push(CapturedChunk { ms: started.elapsed().as_millis() as u64, data: data.trim().to_string() });
+            }
+        }
+    }
+    exchange
+}
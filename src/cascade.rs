@@ -0,0 +1,225 @@
+use axum::{
+    http::{HeaderMap, HeaderValue},
+    response::IntoResponse,
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::HashMap, sync::Arc};
+use tracing::{info, warn};
+
+use crate::{
+    auth::Caller, backend::BackendTable, stream::PayloadStream, AppError, AppState, ChatMessage, ChatRequest, MessageContent,
+    RequestMeta,
+};
+
+pub const HEADER: &str = "x-gateway-cascade";
+
+// --- Speculative Cascades ---
+// Loaded from CASCADES: each entry is a virtual model that serves simple requests
+// with a small `draft` model and everything else with the large `target`. A
+// request is simple when it fits the heuristic limits and, with a `classifier`
+// model, that model answers SIMPLE. The draft's answer is generated in full with
+// logprobs and sent only if the model was confident (its mean token logprob is at
+// least `min_mean_logprob`) and it finished on its own; otherwise the request is
+// escalated to the target. `X-Gateway-Cascade` says which path was taken.
+#[derive(Debug, Deserialize)]
+pub struct CascadeConfig {
+    pub draft: String,
+    pub target: String,
+    #[serde(default = "default_max_prompt_tokens")]
+    pub max_prompt_tokens: usize, // counted with the draft's tokenizer
+    #[serde(default)]
+    pub max_messages: Option<usize>,
+    #[serde(default)]
+    pub draft_tools: bool, // requests with tools go straight to the target otherwise
+    #[serde(default)]
+    pub classifier: Option<String>, // a small model asked SIMPLE or COMPLEX
+    #[serde(default = "default_min_mean_logprob")]
+    pub min_mean_logprob: f64,
+}
+
+fn default_max_prompt_tokens() -> usize {
+    1000
+}
+
+fn default_min_mean_logprob() -> f64 {
+    -0.5
+}
+
+const CLASSIFIER_PROMPT: &str = "Decide whether a small, fast language model can answer the user's request below well, \
+or whether it needs a large model. Reply with exactly one word: SIMPLE or COMPLEX.";
+
+pub fn load(backends: &BackendTable) -> anyhow::Result<HashMap<String, CascadeConfig>> {
+    let cascades: HashMap<String, CascadeConfig> = crate::config::env_json("CASCADES")?.unwrap_or_default();
+    for (name, cascade) in &cascades {
+        if backends.contains(name) {
+            anyhow::bail!("CASCADES: '{}' is also a configured model, which it would hide", name);
+        }
+        for model in [Some(&cascade.draft), Some(&cascade.target), cascade.classifier.as_ref()].into_iter().flatten() {
+            if !backends.contains(model) {
+                anyhow::bail!("CASCADES: '{}' uses unknown model '{}'", name, model);
+            }
+        }
+        info!("Cascade '{}': '{}' first, escalating to '{}'", name, cascade.draft, cascade.target);
+    }
+    Ok(cascades)
+}
+
+pub async fn run(
+    state: Arc<AppState>,
+    caller: Caller,
+    headers: HeaderMap,
+    mut body: ChatRequest,
+    meta: RequestMeta,
+    name: String,
+) -> Result<(HeaderMap, PayloadStream), AppError> {
+    let cascade = &state.cascades[&name];
+    caller.authorize_model(&name)?;
+    let (draft, target) = (cascade.draft.clone(), cascade.target.clone());
+
+    let path = match simple(&state, cascade, &caller, &headers, &body, &meta).await {
+        true => match draft_answer(&state, &caller, &headers, &body, &meta, &draft, cascade.min_mean_logprob).await {
+            Some((mut response_headers, payloads)) => {
+                info!("Cascade '{}' answered with draft model '{}'", name, draft);
+                response_headers.insert(HEADER, HeaderValue::from_static("draft"));
+                return Ok((response_headers, Box::pin(futures::stream::iter(payloads))));
+            }
+            None => "escalated",
+        },
+        false => "target",
+    };
+    info!("Cascade '{}' sending request to target model '{}' ({})", name, target, path);
+    body.model = target;
+    let (mut response_headers, payloads) = Box::pin(crate::start_chat(state.clone(), caller, headers, body, meta)).await?;
+    response_headers.insert(HEADER, HeaderValue::from_static(path));
+    Ok((response_headers, payloads))
+}
+
+// The cheap checks first; the classifier only sees requests that pass them.
+async fn simple(state: &Arc<AppState>, cascade: &CascadeConfig, caller: &Caller, headers: &HeaderMap, body: &ChatRequest, meta: &RequestMeta) -> bool {
+    if body.tools.is_some() && !cascade.draft_tools {
+        return false;
+    }
+    if cascade.max_messages.is_some_and(|max| body.messages.len() > max) {
+        return false;
+    }
+    let Some(draft) = state.vllm_backends.get(&cascade.draft) else { return false };
+    if draft.tokenizer.count_messages(&body.messages) > cascade.max_prompt_tokens {
+        return false;
+    }
+    let Some(classifier) = &cascade.classifier else { return true };
+
+    let request = body.messages.iter().rev().find(|m| m.role == "user").map(|m| m.content.text().into_owned()).unwrap_or_default();
+    let mut question = body.clone();
+    question.model = classifier.clone();
+    question.messages = vec![message("system", CLASSIFIER_PROMPT), message("user", &request)];
+    question.tools = None;
+    question.tool_choice = None;
+    question.stop = None;
+    question.max_tokens = Some(3);
+    question.temperature = Some(0.0);
+    question.stream = Some(false);
+    question.logprobs = None;
+    let answer = match Box::pin(crate::start_chat(state.clone(), caller.clone(), headers.clone(), question, meta.clone())).await {
+        Ok((_, payloads)) => crate::stream::collect(payloads).await,
+        Err(e) => Err(describe(e)),
+    };
+    match answer {
+        Ok(completion) => {
+            let verdict = completion["choices"][0]["message"]["content"].as_str().unwrap_or_default().trim().to_ascii_uppercase();
+            verdict.starts_with("SIMPLE")
+        }
+        Err(e) => {
+            warn!("Cascade classifier '{}' failed: {}; using the target model", classifier, e);
+            false
+        }
+    }
+}
+
+fn describe(e: AppError) -> String {
+    format!("status {}", e.into_response().status())
+}
+
+fn message(role: &str, text: &str) -> ChatMessage {
+    ChatMessage {
+        role: role.to_string(),
+        content: MessageContent::Text(text.to_string()),
+        name: None,
+        tool_calls: None,
+        tool_call_id: None,
+    }
+}
+
+// The draft's whole stream, if it is good enough to send.
+async fn draft_answer(
+    state: &Arc<AppState>,
+    caller: &Caller,
+    headers: &HeaderMap,
+    body: &ChatRequest,
+    meta: &RequestMeta,
+    draft: &str,
+    min_mean_logprob: f64,
+) -> Option<(HeaderMap, Vec<String>)> {
+    let client_wants_logprobs = body.logprobs == Some(true);
+    let mut attempt = body.clone();
+    attempt.model = draft.to_string();
+    attempt.logprobs = Some(true);
+    attempt.stream = Some(false); // buffered in full, so not paced
+    let (response_headers, mut stream) = match Box::pin(crate::start_chat(state.clone(), caller.clone(), headers.clone(), attempt, meta.clone())).await {
+        Ok(started) => started,
+        Err(e) => {
+            warn!("Cascade draft model '{}' failed: {}; escalating", draft, describe(e));
+            return None;
+        }
+    };
+
+    let (mut total, mut tokens, mut finished) = (0.0, 0usize, false);
+    let mut payloads = Vec::new();
+    while let Some(payload) = stream.next().await {
+        if payload == "[DONE]" {
+            payloads.push(payload);
+            break;
+        }
+        let Ok(mut chunk) = serde_json::from_str::<Value>(&payload) else {
+            warn!("Cascade draft model '{}' failed mid-stream; escalating", draft);
+            return None;
+        };
+        for choice in chunk.get_mut("choices").and_then(Value::as_array_mut).into_iter().flatten() {
+            for entry in choice.pointer("/logprobs/content").and_then(Value::as_array).into_iter().flatten() {
+                if let Some(logprob) = entry.get("logprob").and_then(Value::as_f64) {
+                    total += logprob;
+                    tokens += 1;
+                }
+            }
+            match choice.get("finish_reason").and_then(Value::as_str) {
+                Some("stop" | "tool_calls") => finished = true,
+                Some(_) => return None, // cut short, so not a complete answer
+                None => {}
+            }
+            if !client_wants_logprobs {
+                if let Some(choice) = choice.as_object_mut() {
+                    choice.remove("logprobs");
+                }
+            }
+        }
+        payloads.push(chunk.to_string());
+    }
+
+    let mean = (tokens > 0).then(|| total / tokens as f64);
+    match mean {
+        _ if !finished => {
+            warn!("Cascade draft model '{}' ended without finishing; escalating", draft);
+            None
+        }
+        Some(mean) if mean >= min_mean_logprob => Some((response_headers, payloads)),
+        Some(mean) => {
+            info!("Cascade draft model '{}' not confident enough (mean logprob {:.3}); escalating", draft, mean);
+            None
+        }
+        None => {
+            warn!("Cascade draft model '{}' returned no logprobs; escalating", draft);
+            None
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use axum::http::{HeaderMap, StatusCode};
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::{auth::Caller, AppError};
+
+pub const HEADER: &str = "x-gateway-chaos";
+
+// --- Chaos Injection ---
+// Faults for resilience testing, set per backend under `chaos` or per request
+// with an `X-Gateway-Chaos` header holding the same JSON (which replaces the
+// backend's settings; resumes always use the backend's). Each connection
+// attempt rolls for `delay` and `error`; each request rolls once for `drop`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChaosConfig {
+    #[serde(default)]
+    pub delay_ms: u64,
+    #[serde(default = "default_rate")]
+    pub delay_rate: f64, // probability that `delay_ms` is added before connecting
+    #[serde(default)]
+    pub error_rate: f64,
+    #[serde(default = "default_error_status")]
+    pub error_status: u16, // returned instead of contacting the backend
+    #[serde(default)]
+    pub drop_rate: f64,
+    #[serde(default = "default_drop_after")]
+    pub drop_after_chunks: usize, // a dropped stream fails after this many payloads
+}
+
+fn default_rate() -> f64 {
+    1.0
+}
+
+fn default_error_status() -> u16 {
+    500
+}
+
+fn default_drop_after() -> usize {
+    10
+}
+
+fn roll(rate: f64) -> bool {
+    rate > 0.0 && fastrand::f64() < rate
+}
+
+impl ChaosConfig {
+    // Only keys with `chaos` may send the header.
+    pub fn from_headers(caller: &Caller, headers: &HeaderMap) -> Result<Option<Self>, AppError> {
+        let Some(value) = headers.get(HEADER) else { return Ok(None) };
+        if caller.key().is_some_and(|key| !key.chaos) {
+            return Err(AppError::Forbidden("This API key may not use the X-Gateway-Chaos header.".to_string()));
+        }
+        let config = value.to_str().ok().and_then(|v| serde_json::from_str(v).ok())
+            .ok_or_else(|| AppError::InvalidRequest("X-Gateway-Chaos must be a JSON chaos configuration.".to_string()))?;
+        Ok(Some(config))
+    }
+
+    // Runs before each connection attempt: maybe waits, maybe fails it.
+    pub async fn before_connect(&self, url: &str) -> Result<(), AppError> {
+        if self.delay_ms > 0 && roll(self.delay_rate) {
+            warn!("Chaos: delaying {} by {}ms", url, self.delay_ms);
+            tokio::time::sleep(Duration::from_millis(self.delay_ms)).await;
+        }
+        if roll(self.error_rate) {
+            let status = StatusCode::from_u16(self.error_status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            warn!("Chaos: failing {} with {}", url, status);
+            return Err(AppError::BackendRespondedError {
+                status,
+                text: "Injected fault (chaos)".to_string(),
+                url: url.to_string(),
+                retry_after: None,
+            });
+        }
+        Ok(())
+    }
+
+    // How many payloads to pass before breaking the stream, if this one is dropped.
+    pub fn drop_after(&self) -> Option<usize> {
+        roll(self.drop_rate).then_some(self.drop_after_chunks)
+    }
+}
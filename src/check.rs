@@ -0,0 +1,322 @@
+use anyhow::Result;
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    time::Duration,
+};
+
+use crate::{backend::Backends, mock, uds, verify, AppError, AppState};
+
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The model tables, by setting name.
+const TABLES: [&str; 5] = ["VLLM_BACKENDS", "AUDIO_BACKENDS", "IMAGE_BACKENDS", "RERANK_BACKENDS", "REALTIME_BACKENDS"];
+
+// --- Config Check ---
+// `llm_gateway check-config` loads the configuration exactly as `serve` would,
+// then looks for mistakes that load fine but misroute traffic: malformed or
+// suspicious backend URLs, models and aliases defined twice or shadowing each
+// other, and references to models that don't exist. With `--ping`, every
+// replica's `/health` is requested too. Each problem is printed on its own line;
+// any error makes the command exit non-zero, warnings don't. `--ping` also
+// verifies each replica's `/v1/models` as VERIFY_BACKENDS does; a model the
+// replica doesn't serve is an error.
+pub async fn run(mock_mode: bool, ping: bool) -> Result<()> {
+    let mut report = Report::default();
+    for name in TABLES.iter().copied().chain(["TENANTS"]) {
+        if let Ok(raw) = std::env::var(name) {
+            for path in duplicate_keys(&raw) {
+                report.error(format!("{}: '{}' is defined more than once; only the last definition is used", name, path));
+            }
+        }
+    }
+    // The keys of GATEWAY_API_KEYS are secrets, so they're counted, not printed.
+    if let Ok(raw) = std::env::var("GATEWAY_API_KEYS") {
+        let repeated = duplicate_keys(&raw).iter().filter(|path| !path.contains('.')).count();
+        if repeated > 0 {
+            report.error(format!("GATEWAY_API_KEYS: {} key(s) defined more than once; only the last definition is used", repeated));
+        }
+    }
+    match crate::load(mock_mode, false).await {
+        Ok(gateway) => {
+            check_state(&gateway.state, &mut report);
+            if ping {
+                ping_all(&gateway.state, &mut report).await;
+            }
+        }
+        Err(e) => report.error(format!("{:#}", e)),
+    }
+    report.finish()
+}
+
+#[derive(Default)]
+struct Report {
+    errors: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn error(&mut self, message: String) {
+        println!("error: {}", message);
+        self.errors += 1;
+    }
+
+    fn warning(&mut self, message: String) {
+        println!("warning: {}", message);
+        self.warnings += 1;
+    }
+
+    fn finish(self) -> Result<()> {
+        if self.errors > 0 {
+            println!("{} error(s), {} warning(s)", self.errors, self.warnings);
+            std::process::exit(1);
+        }
+        match self.warnings {
+            0 => println!("Configuration OK"),
+            warnings => println!("Configuration OK, {} warning(s)", warnings),
+        }
+        Ok(())
+    }
+}
+
+// Every backend table, labeled for diagnostics, tenants' included.
+pub fn tables(state: &AppState) -> Vec<(String, Backends)> {
+    let mut tables = vec![
+        ("VLLM_BACKENDS".to_string(), (*state.vllm_backends.snapshot()).clone()),
+        ("AUDIO_BACKENDS".to_string(), state.audio_backends.clone()),
+        ("IMAGE_BACKENDS".to_string(), state.image_backends.clone()),
+        ("RERANK_BACKENDS".to_string(), state.rerank_backends.clone()),
+        ("REALTIME_BACKENDS".to_string(), state.realtime_backends.clone()),
+    ];
+    for tenant in state.tenants.iter() {
+        tables.push((format!("TENANTS.{}.backends", tenant.name), tenant.backends.clone()));
+    }
+    tables.sort_by(|a, b| a.0.cmp(&b.0));
+    tables
+}
+
+fn check_state(state: &AppState, report: &mut Report) {
+    let tables = tables(state);
+    for (table, backends) in &tables {
+        let mut models: Vec<_> = backends.iter().collect();
+        models.sort_by(|a, b| a.0.cmp(b.0));
+        let mut folded: HashMap<String, &String> = HashMap::new();
+        for (model, backend) in models {
+            if let Some(other) = folded.insert(model.to_lowercase(), model) {
+                report.warning(format!("{}: '{}' and '{}' differ only in case", table, other, model));
+            }
+            if backend.config.static_replicas().is_empty() && backend.config.discovery.is_none() {
+                report.error(format!("{}: '{}' has no url, replicas, or discovery", table, model));
+            }
+            for url in backend.config.static_replicas() {
+                if let Err(problem) = check_url(&url) {
+                    report.error(format!("{}: '{}' has {}", table, model, problem));
+                } else if url.ends_with("/v1") {
+                    report.warning(format!("{}: '{}' URL '{}' ends in /v1, which the gateway adds itself", table, model, url));
+                }
+            }
+            for fallback in &backend.config.capability_fallbacks {
+                if !backends.contains_key(fallback) && !state.vllm_backends.contains(fallback) {
+                    report.error(format!("{}: '{}' falls back to unknown model '{}'", table, model, fallback));
+                }
+            }
+        }
+    }
+
+    let gateway = state.vllm_backends.snapshot();
+    let served = |model: &str| tables.iter().any(|(table, backends)| !table.starts_with("TENANTS.") && backends.contains_key(model));
+    let mut tenants: Vec<_> = state.tenants.iter().collect();
+    tenants.sort_by(|a, b| a.name.cmp(&b.name));
+    for tenant in tenants {
+        if !tenant.isolated {
+            let mut shadowed: Vec<_> = tenant.backends.keys().filter(|model| gateway.contains_key(*model)).collect();
+            shadowed.sort();
+            for model in shadowed {
+                report.warning(format!("TENANTS.{}: model '{}' shadows the gateway's model of the same name", tenant.name, model));
+            }
+        }
+        let mut aliases: Vec<_> = tenant.aliases.iter().collect();
+        aliases.sort();
+        for (alias, target) in aliases {
+            if tenant.backends.contains_key(alias) {
+                report.error(format!("TENANTS.{}: alias '{}' hides the tenant's own model of the same name", tenant.name, alias));
+            } else if !tenant.isolated && served(alias) {
+                report.warning(format!("TENANTS.{}: alias '{}' hides the gateway's model of the same name", tenant.name, alias));
+            }
+            if tenant.aliases.contains_key(target) {
+                report.error(format!("TENANTS.{}: alias '{}' points at alias '{}'; aliases are not chained", tenant.name, alias, target));
+            } else if !tenant.backends.contains_key(target) && (tenant.isolated || !served(target)) {
+                report.error(format!("TENANTS.{}: alias '{}' points at unknown model '{}'", tenant.name, alias, target));
+            }
+        }
+    }
+
+    // Aliases may also name the `auto` router or a cascade.
+    let routable = |model: &str| served(model)
+        || state.auto_router.as_ref().is_some_and(|auto| auto.name == model)
+        || state.cascades.contains_key(model);
+    for (_, key) in state.api_keys.iter().flat_map(|keys| keys.configured()) {
+        if let Err(AppError::Unauthorized(message)) = key.check_expiry() {
+            report.warning(format!("GATEWAY_API_KEYS: key '{}': {}", key.name, message));
+        }
+        let tenant = key.tenant.as_ref().and_then(|name| state.tenants.get(name));
+        let mut aliases: Vec<_> = key.aliases.iter().collect();
+        aliases.sort();
+        for (alias, target) in aliases {
+            if key.aliases.contains_key(target) || tenant.is_some_and(|tenant| tenant.aliases.contains_key(target)) {
+                report.error(format!("GATEWAY_API_KEYS: key '{}': alias '{}' points at alias '{}'; aliases are not chained", key.name, alias, target));
+            } else if !tenant.is_some_and(|tenant| tenant.backends.contains_key(target))
+                && (tenant.is_some_and(|tenant| tenant.isolated) || !routable(target))
+            {
+                report.error(format!("GATEWAY_API_KEYS: key '{}': alias '{}' points at unknown model '{}'", key.name, alias, target));
+            }
+        }
+    }
+
+    if !state.store.pending().is_empty() {
+        report.warning(format!("STORE_URL: migration(s) {} are not applied yet; `serve` applies them at startup", state.store.pending().join(", ")));
+    }
+
+    if let Some(auto) = &state.auto_router {
+        if served(&auto.name) {
+            report.error(format!("AUTO_ROUTER: name '{}' is also a configured model, which it hides", auto.name));
+        }
+        for model in auto.models.iter().flatten().filter(|model| !gateway.contains_key(*model)) {
+            report.error(format!("AUTO_ROUTER: unknown candidate model '{}'", model));
+        }
+    }
+}
+
+fn check_url(url: &str) -> Result<(), String> {
+    if mock::is_mock(url) || uds::socket_path(url).is_some() {
+        return Ok(()); // parsed when the backend was loaded
+    }
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("invalid URL '{}': {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("URL '{}' with unsupported scheme '{}'; use http, https, or mock", url, parsed.scheme()));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err(format!("URL '{}' without a host", url));
+    }
+    if parsed.query().is_some() || parsed.fragment().is_some() {
+        return Err(format!("URL '{}' with a query or fragment, which would end up mid-path", url));
+    }
+    Ok(())
+}
+
+// Unreachable replicas and 5xx answers are errors; other non-2xx answers (a
+// hosted provider without /health) are warnings.
+async fn ping_all(state: &AppState, report: &mut Report) {
+    let mut probes = Vec::new();
+    for (table, backends) in tables(state) {
+        let mut backends: Vec<_> = backends.into_iter().collect();
+        backends.sort_by(|a, b| a.0.cmp(&b.0));
+        for (model, backend) in backends {
+            // Invalid URLs were already reported.
+            for url in backend.replicas.get().iter().filter(|url| !mock::is_mock(url) && check_url(url).is_ok()) {
+                let (table, model, backend, url) = (table.clone(), model.clone(), backend.clone(), url.clone());
+                probes.push(async move {
+                    let result = backend.authorize(backend.client_for(&url).get(format!("{}/health", backend.base_url(&url)))).timeout(PING_TIMEOUT).send().await;
+                    (table, model, url, result)
+                });
+            }
+        }
+    }
+    for (table, model, url, result) in futures::future::join_all(probes).await {
+        match result {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) if response.status().is_server_error() => {
+                report.error(format!("{}: '{}' replica {} answered /health with {}", table, model, url, response.status()));
+            }
+            Ok(response) => {
+                report.warning(format!("{}: '{}' replica {} answered /health with {}", table, model, url, response.status()));
+            }
+            Err(e) => report.error(format!("{}: '{}' replica {} is unreachable: {:#}", table, model, url, anyhow::Error::from(e))),
+        }
+    }
+    // Unreachable replicas were reported above.
+    for finding in verify::verify(state).await {
+        match finding.problem {
+            verify::Problem::Mismatch => report.error(finding.message),
+            verify::Problem::Unlisted => report.warning(finding.message),
+            verify::Problem::Unreachable => {}
+        }
+    }
+}
+
+// --- Duplicate Keys ---
+// Object keys that appear more than once anywhere in a JSON value, as dotted
+// paths. Parsing keeps the last one without a word.
+fn duplicate_keys(json: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    // Invalid JSON is reported when the config is loaded.
+    let _ = Walk { path: String::new(), found: &mut found }.deserialize(&mut deserializer);
+    found
+}
+
+struct Walk<'a> {
+    path: String,
+    found: &'a mut Vec<String>,
+}
+
+impl<'de> DeserializeSeed<'de> for Walk<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Walk<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut index = 0;
+        while seq.next_element_seed(Walk { path: format!("{}[{}]", self.path, index), found: &mut *self.found })?.is_some() {
+            index += 1;
+        }
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut seen = HashSet::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let path = if self.path.is_empty() { key.clone() } else { format!("{}.{}", self.path, key) };
+            if !seen.insert(key) {
+                self.found.push(path.clone());
+            }
+            map.next_value_seed(Walk { path, found: &mut *self.found })?;
+        }
+        Ok(())
+    }
+}
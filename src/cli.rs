@@ -0,0 +1,174 @@
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::{backend::Backends, bench::BenchArgs, capture::ReplayArgs, AppState};
+
+// --- Command Line ---
+// `llm_gateway [serve]` runs the gateway, `check-config` loads the whole
+// configuration and exits, `print-routes` shows where each model goes, `replay`
+// re-sends captured traffic, and `bench` load-tests a deployment. Settings are
+// still the environment variables documented in the readme; a `--config` TOML
+// file supplies them as top-level keys, the environment (including .env)
+// overrides the file, and flags override both.
+#[derive(Debug, Parser)]
+#[command(version, about = "An OpenAI-compatible gateway for vLLM backends", args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub settings: Settings, // for a bare `llm_gateway`, which serves
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    #[command(about = "Run the gateway (the default)")]
+    Serve(Settings),
+    #[command(about = "Validate the configuration and exit non-zero on errors")]
+    CheckConfig(CheckArgs),
+    #[command(about = "Print each endpoint's models and the backends they are routed to")]
+    PrintRoutes(Settings),
+    #[command(about = "Re-send captured requests to another deployment and compare the answers")]
+    Replay(ReplayArgs),
+    #[command(about = "Load-test a deployment with streaming requests and report latency distributions")]
+    Bench(BenchArgs),
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct Settings {
+    // Tables and arrays in the file are read as their JSON.
+    #[arg(short, long, env = "GATEWAY_CONFIG", value_name = "FILE", help = "TOML file of settings, keyed by environment variable name")]
+    pub config: Option<PathBuf>,
+    #[arg(long, value_name = "ADDR", help = "Overrides GATEWAY_LISTEN_ADDR")]
+    pub listen: Option<String>,
+    #[arg(long, value_name = "ADDR", help = "Overrides GRPC_LISTEN_ADDR")]
+    pub grpc_listen: Option<String>,
+    #[arg(long, value_name = "JSON", help = "Overrides VLLM_BACKENDS")]
+    pub backends: Option<String>,
+    #[arg(long, help = "Serve every model from mock://lorem")]
+    pub mock: bool,
+    #[arg(long, help = "Check each backend's /v1/models at startup and warn about mismatches (VERIFY_BACKENDS=warn)")]
+    pub verify_backends: bool,
+    #[arg(long, help = "Like --verify-backends, but refuse to start on a mismatch (VERIFY_BACKENDS=strict)")]
+    pub strict: bool,
+    #[arg(long = "set", value_name = "NAME=VALUE", value_parser = parse_setting, help = "Overrides any setting; repeatable")]
+    pub set: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct CheckArgs {
+    #[command(flatten)]
+    pub settings: Settings,
+    #[arg(long, help = "Also request every replica's /health")]
+    pub ping: bool,
+}
+
+fn parse_setting(raw: &str) -> Result<(String, String), String> {
+    let (name, value) = raw.split_once('=').ok_or_else(|| format!("expected NAME=VALUE, got '{}'", raw))?;
+    Ok((name.trim().to_string(), value.to_string()))
+}
+
+impl Settings {
+    // Puts the file's values and the flags into the environment, where the rest
+    // of the gateway reads its settings. Runs before anything else is started.
+    pub fn apply(&self) -> Result<()> {
+        if let Some(path) = &self.config {
+            for (name, value) in read_config(path)? {
+                if std::env::var_os(&name).is_none() {
+                    std::env::set_var(name, value);
+                }
+            }
+        }
+        let flags = [("GATEWAY_LISTEN_ADDR", &self.listen), ("GRPC_LISTEN_ADDR", &self.grpc_listen), ("VLLM_BACKENDS", &self.backends)];
+        for (name, value) in flags {
+            if let Some(value) = value {
+                std::env::set_var(name, value);
+            }
+        }
+        if self.strict || self.verify_backends {
+            std::env::set_var("VERIFY_BACKENDS", if self.strict { "strict" } else { "warn" });
+        }
+        for (name, value) in &self.set {
+            std::env::set_var(name, value);
+        }
+        Ok(())
+    }
+}
+
+fn read_config(path: &PathBuf) -> Result<Vec<(String, String)>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let table: toml::Table = text.parse().with_context(|| format!("Failed to parse {}", path.display()))?;
+    table.into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                toml::Value::String(text) => text,
+                toml::Value::Table(_) | toml::Value::Array(_) => serde_json::to_string(&value)
+                    .with_context(|| format!("Invalid value for {} in {}", name, path.display()))?,
+                scalar => scalar.to_string(),
+            };
+            Ok((name, value))
+        })
+        .collect()
+}
+
+// --- print-routes ---
+pub fn print_routes(state: &AppState) {
+    print_table("/v1/chat/completions, /v1/responses (VLLM_BACKENDS)", &state.vllm_backends.snapshot());
+    print_table("/v1/audio (AUDIO_BACKENDS)", &state.audio_backends);
+    print_table("/v1/images/generations (IMAGE_BACKENDS)", &state.image_backends);
+    print_table("/v1/rerank (RERANK_BACKENDS)", &state.rerank_backends);
+    print_table("/v1/realtime (REALTIME_BACKENDS)", &state.realtime_backends);
+    if let Some(auto) = &state.auto_router {
+        let candidates = auto.models.as_ref().map_or("every priced model".to_string(), |models| models.join(", "));
+        println!("\nauto router '{}' -> cheapest of {}", auto.name, candidates);
+    }
+    let mut cascades: Vec<_> = state.cascades.iter().collect();
+    cascades.sort_by_key(|(name, _)| *name);
+    for (name, cascade) in cascades {
+        println!("\ncascade '{}' -> '{}' for simple requests, else '{}'", name, cascade.draft, cascade.target);
+    }
+    if !state.routing_rules.is_empty() {
+        println!("\nROUTING_RULES, first match wins");
+        for rule in &state.routing_rules {
+            println!("  {} -> {}", rule.model.as_deref().unwrap_or("*"), rule.route_to);
+        }
+    }
+    let mut tenants: Vec<_> = state.tenants.iter().collect();
+    tenants.sort_by(|a, b| a.name.cmp(&b.name));
+    for tenant in tenants {
+        let scope = if tenant.isolated { ", isolated" } else { "" };
+        println!("\ntenant '{}'{}", tenant.name, scope);
+        print_models(&tenant.backends);
+        let mut aliases: Vec<_> = tenant.aliases.iter().collect();
+        aliases.sort();
+        for (alias, model) in aliases {
+            println!("  {} => {}", alias, model);
+        }
+    }
+    for (_, key) in state.api_keys.iter().flat_map(|keys| keys.configured()).filter(|(_, key)| !key.aliases.is_empty()) {
+        println!("\nkey '{}', ahead of its tenant's aliases", key.name);
+        let mut aliases: Vec<_> = key.aliases.iter().collect();
+        aliases.sort();
+        for (alias, model) in aliases {
+            println!("  {} => {}", alias, model);
+        }
+    }
+}
+
+fn print_table(title: &str, backends: &Backends) {
+    if backends.is_empty() {
+        return;
+    }
+    println!("\n{}", title);
+    print_models(backends);
+}
+
+fn print_models(backends: &Backends) {
+    let mut models: Vec<_> = backends.iter().collect();
+    models.sort_by(|a, b| a.0.cmp(b.0));
+    for (model, backend) in models {
+        let replicas = backend.replicas.get();
+        let replicas = if replicas.is_empty() { "(no replicas yet)".to_string() } else { replicas.join(", ") };
+        println!("  {} -> {}", model, replicas);
+    }
+}
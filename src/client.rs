@@ -0,0 +1,362 @@
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ipnet::IpNet;
+use percent_encoding::percent_decode_str;
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    Certificate, Client, ClientBuilder, Identity, NoProxy, Proxy, Url,
+};
+use serde::Deserialize;
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_socks::tcp::Socks5Stream;
+
+// --- Upstream TLS Configuration ---
+// Set globally with UPSTREAM_TLS or per backend with `"tls"`; a backend's own
+// settings replace the global ones rather than merging with them.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UpstreamTls {
+    #[serde(default)]
+    pub ca_bundle: Option<String>, // PEM file with one or more CA certificates to trust
+    #[serde(default)]
+    pub client_cert: Option<String>, // PEM client certificate (chain) for mTLS
+    #[serde(default)]
+    pub client_key: Option<String>, // PKCS#8 PEM private key for client_cert
+    #[serde(default)]
+    pub insecure_skip_verify: bool, // lab setups only
+}
+
+// --- Connection Pool Tuning ---
+// Set globally with UPSTREAM_POOL or per backend with `"pool"`; unset fields keep
+// reqwest's defaults. Like TLS, a backend's section replaces the global one.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PoolConfig {
+    #[serde(default)]
+    pub http2_prior_knowledge: bool, // speak h2c to plain-HTTP backends (vLLM behind an h2 proxy)
+    #[serde(default)]
+    pub http2_keep_alive_interval_secs: Option<u64>,
+    #[serde(default)]
+    pub pool_max_idle_per_host: Option<usize>,
+    #[serde(default)]
+    pub pool_idle_timeout_secs: Option<u64>, // 0 keeps idle connections forever
+    #[serde(default)]
+    pub tcp_keepalive_secs: Option<u64>,
+    #[serde(default)]
+    pub tcp_nodelay: Option<bool>,
+}
+
+// --- Upstream Proxy ---
+// Set globally with UPSTREAM_PROXY or per backend with `"proxy"`, which replaces
+// the global section like the ones above. A section without `url` connects
+// directly; with neither, the HTTP_PROXY / HTTPS_PROXY / NO_PROXY environment
+// variables apply as before. `url` may carry `user:password@` for basic auth, or
+// for a SOCKS5 proxy's username and password.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProxyConfig {
+    #[serde(default)]
+    pub url: Option<String>, // http://, https://, socks5://, or socks5h:// (names resolved by the proxy)
+    #[serde(default)]
+    pub no_proxy: Option<String>, // comma-separated hosts, domains, and CIDRs reached directly
+}
+
+// --- Upstream Timeouts ---
+// Set globally with UPSTREAM_TIMEOUTS or per backend with `"timeouts"`, which
+// replaces the global section like the ones above. Unset fields mean no limit.
+// A dead pod should fail fast on `connect_ms`, while a long generation is only
+// bounded by `total_ms` as long as tokens keep arriving within `idle_ms`.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct Timeouts {
+    #[serde(default)]
+    pub connect_ms: Option<u64>, // TCP and TLS handshake with a replica
+    #[serde(default)]
+    pub first_byte_ms: Option<u64>, // from sending the request to the first streamed chunk
+    #[serde(default)]
+    pub idle_ms: Option<u64>, // longest gap between chunks once streaming
+    #[serde(default)]
+    pub total_ms: Option<u64>, // the whole upstream request, connecting included
+}
+
+fn millis(ms: Option<u64>) -> Option<Duration> {
+    ms.map(Duration::from_millis)
+}
+
+impl Timeouts {
+    pub fn first_byte(&self) -> Option<Duration> {
+        millis(self.first_byte_ms)
+    }
+
+    pub fn idle(&self) -> Option<Duration> {
+        millis(self.idle_ms)
+    }
+
+    pub fn total(&self) -> Option<Duration> {
+        millis(self.total_ms)
+    }
+}
+
+// --- Address Families ---
+// A backend's `ip_family` decides which of a host name's addresses are dialed:
+// `auto` (the default) uses every address in the resolver's order, `ipv4` or
+// `ipv6` only that family, and `prefer_ipv4` / `prefer_ipv6` that family first.
+// Every address is tried in turn, and the other family is raced in after 300ms
+// (happy eyeballs). IP literals such as `http://[fd00::1]:8000` are dialed as
+// written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IpFamily {
+    #[default]
+    Auto,
+    Ipv4,
+    Ipv6,
+    PreferIpv4,
+    PreferIpv6,
+}
+
+struct FamilyResolver(IpFamily);
+
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let family = self.0;
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let found: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await
+                .map_err(|e| format!("failed to resolve {}: {}", host, e))?
+                .collect();
+            let mut addrs: Vec<SocketAddr> = match family {
+                IpFamily::Ipv4 => found.iter().copied().filter(SocketAddr::is_ipv4).collect(),
+                IpFamily::Ipv6 => found.iter().copied().filter(SocketAddr::is_ipv6).collect(),
+                _ => found.clone(),
+            };
+            match family {
+                IpFamily::PreferIpv4 => addrs.sort_by_key(|addr| addr.is_ipv6()), // stable, so each family keeps its order
+                IpFamily::PreferIpv6 => addrs.sort_by_key(|addr| addr.is_ipv4()),
+                _ => {}
+            }
+            if addrs.is_empty() {
+                let wanted = if family == IpFamily::Ipv4 { "IPv4" } else { "IPv6" };
+                let found: Vec<String> = found.iter().map(|addr| addr.ip().to_string()).collect();
+                return Err(format!("{} has no {} address (found: {})", host, wanted, found.join(", ")).into());
+            }
+            tracing::debug!("Resolved {} to {:?}", host, addrs.iter().map(|addr| addr.ip()).collect::<Vec<_>>());
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientSettings {
+    pub tls: Option<UpstreamTls>,
+    pub pool: Option<PoolConfig>,
+    pub proxy: Option<ProxyConfig>,
+    pub timeouts: Option<Timeouts>,
+    pub ip_family: IpFamily,
+}
+
+pub fn build_client(settings: &ClientSettings) -> Result<Client> {
+    builder(settings)?.build().context("Failed to build upstream HTTP client")
+}
+
+// For a `unix:` replica: every connection is made to the socket, so the proxy,
+// address family, and TCP settings don't apply.
+pub fn socket_client(path: &std::path::Path, settings: &ClientSettings) -> Result<Client> {
+    builder(settings)?.unix_socket(path).build()
+        .with_context(|| format!("Failed to build the HTTP client for socket {}", path.display()))
+}
+
+fn builder(settings: &ClientSettings) -> Result<ClientBuilder> {
+    let mut builder = Client::builder();
+
+    if let Some(pool) = &settings.pool {
+        if pool.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(secs) = pool.http2_keep_alive_interval_secs {
+            builder = builder.http2_keep_alive_interval(Duration::from_secs(secs)).http2_keep_alive_while_idle(true);
+        }
+        if let Some(max) = pool.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(secs) = pool.pool_idle_timeout_secs {
+            builder = builder.pool_idle_timeout((secs > 0).then(|| Duration::from_secs(secs)));
+        }
+        if let Some(secs) = pool.tcp_keepalive_secs {
+            builder = builder.tcp_keepalive(Duration::from_secs(secs));
+        }
+        if let Some(nodelay) = pool.tcp_nodelay {
+            builder = builder.tcp_nodelay(nodelay);
+        }
+    }
+
+    if let Some(proxy) = &settings.proxy {
+        builder = builder.no_proxy(); // no environment variables
+        if let Some(url) = &proxy.url {
+            let parsed = reqwest::Url::parse(url).with_context(|| format!("Invalid proxy URL '{}'", url))?;
+            if !matches!(parsed.scheme(), "http" | "https" | "socks5" | "socks5h") {
+                anyhow::bail!("Unsupported proxy scheme '{}' in '{}'; use an http://, https://, socks5://, or socks5h:// proxy", parsed.scheme(), url);
+            }
+            let mut outbound = Proxy::all(parsed).with_context(|| format!("Invalid proxy URL '{}'", url))?;
+            if let Some(hosts) = &proxy.no_proxy {
+                outbound = outbound.no_proxy(NoProxy::from_string(hosts));
+            }
+            builder = builder.proxy(outbound);
+        }
+    }
+
+    if let Some(connect) = settings.timeouts.and_then(|t| millis(t.connect_ms)) {
+        builder = builder.connect_timeout(connect);
+    }
+
+    if settings.ip_family != IpFamily::Auto {
+        builder = builder.dns_resolver(Arc::new(FamilyResolver(settings.ip_family)));
+    }
+
+    if let Some(tls) = &settings.tls {
+        if let Some(path) = &tls.ca_bundle {
+            let pem = std::fs::read(path).with_context(|| format!("Failed to read CA bundle '{}'", path))?;
+            for cert in Certificate::from_pem_bundle(&pem).with_context(|| format!("Invalid CA bundle '{}'", path))? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        match (&tls.client_cert, &tls.client_key) {
+            (Some(cert_path), Some(key_path)) => {
+                let cert = std::fs::read(cert_path)
+                    .with_context(|| format!("Failed to read client certificate '{}'", cert_path))?;
+                let key = std::fs::read(key_path)
+                    .with_context(|| format!("Failed to read client key '{}'", key_path))?;
+                let identity = Identity::from_pkcs8_pem(&cert, &key)
+                    .context("Invalid client certificate/key pair (the key must be PKCS#8 PEM)")?;
+                builder = builder.identity(identity);
+            }
+            (None, None) => {}
+            _ => anyhow::bail!("client_cert and client_key must be set together"),
+        }
+
+        if tls.insecure_skip_verify {
+            tracing::warn!("Upstream TLS certificate verification is disabled");
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+    }
+
+    Ok(builder)
+}
+
+// --- Proxied Connections ---
+// Realtime WebSockets aren't made by reqwest, so they are tunneled here: through
+// the proxy the backend's HTTP client would use for the same URL, with CONNECT
+// for an http:// or https:// proxy and a SOCKS5 handshake otherwise.
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+const MAX_CONNECT_RESPONSE: usize = 16 * 1024;
+
+// The proxy for `url`, if any: the backend's section (or UPSTREAM_PROXY), else
+// the environment variables.
+pub fn proxy_for(proxy: Option<&ProxyConfig>, url: &Url) -> Result<Option<Url>> {
+    let host = url.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+    let (proxy_url, no_proxy) = match proxy {
+        Some(proxy) => (proxy.url.clone(), proxy.no_proxy.clone()),
+        None => {
+            let var = |names: &[&str]| names.iter().find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()));
+            let proxy_url = match url.scheme() {
+                "https" | "wss" => var(&["HTTPS_PROXY", "https_proxy"]),
+                _ => var(&["HTTP_PROXY", "http_proxy"]),
+            };
+            (proxy_url, var(&["NO_PROXY", "no_proxy"]))
+        }
+    };
+    let Some(proxy_url) = proxy_url.filter(|_| !no_proxy.is_some_and(|list| bypasses(&list, host))) else { return Ok(None) };
+    // A bare host:port in the environment means an http:// proxy.
+    let proxy_url = match proxy_url.contains("://") {
+        true => proxy_url,
+        false => format!("http://{}", proxy_url),
+    };
+    Url::parse(&proxy_url).map(Some).with_context(|| format!("Invalid proxy URL '{}'", proxy_url))
+}
+
+// Whether `host` is on a comma-separated NO_PROXY list of hosts, domains (which
+// include their subdomains), IPs, and CIDRs.
+fn bypasses(no_proxy: &str, host: &str) -> bool {
+    let ip = host.parse::<IpAddr>().ok();
+    no_proxy.split(',').map(str::trim).filter(|entry| !entry.is_empty()).any(|entry| {
+        if entry == "*" {
+            return true;
+        }
+        if let Some(ip) = ip {
+            return entry.parse::<IpNet>().is_ok_and(|net| net.contains(&ip)) || entry.parse::<IpAddr>().is_ok_and(|other| other == ip);
+        }
+        let domain = entry.trim_start_matches("*.").trim_start_matches('.');
+        host.eq_ignore_ascii_case(domain) || host.to_ascii_lowercase().ends_with(&format!(".{}", domain.to_ascii_lowercase()))
+    })
+}
+
+// A connection to `host:port` through `proxy`.
+pub async fn connect_via(proxy: &Url, host: &str, port: u16) -> io::Result<Box<dyn Transport>> {
+    let proxy_host = proxy.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+    let default_port = if proxy.scheme().starts_with("socks") { 1080 } else { 80 };
+    let tcp = TcpStream::connect((proxy_host, proxy.port_or_known_default().unwrap_or(default_port))).await?;
+    let decode = |part: &str| percent_decode_str(part).decode_utf8_lossy().into_owned();
+    let credentials = (!proxy.username().is_empty())
+        .then(|| (decode(proxy.username()), decode(proxy.password().unwrap_or_default())));
+    match proxy.scheme() {
+        "socks5" | "socks5h" => {
+            // With socks5:// the gateway resolves the name itself; socks5h:// leaves it to the proxy.
+            let target = match proxy.scheme() {
+                "socks5" => tokio::net::lookup_host((host, port)).await?.next()
+                    .ok_or_else(|| io::Error::other(format!("{} has no address", host)))?.to_string(),
+                _ => format!("{}:{}", authority(host), port),
+            };
+            let stream = match &credentials {
+                Some((user, password)) => Socks5Stream::connect_with_password_and_socket(tcp, target, user, password).await,
+                None => Socks5Stream::connect_with_socket(tcp, target).await,
+            };
+            Ok(Box::new(stream.map_err(io::Error::other)?))
+        }
+        "https" => {
+            let tls = tokio_native_tls::native_tls::TlsConnector::new().map_err(io::Error::other)?;
+            let tls = tokio_native_tls::TlsConnector::from(tls).connect(proxy_host, tcp).await.map_err(io::Error::other)?;
+            Ok(Box::new(http_connect(tls, host, port, credentials).await?))
+        }
+        _ => Ok(Box::new(http_connect(tcp, host, port, credentials).await?)),
+    }
+}
+
+fn authority(host: &str) -> String {
+    match host.contains(':') {
+        true => format!("[{}]", host), // an IPv6 literal
+        false => host.to_string(),
+    }
+}
+
+// Asks an HTTP proxy for a tunnel. The response is read a byte at a time, so
+// nothing after its headers is consumed.
+async fn http_connect<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, host: &str, port: u16, credentials: Option<(String, String)>) -> io::Result<S> {
+    let target = format!("{}:{}", authority(host), port);
+    let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", target, target);
+    if let Some((user, password)) = credentials {
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", BASE64.encode(format!("{}:{}", user, password))));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_CONNECT_RESPONSE {
+            return Err(io::Error::other("the proxy's CONNECT response is too long"));
+        }
+        response.push(stream.read_u8().await?);
+    }
+    let status_line = String::from_utf8_lossy(&response).lines().next().unwrap_or_default().to_string();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(stream),
+        _ => Err(io::Error::other(format!("the proxy refused CONNECT {}: {}", target, status_line))),
+    }
+}
@@ -0,0 +1,26 @@
+use anyhow::{bail, Result};
+use tower_http::compression::CompressionLayer;
+
+use crate::config;
+
+// --- Response Compression ---
+// Off unless RESPONSE_COMPRESSION lists the encodings to offer (`gzip`, `br`).
+// Responses are only compressed when the client sends a matching Accept-Encoding.
+// The default predicate skips bodies under 32 bytes, images, gRPC, and
+// `text/event-stream`, so SSE chunks are never held back by an encoder buffer.
+pub fn layer_from_env() -> Result<Option<CompressionLayer>> {
+    let encodings = config::env_list("RESPONSE_COMPRESSION");
+    if encodings.is_empty() {
+        return Ok(None);
+    }
+
+    let mut layer = CompressionLayer::new().gzip(false).br(false).no_deflate().no_zstd();
+    for encoding in &encodings {
+        layer = match encoding.as_str() {
+            "gzip" => layer.gzip(true),
+            "br" => layer.br(true),
+            other => bail!("Unsupported RESPONSE_COMPRESSION encoding '{}' (expected gzip or br)", other),
+        };
+    }
+    Ok(Some(layer))
+}
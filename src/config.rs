@@ -0,0 +1,221 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::chaos::ChaosConfig;
+use crate::client::{IpFamily, PoolConfig, ProxyConfig, Timeouts, UpstreamTls};
+use crate::context::OverflowPolicy;
+use crate::discovery::DiscoveryConfig;
+use crate::models::Capability;
+use crate::output_limit::ResponseLimit;
+use crate::pacing::PacingConfig;
+use crate::prompt_cache::CacheControlMode;
+use crate::params::{ParamDefaults, ParamLimits};
+use crate::prompt::SystemPromptConfig;
+use crate::routing::Balance;
+use crate::tokenizer::TokenizerConfig;
+use crate::usage::Pricing;
+
+// --- Backend Configuration ---
+// A VLLM_BACKENDS entry is either a bare base URL (the original format) or an
+// object that carries per-model request policy alongside the URL.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum BackendEntry {
+    Url(String),
+    Detailed(Box<BackendConfig>),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BackendConfig {
+    #[serde(default)]
+    pub url: String, // may be omitted when `discovery` finds the replicas
+    #[serde(default)]
+    pub replicas: Vec<String>, // extra base URLs serving the same model
+    #[serde(default)]
+    pub discovery: Option<DiscoveryConfig>,
+    #[serde(default)]
+    pub balance: Balance, // how requests are spread over replicas
+    #[serde(default = "default_explore_every")]
+    pub explore_every: u32, // with `latency` balancing, every Nth request goes round-robin
+    #[serde(default)]
+    pub hedge_after_ms: Option<u64>, // race a second replica if no first token by then
+    #[serde(default)]
+    pub resume_attempts: u32, // continue on another replica if a stream drops midway
+    #[serde(default)]
+    pub max_concurrent_requests: Option<usize>, // more wait in a fair queue
+    #[serde(default = "default_queue_timeout")]
+    pub queue_timeout_ms: u64, // queued longer than this gets a 503
+    #[serde(default)]
+    pub max_concurrent_streams: Option<usize>, // more are rejected outright with a 503
+    #[serde(default = "default_batch_max_utilization")]
+    pub batch_max_utilization: f64, // batch priority requests are turned away from here up
+    #[serde(default)]
+    pub max_stream_duration_secs: Option<u64>, // longer generations are ended with `length`
+    #[serde(default)]
+    pub response_limit: Option<ResponseLimit>, // so are longer ones, by characters or tokens
+    #[serde(default)]
+    pub normalize_stream: bool, // rewrite loosely compatible chunks into OpenAI's shape
+    #[serde(default)]
+    pub stop_sequences: Vec<String>, // enforced by the gateway on every request
+    #[serde(default)]
+    pub enforce_stop: bool, // also enforce each request's `stop`
+    #[serde(default)]
+    pub pacing: Option<PacingConfig>, // caps tokens per second to streaming clients
+    #[serde(default)]
+    pub cache_control: CacheControlMode, // what to do with Anthropic-style prompt cache markers
+    #[serde(default)]
+    pub defaults: ParamDefaults,
+    #[serde(default)]
+    pub limits: ParamLimits,
+    #[serde(default)]
+    pub system_prompt: Option<SystemPromptConfig>,
+    #[serde(default)]
+    pub context_length: Option<u32>,
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>, // requests asking for more are rejected
+    #[serde(default = "default_true")]
+    pub fill_max_tokens: bool, // set a missing max_tokens from what's left of the context window
+    #[serde(default = "default_max_tokens_headroom")]
+    pub max_tokens_headroom: u32,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    #[serde(default)]
+    pub capability_fallbacks: Vec<String>, // models to use when this one lacks a needed capability
+    #[serde(default)]
+    pub chaos: Option<ChaosConfig>, // injected faults, for resilience testing
+    #[serde(default)]
+    pub tokenizer: Option<TokenizerConfig>,
+    #[serde(default)]
+    pub on_context_overflow: OverflowPolicy,
+    #[serde(default)]
+    pub redact_pii: bool,
+    #[serde(default)]
+    pub tls: Option<UpstreamTls>,
+    #[serde(default)]
+    pub pool: Option<PoolConfig>,
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>,
+    #[serde(default)]
+    pub ip_family: IpFamily, // which address families of the replica host names to dial
+    #[serde(default)]
+    pub timeouts: Option<Timeouts>,
+    #[serde(default)]
+    pub pricing: Option<Pricing>,
+    #[serde(default)]
+    pub api: BackendApi, // for endpoints where servers differ (images, rerank)
+    #[serde(default)]
+    pub api_key: Option<String>, // sent as a bearer token; may be a credential reference
+    #[serde(default)]
+    pub upstream_model: Option<String>, // the name the backend serves the model under
+    #[serde(default = "default_path")]
+    pub path: String, // the upstream path template, with {endpoint} and optionally {model}
+    #[serde(default)]
+    pub headers: HashMap<String, String>, // sent on every request to the backend; values may be credential references
+    #[serde(default)]
+    pub forward_metadata: bool, // send requests' `metadata` on to the backend
+}
+
+// What a backend speaks beyond OpenAI's API.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendApi {
+    #[default]
+    #[serde(rename = "openai")]
+    OpenAi,
+    SdWebui, // AUTOMATIC1111 / Forge `sdapi`
+    Tei, // Hugging Face text-embeddings-inference
+}
+
+fn default_explore_every() -> u32 {
+    10
+}
+
+fn default_batch_max_utilization() -> f64 {
+    0.8
+}
+
+fn default_path() -> String {
+    "/v1/{endpoint}".to_string()
+}
+
+fn default_queue_timeout() -> u64 {
+    30_000
+}
+
+pub fn default_true() -> bool {
+    true
+}
+
+fn default_max_tokens_headroom() -> u32 {
+    64
+}
+
+impl BackendConfig {
+    // `url` and `replicas`, normalized; discovered replicas are added to these.
+    pub fn static_replicas(&self) -> Vec<String> {
+        std::iter::once(&self.url).chain(&self.replicas)
+            .filter(|url| !url.is_empty())
+            .map(|url| url.trim_end_matches('/').to_string())
+            .collect()
+    }
+}
+
+impl From<BackendEntry> for BackendConfig {
+    fn from(entry: BackendEntry) -> Self {
+        match entry {
+            // The same defaults as `{"url": ...}`.
+            BackendEntry::Url(url) => serde_json::from_value(serde_json::json!({ "url": url }))
+                .expect("a URL alone is a valid backend config"),
+            BackendEntry::Detailed(config) => *config,
+        }
+    }
+}
+
+pub fn parse_backends(json: &str) -> Result<HashMap<String, BackendConfig>> {
+    let entries: HashMap<String, BackendEntry> = serde_json::from_str(json)
+        .context("Failed to parse VLLM_BACKENDS. Make sure it's valid JSON on a single line.")?;
+    Ok(entries.into_iter().map(|(model, entry)| (model, entry.into())).collect())
+}
+
+// A separate model table in the same format as VLLM_BACKENDS (e.g. AUDIO_BACKENDS);
+// empty when the variable is unset.
+pub fn env_backends(name: &str) -> Result<HashMap<String, BackendConfig>> {
+    let entries: HashMap<String, BackendEntry> = env_json(name)?.unwrap_or_default();
+    Ok(entries.into_iter().map(|(model, entry)| (model, entry.into())).collect())
+}
+
+// One entry's value, in the same format as VLLM_BACKENDS (used for registry entries).
+pub fn parse_backend(value: serde_json::Value) -> Result<BackendConfig> {
+    Ok(serde_json::from_value::<BackendEntry>(value)?.into())
+}
+
+// Reads an optional environment variable holding a single-line JSON value.
+pub fn env_json<T: serde::de::DeserializeOwned>(name: &str) -> Result<Option<T>> {
+    match std::env::var(name) {
+        Ok(raw) if !raw.trim().is_empty() => serde_json::from_str(&raw)
+            .map(Some)
+            .with_context(|| format!("Failed to parse {}. Make sure it's valid JSON on a single line.", name)),
+        _ => Ok(None),
+    }
+}
+
+// Reads an optional comma-separated list, trimming whitespace and dropping empty items.
+pub fn env_list(name: &str) -> Vec<String> {
+    std::env::var(name)
+        .map(|raw| raw.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect())
+        .unwrap_or_default()
+}
+
+// Reads an optional scalar (number, bool, ...) from the environment.
+pub fn env_parse<T: std::str::FromStr>(name: &str) -> Result<Option<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match std::env::var(name) {
+        Ok(raw) if !raw.trim().is_empty() => raw.trim().parse()
+            .map(Some)
+            .with_context(|| format!("Invalid value for {}: '{}'", name, raw)),
+        _ => Ok(None),
+    }
+}
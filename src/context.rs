@@ -0,0 +1,82 @@
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{tokenizer::Tokenizer, AppError, ChatRequest};
+
+// --- Context-Window Enforcement ---
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    #[default]
+    Reject,
+    TruncateOldest,
+}
+
+// Checks that the prompt plus the requested completion fits in the model's context
+// window. With `TruncateOldest`, the oldest non-system messages are dropped until it
+// does; the final message is never dropped, since that is what the client is asking.
+// Returns the prompt's token count.
+pub fn enforce_context_window(
+    body: &mut ChatRequest,
+    tokenizer: &Tokenizer,
+    context_length: u32,
+    policy: OverflowPolicy,
+) -> Result<usize, AppError> {
+    let completion_tokens = body.max_tokens.unwrap_or(0) as usize;
+    let context_length = context_length as usize;
+    let mut prompt_tokens = tokenizer.count_messages(&body.messages);
+    let original_count = body.messages.len();
+
+    if policy == OverflowPolicy::TruncateOldest {
+        while prompt_tokens + completion_tokens > context_length {
+            let Some(oldest) = body.messages.iter()
+                .position(|m| m.role != "system")
+                .filter(|&i| i + 1 < body.messages.len())
+            else {
+                break;
+            };
+
+            body.messages.remove(oldest);
+            // Tool results are meaningless without the assistant turn that requested them.
+            while oldest + 1 < body.messages.len() && body.messages[oldest].role == "tool" {
+                body.messages.remove(oldest);
+            }
+            prompt_tokens = tokenizer.count_messages(&body.messages);
+        }
+
+        if body.messages.len() != original_count {
+            info!(
+                "Truncated {} oldest message(s) for model '{}' to fit its {}-token context window",
+                original_count - body.messages.len(), body.model, context_length
+            );
+        }
+    }
+
+    if prompt_tokens + completion_tokens > context_length {
+        return Err(AppError::ContextLengthExceeded {
+            model: body.model.clone(),
+            context_length,
+            prompt_tokens,
+            completion_tokens,
+        });
+    }
+
+    Ok(prompt_tokens)
+}
+
+// --- max_tokens Auto-Fill ---
+// A request without `max_tokens` leaves the limit to the backend, and backends
+// differ: some default to a few dozen tokens, others to the whole context. With a
+// known context window, the gateway instead asks for what is left of it after the
+// prompt, less `max_tokens_headroom` for tokenizer differences, and no more than
+// `max_output_tokens`.
+pub fn fill_max_tokens(body: &mut ChatRequest, prompt_tokens: usize, context_length: u32, headroom: u32, max_output_tokens: Option<u32>) {
+    if body.max_tokens.is_some() {
+        return;
+    }
+    let room = (context_length as usize).saturating_sub(prompt_tokens).saturating_sub(headroom as usize);
+    let fill = max_output_tokens.map_or(room, |max| room.min(max as usize));
+    if fill > 0 {
+        body.max_tokens = Some(fill as u32);
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::{bail, Context, Result};
+use axum::http::{HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer, ExposeHeaders};
+
+use crate::config;
+
+// --- CORS ---
+// Off unless CORS_ALLOWED_ORIGINS is set. Preflights are answered by the layer
+// itself, and because CORS headers go on the response head they also apply to
+// SSE streams.
+pub fn layer_from_env() -> Result<Option<CorsLayer>> {
+    let origins = config::env_list("CORS_ALLOWED_ORIGINS");
+    if origins.is_empty() {
+        return Ok(None);
+    }
+
+    let allow_credentials = std::env::var("CORS_ALLOW_CREDENTIALS").is_ok_and(|v| v == "true");
+    let wildcard = origins.iter().any(|o| o == "*");
+    if wildcard && allow_credentials {
+        bail!("CORS_ALLOW_CREDENTIALS=true cannot be combined with CORS_ALLOWED_ORIGINS=\"*\"; list the origins explicitly");
+    }
+
+    let allow_origin = if wildcard {
+        AllowOrigin::any()
+    } else {
+        let values = origins.iter()
+            .map(|o| HeaderValue::from_str(o).with_context(|| format!("Invalid CORS origin '{}'", o)))
+            .collect::<Result<Vec<_>>>()?;
+        AllowOrigin::list(values)
+    };
+
+    let methods = list_or("CORS_ALLOWED_METHODS", &["GET", "POST", "OPTIONS"]);
+    let allow_methods = if methods.iter().any(|m| m == "*") {
+        AllowMethods::any()
+    } else {
+        let parsed = methods.iter()
+            .map(|m| Method::from_bytes(m.to_ascii_uppercase().as_bytes()).with_context(|| format!("Invalid CORS method '{}'", m)))
+            .collect::<Result<Vec<_>>>()?;
+        AllowMethods::list(parsed)
+    };
+
+    let headers = list_or("CORS_ALLOWED_HEADERS", &["authorization", "content-type"]);
+    let allow_headers = if headers.iter().any(|h| h == "*") {
+        AllowHeaders::any()
+    } else {
+        AllowHeaders::list(parse_header_names(&headers)?)
+    };
+
+    let mut layer = CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(allow_methods)
+        .allow_headers(allow_headers)
+        .allow_credentials(allow_credentials)
+        .expose_headers(ExposeHeaders::list(parse_header_names(&config::env_list("CORS_EXPOSE_HEADERS"))?));
+
+    if let Ok(raw) = std::env::var("CORS_MAX_AGE_SECS") {
+        let secs: u64 = raw.parse().context("Invalid CORS_MAX_AGE_SECS")?;
+        layer = layer.max_age(Duration::from_secs(secs));
+    }
+
+    Ok(Some(layer))
+}
+
+fn list_or(var: &str, default: &[&str]) -> Vec<String> {
+    let values = config::env_list(var);
+    if values.is_empty() {
+        default.iter().map(|s| s.to_string()).collect()
+    } else {
+        values
+    }
+}
+
+fn parse_header_names(names: &[String]) -> Result<Vec<HeaderName>> {
+    names.iter()
+        .map(|h| HeaderName::try_from(h.to_ascii_lowercase()).with_context(|| format!("Invalid CORS header '{}'", h)))
+        .collect()
+}
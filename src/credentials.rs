@@ -0,0 +1,715 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::{Context, Result};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    routing::{get, post, put},
+    Extension, Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::{info, warn};
+
+use crate::{
+    admin::Admin,
+    auth::ApiKey,
+    aws::{hmac, AwsCredentials},
+    secrets::{Secret, Secrets},
+    store::Store,
+    AppError, AppState,
+};
+
+const SEALED_PREFIX: &str = "enc:v1:";
+// Sealed into every store file, so a wrong master key fails at startup rather
+// than on the first credential it can't open.
+const CHECK_VALUE: &str = "llm-gateway credential store";
+const KEY_PREFIX: &str = "sk-gw-";
+const REFRESH: Duration = Duration::from_secs(10);
+const MAX_CONFLICTS: usize = 5; // times a change is retried on top of another instance's
+
+// --- Persisted Credentials ---
+// With CREDENTIAL_STORE set, gateway API keys and upstream credentials can be
+// created through the admin API and are kept in one JSON file. Credentials are
+// encrypted with AES-256-GCM under the master key; API keys are kept only as a
+// keyed hash, so not even the store can give them back. Admin responses carry
+// truncated hints and fingerprints, never the values. The master key is 32
+// bytes, base64-encoded, given directly or as a credential reference (env:,
+// file:, vault:), or as a data key encrypted by AWS KMS. Without `path`, the
+// same document is kept in STORE_URL's store, where other instances pick up
+// changes within REFRESH. Each change replaces the stored revision it started
+// from; if another instance got there first, it is applied again on top of
+// theirs, so concurrent admin changes are all kept.
+#[derive(Debug, Deserialize)]
+pub struct CredentialStoreConfig {
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    #[serde(default)]
+    pub master_key: Option<String>,
+    #[serde(default)]
+    pub kms: Option<KmsConfig>,
+}
+
+// A data key from `aws kms generate-data-key --key-spec AES_256`; the gateway
+// keeps only the plaintext it gets back from Decrypt, in memory.
+#[derive(Debug, Deserialize)]
+pub struct KmsConfig {
+    pub ciphertext: String, // the base64 CiphertextBlob
+    #[serde(default = "default_region")]
+    pub region: String,
+    #[serde(default)]
+    pub endpoint: Option<String>, // defaults to https://kms.<region>.amazonaws.com
+    #[serde(default)]
+    pub access_key_id: Option<String>, // falls back to AWS_ACCESS_KEY_ID
+    #[serde(default)]
+    pub secret_access_key: Option<String>, // falls back to AWS_SECRET_ACCESS_KEY
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct StoreFile {
+    check: String,
+    #[serde(default)]
+    revision: u64, // bumped by every change, so an older copy never replaces a newer one
+    #[serde(default)]
+    keys: Vec<StoredKey>,
+    #[serde(default)]
+    credentials: BTreeMap<String, StoredCredential>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredKey {
+    id: String,
+    hint: String,
+    digest: String, // hex HMAC-SHA256 of the key under the master key
+    created_at: DateTime<Utc>,
+    settings: Value, // the GATEWAY_API_KEYS fields
+    #[serde(default)]
+    revoked_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    rotated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    previous: Vec<PreviousSecret>, // replaced by rotation, still accepted until `valid_until`
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct PreviousSecret {
+    hint: String,
+    digest: String,
+    valid_until: DateTime<Utc>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredCredential {
+    value: String, // sealed
+    hint: String,
+    updated_at: DateTime<Utc>,
+}
+
+struct Contents {
+    file: StoreFile,
+    keys: HashMap<String, Arc<ApiKey>>, // by key id
+    digests: HashMap<String, (String, Option<DateTime<Utc>>)>, // -> key id, and until when for a replaced secret
+    secrets: HashMap<String, Secret>, // handed out to backends, updated in place
+}
+
+impl Contents {
+    // Replaces the file and rebuilds the key indexes from it.
+    fn update(&mut self, file: StoreFile) -> Result<()> {
+        let mut keys = HashMap::new();
+        let mut digests = HashMap::new();
+        for stored in &file.keys {
+            let key: ApiKey = serde_json::from_value(stored.settings.clone())
+                .with_context(|| format!("Invalid stored API key {}", stored.id))?;
+            keys.insert(stored.id.clone(), Arc::new(key));
+            digests.insert(stored.digest.clone(), (stored.id.clone(), None));
+            for previous in &stored.previous {
+                digests.insert(previous.digest.clone(), (stored.id.clone(), Some(previous.valid_until)));
+            }
+        }
+        (self.file, self.keys, self.digests) = (file, keys, digests);
+        Ok(())
+    }
+}
+
+pub struct CredentialStore {
+    path: Option<PathBuf>,
+    store: Arc<dyn Store>,
+    cipher: Aes256Gcm,
+    digest_key: Vec<u8>,
+    contents: Mutex<Contents>,
+    writing: tokio::sync::Mutex<()>, // one change at a time, across awaiting the store
+}
+
+impl CredentialStore {
+    pub async fn open(config: CredentialStoreConfig, secrets: &Secrets, client: &Client, shared: Arc<dyn Store>) -> Result<Arc<Self>> {
+        let encoded = match (&config.master_key, &config.kms) {
+            (Some(reference), None) => secrets.read(reference).await.context("Failed to read the CREDENTIAL_STORE master key")?,
+            (None, Some(kms)) => kms_decrypt(kms, client).await.context("Failed to decrypt the CREDENTIAL_STORE data key with KMS")?,
+            _ => anyhow::bail!("CREDENTIAL_STORE needs exactly one of `master_key` and `kms`"),
+        };
+        let master = BASE64.decode(encoded.trim()).ok().filter(|key| key.len() == 32)
+            .context("The CREDENTIAL_STORE master key must be 32 bytes, base64-encoded (e.g. `openssl rand -base64 32`)")?;
+        let store = CredentialStore {
+            path: config.path,
+            store: shared,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&master)),
+            digest_key: hmac(&master, b"api key digest"),
+            contents: Mutex::new(Contents {
+                file: StoreFile::default(),
+                keys: HashMap::new(),
+                digests: HashMap::new(),
+                secrets: HashMap::new(),
+            }),
+            writing: tokio::sync::Mutex::new(()),
+        };
+
+        let document = match &store.path {
+            Some(path) => match std::fs::read(path) {
+                Ok(bytes) => Some(bytes),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+            },
+            None => {
+                if !store.store.persistent() {
+                    warn!("CREDENTIAL_STORE has no `path` and STORE_URL is not persistent; stored keys and credentials are lost on restart");
+                }
+                store.store.load_keys().await?.map(String::into_bytes)
+            }
+        };
+        let file = match document {
+            Some(bytes) => store.parse(&bytes)?,
+            None => {
+                let file = StoreFile { check: store.seal("", CHECK_VALUE)?, ..Default::default() };
+                match store.write(&file, 0).await? {
+                    true => file,
+                    // Another instance created it first.
+                    false => {
+                        let document = store.store.load_keys().await?.context("The credential store vanished while it was created")?;
+                        store.parse(document.as_bytes())?
+                    }
+                }
+            }
+        };
+        {
+            let mut contents = store.contents.lock().unwrap();
+            for (name, credential) in &file.credentials {
+                let value = store.open_sealed(name, &credential.value).with_context(|| format!("Failed to decrypt credential '{}'", name))?;
+                contents.secrets.insert(name.clone(), Secret::new(value));
+            }
+            info!(
+                "Credential store {} ({} API keys, {} credentials)",
+                store.location(), file.keys.len(), file.credentials.len()
+            );
+            contents.update(file)?;
+        }
+        let store = Arc::new(store);
+        if store.path.is_none() && store.store.persistent() {
+            tokio::spawn(refresh(store.clone()));
+        }
+        Ok(store)
+    }
+
+    fn location(&self) -> String {
+        match &self.path {
+            Some(path) => format!("'{}'", path.display()),
+            None => format!("in the {} store", self.store.name()),
+        }
+    }
+
+    fn parse(&self, bytes: &[u8]) -> Result<StoreFile> {
+        let file: StoreFile = serde_json::from_slice(bytes).with_context(|| format!("Invalid credential store {}", self.location()))?;
+        if self.open_sealed("", &file.check).ok().as_deref() != Some(CHECK_VALUE) {
+            anyhow::bail!("The credential store {} was written with a different master key", self.location());
+        }
+        Ok(file)
+    }
+
+    // Takes a newer revision written by another instance.
+    fn apply(&self, file: StoreFile) -> Result<()> {
+        let mut opened = HashMap::new();
+        for (name, credential) in &file.credentials {
+            let value = self.open_sealed(name, &credential.value).with_context(|| format!("Failed to decrypt credential '{}'", name))?;
+            opened.insert(name.clone(), value);
+        }
+        let mut contents = self.contents.lock().unwrap();
+        if file.revision <= contents.file.revision {
+            return Ok(());
+        }
+        for (name, secret) in &contents.secrets {
+            if !opened.contains_key(name) {
+                secret.set(String::new());
+            }
+        }
+        for (name, value) in opened {
+            contents.secrets.entry(name).or_insert_with(|| Secret::new(String::new())).set(value);
+        }
+        info!("Credential store updated to revision {} by another instance", file.revision);
+        contents.update(file)
+    }
+
+    // --- Encryption ---
+    // The credential's name is bound in as associated data, so a sealed value
+    // can't be moved to another name.
+    fn seal(&self, name: &str, value: &str) -> Result<String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let sealed = self.cipher.encrypt(&nonce, Payload { msg: value.as_bytes(), aad: name.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt '{}'", name))?;
+        Ok(format!("{}{}", SEALED_PREFIX, BASE64.encode([nonce.as_slice(), &sealed].concat())))
+    }
+
+    fn open_sealed(&self, name: &str, sealed: &str) -> Result<String> {
+        let bytes = sealed.strip_prefix(SEALED_PREFIX).and_then(|encoded| BASE64.decode(encoded).ok())
+            .filter(|bytes| bytes.len() > 12)
+            .context("not a sealed value")?;
+        let (nonce, ciphertext) = bytes.split_at(12);
+        let plain = self.cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: name.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("wrong master key or corrupted value"))?;
+        Ok(String::from_utf8(plain)?)
+    }
+
+    fn digest(&self, key: &str) -> String {
+        hmac(&self.digest_key, key.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    // Replaced as a whole, so a crash mid-write leaves the previous file. False if
+    // the shared document is no longer at revision `expected`.
+    async fn write(&self, file: &StoreFile, expected: u64) -> Result<bool> {
+        let Some(path) = &self.path else {
+            return self.store.save_keys(serde_json::to_string(file)?, file.revision, expected).await;
+        };
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, serde_json::to_vec_pretty(file)?).with_context(|| format!("Failed to write '{}'", temp.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&temp, std::fs::Permissions::from_mode(0o600))?;
+        }
+        std::fs::rename(&temp, path).with_context(|| format!("Failed to replace '{}'", path.display()))?;
+        Ok(true)
+    }
+
+    // Applies `change` to a copy of the current file, writes it as the next
+    // revision, and makes it current. When another instance changed the shared
+    // document first, theirs is loaded and `change` runs again on top of it.
+    async fn save<T>(&self, mut change: impl FnMut(&Contents, &mut StoreFile) -> Result<T, AppError>) -> Result<T, AppError> {
+        let _writing = self.writing.lock().await;
+        for _ in 0..MAX_CONFLICTS {
+            let (mut file, output) = {
+                let contents = self.contents.lock().unwrap();
+                let mut file = contents.file.clone();
+                let output = change(&contents, &mut file)?;
+                (file, output)
+            };
+            let expected = file.revision;
+            file.revision += 1;
+            if self.write(&file, expected).await.map_err(internal)? {
+                let mut contents = self.contents.lock().unwrap();
+                // Unless a refresh already took a later revision.
+                if file.revision > contents.file.revision {
+                    contents.update(file).map_err(internal)?;
+                }
+                return Ok(output);
+            }
+            warn!("The credential store was changed by another instance since revision {}; applying the change again", expected);
+            let document = self.store.load_keys().await.map_err(internal)?
+                .ok_or_else(|| AppError::Internal("The credential store is missing from the store.".to_string()))?;
+            self.parse(document.as_bytes()).and_then(|file| self.apply(file)).map_err(internal)?;
+        }
+        Err(AppError::Internal("The credential store kept changing under this change; try again.".to_string()))
+    }
+
+    // --- Lookups ---
+    // Revoked and expired keys, and replaced secrets past their grace period, are
+    // refused with a message saying so. Requests already running aren't affected.
+    pub fn find_key(&self, token: &str) -> Result<Option<Arc<ApiKey>>, AppError> {
+        if !token.starts_with(KEY_PREFIX) {
+            return Ok(None);
+        }
+        let digest = self.digest(token);
+        let contents = self.contents.lock().unwrap();
+        let Some((id, valid_until)) = contents.digests.get(&digest) else { return Ok(None) };
+        if valid_until.is_some_and(|until| until <= Utc::now()) {
+            return Err(AppError::Unauthorized("This API key was replaced; use the rotated key.".to_string()));
+        }
+        usable(&contents, id).map(Some)
+    }
+
+    // For signed requests, which name their key instead of sending it.
+    pub fn key_named(&self, name: &str) -> Result<Option<Arc<ApiKey>>, AppError> {
+        let contents = self.contents.lock().unwrap();
+        let Some(id) = contents.keys.iter().find(|(_, key)| key.name == name).map(|(id, _)| id.clone()) else { return Ok(None) };
+        usable(&contents, &id).map(Some)
+    }
+
+    pub fn keys(&self) -> Vec<Arc<ApiKey>> {
+        self.contents.lock().unwrap().keys.values().cloned().collect()
+    }
+
+    // For `store:<name>` references. A credential that isn't stored yet is empty
+    // until it's PUT; requests go without it in the meantime.
+    pub fn credential(&self, name: &str) -> Secret {
+        let mut contents = self.contents.lock().unwrap();
+        if !contents.secrets.contains_key(name) {
+            warn!("Credential 'store:{}' is not in CREDENTIAL_STORE yet", name);
+        }
+        contents.secrets.entry(name.to_string()).or_insert_with(|| Secret::new(String::new())).clone()
+    }
+}
+
+// Picks up changes other instances made to the shared document.
+async fn refresh(store: Arc<CredentialStore>) {
+    let mut interval = tokio::time::interval(REFRESH);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let result = match store.store.load_keys().await {
+            Ok(Some(document)) => store.parse(document.as_bytes()).and_then(|file| store.apply(file)),
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("Failed to refresh the credential store: {:#}", e);
+        }
+    }
+}
+
+fn usable(contents: &Contents, id: &str) -> Result<Arc<ApiKey>, AppError> {
+    if contents.file.keys.iter().any(|stored| stored.id == id && stored.revoked_at.is_some()) {
+        return Err(AppError::Unauthorized("This API key was revoked.".to_string()));
+    }
+    let key = contents.keys[id].clone();
+    key.check_expiry()?;
+    Ok(key)
+}
+
+// Enough of a secret to recognize it: `sk-gw-ab...wxyz`.
+pub fn hint(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() < 16 {
+        return "...".to_string();
+    }
+    let head: String = chars[..8].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}...{}", head, tail)
+}
+
+async fn kms_decrypt(config: &KmsConfig, client: &Client) -> Result<String> {
+    let endpoint = config.endpoint.clone().unwrap_or_else(|| format!("https://kms.{}.amazonaws.com", config.region));
+    let url = Url::parse(&endpoint).with_context(|| format!("Invalid KMS endpoint '{}'", endpoint))?;
+    let body = serde_json::to_vec(&json!({ "CiphertextBlob": config.ciphertext }))?;
+    let credentials = AwsCredentials::resolve(&config.access_key_id, &config.secret_access_key, &config.region, "CREDENTIAL_STORE kms")?;
+    let headers = vec![
+        ("content-type", "application/x-amz-json-1.1".to_string()),
+        ("x-amz-target", "TrentService.Decrypt".to_string()),
+    ];
+    let mut request = client.post(url.clone());
+    for (name, value) in credentials.sign("POST", &url, "kms", &body, headers, Utc::now()) {
+        request = request.header(name, value);
+    }
+    let response = request.body(body).send().await.context("Failed to reach KMS")?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        anyhow::bail!("KMS returned {}: {}", status, text.trim());
+    }
+    let answer: Value = response.json().await?;
+    let plaintext = answer["Plaintext"].as_str().context("KMS answered without a Plaintext")?;
+    // The plaintext is the raw key; hand it on base64-encoded like `master_key`.
+    Ok(plaintext.to_string())
+}
+
+// --- Admin Routes ---
+// Merged into the admin router when CREDENTIAL_STORE is set.
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/admin/keys", get(list_keys).post(create_key))
+        .route("/admin/keys/:id", axum::routing::delete(delete_key))
+        .route("/admin/keys/:id/rotate", post(rotate_key))
+        .route("/admin/keys/:id/revoke", post(revoke_key))
+        .route("/admin/credentials", get(list_credentials))
+        .route("/admin/credentials/:name", put(put_credential).delete(delete_credential))
+}
+
+fn store(state: &AppState) -> &Arc<CredentialStore> {
+    state.credentials.as_ref().expect("credential routes are only mounted with CREDENTIAL_STORE")
+}
+
+fn internal(e: anyhow::Error) -> AppError {
+    AppError::Internal(format!("{:#}", e))
+}
+
+fn new_secret() -> String {
+    format!("{}{}{}", KEY_PREFIX, uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+// What admin responses and the audit log show of a stored key or credential.
+fn key_summary(stored: &StoredKey, key: &ApiKey) -> Value {
+    let now = Utc::now();
+    let status = if stored.revoked_at.is_some() { "revoked" } else { key.status() };
+    let previous: Vec<Value> = stored.previous.iter()
+        .filter(|previous| previous.valid_until > now)
+        .map(|previous| json!({ "hint": previous.hint, "valid_until": previous.valid_until.timestamp() }))
+        .collect();
+    json!({
+        "id": stored.id, "object": "api_key", "name": key.name, "source": "CREDENTIAL_STORE", "status": status,
+        "hint": stored.hint, "fingerprint": &stored.digest[..16], "tenant": key.tenant, "models": key.models,
+        "aliases": key.aliases, "created_at": stored.created_at.timestamp(), "expires_at": key.expires_at.map(|at| at.timestamp()),
+        "rotated_at": stored.rotated_at.map(|at| at.timestamp()), "revoked_at": stored.revoked_at.map(|at| at.timestamp()),
+        "previous": previous,
+    })
+}
+
+fn credential_summary(name: &str, credential: &StoredCredential) -> Value {
+    json!({
+        "name": name, "reference": format!("store:{}", name), "hint": credential.hint,
+        "updated_at": credential.updated_at.timestamp(),
+    })
+}
+
+#[utoipa::path(
+    post, path = "/admin/keys", tag = "Admin",
+    request_body(content = Object, description = "The key's settings, as in GATEWAY_API_KEYS: `name`, `models`, `tenant`, `expires_at`, ..."),
+    responses((status = 200, description = "The new key. `key` is only ever shown here.", body = Object)),
+)]
+async fn create_key(
+    State(state): State<Arc<AppState>>,
+    Extension(admin): Extension<Admin>,
+    Json(settings): Json<Value>,
+) -> Result<Json<Value>, AppError> {
+    let Some(api_keys) = &state.api_keys else {
+        return Err(AppError::InvalidRequest(
+            "GATEWAY_API_KEYS is not set, so /v1 is unauthenticated; set it (to {} for stored keys only) first.".to_string(),
+        ));
+    };
+    let key: ApiKey = serde_json::from_value(settings.clone())
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid key settings: {}", e)))?;
+    // The store keeps settings in the clear.
+    if key.signing.is_some() {
+        return Err(AppError::InvalidRequest("Signing secrets can only be set in GATEWAY_API_KEYS.".to_string()));
+    }
+    if let Some(tenant) = key.tenant.as_ref().filter(|tenant| state.tenants.get(tenant).is_none()) {
+        return Err(AppError::InvalidRequest(format!("Unknown tenant '{}'.", tenant)));
+    }
+    let duplicate = || AppError::InvalidRequest(format!("A key named '{}' already exists.", key.name));
+    if api_keys.configured().iter().any(|(_, configured)| configured.name == key.name) {
+        return Err(duplicate());
+    }
+
+    let store = store(&state);
+    let secret = new_secret();
+    let stored = StoredKey {
+        id: format!("key_{}", uuid::Uuid::new_v4().simple()),
+        hint: hint(&secret),
+        digest: store.digest(&secret),
+        created_at: Utc::now(),
+        settings,
+        revoked_at: None,
+        rotated_at: None,
+        previous: Vec::new(),
+    };
+    // Checked again on every attempt, against the keys as the store has them then.
+    store.save(|contents, file| {
+        if contents.keys.values().any(|existing| existing.name == key.name) {
+            return Err(duplicate());
+        }
+        file.keys.push(stored.clone());
+        Ok(())
+    }).await?;
+    let mut summary = key_summary(&stored, &key);
+    state.audit.record(&admin, "key.create", &stored.id, Value::Null, summary.clone()).await;
+    summary["key"] = json!(secret);
+    Ok(Json(summary))
+}
+
+#[utoipa::path(get, path = "/admin/keys", tag = "Admin", responses((status = 200, description = "Stored and configured keys, as hints and fingerprints.", body = Object)))]
+async fn list_keys(State(state): State<Arc<AppState>>) -> Json<Value> {
+    let store = store(&state);
+    let mut data: Vec<Value> = state.api_keys.iter().flat_map(|keys| keys.configured())
+        .map(|(hint, key)| json!({
+            "id": null, "object": "api_key", "name": key.name, "source": "GATEWAY_API_KEYS", "status": key.status(),
+            "hint": hint, "tenant": key.tenant, "models": key.models, "aliases": key.aliases, "expires_at": key.expires_at.map(|at| at.timestamp()),
+        }))
+        .collect();
+    let contents = store.contents.lock().unwrap();
+    data.extend(contents.file.keys.iter().map(|stored| key_summary(stored, &contents.keys[&stored.id])));
+    Json(json!({ "object": "list", "data": data }))
+}
+
+// Applies `change` to the stored key and saves it; returns its summary before
+// and after.
+async fn change_key(store: &CredentialStore, id: &str, mut change: impl FnMut(&mut StoredKey) -> Result<(), AppError>) -> Result<(Value, Value), AppError> {
+    let not_found = || AppError::NotFound(format!("No such key: '{}'.", id));
+    let (before, after) = store.save(|contents, file| {
+        let stored = file.keys.iter_mut().find(|stored| stored.id == id).ok_or_else(not_found)?;
+        let before = key_summary(stored, &contents.keys[id]);
+        change(stored)?;
+        Ok((before, stored.clone()))
+    }).await?;
+    let contents = store.contents.lock().unwrap();
+    let key = contents.keys.get(id).ok_or_else(not_found)?;
+    Ok((before, key_summary(&after, key)))
+}
+
+#[derive(Debug, Deserialize)]
+struct RotateRequest {
+    #[serde(default = "default_grace")]
+    grace_secs: i64, // how long the replaced secret keeps working
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>, // a new expiry; the current one is kept otherwise
+}
+
+fn default_grace() -> i64 {
+    86_400
+}
+
+#[utoipa::path(
+    post, path = "/admin/keys/{id}/rotate", tag = "Admin", params(("id" = String, Path)),
+    request_body(content = Object, description = "Optional `grace_secs` (default 86400) and a new `expires_at`."),
+    responses((status = 200, description = "The key with its new secret, shown only here. Its id, name, and usage history stay the same.", body = Object)),
+)]
+async fn rotate_key(
+    State(state): State<Arc<AppState>>,
+    Extension(admin): Extension<Admin>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<Json<Value>, AppError> {
+    // The body is optional, but one that is there must be valid.
+    let request = match body.trim_ascii().is_empty() {
+        true => RotateRequest { grace_secs: default_grace(), expires_at: None },
+        false => serde_json::from_slice(&body)
+            .map_err(|e| AppError::InvalidRequest(format!("Invalid rotation request: {}", e)))?,
+    };
+    let store = store(&state);
+    let secret = new_secret();
+    let now = Utc::now();
+    let (before, mut after) = change_key(store, &id, |stored| {
+        if stored.revoked_at.is_some() {
+            return Err(AppError::InvalidRequest("A revoked key can't be rotated.".to_string()));
+        }
+        stored.previous.retain(|previous| previous.valid_until > now);
+        stored.previous.push(PreviousSecret {
+            hint: stored.hint.clone(),
+            digest: stored.digest.clone(),
+            valid_until: now + chrono::Duration::seconds(request.grace_secs.max(0)),
+        });
+        stored.hint = hint(&secret);
+        stored.digest = store.digest(&secret);
+        stored.rotated_at = Some(now);
+        if let Some(expires_at) = request.expires_at {
+            stored.settings["expires_at"] = json!(expires_at);
+        }
+        Ok(())
+    }).await?;
+    state.audit.record(&admin, "key.rotate", &id, before, after.clone()).await;
+    after["key"] = json!(secret);
+    Ok(Json(after))
+}
+
+#[utoipa::path(
+    post, path = "/admin/keys/{id}/revoke", tag = "Admin", params(("id" = String, Path)),
+    responses((status = 200, description = "The key now refuses new requests; requests already running finish.", body = Object)),
+)]
+async fn revoke_key(
+    State(state): State<Arc<AppState>>,
+    Extension(admin): Extension<Admin>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let (before, after) = change_key(store(&state), &id, |stored| {
+        stored.revoked_at.get_or_insert_with(Utc::now);
+        Ok(())
+    }).await?;
+    state.audit.record(&admin, "key.revoke", &id, before, after.clone()).await;
+    Ok(Json(after))
+}
+
+#[utoipa::path(delete, path = "/admin/keys/{id}", tag = "Admin", params(("id" = String, Path)), responses((status = 200, description = "The key was deleted.", body = Object)))]
+async fn delete_key(
+    State(state): State<Arc<AppState>>,
+    Extension(admin): Extension<Admin>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let before = store(&state).save(|contents, file| {
+        let Some(index) = file.keys.iter().position(|key| key.id == id) else {
+            return Err(AppError::NotFound(format!("No such key: '{}'.", id)));
+        };
+        let removed = file.keys.remove(index);
+        Ok(key_summary(&removed, &contents.keys[&id]))
+    }).await?;
+    state.audit.record(&admin, "key.delete", &id, before, Value::Null).await;
+    Ok(Json(json!({ "id": id, "object": "api_key", "deleted": true })))
+}
+
+#[utoipa::path(get, path = "/admin/credentials", tag = "Admin", responses((status = 200, description = "Stored upstream credentials, as hints.", body = Object)))]
+async fn list_credentials(State(state): State<Arc<AppState>>) -> Json<Value> {
+    let contents = store(&state).contents.lock().unwrap();
+    let data: Vec<Value> = contents.file.credentials.iter()
+        .map(|(name, credential)| credential_summary(name, credential))
+        .collect();
+    Json(json!({ "object": "list", "data": data }))
+}
+
+#[derive(Debug, Deserialize)]
+struct CredentialValue {
+    value: String,
+}
+
+#[utoipa::path(
+    put, path = "/admin/credentials/{name}", tag = "Admin", params(("name" = String, Path)),
+    request_body(content = Object, description = "`{\"value\": ...}`"),
+    responses((status = 200, description = "The credential was stored; backends referencing it use it from the next request.", body = Object)),
+)]
+async fn put_credential(
+    State(state): State<Arc<AppState>>,
+    Extension(admin): Extension<Admin>,
+    Path(name): Path<String>,
+    Json(body): Json<CredentialValue>,
+) -> Result<Json<Value>, AppError> {
+    if body.value.is_empty() {
+        return Err(AppError::InvalidRequest("'value' must not be empty.".to_string()));
+    }
+    let store = store(&state);
+    let credential = StoredCredential {
+        value: store.seal(&name, &body.value).map_err(internal)?,
+        hint: hint(&body.value),
+        updated_at: Utc::now(),
+    };
+    let before = store.save(|_, file| Ok(file.credentials.insert(name.clone(), credential.clone()))).await?;
+    store.contents.lock().unwrap().secrets.entry(name.clone()).or_insert_with(|| Secret::new(String::new())).set(body.value);
+    let after = credential_summary(&name, &credential);
+    let before = before.map_or(Value::Null, |before| credential_summary(&name, &before));
+    state.audit.record(&admin, "credential.put", &name, before, after.clone()).await;
+    Ok(Json(after))
+}
+
+#[utoipa::path(delete, path = "/admin/credentials/{name}", tag = "Admin", params(("name" = String, Path)), responses((status = 200, description = "The credential was deleted.", body = Object)))]
+async fn delete_credential(
+    State(state): State<Arc<AppState>>,
+    Extension(admin): Extension<Admin>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let store = store(&state);
+    let removed = store.save(|_, file| {
+        file.credentials.remove(&name).ok_or_else(|| AppError::NotFound(format!("No such credential: '{}'.", name)))
+    }).await?;
+    // Backends still referencing it go without a key from now on.
+    if let Some(secret) = store.contents.lock().unwrap().secrets.get(&name) {
+        secret.set(String::new());
+    }
+    state.audit.record(&admin, "credential.delete", &name, credential_summary(&name, &removed), Value::Null).await;
+    Ok(Json(json!({ "name": name, "deleted": true })))
+}
@@ -0,0 +1,368 @@
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use hickory_resolver::TokioResolver;
+use reqwest::{Certificate, Client, RequestBuilder, Url};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Weak},
+    time::Duration,
+};
+use tracing::{info, warn};
+
+use crate::backend::{Backends, ReplicaSet};
+
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+// Watches are re-established (with a fresh list) at least this often.
+const WATCH_TIMEOUT_SECS: u64 = 300;
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+// --- Backend Discovery ---
+// A backend's `discovery` finds replicas at runtime. They are added to its static
+// `url` and `replicas` (both optional when discovery is set), and the set is
+// swapped in place whenever it changes. Discovery for a backend stops once the
+// backend registry has replaced it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscoveryConfig {
+    Kubernetes(KubernetesDiscovery),
+    Dns(DnsDiscovery),
+}
+
+// Either `service` (its EndpointSlices, ready endpoints only) or `selector` (pods
+// matching the label selector that are Running and Ready).
+#[derive(Debug, Clone, Deserialize)]
+pub struct KubernetesDiscovery {
+    #[serde(default)]
+    pub namespace: Option<String>, // the gateway's own namespace when omitted
+    #[serde(default)]
+    pub service: Option<String>,
+    #[serde(default)]
+    pub selector: Option<String>, // e.g. "app=vllm,model=llama-3-8b"
+    #[serde(default)]
+    pub port: Option<PortRef>, // defaults: the service's first port, or 8000 for pods
+    #[serde(default = "default_scheme")]
+    pub scheme: String,
+}
+
+fn default_scheme() -> String {
+    "http".to_string()
+}
+
+// Re-resolved every `interval_secs` (record TTLs permitting); each address is a
+// replica. With `srv`, the SRV records' targets and ports are used instead of
+// A/AAAA records for `name`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DnsDiscovery {
+    pub name: String,
+    #[serde(default)]
+    pub srv: bool,
+    #[serde(default)]
+    pub port: Option<u16>, // required without `srv`
+    #[serde(default = "default_dns_interval")]
+    pub interval_secs: u64,
+    #[serde(default = "default_scheme")]
+    pub scheme: String,
+}
+
+fn default_dns_interval() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum PortRef {
+    Number(u16),
+    Name(String),
+}
+
+pub fn start(backends: &Backends) -> Result<()> {
+    let mut kube = None;
+    for (model, backend) in backends {
+        let fixed = backend.config.static_replicas();
+        match &backend.config.discovery {
+            None => {}
+            Some(DiscoveryConfig::Kubernetes(config)) => {
+                if config.service.is_some() == config.selector.is_some() {
+                    anyhow::bail!("Kubernetes discovery for model '{}' needs exactly one of `service` and `selector`", model);
+                }
+                let api = match &kube {
+                    Some(api) => Arc::clone(api),
+                    None => kube.insert(Arc::new(KubeApi::from_env()?)).clone(),
+                };
+                let namespace = config.namespace.clone().unwrap_or_else(|| api.namespace.clone());
+                info!(
+                    "Discovering replicas for model '{}' from Kubernetes {} in namespace '{}'",
+                    model,
+                    match (&config.service, &config.selector) {
+                        (Some(service), _) => format!("service '{}'", service),
+                        (_, selector) => format!("pods matching '{}'", selector.as_deref().unwrap_or_default()),
+                    },
+                    namespace
+                );
+                let watch = KubeWatch {
+                    api,
+                    model: model.clone(),
+                    namespace,
+                    config: config.clone(),
+                    fixed,
+                    replicas: Arc::downgrade(&backend.replicas),
+                };
+                tokio::spawn(watch.run());
+            }
+            Some(DiscoveryConfig::Dns(config)) => {
+                if !config.srv && config.port.is_none() {
+                    anyhow::bail!("DNS discovery for model '{}' needs a `port` (or `srv: true`)", model);
+                }
+                let resolver = TokioResolver::builder_tokio()
+                    .context("Failed to read the system DNS configuration")?
+                    .build();
+                info!(
+                    "Discovering replicas for model '{}' from DNS {} records for '{}' every {}s",
+                    model, if config.srv { "SRV" } else { "A/AAAA" }, config.name, config.interval_secs
+                );
+                tokio::spawn(watch_dns(resolver, model.clone(), config.clone(), fixed, Arc::downgrade(&backend.replicas)));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Static replicas first, then discovered ones in a stable order. Returns false
+// once the backend is gone.
+fn publish(model: &str, source: &str, fixed: &[String], found: BTreeSet<String>, replicas: &Weak<ReplicaSet>) -> bool {
+    let Some(replicas) = replicas.upgrade() else { return false };
+    let mut urls = fixed.to_vec();
+    urls.extend(found.into_iter().filter(|url| !fixed.contains(url)));
+    let count = urls.len();
+    if replicas.set(urls) {
+        info!("Model '{}' now has {} replica(s) ({})", model, count, source);
+    }
+    true
+}
+
+// --- DNS ---
+async fn watch_dns(resolver: TokioResolver, model: String, config: DnsDiscovery, fixed: Vec<String>, replicas: Weak<ReplicaSet>) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(config.interval_secs.max(1)));
+    while replicas.strong_count() > 0 {
+        ticker.tick().await;
+        // A failed lookup keeps the previous replicas rather than emptying the pool.
+        match resolve(&resolver, &config).await {
+            Ok(addresses) => {
+                let found = addresses.into_iter().map(|address| format!("{}://{}", config.scheme, address)).collect();
+                if !publish(&model, "DNS", &fixed, found, &replicas) {
+                    return;
+                }
+            }
+            Err(e) => warn!("DNS discovery for model '{}' failed: {:#}", model, e),
+        }
+    }
+}
+
+async fn resolve(resolver: &TokioResolver, config: &DnsDiscovery) -> Result<Vec<SocketAddr>> {
+    if !config.srv {
+        let port = config.port.unwrap_or_default();
+        let ips = resolver.lookup_ip(config.name.as_str()).await?;
+        return Ok(ips.iter().map(|ip| SocketAddr::new(ip, port)).collect());
+    }
+    let records = resolver.srv_lookup(config.name.as_str()).await?;
+    let mut addresses = Vec::new();
+    for record in records.iter() {
+        let ips = resolver.lookup_ip(record.target().clone()).await
+            .with_context(|| format!("Failed to resolve SRV target {}", record.target()))?;
+        addresses.extend(ips.iter().map(|ip| SocketAddr::new(ip, record.port())));
+    }
+    Ok(addresses)
+}
+
+// --- Kubernetes API Client ---
+// In-cluster by default (service account token and CA). KUBERNETES_API_URL points
+// it elsewhere, e.g. at `kubectl proxy`, with an optional KUBERNETES_TOKEN.
+struct KubeApi {
+    client: Client,
+    base: Url,
+    token_file: Option<PathBuf>, // re-read per request, since projected tokens rotate
+    token: Option<String>,
+    namespace: String,
+}
+
+impl KubeApi {
+    fn from_env() -> Result<Self> {
+        let account = PathBuf::from(SERVICE_ACCOUNT_DIR);
+        let namespace = std::fs::read_to_string(account.join("namespace"))
+            .map(|ns| ns.trim().to_string())
+            .unwrap_or_else(|_| "default".to_string());
+        if let Ok(url) = std::env::var("KUBERNETES_API_URL") {
+            return Ok(KubeApi {
+                client: Client::new(),
+                base: Url::parse(&url).with_context(|| format!("Invalid KUBERNETES_API_URL '{}'", url))?,
+                token_file: None,
+                token: std::env::var("KUBERNETES_TOKEN").ok(),
+                namespace,
+            });
+        }
+
+        let host = std::env::var("KUBERNETES_SERVICE_HOST")
+            .context("Kubernetes discovery needs to run in a pod (KUBERNETES_SERVICE_HOST) or KUBERNETES_API_URL")?;
+        let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_string());
+        let host = if host.contains(':') { format!("[{}]", host) } else { host };
+        let ca = std::fs::read(account.join("ca.crt")).context("Failed to read the service account CA certificate")?;
+        let client = Client::builder()
+            .add_root_certificate(Certificate::from_pem(&ca).context("Invalid service account CA certificate")?)
+            .build()?;
+        Ok(KubeApi {
+            client,
+            base: Url::parse(&format!("https://{}:{}", host, port))?,
+            token_file: Some(account.join("token")),
+            token: None,
+            namespace,
+        })
+    }
+
+    fn get(&self, path: &str, query: &[(&str, &str)]) -> RequestBuilder {
+        let mut url = self.base.clone();
+        url.set_path(path);
+        url.query_pairs_mut().extend_pairs(query);
+        let token = match &self.token_file {
+            Some(file) => std::fs::read_to_string(file).ok().map(|t| t.trim().to_string()),
+            None => self.token.clone(),
+        };
+        let request = self.client.get(url);
+        match token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+// --- Kubernetes Watch ---
+// Lists the objects, then follows a watch until it times out or fails, then
+// lists again. Each object (an EndpointSlice or a pod) contributes its own URLs.
+struct KubeWatch {
+    api: Arc<KubeApi>,
+    model: String,
+    namespace: String,
+    config: KubernetesDiscovery,
+    fixed: Vec<String>,
+    replicas: Weak<ReplicaSet>,
+}
+
+impl KubeWatch {
+    async fn run(self) {
+        while self.replicas.strong_count() > 0 {
+            if let Err(e) = self.list_and_watch().await {
+                warn!("Kubernetes discovery for model '{}' failed: {:#}; retrying", self.model, e);
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+
+    async fn list_and_watch(&self) -> Result<()> {
+        let (path, selector) = match &self.config.service {
+            Some(service) => (
+                format!("/apis/discovery.k8s.io/v1/namespaces/{}/endpointslices", self.namespace),
+                format!("kubernetes.io/service-name={}", service),
+            ),
+            None => (
+                format!("/api/v1/namespaces/{}/pods", self.namespace),
+                self.config.selector.clone().unwrap_or_default(),
+            ),
+        };
+
+        let list: Value = self.api.get(&path, &[("labelSelector", &selector)])
+            .send().await?.error_for_status()?.json().await?;
+        let mut objects: BTreeMap<String, Vec<String>> = list["items"].as_array().into_iter().flatten()
+            .map(|item| (object_name(item), self.urls(item)))
+            .collect();
+        if !self.publish(&objects) {
+            return Ok(());
+        }
+
+        let version = list["metadata"]["resourceVersion"].as_str().unwrap_or_default().to_string();
+        let timeout = WATCH_TIMEOUT_SECS.to_string();
+        let response = self.api.get(&path, &[
+            ("labelSelector", &selector),
+            ("watch", "1"),
+            ("resourceVersion", &version),
+            ("timeoutSeconds", &timeout),
+        ]).send().await?.error_for_status()?;
+
+        let mut body = response.bytes_stream();
+        let mut buffer = Vec::new();
+        while let Some(bytes) = body.next().await {
+            buffer.extend_from_slice(&bytes?);
+            while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let Ok(event) = serde_json::from_slice::<Value>(&line) else { continue };
+                let object = &event["object"];
+                match event["type"].as_str() {
+                    Some("ADDED" | "MODIFIED") => {
+                        objects.insert(object_name(object), self.urls(object));
+                    }
+                    Some("DELETED") => {
+                        objects.remove(&object_name(object));
+                    }
+                    // Typically 410 Gone: the version is too old, so list again.
+                    Some("ERROR") => anyhow::bail!("watch error: {}", object["message"].as_str().unwrap_or("unknown")),
+                    _ => continue,
+                }
+                if !self.publish(&objects) {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn publish(&self, objects: &BTreeMap<String, Vec<String>>) -> bool {
+        let found = objects.values().flatten().cloned().collect();
+        publish(&self.model, "Kubernetes", &self.fixed, found, &self.replicas)
+    }
+
+    fn urls(&self, object: &Value) -> Vec<String> {
+        let url = |ip: &str, port: u64| {
+            let host = if ip.contains(':') { format!("[{}]", ip) } else { ip.to_string() };
+            format!("{}://{}:{}", self.config.scheme, host, port)
+        };
+        if self.config.service.is_some() {
+            // EndpointSlice: pick the port, then every ready endpoint's addresses.
+            let ports = object["ports"].as_array().cloned().unwrap_or_default();
+            let port = match &self.config.port {
+                Some(PortRef::Number(number)) => Some(*number as u64),
+                Some(PortRef::Name(name)) => ports.iter().find(|p| p["name"] == name.as_str()).and_then(|p| p["port"].as_u64()),
+                None => ports.first().and_then(|p| p["port"].as_u64()),
+            };
+            let Some(port) = port else { return Vec::new() };
+            object["endpoints"].as_array().into_iter().flatten()
+                .filter(|endpoint| endpoint["conditions"]["ready"].as_bool() != Some(false))
+                .flat_map(|endpoint| endpoint["addresses"].as_array().cloned().unwrap_or_default())
+                .filter_map(|address| address.as_str().map(|ip| url(ip, port)))
+                .collect()
+        } else {
+            // Pod: only once it is running, ready, and not terminating.
+            let status = &object["status"];
+            let ready = status["conditions"].as_array().into_iter().flatten()
+                .any(|c| c["type"] == "Ready" && c["status"] == "True");
+            let Some(ip) = status["podIP"].as_str() else { return Vec::new() };
+            if status["phase"] != "Running" || !ready || !object["metadata"]["deletionTimestamp"].is_null() {
+                return Vec::new();
+            }
+            let port = match &self.config.port {
+                Some(PortRef::Number(number)) => Some(*number as u64),
+                Some(PortRef::Name(name)) => object["spec"]["containers"].as_array().into_iter().flatten()
+                    .flat_map(|c| c["ports"].as_array().cloned().unwrap_or_default())
+                    .find(|p| p["name"] == name.as_str())
+                    .and_then(|p| p["containerPort"].as_u64()),
+                None => Some(8000),
+            };
+            port.map(|port| vec![url(ip, port)]).unwrap_or_default()
+        }
+    }
+}
+
+fn object_name(object: &Value) -> String {
+    object["metadata"]["name"].as_str().unwrap_or_default().to_string()
+}
@@ -0,0 +1,123 @@
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+use tracing::info;
+
+use crate::{admin::Admin, fleet::FleetEvent, AppError, AppState};
+
+// --- Drain Mode ---
+// POST /admin/backends/{id}/drain stops routing new requests to a replica while
+// its in-flight streams finish, so a GPU node can be taken down without cutting
+// anyone off; POST /admin/backends/{id}/undrain puts it back. `{id}` is a replica
+// URL or its `host:port`, which drains every model served from that node. GET
+// /admin/backends/{id}/drain reports how many requests are still running there.
+// Drained replicas are skipped by balancing, sticky sessions, hedging, and stream
+// resumes; only an explicit X-Gateway-Backend pin still reaches them. A model
+// whose replicas are all drained answers 503. Drains are kept in memory and
+// shared with other instances over FLEET_REDIS_URL.
+#[derive(Default)]
+pub struct Drains {
+    urls: Mutex<BTreeSet<String>>,
+}
+
+impl Drains {
+    pub fn contains(&self, url: &str) -> bool {
+        self.urls.lock().unwrap().contains(url)
+    }
+
+    // Applies a drain or undrain, as from another instance.
+    pub fn set(&self, urls: &[String], drained: bool) {
+        let mut set = self.urls.lock().unwrap();
+        for url in urls {
+            match drained {
+                true => set.insert(url.clone()),
+                false => set.remove(url),
+            };
+        }
+    }
+}
+
+// The replica URLs `id` names, across every model.
+fn resolve(state: &AppState, id: &str) -> Result<Vec<String>, AppError> {
+    let id = id.trim_end_matches('/');
+    let mut urls = BTreeSet::new();
+    for backend in state.vllm_backends.snapshot().values() {
+        for url in backend.replicas.get().iter() {
+            let authority = reqwest::Url::parse(url).ok()
+                .and_then(|parsed| Some(format!("{}:{}", parsed.host_str()?, parsed.port_or_known_default()?)));
+            if url.trim_end_matches('/') == id || authority.as_deref() == Some(id) {
+                urls.insert(url.clone());
+            }
+        }
+    }
+    if urls.is_empty() {
+        return Err(AppError::NotFound(format!("No backend replica matches '{}'.", id)));
+    }
+    Ok(urls.into_iter().collect())
+}
+
+fn status(state: &AppState, id: &str, urls: &[String]) -> Json<Value> {
+    let replicas: Vec<Value> = urls.iter()
+        .map(|url| json!({ "url": url, "drained": state.drains.contains(url), "in_flight": state.active.on_backend(url) }))
+        .collect();
+    Json(json!({ "id": id, "object": "backend.drain", "replicas": replicas }))
+}
+
+async fn change(state: &AppState, admin: &Admin, id: &str, drained: bool) -> Result<Json<Value>, AppError> {
+    let urls = resolve(state, id)?;
+    let before = status(state, id, &urls).0;
+    state.drains.set(&urls, drained);
+    if let Some(fleet) = &state.fleet {
+        fleet.publish(FleetEvent::Drain { urls: urls.clone(), drained });
+    }
+    info!("Admin '{}' {} {}", admin.name, if drained { "drained" } else { "undrained" }, urls.join(", "));
+    let after = status(state, id, &urls);
+    let action = if drained { "backend.drain" } else { "backend.undrain" };
+    state.audit.record(admin, action, id, before, after.0.clone()).await;
+    Ok(after)
+}
+
+// GET /admin/backends/:id/drain
+#[utoipa::path(
+    get, path = "/admin/backends/{id}/drain", tag = "Admin",
+    params(("id" = String, Path, description = "A replica URL or its `host:port`.")),
+    responses((status = 200, description = "Whether each matching replica is drained, and its requests in flight.", body = Object)),
+)]
+pub async fn get(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Result<Json<Value>, AppError> {
+    let urls = resolve(&state, &id)?;
+    Ok(status(&state, &id, &urls))
+}
+
+// POST /admin/backends/:id/drain
+#[utoipa::path(
+    post, path = "/admin/backends/{id}/drain", tag = "Admin",
+    params(("id" = String, Path, description = "A replica URL or its `host:port`.")),
+    responses((status = 200, description = "The replicas take no new requests; `in_flight` counts those still running.", body = Object)),
+)]
+pub async fn drain(
+    State(state): State<Arc<AppState>>,
+    Extension(admin): Extension<Admin>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    change(&state, &admin, &id, true).await
+}
+
+// POST /admin/backends/:id/undrain
+#[utoipa::path(
+    post, path = "/admin/backends/{id}/undrain", tag = "Admin",
+    params(("id" = String, Path, description = "A replica URL or its `host:port`.")),
+    responses((status = 200, description = "The replicas take new requests again.", body = Object)),
+)]
+pub async fn undrain(
+    State(state): State<Arc<AppState>>,
+    Extension(admin): Extension<Admin>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    change(&state, &admin, &id, false).await
+}
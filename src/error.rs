@@ -0,0 +1,216 @@
+use axum::{
+    extract::Json,
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+use tracing::{error, info};
+
+use crate::models::Capability;
+
+const SHOULD_RETRY: &str = "x-should-retry";
+
+// An error with its causes, such as "error sending request: client error
+// (Connect): tcp connect error: Network is unreachable", rather than only the
+// outermost one.
+pub fn chain(e: &dyn std::error::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        let text = cause.to_string();
+        if !message.ends_with(&text) {
+            message = format!("{}: {}", message, text);
+        }
+        source = cause.source();
+    }
+    message
+}
+
+// --- Custom Error Type ---
+pub enum AppError {
+    ModelNotFound(String),
+    BackendRequestFailed(reqwest::Error),
+    BackendConnectFailed { url: String, error: String }, // outside reqwest (WebSocket backends)
+    BackendRespondedError { status: StatusCode, text: String, url: String, retry_after: Option<HeaderValue> },
+    NoBackendAvailable(String),
+    UpstreamTimeout { url: String, message: String },
+    QuotaExceeded { message: String, resets_soon: bool },
+    Overloaded { message: String, retry_after_secs: u64 },
+    ContextLengthExceeded { model: String, context_length: usize, prompt_tokens: usize, completion_tokens: usize },
+    Unauthorized(String),
+    ModelAccessDenied(String),
+    Forbidden(String),
+    InvalidRequest(String),
+    NotAcceptable(String), // the Accept header rules out the response format
+    MethodNotAllowed(String),
+    StreamFailed(String), // a buffered completion's stream ended in a gateway error
+    UnsupportedCapability { model: String, missing: Vec<Capability> },
+    IdempotencyKeyReused(String),
+    RequestNotFound(String),
+    NotFound(String),
+    Internal(String),
+    PolicyViolation { guardrail: String, reason: String },
+    GuardrailUnavailable { guardrail: String, error: String },
+    #[cfg_attr(not(feature = "wasm-plugins"), allow(dead_code))]
+    PluginRejected { plugin: String, message: String },
+    #[cfg_attr(not(feature = "wasm-plugins"), allow(dead_code))]
+    PluginFailed { plugin: String, error: String },
+}
+
+// Implement IntoResponse to convert AppError into an HTTP response. Bodies follow
+// OpenAI's error envelope so SDKs surface `message` and `code` as usual.
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let retry_after = match &self {
+            AppError::Overloaded { retry_after_secs, .. } => Some(HeaderValue::from(*retry_after_secs)),
+            AppError::BackendRespondedError { retry_after, .. } => retry_after.clone(),
+            _ => None,
+        };
+        let should_retry = self.should_retry();
+        let (status, error_type, code, error_message) = match self {
+            AppError::ModelNotFound(model) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                Some("model_not_found"),
+                format!("Model '{}' not found in gateway configuration.", model),
+            ),
+            AppError::BackendRequestFailed(e) => {
+                let e = chain(&e);
+                error!("Request to backend failed: {}", e);
+                (StatusCode::BAD_GATEWAY, "api_error", None, format!("Upstream request failed: {}", e))
+            }
+            AppError::BackendConnectFailed { url, error } => {
+                error!("Connection to backend at {} failed: {}", url, error);
+                (StatusCode::BAD_GATEWAY, "api_error", None, format!("Upstream request failed: {}", error))
+            }
+            AppError::BackendRespondedError { status, text, url, .. } => {
+                error!("Backend at {} returned error {}: {}", url, status, text);
+                (status, "api_error", None, format!("Upstream service error: {}", text))
+            }
+            AppError::NoBackendAvailable(model) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "api_error",
+                Some("no_backend_available"),
+                format!("No backend is currently available for model '{}'.", model),
+            ),
+            AppError::UpstreamTimeout { url, message } => {
+                error!("Backend at {} timed out: {}", url, message);
+                (StatusCode::GATEWAY_TIMEOUT, "api_error", Some("upstream_timeout"), message)
+            }
+            AppError::Overloaded { message, .. } => (StatusCode::SERVICE_UNAVAILABLE, "api_error", Some("overloaded"), message),
+            AppError::QuotaExceeded { message, .. } => (StatusCode::TOO_MANY_REQUESTS, "rate_limit_error", Some("quota_exceeded"), message),
+            AppError::ContextLengthExceeded { model, context_length, prompt_tokens, completion_tokens } => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                Some("context_length_exceeded"),
+                format!(
+                    "Model '{}' has a maximum context length of {} tokens, but {} tokens were requested ({} in the messages, {} in the completion).",
+                    model, context_length, prompt_tokens + completion_tokens, prompt_tokens, completion_tokens
+                ),
+            ),
+            AppError::Unauthorized(message) => (StatusCode::UNAUTHORIZED, "authentication_error", Some("invalid_api_key"), message),
+            // Deliberately worded the same whether or not the model exists.
+            AppError::ModelAccessDenied(model) => (
+                StatusCode::FORBIDDEN,
+                "invalid_request_error",
+                Some("model_not_found"),
+                format!("The model '{}' does not exist or you do not have access to it.", model),
+            ),
+            AppError::Forbidden(message) => (StatusCode::FORBIDDEN, "permission_error", None, message),
+            AppError::InvalidRequest(message) => (StatusCode::BAD_REQUEST, "invalid_request_error", None, message),
+            AppError::UnsupportedCapability { model, missing } => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                Some("unsupported_capability"),
+                format!(
+                    "Model '{}' does not support {} used by this request.",
+                    model,
+                    missing.iter().map(|c| match c {
+                        Capability::Tools => "tools",
+                        Capability::Vision => "image inputs",
+                    }).collect::<Vec<_>>().join(" or ")
+                ),
+            ),
+            AppError::IdempotencyKeyReused(key) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_request_error",
+                Some("idempotency_key_reused"),
+                format!("Idempotency-Key '{}' was already used with a different request body.", key),
+            ),
+            AppError::RequestNotFound(id) => (
+                StatusCode::NOT_FOUND,
+                "invalid_request_error",
+                Some("request_not_found"),
+                format!("No request '{}' is in flight.", id),
+            ),
+            AppError::NotFound(message) => (StatusCode::NOT_FOUND, "invalid_request_error", None, message),
+            AppError::NotAcceptable(message) => (StatusCode::NOT_ACCEPTABLE, "invalid_request_error", None, message),
+            AppError::MethodNotAllowed(message) => (StatusCode::METHOD_NOT_ALLOWED, "invalid_request_error", Some("method_not_allowed"), message),
+            AppError::StreamFailed(message) => (StatusCode::BAD_GATEWAY, "api_error", None, message),
+            AppError::Internal(message) => {
+                error!("Internal error: {}", message);
+                (StatusCode::INTERNAL_SERVER_ERROR, "api_error", None, "The gateway hit an internal error.".to_string())
+            }
+            AppError::PolicyViolation { guardrail, reason } => {
+                info!("Request blocked by guardrail '{}': {}", guardrail, reason);
+                (
+                    StatusCode::BAD_REQUEST,
+                    "invalid_request_error",
+                    Some("content_policy_violation"),
+                    format!("Request rejected by content policy ({}).", reason),
+                )
+            }
+            AppError::GuardrailUnavailable { guardrail, error } => {
+                error!("Guardrail '{}' unavailable: {}", guardrail, error);
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "api_error",
+                    None,
+                    format!("Content policy check '{}' is unavailable.", guardrail),
+                )
+            }
+            AppError::PluginRejected { plugin, message } => {
+                info!("Request rejected by plugin '{}': {}", plugin, message);
+                (StatusCode::BAD_REQUEST, "invalid_request_error", None, message)
+            }
+            AppError::PluginFailed { plugin, error } => {
+                error!("Plugin '{}' failed: {}", plugin, error);
+                (StatusCode::INTERNAL_SERVER_ERROR, "api_error", None, format!("Gateway plugin '{}' failed.", plugin))
+            }
+        };
+
+        let body = Json(json!({
+            "error": { "message": error_message, "type": error_type, "code": code }
+        }));
+        let mut response = (status, body).into_response();
+        if let Some(retry_after) = retry_after {
+            response.headers_mut().insert(RETRY_AFTER, retry_after);
+        }
+        response.headers_mut().insert(SHOULD_RETRY, HeaderValue::from_static(if should_retry { "true" } else { "false" }));
+        response
+    }
+}
+
+impl AppError {
+    // Sent as X-Should-Retry, which OpenAI SDKs obey over their own status-code
+    // rules: transient capacity and connection problems are worth retrying, while
+    // anything wrong with the request itself will fail again.
+    pub fn should_retry(&self) -> bool {
+        match self {
+            AppError::BackendRequestFailed(_)
+            | AppError::BackendConnectFailed { .. }
+            | AppError::NoBackendAvailable(_)
+            | AppError::UpstreamTimeout { .. }
+            | AppError::StreamFailed(_)
+            | AppError::Overloaded { .. }
+            | AppError::GuardrailUnavailable { .. } => true,
+            AppError::BackendRespondedError { status, .. } => {
+                matches!(*status, StatusCode::REQUEST_TIMEOUT | StatusCode::CONFLICT | StatusCode::TOO_MANY_REQUESTS)
+                    || (status.is_server_error() && *status != StatusCode::NOT_IMPLEMENTED)
+            }
+            // A per-minute quota frees up within the SDK's backoff; a daily one doesn't.
+            AppError::QuotaExceeded { resets_soon, .. } => *resets_soon,
+            _ => false,
+        }
+    }
+}
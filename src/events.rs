@@ -0,0 +1,193 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::metadata::Metadata;
+
+// Events waiting for the sink; beyond this they are dropped rather than slowing
+// requests down.
+const EVENT_BUFFER: usize = 10_000;
+
+// --- Lifecycle Events ---
+// With EVENT_SINK set, every generation publishes `request_started`,
+// `first_token`, and `request_completed` events as JSON, plus `stream_stalled`
+// when its backend stops sending for longer than `idle_ms`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventSinkConfig {
+    // Published to `<subject>.<event>`.
+    Nats {
+        url: String,
+        #[serde(default = "default_subject")]
+        subject: String,
+    },
+    // Published to one topic partition, keyed by request id, with the event name
+    // in an `event` record header.
+    Kafka {
+        brokers: Vec<String>,
+        topic: String,
+        #[serde(default)]
+        partition: i32,
+    },
+}
+
+fn default_subject() -> String {
+    "llm_gateway".to_string()
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LifecycleEvent {
+    RequestStarted {
+        request_id: String,
+        at: DateTime<Utc>,
+        model: String,
+        key: Option<String>,
+        #[serde(skip_serializing_if = "Metadata::is_empty")]
+        metadata: Metadata,
+    },
+    FirstToken {
+        request_id: String,
+        at: DateTime<Utc>,
+        model: String,
+        ttft_ms: u64,
+    },
+    RequestCompleted {
+        request_id: String,
+        at: DateTime<Utc>,
+        model: String,
+        key: Option<String>,
+        status: u16,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+        cost: Option<f64>,
+        latency_ms: u64,
+        #[serde(skip_serializing_if = "Metadata::is_empty")]
+        metadata: Metadata,
+    },
+    StreamStalled {
+        request_id: String,
+        at: DateTime<Utc>,
+        model: String,
+        backend: String,
+        idle_ms: u64,
+    },
+    OutputLimited {
+        request_id: String,
+        at: DateTime<Utc>,
+        model: String,
+        key: Option<String>,
+        chars: usize,
+        tokens: usize,
+    },
+}
+
+impl LifecycleEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LifecycleEvent::RequestStarted { .. } => "request_started",
+            LifecycleEvent::FirstToken { .. } => "first_token",
+            LifecycleEvent::RequestCompleted { .. } => "request_completed",
+            LifecycleEvent::StreamStalled { .. } => "stream_stalled",
+            LifecycleEvent::OutputLimited { .. } => "output_limited",
+        }
+    }
+
+    pub fn request_id(&self) -> &str {
+        match self {
+            LifecycleEvent::RequestStarted { request_id, .. }
+            | LifecycleEvent::FirstToken { request_id, .. }
+            | LifecycleEvent::RequestCompleted { request_id, .. }
+            | LifecycleEvent::StreamStalled { request_id, .. }
+            | LifecycleEvent::OutputLimited { request_id, .. } => request_id,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    tx: mpsc::Sender<LifecycleEvent>,
+}
+
+impl EventBus {
+    pub async fn connect(config: EventSinkConfig) -> Result<Self> {
+        let (tx, rx) = mpsc::channel(EVENT_BUFFER);
+        match config {
+            EventSinkConfig::Nats { url, subject } => spawn_nats(url, subject, rx).await?,
+            EventSinkConfig::Kafka { brokers, topic, partition } => spawn_kafka(brokers, topic, partition, rx).await?,
+        }
+        Ok(EventBus { tx })
+    }
+
+    pub fn publish(&self, event: LifecycleEvent) {
+        if let Err(mpsc::error::TrySendError::Full(event)) = self.tx.try_send(event) {
+            warn!("Event sink is falling behind; dropped {} for {}", event.name(), event.request_id());
+        }
+    }
+}
+
+// --- Sinks ---
+#[cfg(feature = "nats")]
+async fn spawn_nats(url: String, subject: String, mut rx: mpsc::Receiver<LifecycleEvent>) -> Result<()> {
+    use anyhow::Context;
+
+    let client = async_nats::connect(url.as_str()).await
+        .with_context(|| format!("Failed to connect to NATS at {}", url))?;
+    tracing::info!("Publishing lifecycle events to NATS {} ({}.*)", url, subject);
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            let payload = serde_json::to_vec(&event).unwrap_or_default();
+            if let Err(e) = client.publish(format!("{}.{}", subject, event.name()), payload.into()).await {
+                warn!("Failed to publish {} to NATS: {}", event.name(), e);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(feature = "nats"))]
+async fn spawn_nats(_url: String, _subject: String, _rx: mpsc::Receiver<LifecycleEvent>) -> Result<()> {
+    anyhow::bail!("EVENT_SINK type 'nats' requires the gateway to be built with the `nats` feature")
+}
+
+#[cfg(feature = "kafka")]
+async fn spawn_kafka(brokers: Vec<String>, topic: String, partition: i32, mut rx: mpsc::Receiver<LifecycleEvent>) -> Result<()> {
+    use anyhow::Context;
+    use rskafka::{
+        client::{partition::{Compression, UnknownTopicHandling}, ClientBuilder},
+        record::Record,
+    };
+
+    let client = ClientBuilder::new(brokers.clone()).build().await
+        .with_context(|| format!("Failed to connect to Kafka at {:?}", brokers))?;
+    let partition_client = client.partition_client(topic.clone(), partition, UnknownTopicHandling::Retry).await
+        .with_context(|| format!("Failed to open Kafka topic '{}' partition {}", topic, partition))?;
+    tracing::info!("Publishing lifecycle events to Kafka topic '{}' partition {}", topic, partition);
+
+    // Whatever has queued up while the previous batch was in flight goes out together.
+    tokio::spawn(async move {
+        let mut batch = Vec::new();
+        while rx.recv_many(&mut batch, 500).await > 0 {
+            let records = batch.drain(..)
+                .map(|event| Record {
+                    key: Some(event.request_id().as_bytes().to_vec()),
+                    value: Some(serde_json::to_vec(&event).unwrap_or_default()),
+                    headers: [("event".to_string(), event.name().as_bytes().to_vec())].into(),
+                    timestamp: Utc::now(),
+                })
+                .collect::<Vec<_>>();
+            let count = records.len();
+            if let Err(e) = partition_client.produce(records, Compression::NoCompression).await {
+                warn!("Failed to publish {} events to Kafka: {}", count, e);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(feature = "kafka"))]
+async fn spawn_kafka(_brokers: Vec<String>, _topic: String, _partition: i32, _rx: mpsc::Receiver<LifecycleEvent>) -> Result<()> {
+    anyhow::bail!("EVENT_SINK type 'kafka' requires the gateway to be built with the `kafka` feature")
+}
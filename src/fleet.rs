@@ -0,0 +1,163 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::{backend::BackendTable, drain::Drains, health::HealthMonitor};
+
+const FLEET_BUFFER: usize = 1024;
+
+// --- Fleet Coordination ---
+// Gateway instances behind a load balancer each find out on their own that a
+// replica is failing. With FLEET_REDIS_URL (and the `redis` feature), they tell
+// each other over the Redis pub/sub channel FLEET_CHANNEL: a replica one instance
+// stops using (its connection was refused or its stream stalled) is avoided by
+// every instance, changes in each instance's own health probes reach every
+// instance's view of the backends (and go no further), and so do admin drains. Messages are JSON and carry the sending instance's id, so an
+// instance ignores its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FleetEvent {
+    Suspect { url: String, secs: u64 },
+    Drain { urls: Vec<String>, drained: bool },
+    Health {
+        model: String,
+        url: String,
+        healthy: bool,
+        #[serde(default)]
+        error: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    from: String,
+    #[serde(flatten)]
+    event: FleetEvent,
+}
+
+pub struct Fleet {
+    instance: String,
+    tx: mpsc::Sender<FleetEvent>,
+    rx: Mutex<Option<mpsc::Receiver<FleetEvent>>>, // taken by `start`
+    url: String,
+    channel: String,
+}
+
+impl Fleet {
+    pub fn from_env() -> Result<Option<Arc<Self>>> {
+        let Some(url) = std::env::var("FLEET_REDIS_URL").ok().filter(|url| !url.is_empty()) else { return Ok(None) };
+        if !cfg!(feature = "redis") {
+            anyhow::bail!("FLEET_REDIS_URL requires the gateway to be built with the `redis` feature");
+        }
+        let channel = std::env::var("FLEET_CHANNEL").unwrap_or_else(|_| "llm-gateway:fleet".to_string());
+        let (tx, rx) = mpsc::channel(FLEET_BUFFER);
+        let instance = uuid::Uuid::new_v4().simple().to_string();
+        Ok(Some(Arc::new(Fleet { instance, tx, rx: Mutex::new(Some(rx)), url, channel })))
+    }
+
+    pub fn publish(&self, event: FleetEvent) {
+        if let Err(mpsc::error::TrySendError::Full(event)) = self.tx.try_send(event) {
+            warn!("Fleet channel is falling behind; dropped {:?}", event);
+        }
+    }
+
+    // Starts exchanging events, once the backends and health monitor they apply to exist.
+    pub fn start(&self, table: Arc<BackendTable>, health: Option<Arc<HealthMonitor>>, drains: Arc<Drains>) -> Result<()> {
+        let Some(rx) = self.rx.lock().unwrap().take() else { return Ok(()) };
+        let apply = Apply { instance: self.instance.clone(), table, health, drains };
+        spawn_redis(self.url.clone(), self.channel.clone(), self.instance.clone(), rx, apply)
+    }
+}
+
+struct Apply {
+    instance: String,
+    table: Arc<BackendTable>,
+    health: Option<Arc<HealthMonitor>>,
+    drains: Arc<Drains>,
+}
+
+impl Apply {
+    #[cfg_attr(not(feature = "redis"), allow(dead_code))]
+    fn received(&self, payload: &str) {
+        let envelope = match serde_json::from_str::<Envelope>(payload) {
+            Ok(envelope) if envelope.from == self.instance => return,
+            Ok(envelope) => envelope,
+            Err(e) => {
+                warn!("Ignoring malformed fleet message: {}", e);
+                return;
+            }
+        };
+        match envelope.event {
+            FleetEvent::Suspect { url, secs } => {
+                for backend in self.table.snapshot().values().filter(|backend| backend.replicas.get().contains(&url)) {
+                    backend.suspect_for(&url, secs);
+                }
+                info!("Instance {} reported backend {} as failing; avoiding it for {}s", envelope.from, url, secs);
+            }
+            FleetEvent::Drain { urls, drained } => {
+                self.drains.set(&urls, drained);
+                info!("Instance {} {} {}", envelope.from, if drained { "drained" } else { "undrained" }, urls.join(", "));
+            }
+            FleetEvent::Health { model, url, healthy, error } => {
+                if let Some(health) = &self.health {
+                    health.record_remote(&model, &url, &envelope.from, healthy, error);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+fn spawn_redis(url: String, channel: String, instance: String, mut rx: mpsc::Receiver<FleetEvent>, apply: Apply) -> Result<()> {
+    use anyhow::Context;
+
+    let client = redis::Client::open(url.as_str()).with_context(|| format!("Invalid FLEET_REDIS_URL '{}'", url))?;
+    info!("Sharing backend state with other gateway instances over Redis channel '{}'", channel);
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = exchange(&client, &channel, &instance, &mut rx, &apply).await {
+                warn!("Fleet connection to Redis failed: {}; reconnecting", e);
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+    });
+    Ok(())
+}
+
+// Runs until the connection fails. Events published meanwhile wait in the channel.
+#[cfg(feature = "redis")]
+async fn exchange(
+    client: &redis::Client,
+    channel: &str,
+    instance: &str,
+    rx: &mut mpsc::Receiver<FleetEvent>,
+    apply: &Apply,
+) -> redis::RedisResult<()> {
+    use futures::StreamExt;
+
+    let mut publisher = client.get_multiplexed_async_connection().await?;
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(channel).await?;
+    let mut messages = pubsub.into_on_message();
+    loop {
+        tokio::select! {
+            message = messages.next() => {
+                let Some(message) = message else { return Ok(()) };
+                let payload: String = message.get_payload()?;
+                apply.received(&payload);
+            }
+            event = rx.recv() => {
+                let Some(event) = event else { return Ok(()) };
+                let payload = serde_json::to_string(&Envelope { from: instance.to_string(), event }).unwrap_or_default();
+                redis::cmd("PUBLISH").arg(channel).arg(payload).query_async::<()>(&mut publisher).await?;
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+fn spawn_redis(_url: String, _channel: String, _instance: String, _rx: mpsc::Receiver<FleetEvent>, _apply: Apply) -> Result<()> {
+    anyhow::bail!("FLEET_REDIS_URL requires the gateway to be built with the `redis` feature")
+}
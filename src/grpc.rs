@@ -0,0 +1,269 @@
+use anyhow::{Context, Result};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response as HttpResponse},
+};
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+use std::{net::SocketAddr, pin::Pin, sync::Arc};
+use tonic::{metadata::MetadataMap, Code, Extensions, Request, Response, Status};
+use tracing::{error, info};
+
+use crate::{
+    auth, request_log::Recorder, stream::{self, PayloadStream}, usage::StreamOptions, AppError, AppState, ChatMessage,
+    ChatRequest, MessageContent, RequestMeta,
+};
+
+pub mod pb {
+    tonic::include_proto!("gateway.v1");
+}
+
+use pb::chat_completions_server::{ChatCompletions, ChatCompletionsServer};
+
+// --- gRPC Front-End ---
+// `gateway.v1.ChatCompletions` (proto/gateway.proto) on GRPC_LISTEN_ADDR, for
+// internal services that would rather not parse SSE. Requests are converted to
+// a ChatRequest and run through `start_chat`, so routing, limits, guardrails,
+// metrics, and logging are the HTTP path's. The API key goes in the
+// `authorization` metadata; the gateway's response headers come back as
+// initial metadata.
+pub fn spawn(state: Arc<AppState>, addr: &str) -> Result<()> {
+    let addr: SocketAddr = addr.parse().with_context(|| format!("Invalid GRPC_LISTEN_ADDR format: {}", addr))?;
+    let service = ChatCompletionsServer::new(ChatService { state });
+    info!("🚀 gRPC listening on {}", addr);
+    tokio::spawn(async move {
+        if let Err(e) = tonic::transport::Server::builder().add_service(service).serve(addr).await {
+            error!("gRPC server on {} failed: {}", addr, e);
+        }
+    });
+    Ok(())
+}
+
+struct ChatService {
+    state: Arc<AppState>,
+}
+
+type ChunkStream = Pin<Box<dyn Stream<Item = Result<pb::ChatCompletionChunk, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl ChatCompletions for ChatService {
+    async fn create(&self, mut request: Request<pb::ChatCompletionRequest>) -> Result<Response<pb::ChatCompletion>, Status> {
+        request.get_mut().include_usage = true;
+        let (metadata, payloads) = self.start(request).await?;
+        let completion = stream::collect(payloads).await.map_err(Status::unavailable)?;
+        Ok(Response::from_parts(metadata, completion_of(&completion), Extensions::default()))
+    }
+
+    type StreamStream = ChunkStream;
+
+    async fn stream(&self, request: Request<pb::ChatCompletionRequest>) -> Result<Response<ChunkStream>, Status> {
+        let (metadata, payloads) = self.start(request).await?;
+        // A gateway error midway (its `[Gateway Error: ...]` text) ends the call.
+        let chunks = futures::stream::unfold(Some(payloads), |payloads| async move {
+            let mut payloads = payloads?;
+            let payload = payloads.next().await.filter(|payload| payload != "[DONE]")?;
+            match serde_json::from_str::<Value>(&payload) {
+                Ok(chunk) => Some((Ok(chunk_of(&chunk)), Some(payloads))),
+                Err(_) => Some((Err(Status::unavailable(payload)), None)),
+            }
+        });
+        Ok(Response::from_parts(metadata, Box::pin(chunks) as ChunkStream, Extensions::default()))
+    }
+}
+
+impl ChatService {
+    async fn start(&self, request: Request<pb::ChatCompletionRequest>) -> Result<(MetadataMap, PayloadStream), Status> {
+        let (metadata, _, request) = request.into_parts();
+        let headers = metadata.into_headers();
+        let meta = RequestMeta::new();
+        let model = request.model.clone();
+        let caller = match auth::identify(&self.state, &headers) {
+            Ok(caller) => caller,
+            Err(e) => return Err(status_of(e.into_response()).await),
+        };
+        let started = match to_chat(request) {
+            Ok(body) => crate::start_chat(self.state.clone(), caller.clone(), headers, body, meta.clone()).await,
+            Err(e) => Err(e),
+        };
+        match started {
+            Ok((response_headers, payloads)) => Ok((MetadataMap::from_headers(response_headers), payloads)),
+            Err(e) => {
+                let response = e.into_response();
+                Recorder::new(&self.state, &meta, model, &caller).finish(response.status().as_u16(), None, None);
+                Err(status_of(response).await)
+            }
+        }
+    }
+}
+
+// The error's HTTP status as the nearest gRPC code, with its message.
+async fn status_of(response: HttpResponse) -> Status {
+    let code = match response.status() {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+        _ => Code::Internal,
+    };
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap_or_default();
+    let message = serde_json::from_slice::<Value>(&body).ok()
+        .and_then(|body| body["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or_default();
+    Status::new(code, message)
+}
+
+// --- Conversions ---
+fn to_chat(request: pb::ChatCompletionRequest) -> Result<ChatRequest, AppError> {
+    let messages = request.messages.into_iter().map(to_message).collect::<Result<_, _>>()?;
+    let tools = (!request.tools.is_empty()).then(|| {
+        Value::Array(request.tools.into_iter().map(|tool| json!({
+            "type": "function",
+            "function": {
+                "name": tool.name,
+                "description": tool.description,
+                "parameters": tool.parameters.map(struct_to_json).unwrap_or_else(|| json!({ "type": "object" })),
+            },
+        })).collect())
+    });
+    let tool_choice = request.tool_choice.map(|choice| match choice.as_str() {
+        "auto" | "none" | "required" => Value::String(choice),
+        name => json!({ "type": "function", "function": { "name": name } }),
+    });
+    Ok(ChatRequest {
+        model: request.model,
+        messages,
+        max_tokens: request.max_tokens,
+        temperature: request.temperature,
+        top_p: request.top_p,
+        presence_penalty: request.presence_penalty,
+        frequency_penalty: request.frequency_penalty,
+        stop: (!request.stop.is_empty()).then_some(crate::stop::StringOrVec::Many(request.stop)),
+        tools,
+        tool_choice,
+        stream: Some(true),
+        stream_options: request.include_usage.then_some(StreamOptions { include_usage: true }),
+        logprobs: None,
+        continue_final_message: None,
+        add_generation_prompt: None,
+        metadata: None,
+    })
+}
+
+fn to_message(message: pb::ChatMessage) -> Result<ChatMessage, AppError> {
+    let content = match (message.content, message.parts.is_empty()) {
+        (Some(_), false) => {
+            return Err(AppError::InvalidRequest("A message has both `content` and `parts`; send one.".to_string()));
+        }
+        (Some(text), true) => MessageContent::Text(text),
+        (None, false) => MessageContent::Parts(message.parts.into_iter().filter_map(|part| match part.part? {
+            pb::content_part::Part::Text(text) => Some(json!({ "type": "text", "text": text })),
+            pb::content_part::Part::ImageUrl(image) => Some(json!({
+                "type": "image_url",
+                "image_url": { "url": image.url, "detail": image.detail },
+            })),
+        }).collect()),
+        (None, true) => MessageContent::Empty,
+    };
+    let tool_calls = (!message.tool_calls.is_empty()).then(|| {
+        Value::Array(message.tool_calls.into_iter().map(|call| json!({
+            "id": call.id,
+            "type": "function",
+            "function": { "name": call.name, "arguments": call.arguments },
+        })).collect())
+    });
+    Ok(ChatMessage { role: message.role, content, name: message.name, tool_calls, tool_call_id: message.tool_call_id })
+}
+
+fn struct_to_json(value: prost_types::Struct) -> Value {
+    Value::Object(value.fields.into_iter().map(|(name, value)| (name, value_to_json(value))).collect())
+}
+
+fn value_to_json(value: prost_types::Value) -> Value {
+    use prost_types::value::Kind;
+    match value.kind {
+        None | Some(Kind::NullValue(_)) => Value::Null,
+        Some(Kind::NumberValue(number)) => json!(number),
+        Some(Kind::StringValue(text)) => Value::String(text),
+        Some(Kind::BoolValue(flag)) => Value::Bool(flag),
+        Some(Kind::StructValue(object)) => struct_to_json(object),
+        Some(Kind::ListValue(list)) => Value::Array(list.values.into_iter().map(value_to_json).collect()),
+    }
+}
+
+fn completion_of(completion: &Value) -> pb::ChatCompletion {
+    let choices = completion["choices"].as_array().into_iter().flatten()
+        .map(|choice| {
+            let message = &choice["message"];
+            pb::Choice {
+                index: choice["index"].as_u64().unwrap_or(0) as u32,
+                message: Some(pb::ChatMessage {
+                    role: message["role"].as_str().unwrap_or("assistant").to_string(),
+                    content: message["content"].as_str().map(str::to_string),
+                    tool_calls: message["tool_calls"].as_array().into_iter().flatten()
+                        .map(|call| pb::ToolCall {
+                            id: text(&call["id"]),
+                            name: text(&call["function"]["name"]),
+                            arguments: text(&call["function"]["arguments"]),
+                        })
+                        .collect(),
+                    ..Default::default()
+                }),
+                finish_reason: choice["finish_reason"].as_str().map(str::to_string),
+            }
+        })
+        .collect();
+    pb::ChatCompletion {
+        id: text(&completion["id"]),
+        created: completion["created"].as_i64().unwrap_or(0),
+        model: text(&completion["model"]),
+        choices,
+        usage: usage_of(&completion["usage"]),
+    }
+}
+
+fn chunk_of(chunk: &Value) -> pb::ChatCompletionChunk {
+    let choices = chunk["choices"].as_array().into_iter().flatten()
+        .map(|choice| {
+            let delta = &choice["delta"];
+            pb::ChunkChoice {
+                index: choice["index"].as_u64().unwrap_or(0) as u32,
+                delta: Some(pb::Delta {
+                    role: delta["role"].as_str().map(str::to_string),
+                    content: delta["content"].as_str().map(str::to_string),
+                    tool_calls: delta["tool_calls"].as_array().into_iter().flatten()
+                        .map(|call| pb::ToolCallDelta {
+                            index: call["index"].as_u64().unwrap_or(0) as u32,
+                            id: call["id"].as_str().map(str::to_string),
+                            name: call["function"]["name"].as_str().map(str::to_string),
+                            arguments: call["function"]["arguments"].as_str().map(str::to_string),
+                        })
+                        .collect(),
+                }),
+                finish_reason: choice["finish_reason"].as_str().map(str::to_string),
+            }
+        })
+        .collect();
+    pb::ChatCompletionChunk {
+        id: text(&chunk["id"]),
+        created: chunk["created"].as_i64().unwrap_or(0),
+        model: text(&chunk["model"]),
+        choices,
+        usage: usage_of(&chunk["usage"]),
+    }
+}
+
+fn usage_of(usage: &Value) -> Option<pb::Usage> {
+    usage.is_object().then(|| pb::Usage {
+        prompt_tokens: usage["prompt_tokens"].as_u64().unwrap_or(0),
+        completion_tokens: usage["completion_tokens"].as_u64().unwrap_or(0),
+        total_tokens: usage["total_tokens"].as_u64().unwrap_or(0),
+        cost: usage["cost"].as_f64(),
+    })
+}
+
+fn text(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
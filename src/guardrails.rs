@@ -0,0 +1,237 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{sync::Arc, time::Duration};
+use tracing::warn;
+
+use crate::{AppError, ChatMessage, ChatRequest};
+
+// --- Guardrail Hooks ---
+// A guardrail can veto a request before it is forwarded and/or flag a completion
+// once the stream has finished. Streamed text has already reached the client by
+// then, so a flagged completion ends with `finish_reason: "content_filter"`.
+pub enum Decision {
+    Allow,
+    Block { reason: String },
+}
+
+#[async_trait]
+pub trait Guardrail: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn check_request(&self, _body: &ChatRequest) -> Result<Decision> {
+        Ok(Decision::Allow)
+    }
+
+    async fn check_response(&self, _body: &ChatRequest, _completion: &str) -> Result<Decision> {
+        Ok(Decision::Allow)
+    }
+
+    fn checks_response(&self) -> bool {
+        false
+    }
+
+    // Whether an unreachable guardrail lets traffic through.
+    fn fail_open(&self) -> bool {
+        false
+    }
+}
+
+// Runs every guardrail's request hook concurrently; the first block wins.
+pub async fn check_request(guardrails: &[Arc<dyn Guardrail>], body: &ChatRequest) -> Result<(), AppError> {
+    let results = futures::future::join_all(guardrails.iter().map(|g| g.check_request(body))).await;
+    for (guardrail, result) in guardrails.iter().zip(results) {
+        enforce(guardrail.as_ref(), result)?;
+    }
+    Ok(())
+}
+
+fn enforce(guardrail: &dyn Guardrail, result: Result<Decision>) -> Result<(), AppError> {
+    match result {
+        Ok(Decision::Allow) => Ok(()),
+        Ok(Decision::Block { reason }) => Err(AppError::PolicyViolation {
+            guardrail: guardrail.name().to_string(),
+            reason,
+        }),
+        Err(e) if guardrail.fail_open() => {
+            warn!("Guardrail '{}' failed, allowing request (fail_open): {}", guardrail.name(), e);
+            Ok(())
+        }
+        Err(e) => Err(AppError::GuardrailUnavailable {
+            guardrail: guardrail.name().to_string(),
+            error: e.to_string(),
+        }),
+    }
+}
+
+// --- HTTP Callout Guardrail ---
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HookPoint {
+    #[default]
+    Request,
+    Response,
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationFormat {
+    // A Llama Guard style classifier served behind a chat completions API,
+    // answering `safe` or `unsafe\n<categories>`.
+    #[default]
+    LlamaGuard,
+    // An OpenAI-compatible `/v1/moderations` endpoint.
+    OpenaiModeration,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpGuardrailConfig {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub format: ModerationFormat,
+    #[serde(default)]
+    pub on: HookPoint,
+    #[serde(default)]
+    pub fail_open: bool,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+pub struct HttpGuardrail {
+    config: HttpGuardrailConfig,
+    client: Client,
+}
+
+impl HttpGuardrail {
+    pub fn new(config: HttpGuardrailConfig, client: Client) -> Self {
+        HttpGuardrail { config, client }
+    }
+
+    async fn classify(&self, conversation: Vec<ChatMessage>) -> Result<Decision> {
+        match self.config.format {
+            ModerationFormat::LlamaGuard => {
+                let payload = json!({
+                    "model": self.config.model,
+                    "messages": conversation,
+                    "max_tokens": 32,
+                    "temperature": 0.0,
+                    "stream": false,
+                });
+                let response: Value = self.post("/v1/chat/completions", &payload).await?;
+                let verdict = response["choices"][0]["message"]["content"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("classifier response has no message content"))?;
+                match llama_guard_verdict(verdict)? {
+                    None => Ok(Decision::Allow),
+                    Some(categories) if categories.is_empty() => Ok(Decision::Block {
+                        reason: "flagged categories: unspecified".to_string(),
+                    }),
+                    Some(categories) => Ok(Decision::Block {
+                        reason: format!("flagged categories: {}", categories.join(",")),
+                    }),
+                }
+            }
+            ModerationFormat::OpenaiModeration => {
+                let input: Vec<String> = conversation.iter().map(|m| m.content.text().into_owned()).collect();
+                let payload = json!({ "model": self.config.model, "input": input });
+                let response: Value = self.post("/v1/moderations", &payload).await?;
+                let results = response["results"].as_array()
+                    .ok_or_else(|| anyhow!("moderation response has no results"))?;
+                let flagged: Vec<String> = results.iter()
+                    .filter(|r| r["flagged"].as_bool().unwrap_or(false))
+                    .flat_map(|r| {
+                        r["categories"].as_object().into_iter().flatten()
+                            .filter(|(_, v)| v.as_bool().unwrap_or(false))
+                            .map(|(k, _)| k.clone())
+                    })
+                    .collect();
+                let any_flagged = results.iter().any(|r| r["flagged"].as_bool().unwrap_or(false));
+                if any_flagged {
+                    Ok(Decision::Block { reason: format!("flagged categories: {}", flagged.join(", ")) })
+                } else {
+                    Ok(Decision::Allow)
+                }
+            }
+        }
+    }
+
+    async fn post(&self, path: &str, payload: &Value) -> Result<Value> {
+        let url = format!("{}{}", self.config.url.trim_end_matches('/'), path);
+        let res = self.client
+            .post(&url)
+            .timeout(Duration::from_millis(self.config.timeout_ms))
+            .json(payload)
+            .send()
+            .await?;
+        let status = res.status();
+        if !status.is_success() {
+            return Err(anyhow!("{} returned {}", url, status));
+        }
+        Ok(res.json().await?)
+    }
+}
+
+// Llama Guard answers `safe`, or `unsafe` with the violated categories (`S1,S10`)
+// on the next line. None if safe.
+pub fn llama_guard_verdict(verdict: &str) -> Result<Option<Vec<String>>> {
+    let mut lines = verdict.trim().lines();
+    match lines.next().map(str::trim) {
+        Some("safe") => Ok(None),
+        Some("unsafe") => Ok(Some(
+            lines.next().unwrap_or("").split(',').map(str::trim).filter(|c| !c.is_empty()).map(str::to_string).collect(),
+        )),
+        _ => Err(anyhow!("unexpected classifier verdict '{}'", verdict.trim())),
+    }
+}
+
+// Llama Guard classifies user/assistant turns; system prompts are not part of its template.
+fn conversation_of(body: &ChatRequest) -> Vec<ChatMessage> {
+    body.messages.iter().filter(|m| m.role != "system").cloned().collect()
+}
+
+#[async_trait]
+impl Guardrail for HttpGuardrail {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    async fn check_request(&self, body: &ChatRequest) -> Result<Decision> {
+        if self.config.on == HookPoint::Response {
+            return Ok(Decision::Allow);
+        }
+        self.classify(conversation_of(body)).await
+    }
+
+    async fn check_response(&self, body: &ChatRequest, completion: &str) -> Result<Decision> {
+        if self.config.on == HookPoint::Request {
+            return Ok(Decision::Allow);
+        }
+        let mut conversation = conversation_of(body);
+        conversation.push(ChatMessage {
+            role: "assistant".to_string(),
+            content: completion.to_string().into(),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        });
+        self.classify(conversation).await
+    }
+
+    fn checks_response(&self) -> bool {
+        self.config.on != HookPoint::Request
+    }
+
+    fn fail_open(&self) -> bool {
+        self.config.fail_open
+    }
+}
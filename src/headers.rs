@@ -0,0 +1,98 @@
+use anyhow::{bail, Context, Result};
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::RequestBuilder;
+use serde_json::Value;
+
+use crate::{config, RequestMeta};
+
+// Headers that describe a single connection hop and must never be copied across.
+const HOP_BY_HOP: &[&str] = &[
+    "connection", "keep-alive", "proxy-authenticate", "proxy-authorization", "te", "trailer",
+    "transfer-encoding", "upgrade", "host", "content-length",
+];
+
+// --- Header Forwarding ---
+// Nothing is forwarded by default; FORWARD_REQUEST_HEADERS and
+// FORWARD_RESPONSE_HEADERS are explicit allowlists.
+pub struct HeaderPolicy {
+    request: Vec<HeaderName>,
+    response: Vec<HeaderName>,
+}
+
+impl HeaderPolicy {
+    pub fn from_env() -> Result<Self> {
+        Ok(HeaderPolicy {
+            request: parse_names("FORWARD_REQUEST_HEADERS")?,
+            response: parse_names("FORWARD_RESPONSE_HEADERS")?,
+        })
+    }
+
+    pub fn forward_request(&self, inbound: &HeaderMap, mut builder: RequestBuilder) -> RequestBuilder {
+        for name in &self.request {
+            for value in inbound.get_all(name) {
+                builder = builder.header(name, value);
+            }
+        }
+        builder
+    }
+
+    // The same allowlist, for requests not sent with reqwest (WebSocket handshakes).
+    pub fn copy_request(&self, inbound: &HeaderMap, outbound: &mut HeaderMap) {
+        for name in &self.request {
+            for value in inbound.get_all(name) {
+                outbound.append(name.clone(), value.clone());
+            }
+        }
+    }
+
+    pub fn forward_response(&self, upstream: &reqwest::header::HeaderMap) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for name in &self.response {
+            for value in upstream.get_all(name) {
+                headers.append(name.clone(), value.clone());
+            }
+        }
+        headers
+    }
+}
+
+fn parse_names(var: &str) -> Result<Vec<HeaderName>> {
+    config::env_list(var).iter()
+        .map(|raw| {
+            let name = HeaderName::try_from(raw.to_ascii_lowercase())
+                .with_context(|| format!("Invalid header name '{}' in {}", raw, var))?;
+            if HOP_BY_HOP.contains(&name.as_str()) {
+                bail!("{} cannot forward the connection-level header '{}'", var, name);
+            }
+            Ok(name)
+        })
+        .collect()
+}
+
+// --- Gateway Metadata Headers ---
+// Added to every chat and token-count response, errors included, so callers can
+// attribute latency without parsing bodies. For streams the latency is the time
+// until the backend accepted the request. A buffered response also gets its cost
+// as x-gateway-cost; a stream's is only known after its headers are sent, so it
+// is reported in the final usage chunk instead (see `usage::UsageTap`).
+pub fn stamp_metadata(headers: &mut HeaderMap, meta: &RequestMeta, model: &str, backend: Option<&str>) {
+    let mut set = |name: &'static str, value: String| {
+        if let Ok(value) = HeaderValue::try_from(value) {
+            headers.insert(name, value);
+        }
+    };
+    set("x-request-id", meta.id.clone());
+    set("x-gateway-model", model.to_string());
+    if let Some(backend) = backend {
+        set("x-gateway-backend", backend.to_string());
+    }
+    set("x-gateway-latency-ms", meta.received.elapsed().as_millis().to_string());
+}
+
+// The `usage.cost` of a buffered `chat.completion`, when its model has pricing.
+pub fn stamp_cost(headers: &mut HeaderMap, completion: &Value) {
+    let Some(cost) = completion.pointer("/usage/cost").and_then(Value::as_f64) else { return };
+    if let Ok(value) = HeaderValue::try_from(cost.to_string()) {
+        headers.insert("x-gateway-cost", value);
+    }
+}
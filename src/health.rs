@@ -0,0 +1,151 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::{info, warn};
+
+use crate::{
+    alerts::Alerter,
+    backend::{Backend, BackendTable},
+    fleet::{Fleet, FleetEvent},
+};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// --- Backend Health ---
+// With HEALTH_CHECK_INTERVAL_SECS set, every replica's `/health` endpoint (served
+// by vLLM) is probed on that interval. Results are informational: routing does
+// not skip unhealthy replicas. Changes in this instance's own probe results are
+// shared with other instances through the fleet channel, if there is one; what
+// they report is shown until this instance probes the replica again, and never
+// passed on.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicaHealth {
+    pub url: String,
+    pub healthy: bool,
+    pub checked_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reported_by: Option<String>, // the instance whose probe this is, if not this one
+    #[serde(skip)]
+    probed_healthy: bool, // this instance's last probe, whose changes are published
+}
+
+pub struct HealthMonitor {
+    models: Mutex<BTreeMap<String, Vec<ReplicaHealth>>>,
+    fleet: Option<Arc<Fleet>>,
+}
+
+impl HealthMonitor {
+    pub fn start(table: Arc<BackendTable>, interval: Duration, alerts: Option<Arc<Alerter>>, fleet: Option<Arc<Fleet>>) -> Arc<Self> {
+        let monitor = Arc::new(HealthMonitor { models: Mutex::new(BTreeMap::new()), fleet });
+        for (model, backend) in table.snapshot().iter() {
+            monitor.update(model, &backend.replicas.get(), Vec::new());
+        }
+        info!("Probing backend health every {}s", interval.as_secs());
+
+        let this = monitor.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                // The registry and discovery may have changed models and replicas since the last round.
+                let backends = table.snapshot();
+                this.models.lock().unwrap().retain(|model, _| backends.contains_key(model));
+                for (model, backend) in backends.iter() {
+                    let urls = backend.replicas.get();
+                    let results = futures::future::join_all(urls.iter().map(|url| probe(backend, url))).await;
+                    if let Some((healthy, total)) = this.update(model, &urls, results) {
+                        if let Some(alerts) = &alerts {
+                            alerts.backends_changed(model, healthy, total);
+                        }
+                    }
+                }
+            }
+        });
+        monitor
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, Vec<ReplicaHealth>> {
+        self.models.lock().unwrap().clone()
+    }
+
+    // Unknown replicas count as healthy.
+    pub fn is_healthy(&self, model: &str, url: &str) -> bool {
+        self.models.lock().unwrap().get(model)
+            .and_then(|replicas| replicas.iter().find(|r| r.url == url))
+            .is_none_or(|r| r.healthy)
+    }
+
+    // Another instance's probe result, kept until this instance's next probe. It
+    // leaves this instance's own result alone, so it is never published again.
+    pub fn record_remote(&self, model: &str, url: &str, instance: &str, healthy: bool, error: Option<String>) {
+        let mut models = self.models.lock().unwrap();
+        let Some(replica) = models.get_mut(model).and_then(|replicas| replicas.iter_mut().find(|r| r.url == url)) else { return };
+        if replica.healthy != healthy {
+            info!("Backend {} for model '{}' reported {} by instance {}", url, model, if healthy { "healthy" } else { "unhealthy" }, instance);
+        }
+        replica.healthy = healthy;
+        replica.checked_at = Some(Utc::now());
+        replica.error = error;
+        replica.reported_by = Some(instance.to_string());
+    }
+
+    // Records one round of probes for a model's current replicas (none yet at
+    // startup). Replicas count as healthy until a probe says otherwise. Returns
+    // (healthy, total) if this instance's probe of any replica changed state.
+    fn update(&self, model: &str, urls: &[String], results: Vec<Result<(), String>>) -> Option<(usize, usize)> {
+        let mut models = self.models.lock().unwrap();
+        let previous = models.remove(model).unwrap_or_default();
+        let mut results = results.into_iter();
+        let mut changed = false;
+        let replicas: Vec<ReplicaHealth> = urls.iter()
+            .map(|url| {
+                let mut replica = previous.iter().find(|r| r.url == *url).cloned()
+                    .unwrap_or_else(|| ReplicaHealth {
+                        url: url.clone(), healthy: true, checked_at: None, error: None, reported_by: None, probed_healthy: true,
+                    });
+                let Some(result) = results.next() else { return replica };
+                let healthy = result.is_ok();
+                if healthy != replica.probed_healthy {
+                    changed = true;
+                    match &result {
+                        Ok(()) => info!("Backend {} for model '{}' is healthy again", replica.url, model),
+                        Err(e) => warn!("Backend {} for model '{}' is unhealthy: {}", replica.url, model, e),
+                    }
+                    if let Some(fleet) = &self.fleet {
+                        fleet.publish(FleetEvent::Health {
+                            model: model.to_string(), url: replica.url.clone(), healthy, error: result.clone().err(),
+                        });
+                    }
+                }
+                replica.healthy = healthy;
+                replica.probed_healthy = healthy;
+                replica.checked_at = Some(Utc::now());
+                replica.error = result.err();
+                replica.reported_by = None;
+                replica
+            })
+            .collect();
+        let counts = (replicas.iter().filter(|r| r.healthy).count(), replicas.len());
+        models.insert(model.to_string(), replicas);
+        changed.then_some(counts)
+    }
+}
+
+async fn probe(backend: &Backend, url: &str) -> Result<(), String> {
+    if crate::mock::is_mock(url) {
+        return Ok(());
+    }
+    let response = backend.authorize(backend.client_for(url).get(format!("{}/health", backend.base_url(url)))).timeout(PROBE_TIMEOUT).send().await
+        .map_err(|e| crate::error::chain(&e))?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("/health returned {}", response.status()))
+    }
+}
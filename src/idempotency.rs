@@ -0,0 +1,320 @@
+use anyhow::Result;
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::{stream, Future, StreamExt};
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{watch, Notify};
+use tracing::{info, warn};
+
+use crate::{config, stream::{sse_response, PayloadStream}, AppError, ChatRequest};
+
+pub const HEADER: &str = "idempotency-key";
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+pub const LAST_EVENT_ID: &str = "last-event-id";
+const MAX_KEY_LEN: usize = 255;
+
+// --- Idempotency Store ---
+// Requests carrying an `Idempotency-Key` header run detached from the client
+// connection and record everything they send. A retry or concurrent duplicate with
+// the same key (scoped per API key) replays that recording and then follows the
+// live generation, so the backend only ever sees one request. Entries live for
+// IDEMPOTENCY_TTL_SECS; failed attempts are not kept, so a retry runs again.
+pub struct IdempotencyStore {
+    entries: Mutex<HashMap<String, Entry>>,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+struct Entry {
+    fingerprint: u64,
+    created: Instant,
+    response: Arc<SharedResponse>,
+}
+
+pub enum Claim {
+    // First request for this key: the caller must start `SharedResponse::run`.
+    Leader(Arc<SharedResponse>),
+    Replay(Arc<SharedResponse>),
+    // The store is full; serve the request without deduplication.
+    Uncached,
+}
+
+impl IdempotencyStore {
+    pub fn from_env() -> Result<Self> {
+        let ttl = config::env_parse("IDEMPOTENCY_TTL_SECS")?.unwrap_or(600);
+        let max_entries = config::env_parse("IDEMPOTENCY_MAX_ENTRIES")?.unwrap_or(10_000);
+        Ok(IdempotencyStore {
+            entries: Mutex::new(HashMap::new()),
+            ttl: Duration::from_secs(ttl),
+            max_entries,
+        })
+    }
+
+    pub fn claim(&self, scope: &str, key: &str, body: &ChatRequest) -> Result<Claim, AppError> {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            return Err(AppError::InvalidRequest(format!(
+                "Idempotency-Key must be between 1 and {} characters.", MAX_KEY_LEN
+            )));
+        }
+        let fingerprint = fingerprint(body);
+        let store_key = format!("{}\n{}", scope, key);
+
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, entry| now.duration_since(entry.created) < self.ttl);
+
+        if let Some(entry) = entries.get(&store_key) {
+            if !entry.response.failed() {
+                if entry.fingerprint != fingerprint {
+                    return Err(AppError::IdempotencyKeyReused(key.to_string()));
+                }
+                info!("Replaying response for idempotency key '{}'", key);
+                return Ok(Claim::Replay(entry.response.clone()));
+            }
+        } else if entries.len() >= self.max_entries {
+            warn!("Idempotency store full ({} entries); not deduplicating '{}'", entries.len(), key);
+            return Ok(Claim::Uncached);
+        }
+
+        let response = Arc::new(SharedResponse::new());
+        entries.insert(store_key, Entry { fingerprint, created: now, response: response.clone() });
+        Ok(Claim::Leader(response))
+    }
+}
+
+// Requests are compared as received, before any gateway-side rewriting.
+fn fingerprint(body: &ChatRequest) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(body).unwrap_or_default().hash(&mut hasher);
+    hasher.finish()
+}
+
+// --- Stream Resumption ---
+// With STREAM_RESUME_SECS set, every streaming chat request is recorded like an
+// idempotent one. A client whose connection drops can send the request again
+// with `X-Request-ID` (from the first response) and `Last-Event-ID` (the `id` of
+// the last event it got) to receive the rest. The generation keeps going for that
+// many seconds with no client attached, and the recording is kept for that long
+// after it ends. Resuming is scoped to the caller's API key.
+pub struct StreamResumes {
+    entries: Mutex<HashMap<String, (String, Arc<SharedResponse>)>>, // request ID -> scope, recording
+    window: Duration,
+}
+
+impl StreamResumes {
+    pub fn from_env() -> Result<Option<Self>> {
+        let window = config::env_parse::<u64>("STREAM_RESUME_SECS")?.unwrap_or(0);
+        Ok((window > 0).then(|| StreamResumes { entries: Mutex::new(HashMap::new()), window: Duration::from_secs(window) }))
+    }
+
+    // How long a generation runs on with nobody reading it.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn track(&self, scope: &str, request_id: &str, response: Arc<SharedResponse>) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (_, response)| !response.ended_before(self.window));
+        entries.insert(request_id.to_string(), (scope.to_string(), response));
+    }
+
+    pub async fn resume(&self, scope: &str, headers: &HeaderMap) -> Result<Response, AppError> {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+        let request_id = header("x-request-id")
+            .ok_or_else(|| AppError::InvalidRequest("Resuming a stream with Last-Event-ID needs the X-Request-ID of the original response.".to_string()))?;
+        let last: usize = header(LAST_EVENT_ID).and_then(|id| id.parse().ok())
+            .ok_or_else(|| AppError::InvalidRequest("Last-Event-ID must be the `id` of a received event.".to_string()))?;
+        let response = {
+            let entries = self.entries.lock().unwrap();
+            entries.get(request_id)
+                .filter(|(owner, response)| owner == scope && !response.ended_before(self.window))
+                .map(|(_, response)| response.clone())
+        };
+        let response = response.ok_or_else(|| AppError::RequestNotFound(request_id.to_string()))?;
+        info!("Resuming stream {} after event {}", request_id, last);
+        Ok(response.subscribe(last).await)
+    }
+}
+
+// --- Shared Response ---
+// A recording of one response that any number of subscribers can read, from the
+// start, while it is still being produced. `version` is bumped on every change.
+pub struct SharedResponse {
+    progress: Mutex<Progress>,
+    version: watch::Sender<u64>,
+    subscribers: AtomicUsize,
+    left: Notify, // a subscriber went away
+}
+
+#[derive(Default)]
+struct Progress {
+    head: Option<Head>,
+    payloads: Vec<String>,
+    done: bool,
+    ended: Option<Instant>,
+}
+
+#[derive(Clone)]
+enum Head {
+    Stream(HeaderMap),
+    Failed { status: StatusCode, headers: HeaderMap, body: Bytes },
+}
+
+impl SharedResponse {
+    pub fn new() -> Self {
+        SharedResponse {
+            progress: Mutex::new(Progress::default()),
+            version: watch::Sender::new(0),
+            subscribers: AtomicUsize::new(0),
+            left: Notify::new(),
+        }
+    }
+
+    fn failed(&self) -> bool {
+        matches!(self.progress.lock().unwrap().head, Some(Head::Failed { .. }))
+    }
+
+    fn ended_before(&self, window: Duration) -> bool {
+        self.progress.lock().unwrap().ended.is_some_and(|ended| ended.elapsed() > window)
+    }
+
+    // Resolves once nobody has been reading for `linger`.
+    async fn abandoned(&self, linger: Duration) {
+        loop {
+            if self.subscribers.load(Ordering::Relaxed) == 0 {
+                tokio::time::sleep(linger).await;
+                if self.subscribers.load(Ordering::Relaxed) == 0 {
+                    return;
+                }
+            } else {
+                self.left.notified().await;
+            }
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Progress)) {
+        f(&mut self.progress.lock().unwrap());
+        self.version.send_modify(|v| *v += 1);
+    }
+
+    // Drives the request in the background. The generation keeps going if the
+    // client disconnects, so that a retry can pick it up: to completion, or with
+    // `linger`, until nobody has been reading for that long.
+    pub fn run<F>(self: &Arc<Self>, request: F, linger: Option<Duration>)
+    where
+        F: Future<Output = Result<(HeaderMap, PayloadStream), AppError>> + Send + 'static,
+    {
+        let shared = self.clone();
+        tokio::spawn(async move {
+            match request.await {
+                Ok((headers, mut payloads)) => {
+                    shared.update(|p| p.head = Some(Head::Stream(headers)));
+                    let abandoned = async {
+                        match linger {
+                            Some(linger) => shared.abandoned(linger).await,
+                            None => std::future::pending().await,
+                        }
+                    };
+                    tokio::pin!(abandoned);
+                    loop {
+                        tokio::select! {
+                            payload = payloads.next() => match payload {
+                                Some(payload) => shared.update(|p| p.payloads.push(payload)),
+                                None => break,
+                            },
+                            _ = &mut abandoned => {
+                                info!("Nobody resumed an abandoned stream; ending it");
+                                break;
+                            }
+                        }
+                    }
+                    shared.update(|p| {
+                        p.done = true;
+                        p.ended = Some(Instant::now());
+                    });
+                }
+                Err(e) => {
+                    let (parts, body) = e.into_response().into_parts();
+                    let body = axum::body::to_bytes(body, usize::MAX).await.unwrap_or_default();
+                    let head = Head::Failed { status: parts.status, headers: parts.headers, body };
+                    shared.update(|p| {
+                        p.head = Some(head);
+                        p.ended = Some(Instant::now());
+                    });
+                }
+            }
+        });
+    }
+
+    // The payloads after the first `skip`, or the recorded error response.
+    pub async fn follow(self: Arc<Self>, skip: usize) -> Result<(HeaderMap, PayloadStream), Response> {
+        let mut version = self.version.subscribe();
+        let head = loop {
+            version.borrow_and_update();
+            if let Some(head) = self.progress.lock().unwrap().head.clone() {
+                break head;
+            }
+            // The sender lives in `self`, so this only returns on a change.
+            let _ = version.changed().await;
+        };
+
+        let headers = match head {
+            Head::Failed { status, headers, body } => return Err((status, headers, body).into_response()),
+            Head::Stream(headers) => headers,
+        };
+        let reader = Reader::new(self);
+        let payloads = stream::unfold((reader, version, skip), |(reader, mut version, next)| async move {
+            let shared = &reader.0;
+            loop {
+                version.borrow_and_update();
+                let (payload, done) = {
+                    let progress = shared.progress.lock().unwrap();
+                    (progress.payloads.get(next).cloned(), progress.done)
+                };
+                if let Some(payload) = payload {
+                    return Some((payload, (reader, version, next + 1)));
+                }
+                if done {
+                    return None;
+                }
+                let _ = version.changed().await;
+            }
+        });
+        Ok((headers, Box::pin(payloads)))
+    }
+
+    pub async fn subscribe(self: Arc<Self>, skip: usize) -> Response {
+        match self.follow(skip).await {
+            Ok((headers, payloads)) => (headers, sse_response(payloads, skip)).into_response(),
+            Err(response) => response,
+        }
+    }
+}
+
+// Counts a subscriber for as long as its stream is alive.
+struct Reader(Arc<SharedResponse>);
+
+impl Reader {
+    fn new(shared: Arc<SharedResponse>) -> Self {
+        shared.subscribers.fetch_add(1, Ordering::Relaxed);
+        Reader(shared)
+    }
+}
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        self.0.subscribers.fetch_sub(1, Ordering::Relaxed);
+        self.0.left.notify_one();
+    }
+}
@@ -0,0 +1,180 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{
+    auth::Caller, backend::Backend, config::BackendApi, headers, mock, request_log::Recorder, stream, tenants, upstream,
+    AppError, AppState, RequestMeta,
+};
+
+// A transparent 1x1 PNG, for mock backends.
+const MOCK_PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==";
+
+// --- Image Generation ---
+// POST /v1/images/generations routes by `model` to IMAGE_BACKENDS, its own model
+// table in the VLLM_BACKENDS format. OpenAI-compatible backends get the request
+// as is, and their response (JSON, or image events with `stream: true`) is passed
+// straight through. Backends with `"api": "sd_webui"` get it translated to a
+// `txt2img` call, and their images come back in OpenAI's format.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ImageRequest {
+    model: String,
+    prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<String>, // "1024x1024", or "auto"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<String>, // "url" or "b64_json"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    #[serde(flatten)]
+    options: Map<String, Value>, // style, background, negative_prompt, steps, ...
+}
+
+#[utoipa::path(
+    post, path = "/v1/images/generations", tag = "Images",
+    request_body = ImageRequest,
+    responses((status = 200, description = "The images, as OpenAI's image response (or the backend's image events with `stream: true`).", body = Object)),
+)]
+pub async fn generations(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    Json(mut request): Json<ImageRequest>,
+) -> Response {
+    let meta = RequestMeta::new();
+    let (model, backend) = match tenants::route_in(&caller, &state.image_backends, &request.model) {
+        Ok(routed) => routed,
+        Err(e) => {
+            let mut response = e.into_response();
+            headers::stamp_metadata(response.headers_mut(), &meta, &request.model, None);
+            Recorder::new(&state, &meta, request.model, &caller).finish(response.status().as_u16(), None, None);
+            return response;
+        }
+    };
+    request.model = backend.served_model(&model).to_string();
+    let mut recorder = Recorder::new(&state, &meta, model.clone(), &caller);
+    recorder.set_tenant(caller.tenant().cloned());
+    recorder.started();
+
+    let replica = backend.pick_replica(&state.latency);
+    if let Some(replica) = &replica {
+        recorder.set_backend(replica);
+    }
+    let generated = match &replica {
+        Some(replica) => generate(&state, &headers, &backend, replica, &request).await,
+        None => Err(AppError::NoBackendAvailable(model.clone())),
+    };
+    let mut response = match generated {
+        Ok(Generated::Passthrough(response_headers, body)) => {
+            let body = Body::from_stream(stream::passthrough(body, &backend.timeouts, recorder));
+            (response_headers, body).into_response()
+        }
+        Ok(Generated::Images(images)) => {
+            recorder.finish(200, None, None);
+            Json(images).into_response()
+        }
+        Err(e) => {
+            let response = e.into_response();
+            recorder.finish(response.status().as_u16(), None, None);
+            response
+        }
+    };
+    headers::stamp_metadata(response.headers_mut(), &meta, &model, replica.as_deref());
+    response
+}
+
+enum Generated {
+    Passthrough(HeaderMap, stream::UpstreamBody), // the backend's own response
+    Images(Value), // translated to OpenAI's format
+}
+
+async fn generate(
+    state: &AppState,
+    headers: &HeaderMap,
+    backend: &Backend,
+    replica: &str,
+    request: &ImageRequest,
+) -> Result<Generated, AppError> {
+    if mock::is_mock(replica) {
+        let images = vec![MOCK_PNG.to_string(); request.n.unwrap_or(1) as usize];
+        return Ok(Generated::Images(openai_images(images, request)));
+    }
+    if backend.config.api == BackendApi::SdWebui {
+        let url = format!("{}/sdapi/v1/txt2img", backend.base_url(replica));
+        let payload = to_txt2img(request)?;
+        info!("Routing image generation for model '{}' to: {}", request.model, url);
+        let exchange = async {
+            let outbound = backend.authorize(state.header_policy.forward_request(headers, backend.client_for(replica).post(&url))).json(&payload);
+            let res = upstream::forward(backend, outbound, url.clone()).await?;
+            res.json::<Value>().await.map_err(AppError::BackendRequestFailed)
+        };
+        let generated = upstream::within_total(backend, &url, exchange).await?;
+        let images = generated.get("images").and_then(Value::as_array)
+            .map(|images| images.iter().filter_map(Value::as_str).map(str::to_string).collect())
+            .ok_or_else(|| AppError::Internal(format!("The backend at {} returned no images.", url)))?;
+        return Ok(Generated::Images(openai_images(images, request)));
+    }
+
+    let url = backend.endpoint_url(replica, "images/generations", &request.model);
+    info!("Routing image generation for model '{}' to: {}", request.model, url);
+    let outbound = backend.authorize(state.header_policy.forward_request(headers, backend.client_for(replica).post(&url))).json(request);
+    let res = upstream::forward(backend, outbound, url).await?;
+    Ok(Generated::Passthrough(upstream::response_headers(&state.header_policy, &res), Box::pin(res.bytes_stream())))
+}
+
+// AUTOMATIC1111 / Forge `txt2img`: `size` becomes width and height, `n` the batch
+// size, and `quality` a step count. Anything else in the request (negative_prompt,
+// steps, cfg_scale, sampler_name, seed, ...) is passed on as is and wins.
+fn to_txt2img(request: &ImageRequest) -> Result<Value, AppError> {
+    if request.stream == Some(true) {
+        return Err(AppError::InvalidRequest(format!("Model '{}' can't stream images.", request.model)));
+    }
+    let mut payload = json!({ "prompt": request.prompt, "batch_size": request.n.unwrap_or(1) });
+    if let Some(size) = request.size.as_deref().filter(|size| *size != "auto") {
+        let (width, height) = size.split_once('x')
+            .and_then(|(width, height)| Some((width.parse::<u32>().ok()?, height.parse::<u32>().ok()?)))
+            .ok_or_else(|| AppError::InvalidRequest(format!("Invalid size '{}'; expected WIDTHxHEIGHT.", size)))?;
+        payload["width"] = width.into();
+        payload["height"] = height.into();
+    }
+    let steps = match request.quality.as_deref() {
+        None | Some("auto") => None,
+        Some("low") => Some(15),
+        Some("standard" | "medium") => Some(25),
+        Some("hd" | "high") => Some(40),
+        Some(other) => return Err(AppError::InvalidRequest(format!("Invalid quality '{}'.", other))),
+    };
+    if let Some(steps) = steps {
+        payload["steps"] = steps.into();
+    }
+    for (name, value) in &request.options {
+        payload[name] = value.clone();
+    }
+    Ok(payload)
+}
+
+// Base64 PNGs as OpenAI's image response. The gateway hosts no files, so `url`
+// gives data: URLs.
+fn openai_images(images: Vec<String>, request: &ImageRequest) -> Value {
+    let data: Vec<Value> = images.into_iter()
+        .map(|image| match request.response_format.as_deref() {
+            Some("url") => json!({ "url": format!("data:image/png;base64,{}", image) }),
+            _ => json!({ "b64_json": image }),
+        })
+        .collect();
+    json!({ "created": chrono::Utc::now().timestamp(), "data": data })
+}
+
@@ -0,0 +1,347 @@
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    http::{header::LOCATION, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::AbortHandle;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+use crate::{auth::Caller, batch, config, metadata, routing, store::Store, tenants, AppError, AppState, ChatRequest};
+
+// --- Async Jobs ---
+// POST /v1/chat/completions?async=true answers 202 at once with a job and keeps
+// generating on the gateway; GET /v1/jobs/{id} returns the job, with its
+// `chat.completion` once it's done, so a multi-minute generation no longer
+// depends on one connection staying up. POST /v1/jobs/{id}/cancel stops it. Jobs
+// run like batch lines (retryable failures are retried) with the request's
+// headers, belong to the key that created them, and are kept for
+// ASYNC_JOB_TTL_SECS (default 3600) after they finish: in memory by the instance
+// running them, and in a persistent store, where every instance sharing it reads
+// them. At most ASYNC_JOB_LIMIT (default 1000) are kept by one instance at once.
+// A cancel through another instance only marks the stored job; the instance
+// running it checks every CANCEL_CHECK and stops the generation.
+const CANCEL_CHECK: Duration = Duration::from_secs(2);
+
+pub struct Jobs {
+    entries: Mutex<HashMap<String, Entry>>,
+    ttl: Duration,
+    limit: usize,
+}
+
+struct Entry {
+    job: Job,
+    owner: Option<String>, // API key name
+    task: Option<AbortHandle>, // until the job finishes
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    InProgress,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Job {
+    pub id: String,
+    pub object: String,
+    pub model: String,
+    pub status: JobStatus,
+    pub created_at: i64,
+    pub completed_at: Option<i64>,
+    pub request_id: Option<String>, // the last attempt's x-request-id
+    pub status_code: Option<u16>, // what the request would have answered
+    #[schema(value_type = Option<Object>)]
+    pub result: Option<Value>, // the chat.completion
+    #[schema(value_type = Option<Object>)]
+    pub error: Option<Value>, // OpenAI's error object
+}
+
+impl Jobs {
+    pub fn from_env() -> Result<Self> {
+        let ttl = config::env_parse("ASYNC_JOB_TTL_SECS")?.unwrap_or(3600);
+        let limit = config::env_parse("ASYNC_JOB_LIMIT")?.unwrap_or(1000);
+        Ok(Jobs { entries: Mutex::new(HashMap::new()), ttl: Duration::from_secs(ttl), limit })
+    }
+
+    // When jobs that finished before it are forgotten.
+    fn cutoff(&self) -> i64 {
+        Utc::now().timestamp() - self.ttl.as_secs() as i64
+    }
+
+    fn sweep(&self, entries: &mut HashMap<String, Entry>) {
+        let cutoff = self.cutoff();
+        entries.retain(|_, entry| entry.job.completed_at.is_none_or(|at| at > cutoff));
+    }
+
+    // Records the outcome here and then in the store, unless the job was
+    // cancelled in the meantime, here or through another instance.
+    async fn finish(&self, store: &dyn Store, id: &str, status: u16, request_id: Option<String>, response: Value) {
+        let job = {
+            let mut entries = self.entries.lock().unwrap();
+            let Some(entry) = entries.get_mut(id).filter(|entry| entry.task.is_some()) else { return };
+            entry.task = None;
+            let job = &mut entry.job;
+            job.completed_at = Some(Utc::now().timestamp());
+            job.request_id = request_id;
+            job.status_code = Some(status);
+            match status {
+                200 => {
+                    job.status = JobStatus::Completed;
+                    job.result = Some(response);
+                }
+                _ => {
+                    job.status = JobStatus::Failed;
+                    job.error = Some(response.get("error").cloned().unwrap_or(response));
+                }
+            }
+            info!("Job {} for model '{}' finished with {}", id, job.model, status);
+            job.clone()
+        };
+        match save_finished(store, &job).await {
+            Ok(true) => {}
+            Ok(false) => info!("Job {} was cancelled through another instance; its result is discarded", id),
+            Err(e) => warn!("Failed to save job {}: {:#}", id, e),
+        }
+    }
+
+    // Takes the store's record of a job cancelled through another instance.
+    fn cancelled_elsewhere(&self, job: Job) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get_mut(&job.id).filter(|entry| entry.task.is_some()) {
+            info!("Job {} was cancelled through another instance; stopping it", job.id);
+            entry.task = None;
+            entry.job = job;
+        }
+    }
+}
+
+// Resolves once the stored job is no longer in progress: only a cancel can do
+// that while this instance is still running it.
+async fn cancelled_in_store(store: &dyn Store, id: &str) -> Job {
+    let mut checks = tokio::time::interval(CANCEL_CHECK);
+    checks.tick().await;
+    loop {
+        checks.tick().await;
+        match store.load_job(id).await {
+            Ok(Some((_, body))) => match serde_json::from_str::<Job>(&body) {
+                Ok(job) if job.status != JobStatus::InProgress => return job,
+                Ok(_) => {}
+                Err(e) => warn!("Invalid job {} in the store: {}", id, e),
+            },
+            Ok(None) => {}
+            Err(e) => warn!("Failed to check job {} for a cancel: {:#}", id, e),
+        }
+    }
+}
+
+async fn save_finished(store: &dyn Store, job: &Job) -> Result<bool> {
+    store.finish_job(&job.id, serde_json::to_string(job)?).await
+}
+
+// A job as the store has it, for jobs run by other instances (or by this one
+// before it restarted).
+async fn load(state: &AppState, caller: &Caller, id: &str) -> Result<Option<Job>, AppError> {
+    let Some((owner, body)) = state.store.load_job(id).await.map_err(internal)? else { return Ok(None) };
+    if !batch::owned_by(caller, &owner) {
+        return Ok(None);
+    }
+    let job: Job = serde_json::from_str(&body).map_err(|e| AppError::Internal(format!("Invalid job {} in the store: {}", id, e)))?;
+    Ok(Some(job).filter(|job| job.completed_at.is_none_or(|at| at > state.jobs.cutoff())))
+}
+
+fn internal(e: anyhow::Error) -> AppError {
+    AppError::Internal(format!("{:#}", e))
+}
+
+fn not_found(id: &str) -> AppError {
+    AppError::NotFound(format!("No such job: '{}'.", id))
+}
+
+// What would turn the request away before it reached a backend: invalid metadata,
+// a model the key may not use, or one nobody serves. Checked before the 202, so
+// a job that can't run isn't accepted. Each attempt is checked again when it runs.
+fn check(state: &AppState, caller: &Caller, headers: &HeaderMap, body: &ChatRequest) -> Result<(), AppError> {
+    metadata::collect(headers, body.metadata.as_ref())?;
+    // Plugins may rewrite the model, and they only run with the job.
+    #[cfg(feature = "wasm-plugins")]
+    if state.plugins.is_some() {
+        return Ok(());
+    }
+    let mut body = body.clone();
+    if let Some(target) = caller.resolve_alias(&body.model) {
+        body.model = target.clone();
+    }
+    let pinned = routing::RoutingOverride::from_headers(caller, headers)?;
+    if let Some(route) = &pinned.route {
+        body.model = route.clone();
+    }
+    if !pinned.is_set() {
+        routing::apply_routing_rules(state, &state.routing_rules, &mut body);
+        if let Some(auto) = state.auto_router.as_ref().filter(|auto| auto.name == body.model) {
+            routing::route_auto(state, auto, caller, headers, &mut body)?;
+        }
+        if state.cascades.contains_key(&body.model) {
+            return caller.authorize_model(&body.model);
+        }
+    }
+    caller.authorize_model(&body.model)?;
+    tenants::backend_for(state, caller, &body.model).map(drop).ok_or_else(|| AppError::ModelNotFound(body.model.clone()))
+}
+
+// Starts `body` as a job instead of answering it. The job is in the store before
+// the 202, so any instance can answer the first poll.
+pub async fn submit(state: Arc<AppState>, caller: Caller, headers: HeaderMap, body: ChatRequest) -> Result<Response, AppError> {
+    if body.stream == Some(true) {
+        return Err(AppError::InvalidRequest("Async jobs return a chat.completion; leave out `stream` or set it to false.".to_string()));
+    }
+    check(&state, &caller, &headers, &body)?;
+    let job = Job {
+        id: format!("job_{}", uuid::Uuid::new_v4().simple()),
+        object: "chat.completion.job".to_string(),
+        model: body.model.clone(),
+        status: JobStatus::InProgress,
+        created_at: Utc::now().timestamp(),
+        completed_at: None,
+        request_id: None,
+        status_code: None,
+        result: None,
+        error: None,
+    };
+    let jobs = &state.jobs;
+    {
+        let mut entries = jobs.entries.lock().unwrap();
+        jobs.sweep(&mut entries);
+        if entries.len() >= jobs.limit {
+            return Err(AppError::Overloaded {
+                message: format!("The gateway is holding its limit of {} async jobs. Try again later.", jobs.limit),
+                retry_after_secs: state.retry_after_secs,
+            });
+        }
+    }
+    let cutoff = chrono::DateTime::from_timestamp(jobs.cutoff(), 0).unwrap_or_default();
+    if let Err(e) = state.store.forget_jobs(cutoff).await {
+        warn!("Failed to forget finished jobs: {:#}", e);
+    }
+    let owner = caller.key().map(|key| key.name.clone());
+    let stored = serde_json::to_string(&job).map_err(|e| AppError::Internal(e.to_string()))?;
+    state.store.insert_job(&job.id, owner.as_deref(), stored).await.map_err(internal)?;
+    info!("Starting job {} for model '{}'", job.id, job.model);
+
+    // The job can't record its result before the entry exists: that needs the lock held here.
+    let mut entries = jobs.entries.lock().unwrap();
+    let run = tokio::spawn({
+        let state = state.clone();
+        async move { batch::run_line(&state, caller, &headers, body).await }
+    });
+    let task = run.abort_handle();
+    tokio::spawn({
+        let state = state.clone();
+        let id = job.id.clone();
+        async move {
+            let mut run = run;
+            let persistent = state.store.persistent();
+            let finished = tokio::select! {
+                finished = &mut run => finished,
+                job = cancelled_in_store(state.store.as_ref(), &id), if persistent => {
+                    run.abort();
+                    state.jobs.cancelled_elsewhere(job);
+                    return;
+                }
+            };
+            let (status, request_id, response) = match finished {
+                Ok((status, request_id, response)) => (status, Some(request_id), response),
+                Err(e) if e.is_cancelled() => return,
+                Err(e) => {
+                    error!("Job {} failed inside the gateway: {}", id, e);
+                    (500, None, json!({ "error": { "message": "The job failed inside the gateway.", "type": "api_error", "code": null } }))
+                }
+            };
+            state.jobs.finish(state.store.as_ref(), &id, status, request_id, response).await;
+        }
+    });
+    entries.insert(job.id.clone(), Entry { job: job.clone(), owner, task: Some(task) });
+    drop(entries);
+    let location = format!("/v1/jobs/{}", job.id);
+    Ok((StatusCode::ACCEPTED, [(LOCATION, location)], Json(job)).into_response())
+}
+
+#[utoipa::path(
+    get, path = "/v1/jobs/{id}", tag = "Chat", params(("id" = String, Path)),
+    responses((status = 200, description = "The job; `result` holds the chat.completion once `status` is `completed`.", body = Job)),
+)]
+pub async fn get_job(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, AppError> {
+    // A persistent store has every instance's jobs, and a cancel made through any of them.
+    if state.store.persistent() {
+        return load(&state, &caller, &id).await?.map(Json).ok_or_else(|| not_found(&id));
+    }
+    let jobs = &state.jobs;
+    let mut entries = jobs.entries.lock().unwrap();
+    jobs.sweep(&mut entries);
+    entries.get(&id)
+        .filter(|entry| batch::owned_by(&caller, &entry.owner))
+        .map(|entry| Json(entry.job.clone()))
+        .ok_or_else(|| not_found(&id))
+}
+
+// Stops the generation; a finished job is returned as it is. A job run by another
+// instance is marked cancelled in the store, and that instance discards its
+// result.
+#[utoipa::path(
+    post, path = "/v1/jobs/{id}/cancel", tag = "Chat", params(("id" = String, Path)),
+    responses((status = 200, description = "The job, cancelled unless it had already finished.", body = Job)),
+)]
+pub async fn cancel_job(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, AppError> {
+    let local = {
+        let mut entries = state.jobs.entries.lock().unwrap();
+        entries.get_mut(&id).filter(|entry| batch::owned_by(&caller, &entry.owner)).map(|entry| {
+            let running = entry.task.take().inspect(|task| task.abort()).is_some();
+            if running {
+                entry.job.status = JobStatus::Cancelled;
+                entry.job.completed_at = Some(Utc::now().timestamp());
+                info!("Cancelled job {}", id);
+            }
+            (entry.job.clone(), running)
+        })
+    };
+    if !state.store.persistent() {
+        return local.map(|(job, _)| Json(job)).ok_or_else(|| not_found(&id));
+    }
+    let job = match local {
+        Some((job, true)) => Some(job),
+        _ => load(&state, &caller, &id).await?.filter(|job| job.status == JobStatus::InProgress).map(|mut job| {
+            job.status = JobStatus::Cancelled;
+            job.completed_at = Some(Utc::now().timestamp());
+            job
+        }),
+    };
+    // Unless it finished (or was cancelled) first elsewhere, whose record is then the answer.
+    if let Some(job) = job {
+        if save_finished(state.store.as_ref(), &job).await.map_err(internal)? {
+            info!("Cancelled job {} in the store", id);
+            return Ok(Json(job));
+        }
+    }
+    load(&state, &caller, &id).await?.map(Json).ok_or_else(|| not_found(&id))
+}
@@ -0,0 +1,107 @@
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{redaction::Redactor, stream::delta_contents_mut, ChatMessage};
+
+// --- Logged Content ---
+// How much of a request's prompt and completion a sink that stores content
+// (ARCHIVE_SINK, CAPTURE_DIR) keeps. Each sink has its own mode, and a tenant's
+// `log_content` makes it stricter for that tenant's requests, never looser. The
+// request log, metrics, and lifecycle events never hold content.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum LogContent {
+    // Prompts and completions verbatim.
+    #[default]
+    Full,
+    // PII masked first.
+    Redacted,
+    // Each text replaced by its SHA-256, so repeated prompts can still be matched.
+    Hashed,
+    // Request log fields only.
+    Metadata,
+}
+
+impl LogContent {
+    pub fn stricter(self, other: Option<LogContent>) -> LogContent {
+        other.map_or(self, |other| self.max(other))
+    }
+
+    // Applies a `redacted` or `hashed` mode to one text.
+    pub fn apply(self, redactor: &Redactor, text: &mut String) {
+        match self {
+            LogContent::Redacted => *text = redactor.redact_text(text),
+            LogContent::Hashed => *text = hash(text),
+            LogContent::Full | LogContent::Metadata => {}
+        }
+    }
+
+    pub fn apply_messages(self, redactor: &Redactor, messages: &mut [ChatMessage]) {
+        for text in messages.iter_mut().flat_map(|m| m.content.texts_mut()) {
+            self.apply(redactor, text);
+        }
+    }
+
+    // Applies the mode to the content of one streamed chunk, given as its JSON payload.
+    pub fn apply_payload(self, redactor: &Redactor, payload: &mut String) {
+        if matches!(self, LogContent::Full | LogContent::Metadata) {
+            return;
+        }
+        let Ok(mut chunk) = serde_json::from_str::<Value>(payload) else { return };
+        for (_, content, _) in delta_contents_mut(&mut chunk) {
+            self.apply(redactor, content);
+        }
+        *payload = chunk.to_string();
+    }
+}
+
+impl std::str::FromStr for LogContent {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(Value::String(s.to_string()))
+    }
+}
+
+fn hash(text: &str) -> String {
+    format!("sha256:{:x}", Sha256::digest(text.as_bytes()))
+}
+
+// --- Content Sampling ---
+// LOG_SAMPLING keeps the content of only a `rate` share of requests in those
+// sinks (the others are archived as metadata and not captured), so busy
+// deployments don't flood them. Failed requests are always kept unless `errors`
+// is false, and an API key's `log_sample_rate` replaces the rate for its requests.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct LogSampling {
+    #[serde(default = "default_rate")]
+    pub rate: f64,
+    #[serde(default = "crate::config::default_true")]
+    pub errors: bool,
+}
+
+fn default_rate() -> f64 {
+    1.0
+}
+
+impl Default for LogSampling {
+    fn default() -> Self {
+        LogSampling { rate: default_rate(), errors: true }
+    }
+}
+
+impl LogSampling {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let sampling: LogSampling = crate::config::env_json("LOG_SAMPLING")?.unwrap_or_default();
+        if !(0.0..=1.0).contains(&sampling.rate) {
+            anyhow::bail!("LOG_SAMPLING rate must be between 0 and 1, got {}", sampling.rate);
+        }
+        Ok(sampling)
+    }
+
+    pub fn sample(&self, key_rate: Option<f64>) -> bool {
+        let rate = key_rate.unwrap_or(self.rate);
+        rate >= 1.0 || fastrand::f64() < rate
+    }
+}
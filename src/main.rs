@@ -1,30 +1,122 @@
 use axum::{
-    extract::{Json, State},
-    response::{sse::Event, IntoResponse, Response, Sse},
+    extract::{rejection::QueryRejection, Json, Query, State},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Router,
-    http::StatusCode,
+    extract::Extension,
+    http::{header::ACCEPT, HeaderMap, HeaderValue},
 };
-use futures_core::stream::Stream;
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use std::{collections::HashMap, convert::Infallible, net::SocketAddr, pin::Pin, sync::Arc};
+use std::{borrow::Cow, collections::HashMap, net::SocketAddr, sync::Arc, time::{Duration, Instant}};
 use tokio::net::TcpListener;
-// use tokio_stream::StreamExt as TokioStreamExt; // <--- FIX: Removed this line to resolve ambiguity
-use tracing::{info, error, Level};
+use tracing::{info, Level};
 use tracing_subscriber::EnvFilter;
+use utoipa::ToSchema;
 use anyhow::{Context, Result};
 use dotenv::dotenv;
-use bytes::Bytes;
-use futures::{stream, StreamExt}; // We will use this trait for both .map() and .flatten()
+
+mod access;
+mod active;
+mod admin;
+mod alerts;
+mod archive;
+mod audio;
+mod audit;
+mod auth;
+mod aws;
+mod backend;
+mod batch;
+mod bench;
+mod capture;
+mod cascade;
+mod chaos;
+mod check;
+mod cli;
+mod client;
+mod config;
+mod compression;
+mod context;
+mod credentials;
+mod error;
+mod events;
+mod drain;
+mod fleet;
+mod cors;
+mod discovery;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod guardrails;
+mod headers;
+mod health;
+mod idempotency;
+mod images;
+mod jobs;
+mod log_content;
+mod metadata;
+mod methods;
+mod metrics;
+mod mock;
+mod models;
+mod moderations;
+mod normalize;
+mod openapi;
+mod output_filter;
+mod output_limit;
+mod pacing;
+mod params;
+mod priority;
+#[cfg(feature = "wasm-plugins")]
+mod plugins;
+mod prompt;
+mod prompt_cache;
+mod queue;
+mod realtime;
+mod redaction;
+mod registry;
+mod request_log;
+mod responses;
+mod rerank;
+mod resume;
+mod routing;
+mod secrets;
+mod sessions;
+mod stop;
+mod store;
+mod stream;
+mod tenants;
+mod tls;
+mod tokenizer;
+mod trace;
+mod uds;
+mod upstream;
+mod usage;
+mod verify;
+mod websocket;
+
+use auth::{Caller, KeyStore};
+use backend::{Backend, BackendLoader, BackendTable};
+use chaos::ChaosConfig;
+use clap::Parser;
+use error::AppError;
+use guardrails::{Guardrail, HttpGuardrail, HttpGuardrailConfig};
+use headers::HeaderPolicy;
+use idempotency::{Claim, IdempotencyStore, SharedResponse, StreamResumes};
+use output_filter::{ContentFilter, OutputPolicy};
+use params::ParamLimits;
+use prompt::SystemPromptConfig;
+use redaction::Redactor;
+use request_log::{Recorder, RequestLog};
+use resume::Resume;
+use stream::{ChunkFilter, PayloadStream, ResponseCheck, StreamControl};
+use usage::{StreamOptions, UsageTap};
 
 
 // --- Data Structures for OpenAI API Compatibility ---
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 struct ChatMessage {
     role: String,
-    content: String,
+    #[serde(default)]
+    content: MessageContent,
     #[serde(skip_serializing_if = "Option::is_none")]
     name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -33,7 +125,59 @@ struct ChatMessage {
     tool_call_id: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+// A plain string, a list of OpenAI content parts (`text`, `image_url`, ...), or
+// null/absent, as on assistant messages that only carry tool calls.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, ToSchema)]
+#[serde(untagged)]
+enum MessageContent {
+    Text(String),
+    Parts(Vec<serde_json::Value>),
+    #[default]
+    Empty,
+}
+
+impl MessageContent {
+    // The text of the message; text parts are joined with newlines.
+    fn text(&self) -> Cow<'_, str> {
+        match self {
+            MessageContent::Text(text) => Cow::Borrowed(text),
+            MessageContent::Parts(parts) => Cow::Owned(
+                parts.iter().filter_map(|p| p.get("text").and_then(|t| t.as_str())).collect::<Vec<_>>().join("\n"),
+            ),
+            MessageContent::Empty => Cow::Borrowed(""),
+        }
+    }
+
+    // Every piece of text, for stages that rewrite it in place.
+    fn texts_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            MessageContent::Text(text) => vec![text],
+            MessageContent::Parts(parts) => parts.iter_mut()
+                .filter_map(|p| match p.get_mut("text") {
+                    Some(serde_json::Value::String(text)) => Some(text),
+                    _ => None,
+                })
+                .collect(),
+            MessageContent::Empty => Vec::new(),
+        }
+    }
+
+    fn has_image(&self) -> bool {
+        match self {
+            MessageContent::Parts(parts) => parts.iter()
+                .any(|p| matches!(p.get("type").and_then(|t| t.as_str()), Some("image_url" | "input_image"))),
+            _ => false,
+        }
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        MessageContent::Text(text)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 struct ChatRequest {
     model: String,
     messages: Vec<ChatMessage>,
@@ -48,88 +192,447 @@ struct ChatRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     frequency_penalty: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    stop: Option<serde_json::Value>,
+    stop: Option<stop::StringOrVec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: Option<bool>,
+    // vLLM extensions, used to continue a partial assistant message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    continue_final_message: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    add_generation_prompt: Option<bool>,
+    // String annotations, see Request Metadata; only forwarded with `forward_metadata`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    metadata: Option<serde_json::Value>,
 }
 
-// --- Application State ---
-struct AppState {
-    http_client: Client,
-    vllm_backends: HashMap<String, String>, // model_name -> vLLM_base_url
+#[derive(Debug, Deserialize)]
+struct ChatQuery {
+    #[serde(default, rename = "async")]
+    run_async: bool,
 }
 
-// --- Custom Error Type ---
-enum AppError {
-    ModelNotFound(String),
-    BackendRequestFailed(reqwest::Error),
-    BackendRespondedError { status: StatusCode, text: String, url: String },
+#[derive(Debug, Deserialize, ToSchema)]
+struct TokenCountRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
 }
 
-// Implement IntoResponse to convert AppError into an HTTP response.
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AppError::ModelNotFound(model) => (
-                StatusCode::BAD_REQUEST,
-                format!("Model '{}' not found in gateway configuration.", model),
-            ),
-            AppError::BackendRequestFailed(e) => {
-                error!("Request to backend failed: {}", e);
-                (StatusCode::BAD_GATEWAY, format!("Upstream request failed: {}", e))
-            }
-            AppError::BackendRespondedError { status, text, url } => {
-                error!("Backend at {} returned error {}: {}", url, status, text);
-                (status, format!("Upstream service error: {}", text))
-            }
-        };
+#[derive(Debug, Serialize, ToSchema)]
+struct TokenCountResponse {
+    object: &'static str,
+    model: String,
+    prompt_tokens: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context_length: Option<u32>,
+}
 
-        let body = Json(json!({ "error": error_message }));
-        (status, body).into_response()
+// Identifies one chat request across logs, events, and response headers.
+#[derive(Debug, Clone)]
+struct RequestMeta {
+    id: String,
+    received: Instant,
+    metadata: Arc<metadata::Metadata>, // the client's annotations
+    client_model: Option<String>, // set by a cascade, whose models answer under its name
+}
+
+impl RequestMeta {
+    fn new() -> Self {
+        RequestMeta { id: uuid::Uuid::new_v4().to_string(), received: Instant::now(), metadata: Arc::default(), client_model: None }
     }
 }
 
+// --- Application State ---
+struct AppState {
+    vllm_backends: Arc<BackendTable>, // model_name -> vLLM backend, swapped by the backend registry
+    audio_backends: backend::Backends, // model_name -> transcription or speech backend
+    image_backends: backend::Backends, // model_name -> image generation backend
+    rerank_backends: backend::Backends, // model_name -> reranker
+    realtime_backends: backend::Backends, // model_name -> Realtime API server
+    realtime_api_key: Option<secrets::Secret>, // bearer token for a hosted Realtime provider
+    tenants: tenants::Tenants, // per-team models, aliases, and quotas
+    max_streams: Option<queue::StreamLimit>, // gateway-wide cap on concurrent streams
+    retry_after_secs: u64, // sent with 503s for capacity limits
+    default_system_prompt: Option<SystemPromptConfig>, // applied to models without their own
+    redactor: Option<Redactor>, // PII masking for backends with redact_pii enabled
+    output_policy: Option<Arc<OutputPolicy>>, // content policy for streamed output
+    guardrails: Vec<Arc<dyn Guardrail>>, // pre-request / post-response moderation hooks
+    moderator: Option<moderations::Moderator>, // the classifier behind /v1/moderations
+    header_policy: HeaderPolicy, // allowlisted headers passed through in each direction
+    api_keys: Option<KeyStore>, // None disables authentication on /v1 routes
+    credentials: Option<Arc<credentials::CredentialStore>>, // keys and upstream credentials created through /admin
+    audit: Arc<audit::AuditLog>, // admin changes, by whom
+    idempotency: IdempotencyStore, // in-flight and recent responses by Idempotency-Key
+    stream_resumes: Option<StreamResumes>, // recent streams by request ID, for Last-Event-ID
+    request_log: Arc<RequestLog>, // recent requests, for the admin usage API
+    store: Arc<dyn store::Store>, // STORE_URL, where requests, audit entries, and stored keys are persisted
+    active: Arc<active::ActiveRequests>, // in-flight requests, for listing and cancelling
+    drains: Arc<drain::Drains>, // replicas taking no new requests
+    fleet: Option<Arc<fleet::Fleet>>, // FLEET_REDIS_URL, for sharing drains
+    batches: Option<Arc<batch::BatchStore>>, // Batch API files and jobs, when enabled
+    jobs: jobs::Jobs, // chat requests sent with ?async=true
+    events: Option<events::EventBus>, // lifecycle event sink (NATS / Kafka)
+    archive: Option<archive::Archiver>, // prompt/completion archive in object storage
+    alerts: Option<Arc<alerts::Alerter>>, // webhook alerts on budgets, error rates, and outages
+    metrics: Arc<metrics::Metrics>, // Prometheus histograms served at /metrics
+    capability_routing: bool, // route tools/vision requests by model capabilities
+    routing_rules: Vec<routing::RoutingRule>, // model rewrites by request shape
+    auto_router: Option<routing::AutoRouterConfig>, // virtual model picking the cheapest fit
+    cascades: HashMap<String, cascade::CascadeConfig>, // virtual models trying a small model first
+    latency: Arc<routing::LatencyTracker>, // recent time to first token per replica
+    health: Option<Arc<health::HealthMonitor>>, // replica probes, when enabled
+    capture: Option<capture::Capturer>, // request/response recording for replay
+    log_sampling: log_content::LogSampling, // which requests' content the archive and capture keep
+    traces: Arc<trace::TraceStore>, // recent stream traces asked for with X-Gateway-Trace
+    sessions: Option<sessions::SessionStore>, // session -> replica, for prefix cache reuse
+    #[cfg(feature = "wasm-plugins")]
+    plugins: Option<plugins::PluginHost>, // WASM request/response transforms
+}
+
 // --- Main Function ---
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize tracing for better logging control via RUST_LOG env var
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env().add_directive(Level::INFO.into()))
-        .init();
+    let cli = cli::Cli::parse();
+    let command = cli.command.unwrap_or(cli::Command::Serve(cli.settings));
+
+    // Initialize tracing for better logging control via RUST_LOG env var. The
+    // one-shot commands log to stderr, keeping stdout for their output.
+    let filter = EnvFilter::from_default_env().add_directive(Level::INFO.into());
+    match command {
+        cli::Command::Serve(_) => tracing_subscriber::fmt().with_env_filter(filter).init(),
+        _ => tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr).init(),
+    }
 
     dotenv().ok(); // Load .env file if it exists
 
+    match command {
+        cli::Command::Serve(settings) => {
+            settings.apply()?;
+            serve(load(settings.mock, true).await?).await
+        }
+        cli::Command::CheckConfig(args) => {
+            args.settings.apply()?;
+            check::run(args.settings.mock, args.ping).await
+        }
+        cli::Command::PrintRoutes(settings) => {
+            settings.apply()?;
+            cli::print_routes(&load(settings.mock, false).await?.state);
+            Ok(())
+        }
+        cli::Command::Replay(args) => capture::replay(args).await,
+        cli::Command::Bench(args) => bench::bench(args).await,
+    }
+}
+
+// Everything `serve` needs, built from the settings without serving anything.
+struct Gateway {
+    state: Arc<AppState>,
+    app: Router,
+    addr: uds::ListenAddr,
+    tls: Option<axum_server::tls_rustls::RustlsConfig>,
+    grpc_addr: Option<String>,
+    verify: verify::Mode,
+}
+
+// The one-shot commands pass `migrate: false` to leave the store's schema alone.
+async fn load(mock_mode: bool, migrate: bool) -> Result<Gateway> {
+    secrets::load_files()?;
+
     // Load and parse backend configuration from environment variables
-    let vllm_backends_json = std::env::var("VLLM_BACKENDS")
-        .context("VLLM_BACKENDS environment variable not set")?;
-    let vllm_backends: HashMap<String, String> = serde_json::from_str(&vllm_backends_json)
-        .context("Failed to parse VLLM_BACKENDS. Make sure it's valid JSON on a single line.")?;
+    let registry: Option<registry::RegistryConfig> = config::env_json("BACKEND_REGISTRY")?;
+    let vllm_backends_json = match std::env::var("VLLM_BACKENDS") {
+        Err(_) if registry.is_some() => "{}".to_string(),
+        Err(_) if mock_mode => r#"{"mock": "mock://lorem"}"#.to_string(),
+        result => result.context("VLLM_BACKENDS environment variable not set")?,
+    };
+    let backend_configs = config::parse_backends(&vllm_backends_json)?;
+    if mock_mode {
+        info!("Mock mode: all models are served by mock://lorem");
+    }
 
+    let default_system_prompt: Option<SystemPromptConfig> = config::env_json("DEFAULT_SYSTEM_PROMPT")?;
+
+    let redactor = config::env_json("PII_REDACTION")?.map(Redactor::new).transpose()?;
+
+    let output_policy = config::env_json("OUTPUT_FILTER")?.map(OutputPolicy::new).transpose()?.map(Arc::new);
+
+    let client_settings = client::ClientSettings {
+        tls: config::env_json("UPSTREAM_TLS")?,
+        pool: config::env_json("UPSTREAM_POOL")?,
+        proxy: config::env_json("UPSTREAM_PROXY")?,
+        timeouts: config::env_json("UPSTREAM_TIMEOUTS")?,
+        ip_family: Default::default(),
+    };
+    let http_client = client::build_client(&client_settings)?;
+    let secrets = secrets::Secrets::from_env(http_client.clone())?;
+    let store = store::from_env(migrate).await?;
+    let credentials = match config::env_json("CREDENTIAL_STORE")? {
+        Some(config) => Some(credentials::CredentialStore::open(config, &secrets, &http_client, store.clone()).await?),
+        None => None,
+    };
+    if let Some(store) = &credentials {
+        secrets.use_store(store.clone());
+    }
+
+    let fleet = fleet::Fleet::from_env()?;
+    let drains = Arc::new(drain::Drains::default());
+    let loader = BackendLoader {
+        client: http_client.clone(),
+        settings: client_settings,
+        mock_mode,
+        redaction: redactor.is_some(),
+        secrets: secrets.clone(),
+        fleet: fleet.clone(),
+        drains: drains.clone(),
+    };
     info!("Configured vLLM Backends:");
-    for (model_name, url) in &vllm_backends {
-        info!("  - Model: '{}' -> URL: '{}'", model_name, url);
+    let mut backends = HashMap::new();
+    for (model_name, backend_config) in backend_configs {
+        let backend = loader.load(&model_name, backend_config)?;
+        backends.insert(model_name, Arc::new(backend));
+    }
+    discovery::start(&backends)?;
+    let vllm_backends = Arc::new(BackendTable::new(backends));
+    let tenants = tenants::Tenants::from_env(&loader)?;
+    let audio_backends = loader.load_table("AUDIO_BACKENDS")?;
+    let image_backends = loader.load_table("IMAGE_BACKENDS")?;
+    let rerank_backends = loader.load_table("RERANK_BACKENDS")?;
+    let realtime_backends = loader.load_table("REALTIME_BACKENDS")?;
+    let realtime_api_key = std::env::var("REALTIME_API_KEY").ok().filter(|key| !key.is_empty())
+        .map(|reference| secrets.reference(&reference))
+        .transpose()
+        .context("Invalid REALTIME_API_KEY")?;
+    if let Some(registry) = registry {
+        registry::start(registry, loader, vllm_backends.clone()).await?;
+    }
+    secrets.start().await?;
+
+    let guardrail_configs: Vec<HttpGuardrailConfig> = config::env_json("GUARDRAILS")?.unwrap_or_default();
+    let guardrails: Vec<Arc<dyn Guardrail>> = guardrail_configs.into_iter()
+        .map(|config| {
+            info!("Guardrail '{}' -> {} ({:?})", config.name, config.url, config.on);
+            Arc::new(HttpGuardrail::new(config, http_client.clone())) as Arc<dyn Guardrail>
+        })
+        .collect();
+
+    let moderator = config::env_json("MODERATIONS")?
+        .map(|config| moderations::Moderator::new(config, http_client.clone()))
+        .transpose()?;
+
+    let plugin_configs: Option<Vec<serde_json::Value>> = config::env_json("WASM_PLUGINS")?;
+    #[cfg(feature = "wasm-plugins")]
+    let plugins = match plugin_configs {
+        Some(configs) => {
+            let configs = serde_json::from_value(serde_json::Value::Array(configs))
+                .context("Failed to parse WASM_PLUGINS")?;
+            Some(plugins::PluginHost::load(configs)?)
+        }
+        None => None,
+    };
+    #[cfg(not(feature = "wasm-plugins"))]
+    if plugin_configs.is_some() {
+        anyhow::bail!("WASM_PLUGINS is set but the gateway was built without the `wasm-plugins` feature");
+    }
+
+    let header_policy = HeaderPolicy::from_env()?;
+
+    let api_keys = KeyStore::from_env(credentials.clone())?;
+    match &api_keys {
+        Some(store) => {
+            store.check_tenants(&tenants)?;
+            info!("API key authentication enabled ({} keys)", store.len());
+        }
+        None => info!("GATEWAY_API_KEYS not set; /v1 routes are unauthenticated"),
+    }
+
+    let idempotency = IdempotencyStore::from_env()?;
+    let stream_resumes = StreamResumes::from_env()?;
+    let request_log = Arc::new(RequestLog::from_env(store.clone()).await?);
+    let batch_config: Option<batch::BatchConfig> = config::env_json("BATCH_API")?;
+    let batch_config_bytes = batch_config.as_ref().map(|config| config.max_file_bytes);
+    let batches = batch_config.map(batch::BatchStore::open).transpose()?;
+    let events = match config::env_json("EVENT_SINK")? {
+        Some(sink) => Some(events::EventBus::connect(sink).await?),
+        None => None,
+    };
+    let archive = config::env_json("ARCHIVE_SINK")?
+        .map(|sink| archive::Archiver::start(sink, http_client.clone()))
+        .transpose()?;
+    let alerts = config::env_json("ALERTS")?
+        .map(|config| Arc::new(alerts::Alerter::new(config, http_client.clone())));
+    if let Some(alerts) = &alerts {
+        alerts.sync_spend(store.clone());
+    }
+    let health = config::env_parse::<u64>("HEALTH_CHECK_INTERVAL_SECS")?
+        .filter(|secs| *secs > 0)
+        .map(|secs| health::HealthMonitor::start(vllm_backends.clone(), Duration::from_secs(secs), alerts.clone(), fleet.clone()));
+    if let Some(fleet) = &fleet {
+        fleet.start(vllm_backends.clone(), health.clone(), drains.clone())?;
+    }
+
+    let auto_router: Option<routing::AutoRouterConfig> = config::env_json("AUTO_ROUTER")?;
+    let cascades = cascade::load(&vllm_backends)?;
+    let routing_rules: Vec<routing::RoutingRule> = config::env_json("ROUTING_RULES")?.unwrap_or_default();
+    for rule in &routing_rules {
+        let is_auto = auto_router.as_ref().is_some_and(|auto| auto.name == rule.route_to);
+        if !is_auto && !cascades.contains_key(&rule.route_to) && !vllm_backends.contains(&rule.route_to) {
+            anyhow::bail!("ROUTING_RULES routes to unknown model '{}'", rule.route_to);
+        }
     }
 
     let app_state = Arc::new(AppState {
-        http_client: Client::new(),
         vllm_backends,
+        audio_backends,
+        image_backends,
+        rerank_backends,
+        realtime_backends,
+        realtime_api_key,
+        tenants,
+        max_streams: config::env_parse("MAX_CONCURRENT_STREAMS")?.map(queue::StreamLimit::new),
+        retry_after_secs: config::env_parse("OVERLOAD_RETRY_AFTER_SECS")?.unwrap_or(1),
+        default_system_prompt,
+        redactor,
+        output_policy,
+        guardrails,
+        moderator,
+        header_policy,
+        api_keys,
+        credentials,
+        audit: audit::AuditLog::from_env(store.clone()).await?,
+        idempotency,
+        stream_resumes,
+        request_log,
+        store,
+        active: Arc::new(active::ActiveRequests::default()),
+        drains,
+        fleet,
+        batches,
+        jobs: jobs::Jobs::from_env()?,
+        events,
+        archive,
+        alerts,
+        metrics: Arc::new(metrics::Metrics::new(metadata::metric_labels()?)?),
+        capability_routing: config::env_parse("CAPABILITY_ROUTING")?.unwrap_or(false),
+        routing_rules,
+        auto_router,
+        cascades,
+        latency: Arc::new(routing::LatencyTracker::default()),
+        health,
+        capture: std::env::var_os("CAPTURE_DIR")
+            .map(|dir| capture::Capturer::start(dir.into(), config::env_parse("CAPTURE_CONTENT")?.unwrap_or_default()))
+            .transpose()?,
+        log_sampling: log_content::LogSampling::from_env()?,
+        traces: Arc::new(trace::TraceStore::from_env()?),
+        sessions: config::env_json("STICKY_SESSIONS")?.map(sessions::SessionStore::new),
+        #[cfg(feature = "wasm-plugins")]
+        plugins,
     });
 
     // Define application routes
-    let app = Router::new()
-        .route("/health", get(health_check))
+    let batch_routes = match &batch_config_bytes {
+        Some(max_file_bytes) => batch::router(*max_file_bytes),
+        None => Router::new(),
+    };
+    let audio_upload_bytes = config::env_parse("AUDIO_MAX_UPLOAD_BYTES")?.unwrap_or(audio::DEFAULT_MAX_UPLOAD_BYTES);
+    let mut app = Router::new()
         .route("/v1/chat/completions", post(proxy_chat)) // OpenAI compatible route
-        .with_state(app_state);
+        .route("/v1/chat/completions/ws", get(websocket::upgrade))
+        .route("/v1/responses", post(responses::create))
+        .route("/v1/images/generations", post(images::generations))
+        .route("/v1/moderations", post(moderations::create))
+        .route("/v1/rerank", post(rerank::rerank))
+        .route("/v1/realtime", get(realtime::connect))
+        .route("/v1/token_count", post(token_count))
+        .route("/v1/requests/cancel", post(active::client_cancel))
+        .route("/v1/jobs/:id", get(jobs::get_job))
+        .route("/v1/jobs/:id/cancel", post(jobs::cancel_job))
+        .route("/v1/models", get(models::list))
+        .route("/v1/models/*id", get(models::get))
+        .merge(audio::router(audio_upload_bytes))
+        .merge(batch_routes)
+        .route_layer(axum::middleware::from_fn_with_state(app_state.clone(), auth::authenticate))
+        .route("/health", get(health_check))
+        .route("/metrics", get(metrics::serve))
+        .route("/openapi.json", get(openapi::serve))
+        .with_state(app_state.clone());
+    let grpc_addr = std::env::var("GRPC_LISTEN_ADDR").ok().filter(|addr| !addr.is_empty());
+    #[cfg(not(feature = "grpc"))]
+    if grpc_addr.is_some() {
+        anyhow::bail!("GRPC_LISTEN_ADDR is set but the gateway was built without the `grpc` feature");
+    }
+
+    match admin::AdminKeys::from_env()? {
+        Some(admins) => {
+            info!("Admin API enabled ({} admin keys)", admins.len());
+            app = app.merge(admin::router(app_state.clone(), admins));
+        }
+        None => info!("ADMIN_API_KEY and ADMIN_KEYS not set; /admin routes are disabled"),
+    }
+    // Wrapped in an outer router so the middleware sees 405s with their Allow header.
+    app = Router::new().fallback_service(app.fallback(methods::not_found)).layer(axum::middleware::from_fn(methods::handle));
+    if let Some(ip_access) = config::env_json::<access::IpAccessConfig>("IP_ACCESS")? {
+        let policy = Arc::new(access::IpAccessPolicy::new(ip_access));
+        app = app.layer(axum::middleware::from_fn_with_state(policy, access::enforce));
+    }
+    if let Some(compression) = compression::layer_from_env()? {
+        app = app.layer(compression);
+    }
+    if let Some(cors) = cors::layer_from_env()? {
+        app = app.layer(cors);
+    }
 
     // Get listen address from environment or use default
     let addr_str = std::env::var("GATEWAY_LISTEN_ADDR").unwrap_or_else(|_| "0.0.0.0:3000".to_string());
-    let addr: SocketAddr = addr_str.parse()
-        .context(format!("Invalid GATEWAY_LISTEN_ADDR format: {}", addr_str))?;
+    let addr = uds::ListenAddr::parse(&addr_str)?;
+    let tls = match tls::TlsSettings::from_env()? {
+        Some(tls) => Some(tls.load().await?),
+        None => None,
+    };
+    if tls.is_some() && matches!(addr, uds::ListenAddr::Unix(_)) {
+        anyhow::bail!("TLS is not supported on a Unix socket GATEWAY_LISTEN_ADDR");
+    }
+
+    let verify = verify::Mode::from_env()?;
+
+    Ok(Gateway { state: app_state, app, addr, tls, grpc_addr, verify })
+}
+
+async fn serve(gateway: Gateway) -> Result<()> {
+    let Gateway { state, app, addr, tls, grpc_addr, verify } = gateway;
+    verify::run(&state, verify).await?;
+    batch::resume(&state);
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_addr) = &grpc_addr {
+        grpc::spawn(state.clone(), grpc_addr)?;
+    }
+    #[cfg(not(feature = "grpc"))]
+    let _ = grpc_addr; // `load` rejects it in this build
+
+    let addr = match addr {
+        uds::ListenAddr::Tcp(addr) => addr,
+        uds::ListenAddr::Unix(path) => return uds::serve(&path, app).await,
+    };
+    if let Some(rustls_config) = tls {
+        info!("🚀 Gateway listening on https://{}", addr);
+        axum_server::bind_rustls(addr, rustls_config)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .context(format!("Failed to serve HTTPS on {}", addr))?;
+        return Ok(());
+    }
 
     let listener = TcpListener::bind(&addr).await
-        .context(format!("Failed to bind to address: {}", addr_str))?;
+        .context(format!("Failed to bind to address: {}", addr))?;
     info!("🚀 Gateway listening on http://{}", listener.local_addr()?);
-    axum::serve(listener, app.into_make_service())
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .context("Server failed to start")?;
 
@@ -137,79 +640,474 @@ async fn main() -> Result<()> {
 }
 
 // --- Handlers ---
+#[utoipa::path(get, path = "/health", tag = "Operations", responses((status = 200, description = "The gateway is up.", body = String)))]
 async fn health_check() -> &'static str {
     "OK"
 }
 
+#[utoipa::path(
+    post, path = "/v1/chat/completions", tag = "Chat",
+    description = "Streams a chat completion as server-sent `chat.completion.chunk` events, ending with `[DONE]`, or returns a buffered `chat.completion` for `stream: false` or `Accept: application/json`.",
+    request_body = ChatRequest,
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays the first response for retries with the same key and body."),
+        ("Last-Event-ID" = Option<String>, Header, description = "With X-Request-ID, resumes a dropped stream after this event (needs STREAM_RESUME_SECS)."),
+        ("async" = Option<bool>, Query, description = "Runs the request as a job fetched from /v1/jobs/{id} instead of answering it."),
+    ),
+    responses(
+        (status = 200, description = "The completion stream, or the buffered completion.", content_type = "text/event-stream", body = String),
+        (status = 202, description = "With `async=true`: the job, also linked in the Location header.", body = jobs::Job),
+        (status = 406, description = "The Accept header rules out the format `stream` asks for."),
+    ),
+)]
 async fn proxy_chat(
     State(state): State<Arc<AppState>>,
-    Json(mut body): Json<ChatRequest>,
-) -> Result<Sse<Pin<Box<dyn Stream<Item = Result<Event, Infallible>> + Send>>>, AppError> {
+    Extension(caller): Extension<Caller>,
+    query: Result<Query<ChatQuery>, QueryRejection>,
+    headers: HeaderMap,
+    Json(body): Json<ChatRequest>,
+) -> Response {
+    let query = match query {
+        Ok(Query(query)) => query,
+        Err(e) => return AppError::InvalidRequest(format!("Invalid query string: {}", e.body_text())).into_response(),
+    };
+    if query.run_async {
+        return jobs::submit(state, caller, headers, body).await.unwrap_or_else(IntoResponse::into_response);
+    }
+    let mut meta = RequestMeta::new();
+    let model = body.model.clone();
+    // Kept for the archive in case the request fails before its stream starts.
+    let messages = (state.archive.is_some() && state.log_sampling.errors).then(|| body.messages.clone());
+    let result = match metadata::collect(&headers, body.metadata.as_ref()) {
+        Ok(metadata) => {
+            meta.metadata = Arc::new(metadata);
+            chat_response(state.clone(), caller.clone(), headers, body, meta.clone()).await
+        }
+        Err(e) => Err(e),
+    };
+    let mut response = match result {
+        Ok(response) => response,
+        Err(e) => e.into_response(),
+    };
+    // Streams were stamped with the backend once it was chosen, and are logged
+    // when they end (even if the buffered response then fails); replays were
+    // logged the first time around.
+    let started = response.headers().contains_key("x-gateway-model");
+    if !started {
+        headers::stamp_metadata(response.headers_mut(), &meta, &model, None);
+    }
+    if !(started || response.status().is_success() || response.headers().contains_key(idempotency::REPLAYED_HEADER)) {
+        let mut recorder = Recorder::new(&state, &meta, model, &caller);
+        recorder.set_tenant(caller.tenant().cloned());
+        if let Some(messages) = &messages {
+            recorder.set_messages(messages);
+        }
+        recorder.finish(response.status().as_u16(), None, None);
+    }
+    response
+}
+
+async fn chat_response(
+    state: Arc<AppState>,
+    caller: Caller,
+    headers: HeaderMap,
+    mut body: ChatRequest,
+    meta: RequestMeta,
+) -> Result<Response, AppError> {
+    let format = negotiate(&headers, body.stream)?;
+    let scope = caller.key().map_or(String::new(), |k| k.name.clone());
+    if let Some(resumes) = state.stream_resumes.as_ref().filter(|_| headers.contains_key(idempotency::LAST_EVENT_ID)) {
+        return resumes.resume(&scope, &headers).await;
+    }
+    let mut leader = None;
+    let mut replay = None;
+    if let Some(key) = headers.get(idempotency::HEADER) {
+        let key = key.to_str().map_err(|_| AppError::InvalidRequest("Idempotency-Key must be ASCII.".to_string()))?;
+        match state.idempotency.claim(&scope, key, &body)? {
+            Claim::Leader(shared) => leader = Some((shared, None)),
+            Claim::Replay(shared) => replay = Some(shared),
+            Claim::Uncached => {}
+        }
+    }
+    // Only now, so the idempotency fingerprint is of the body as the client sent it.
+    body.stream = Some(format == Format::Sse);
+    if format == Format::Json {
+        body.stream_options = Some(StreamOptions { include_usage: true }); // a chat.completion always has usage
+    }
+    if let Some(resumes) = state.stream_resumes.as_ref().filter(|_| format == Format::Sse) {
+        let (shared, _) = leader.get_or_insert_with(|| (Arc::new(SharedResponse::new()), Some(resumes.window())));
+        resumes.track(&scope, &meta.id, shared.clone());
+    }
+
+    let replayed = replay.is_some();
+    let (mut response_headers, payloads) = match (leader, replay) {
+        (Some((shared, linger)), _) => {
+            shared.run(start_chat(state.clone(), caller, headers, body, meta), linger);
+            match shared.follow(0).await {
+                Ok(started) => started,
+                Err(response) => return Ok(response),
+            }
+        }
+        (None, Some(shared)) => match shared.follow(0).await {
+            Ok(started) => started,
+            Err(mut response) => {
+                response.headers_mut().insert(idempotency::REPLAYED_HEADER, HeaderValue::from_static("true"));
+                return Ok(response);
+            }
+        },
+        (None, None) => start_chat(state, caller, headers, body, meta).await?,
+    };
+    let mut response = match format {
+        Format::Sse => (response_headers, stream::sse_response(payloads, 0)).into_response(),
+        Format::Json => match stream::collect(payloads).await {
+            Ok(completion) => {
+                headers::stamp_cost(&mut response_headers, &completion);
+                (response_headers, Json(completion)).into_response()
+            }
+            Err(message) => (response_headers, AppError::StreamFailed(message)).into_response(),
+        },
+    };
+    if replayed {
+        response.headers_mut().insert(idempotency::REPLAYED_HEADER, HeaderValue::from_static("true"));
+    }
+    Ok(response)
+}
+
+// --- Response Format ---
+// `stream: true` gets server-sent events and `stream: false` a buffered
+// `chat.completion`. Without `stream`, an Accept header that names
+// application/json (and not text/event-stream) gets JSON; anything else streams,
+// as before. A `stream` flag the Accept header rules out gets a 406.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Sse,
+    Json,
+}
+
+fn negotiate(headers: &HeaderMap, stream: Option<bool>) -> Result<Format, AppError> {
+    let accept = headers.get_all(ACCEPT).iter().filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let media = parts.next()?.to_ascii_lowercase();
+            let refused = parts.any(|param| param.strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0));
+            (!media.is_empty() && !refused).then_some(media)
+        })
+        .collect::<Vec<_>>();
+    let named = |media: &str| accept.iter().any(|range| range == media);
+    let allows = |media: &str| {
+        let kind = media.split('/').next().unwrap_or_default();
+        accept.is_empty() || accept.iter().any(|range| range == media || range == "*/*" || *range == format!("{}/*", kind))
+    };
+    let (sse, json) = (allows("text/event-stream"), allows("application/json"));
+    match stream {
+        Some(true) if sse => Ok(Format::Sse),
+        Some(false) if json => Ok(Format::Json),
+        Some(true) => Err(AppError::NotAcceptable(
+            "`stream: true` responds with text/event-stream, which the Accept header doesn't allow. Drop `stream` or accept text/event-stream.".to_string(),
+        )),
+        Some(false) => Err(AppError::NotAcceptable(
+            "`stream: false` responds with application/json, which the Accept header doesn't allow. Drop `stream` or accept application/json.".to_string(),
+        )),
+        None if named("application/json") && !named("text/event-stream") => Ok(Format::Json),
+        None if sse => Ok(Format::Sse),
+        None if json => Ok(Format::Json),
+        None => Err(AppError::NotAcceptable(
+            "Chat completions are sent as text/event-stream or application/json; the Accept header allows neither.".to_string(),
+        )),
+    }
+}
+
+// Everything up to the start of the response stream; owns its inputs so it can
+// also run detached for idempotent requests.
+async fn start_chat(
+    state: Arc<AppState>,
+    caller: Caller,
+    headers: HeaderMap,
+    mut body: ChatRequest,
+    meta: RequestMeta,
+) -> Result<(HeaderMap, PayloadStream), AppError> {
+    let client_streams = body.stream == Some(true); // batch and non-streaming callers aren't paced
+    // Before plugins, aliases, and routing pick another.
+    let client_model = meta.client_model.clone().unwrap_or_else(|| body.model.clone());
     body.stream = Some(true);
+    let client_wants_usage = body.stream_options.is_some_and(|o| o.include_usage);
+    body.stream_options = Some(StreamOptions { include_usage: true });
 
-    info!("Received chat request for model: {}", body.model);
+    // Plugins run before routing so they can rewrite the target model.
+    #[cfg(feature = "wasm-plugins")]
+    if let Some(plugins) = &state.plugins {
+        plugins.on_request(&mut body)?;
+    }
+    stop::normalize_stop(&mut body)?;
+
+    match meta.metadata.is_empty() {
+        true => info!("Received chat request for model: {}", body.model),
+        false => info!("Received chat request for model: {} ({})", body.model, metadata::describe(&meta.metadata)),
+    }
+    let stream_permit = state.max_streams.as_ref().map(|streams| {
+        streams.try_acquire().ok_or_else(|| AppError::Overloaded {
+            message: format!("The gateway is at its limit of {} concurrent streams. Try again later.", streams.limit()),
+            retry_after_secs: state.retry_after_secs,
+        })
+    }).transpose()?;
+    if let Some(tenant) = caller.tenant() {
+        tenant.admit()?;
+    }
+    if let Some(target) = caller.resolve_alias(&body.model) {
+        body.model = target.clone();
+    }
+    let pinned = routing::RoutingOverride::from_headers(&caller, &headers)?;
+    let header_chaos = chaos::ChaosConfig::from_headers(&caller, &headers)?;
+    let priority = priority::from_headers(&caller, &headers)?;
+    if let Some(route) = &pinned.route {
+        body.model = route.clone();
+    }
+    if !pinned.is_set() {
+        routing::apply_routing_rules(&state, &state.routing_rules, &mut body);
+        if let Some(auto) = state.auto_router.as_ref().filter(|auto| auto.name == body.model) {
+            routing::route_auto(&state, auto, &caller, &headers, &mut body)?;
+        }
+        if state.cascades.contains_key(&body.model) {
+            drop(stream_permit); // each model in the cascade takes its own
+            body.stream = Some(client_streams);
+            body.stream_options = Some(StreamOptions { include_usage: client_wants_usage });
+            let name = body.model.clone();
+            let meta = RequestMeta { client_model: Some(client_model), ..meta };
+            return cascade::run(state, caller, headers, body, meta, name).await;
+        }
+    }
+    // Failures before the stream starts are logged by `proxy_chat` instead.
+    let mut recorder = Recorder::new(&state, &meta, body.model.clone(), &caller);
+    recorder.set_tenant(caller.tenant().cloned());
+    if let Some(permit) = stream_permit {
+        recorder.hold(permit);
+    }
+    recorder.set_messages(&body.messages);
+    if trace::requested(&caller, &headers)? {
+        recorder.trace();
+    }
 
-    let vllm_base_url = state.vllm_backends.get(&body.model)
+    caller.authorize_model(&body.model)?;
+    let mut backend = tenants::backend_for(&state, &caller, &body.model)
         .ok_or_else(|| AppError::ModelNotFound(body.model.clone()))?;
+    // Only requests for a model this caller may use count as in flight.
+    recorder.started();
+    if state.capability_routing && !pinned.is_set() {
+        backend = routing::route_by_capability(&state, &caller, &mut body, backend)?;
+    }
+    priority::admit(priority, &body.model, &backend, state.retry_after_secs)?;
+    if let Some(streams) = &backend.streams {
+        let permit = streams.try_acquire().ok_or_else(|| AppError::Overloaded {
+            message: format!("Model '{}' is at its limit of {} concurrent streams. Try again later.", body.model, streams.limit()),
+            retry_after_secs: state.retry_after_secs,
+        })?;
+        recorder.hold(permit);
+    }
+    let pinned_replica = pinned.replica(&backend, &body.model)?;
+    let config = &backend.config;
+    let chaos = header_chaos.as_ref().or(config.chaos.as_ref());
 
-    let target_url = format!("{}/v1/chat/completions", vllm_base_url);
-    info!("Routing request for model '{}' to: {}", body.model, &target_url);
+    if let Some(tenant) = caller.tenant() {
+        params::apply_param_policy(&mut body, &tenant.defaults, &ParamLimits::default());
+    }
+    params::apply_param_policy(&mut body, &config.defaults, &config.limits);
+    let omitted_max_tokens = body.max_tokens.is_none();
+    if let Some(system_prompt) = system_prompt_for(&state, &caller, &backend) {
+        prompt::apply_system_prompt(&mut body, system_prompt, &headers);
+    }
+    if config.redact_pii {
+        if let Some(redactor) = &state.redactor {
+            redactor.redact_request(&mut body);
+        }
+    }
+    if let (Some(requested), Some(max)) = (body.max_tokens, config.max_output_tokens) {
+        if requested > max {
+            return Err(AppError::InvalidRequest(format!(
+                "max_tokens is too large: {}. Model '{}' supports at most {} completion tokens.", requested, body.model, max
+            )));
+        }
+    }
+    let mut prompt_tokens = None;
+    if let Some(context_length) = config.context_length {
+        let counted = context::enforce_context_window(&mut body, &backend.tokenizer, context_length, config.on_context_overflow)?;
+        prompt_tokens = Some(counted);
+        if config.fill_max_tokens {
+            context::fill_max_tokens(&mut body, counted, context_length, config.max_tokens_headroom, config.max_output_tokens);
+        }
+    }
+    if omitted_max_tokens {
+        let default = caller.tenant().and_then(|tenant| tenant.defaults.max_tokens).or(config.defaults.max_tokens);
+        params::settle_omitted_max_tokens(&mut body, default, config.limits.max_tokens);
+    }
 
-    let res = state.http_client
-        .post(&target_url)
-        .json(&body)
-        .send()
-        .await
-        .map_err(AppError::BackendRequestFailed)?;
-
-    if !res.status().is_success() {
-        let status = res.status();
-        let text = res.text().await.unwrap_or_else(|_| "No response body".to_string());
-        return Err(AppError::BackendRespondedError { status, text, url: target_url });
-    }
-
-    Ok(Sse::new(stream_response(res)))
-}
-
-// --- Stream Response Function ---
-fn stream_response(
-    res: reqwest::Response,
-) -> Pin<Box<dyn Stream<Item = Result<Event, Infallible>> + Send>> {
-    let stream = res.bytes_stream()
-        .map(|chunk_result| { // Now this unambiguously uses `futures::StreamExt::map`
-            let chunk: Bytes = match chunk_result {
-                Ok(c) => c,
-                Err(e) => {
-                    let err_msg = format!("[Gateway Error: Could not read chunk from backend: {}]", e);
-                    error!("{}", err_msg);
-                    let event = Event::default().data(err_msg);
-                    return stream::iter(vec![Ok(event)]);
-                }
-            };
-
-            let text = match String::from_utf8(chunk.to_vec()) {
-                 Ok(s) => s,
-                 Err(e) => {
-                    let err_msg = format!("[Gateway Error: Non-UTF8 data received: {}]", e);
-                    error!("{}", err_msg);
-                    let event = Event::default().data(err_msg);
-                    return stream::iter(vec![Ok(event)]);
-                 }
-            };
-
-            let events = text.lines()
-                .filter_map(|line| {
-                    if let Some(data) = line.strip_prefix("data: ") {
-                        Some(Ok(Event::default().data(data.trim().to_string())))
-                    } else {
-                        None
-                    }
-                })
-                .collect::<Vec<_>>();
+    let cached_prefix = prompt_cache::prepare(&mut body, config.cache_control);
 
-            stream::iter(events)
-        })
-        .flatten(); // This also uses `futures::StreamExt`
+    guardrails::check_request(&state.guardrails, &body).await?;
+    body.metadata = (config.forward_metadata && !meta.metadata.is_empty())
+        .then(|| serde_json::to_value(&*meta.metadata).unwrap_or_default());
+    recorder.set_upstream_request(&body);
 
-    Box::pin(stream)
-}
\ No newline at end of file
+    if let Some(queue) = &backend.queue {
+        let (flow, weight) = caller.flow();
+        let slot = queue.acquire(&flow, weight, Duration::from_millis(config.queue_timeout_ms)).await
+            .ok_or_else(|| AppError::Overloaded {
+                message: format!(
+                    "Model '{}' is at capacity and the request waited {}ms without a slot. Try again later.",
+                    body.model, config.queue_timeout_ms
+                ),
+                retry_after_secs: state.retry_after_secs,
+            })?;
+        recorder.hold(slot);
+    }
+
+    let build = |request| backend.authorize(state.header_policy.forward_request(&headers, request));
+    let sticky = state.sessions.as_ref()
+        .filter(|_| pinned_replica.is_none() && backend.replicas.get().len() > 1)
+        .and_then(|sessions| {
+            let key = sessions.key(&caller, &headers, &body.model)
+                .or_else(|| Some(sessions.prefix_key(&body.model, cached_prefix.as_deref()?)))?;
+            Some((sessions, key))
+        });
+    let first = match (&pinned_replica, &sticky) {
+        (Some(replica), _) => Some(replica.clone()),
+        (None, Some((sessions, session))) => sessions.pick(session, &body.model, &backend, &state.latency, state.health.as_deref()),
+        (None, None) => backend.pick_replica(&state.latency),
+    }.ok_or_else(|| AppError::NoBackendAvailable(body.model.clone()))?;
+    let timeouts = backend.timeouts;
+    let deadline = timeouts.total().map(|total| tokio::time::Instant::now() + total);
+    let opening = async {
+        match &pinned_replica {
+            Some(_) => upstream::connect(&backend, &first, chaos, &body, &build).await,
+            None => upstream::open(&backend, first.clone(), chaos, &body, build).await,
+        }
+    };
+    let opened = match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, opening).await.unwrap_or_else(|_| Err(AppError::UpstreamTimeout {
+            url: first.clone(),
+            message: format!("The backend request did not complete within {}ms.", timeouts.total_ms.unwrap_or_default()),
+        })),
+        None => opening.await,
+    };
+    if let Some((sessions, session)) = &sticky {
+        match &opened {
+            Ok(upstream) => sessions.assign(session, &upstream.replica),
+            Err(_) => sessions.forget(session),
+        }
+    }
+    let upstream = opened?;
+
+    let mut filters: Vec<Box<dyn ChunkFilter>> = Vec::new();
+    // First, so the other filters see OpenAI's chunk shape.
+    if config.normalize_stream {
+        filters.push(Box::new(normalize::Normalizer::new(&client_model)));
+    }
+    if let Some(policy) = &state.output_policy {
+        filters.push(Box::new(ContentFilter::new(policy.clone())));
+    }
+    #[cfg(feature = "wasm-plugins")]
+    if let Some(filter) = state.plugins.as_ref().and_then(|p| p.stream_filter(&body)) {
+        filters.push(Box::new(filter));
+    }
+    let response_limit = output_limit::ResponseLimit::merge(config.response_limit, caller.key().and_then(|key| key.response_limit));
+    if let Some(limit) = response_limit {
+        let key = caller.key().map(|key| key.name.clone());
+        if let Some(filter) = output_limit::OutputLimit::new(limit, backend.clone(), state.events.clone(), &meta.id, &body.model, key) {
+            filters.push(Box::new(filter));
+        }
+    }
+    // Last, so it sees the text the other filters release.
+    if let Some(filter) = stop::StopFilter::new(config, &body) {
+        filters.push(Box::new(filter));
+    }
+
+    // Only a stream the gateway may end early needs its own count.
+    let prompt_tokens = (!filters.is_empty() || config.max_stream_duration_secs.is_some())
+        .then(|| prompt_tokens.unwrap_or_else(|| backend.tokenizer.count_messages(&body.messages)));
+    let response_guardrails: Vec<_> = state.guardrails.iter().filter(|g| g.checks_response()).cloned().collect();
+    let resume = (config.resume_attempts > 0 && pinned_replica.is_none()).then(|| {
+        Resume::new(state.clone(), backend.clone(), headers.clone(), body.clone(), upstream.replica.clone(), config.resume_attempts)
+    });
+    let model = body.model.clone();
+    let response_check = (!response_guardrails.is_empty())
+        .then(|| ResponseCheck { guardrails: response_guardrails, request: body });
+
+    let usage = UsageTap::new(model, client_wants_usage, config.pricing);
+    let control = StreamControl {
+        max_duration: config.max_stream_duration_secs.map(Duration::from_secs),
+        drop_after: chaos.and_then(ChaosConfig::drop_after),
+        idle: timeouts.idle(),
+        deadline,
+        source: Some((backend.clone(), upstream.replica.clone())),
+        model: Some(client_model),
+        prompt_tokens,
+    };
+
+    let mut response_headers = state.header_policy.forward_response(&upstream.headers);
+    let replica_url = &upstream.replica;
+    headers::stamp_metadata(&mut response_headers, &meta, &usage.model, Some(replica_url));
+    recorder.set_backend(replica_url);
+    let payloads = stream::stream_response(upstream.body, filters, response_check, resume, control, usage, recorder);
+    let payloads = match config.pacing.filter(|_| client_streams) {
+        Some(pacing) => pacing::pace(payloads, pacing, backend.clone()),
+        None => payloads,
+    };
+    Ok((response_headers, payloads))
+}
+
+// Most specific wins: the caller's key, then the model, then the gateway default.
+fn system_prompt_for<'a>(state: &'a AppState, caller: &'a Caller, backend: &'a Backend) -> Option<&'a SystemPromptConfig> {
+    caller.key().and_then(|key| key.system_prompt.as_ref())
+        .or(backend.config.system_prompt.as_ref())
+        .or(state.default_system_prompt.as_ref())
+}
+
+// Counts prompt tokens exactly as they would be forwarded, including any injected system prompt.
+#[utoipa::path(
+    post, path = "/v1/token_count", tag = "Chat",
+    request_body = TokenCountRequest,
+    responses((status = 200, description = "The prompt's token count.", body = TokenCountResponse)),
+)]
+async fn token_count(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    Json(req): Json<TokenCountRequest>,
+) -> Result<Json<TokenCountResponse>, AppError> {
+    let mut req = req;
+    if let Some(target) = caller.resolve_alias(&req.model) {
+        req.model = target.clone();
+    }
+    caller.authorize_model(&req.model)?;
+    let backend = tenants::backend_for(&state, &caller, &req.model)
+        .ok_or_else(|| AppError::ModelNotFound(req.model.clone()))?;
+
+    let mut body = ChatRequest {
+        model: req.model,
+        messages: req.messages,
+        max_tokens: None,
+        temperature: None,
+        top_p: None,
+        presence_penalty: None,
+        frequency_penalty: None,
+        stop: None,
+        tools: None,
+        tool_choice: None,
+        stream: None,
+        stream_options: None,
+        logprobs: None,
+        continue_final_message: None,
+        add_generation_prompt: None,
+        metadata: None,
+    };
+    if let Some(system_prompt) = system_prompt_for(&state, &caller, &backend) {
+        prompt::apply_system_prompt(&mut body, system_prompt, &headers);
+    }
+
+    Ok(Json(TokenCountResponse {
+        object: "token_count",
+        prompt_tokens: backend.tokenizer.count_messages(&body.messages),
+        model: body.model,
+        context_length: backend.config.context_length,
+    }))
+}
@@ -0,0 +1,77 @@
+use anyhow::Result;
+use axum::http::HeaderMap;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::{config, AppError};
+
+pub const HEADER_PREFIX: &str = "x-metadata-";
+const MAX_PAIRS: usize = 16;
+const MAX_KEY_CHARS: usize = 64;
+const MAX_VALUE_CHARS: usize = 512;
+
+// --- Request Metadata ---
+// A chat or Responses request may carry a `metadata` object of strings (e.g.
+// {"experiment": "exp-42"}), as OpenAI's API allows, and X-Metadata-<key> headers
+// add keys the body doesn't set, for clients that can't change the body. OpenAI's
+// limits apply: at most 16 keys of up to 64 characters, each value up to 512.
+// The metadata is kept with the request's log record (and so in the store,
+// archive, and usage exports), sent with its lifecycle events, and labels
+// `llm_gateway_requests_by_metadata_total` for the keys in
+// METADATA_METRIC_LABELS. Backends only receive it with `forward_metadata`.
+pub type Metadata = BTreeMap<String, String>;
+
+pub fn collect(headers: &HeaderMap, body: Option<&Value>) -> Result<Metadata, AppError> {
+    let mut metadata = Metadata::new();
+    match body {
+        None | Some(Value::Null) => {}
+        Some(Value::Object(entries)) => {
+            for (key, value) in entries {
+                let Value::String(value) = value else {
+                    return Err(invalid(format!("metadata.{} must be a string.", key)));
+                };
+                metadata.insert(key.clone(), value.clone());
+            }
+        }
+        Some(_) => return Err(invalid("metadata must be an object of strings.".to_string())),
+    }
+    for (name, value) in headers {
+        let Some(key) = name.as_str().strip_prefix(HEADER_PREFIX) else { continue };
+        let value = value.to_str().map_err(|_| invalid(format!("The {} header must be ASCII.", name)))?;
+        metadata.entry(key.to_string()).or_insert_with(|| value.to_string());
+    }
+    if metadata.len() > MAX_PAIRS {
+        return Err(invalid(format!("metadata has {} keys; at most {} are allowed.", metadata.len(), MAX_PAIRS)));
+    }
+    for (key, value) in &metadata {
+        if key.is_empty() || key.chars().count() > MAX_KEY_CHARS {
+            return Err(invalid(format!("metadata keys must be 1 to {} characters; '{}' is not.", MAX_KEY_CHARS, key)));
+        }
+        if value.chars().count() > MAX_VALUE_CHARS {
+            return Err(invalid(format!("metadata.{} is longer than {} characters.", key, MAX_VALUE_CHARS)));
+        }
+    }
+    Ok(metadata)
+}
+
+fn invalid(message: String) -> AppError {
+    AppError::InvalidRequest(message)
+}
+
+// `key=value, ...`, for log lines.
+pub fn describe(metadata: &Metadata) -> String {
+    metadata.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join(", ")
+}
+
+// The metadata keys in METADATA_METRIC_LABELS, labeled `metadata_<key>` with `-`
+// as `_`. Each distinct value becomes a time series, so list only keys with a
+// handful of values.
+pub fn metric_labels() -> Result<Vec<String>> {
+    let keys = config::env_list("METADATA_METRIC_LABELS");
+    for key in &keys {
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            anyhow::bail!("Invalid METADATA_METRIC_LABELS key '{}'; use letters, digits, `_`, and `-`", key);
+        }
+    }
+    Ok(keys)
+}
@@ -0,0 +1,49 @@
+use axum::{
+    extract::Request,
+    http::{header::ALLOW, HeaderValue, Method, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::AppError;
+
+// --- Method Handling ---
+// axum answers a known path with the wrong method with an empty 405, and an
+// unknown path with an empty 404. SDKs parse OpenAI's JSON error body, so both
+// get one instead, and 405s keep their Allow header. OPTIONS on a known path
+// gets a 204 listing its methods (CORS preflights are answered earlier, by the
+// CORS layer). HEAD works wherever GET does.
+pub async fn handle(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return response;
+    }
+    let allowed = response.headers().get(ALLOW).and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+
+    if method == Method::OPTIONS {
+        let allow = match allowed.is_empty() {
+            true => "OPTIONS".to_string(),
+            false => format!("{},OPTIONS", allowed),
+        };
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        if let Ok(allow) = HeaderValue::from_str(&allow) {
+            response.headers_mut().insert(ALLOW, allow);
+        }
+        return response;
+    }
+
+    let mut error = AppError::MethodNotAllowed(format!(
+        "{} is not supported for {}. Use {}.", method, path, allowed.replace(',', ", ")
+    )).into_response();
+    if let Ok(allow) = HeaderValue::from_str(&allowed) {
+        error.headers_mut().insert(ALLOW, allow);
+    }
+    error
+}
+
+// The router's fallback, for paths no route matches.
+pub async fn not_found(method: Method, uri: Uri) -> AppError {
+    AppError::NotFound(format!("Invalid URL ({} {}).", method, uri.path()))
+}
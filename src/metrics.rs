@@ -0,0 +1,132 @@
+use axum::{extract::State, http::header::CONTENT_TYPE, response::IntoResponse};
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+use anyhow::Result;
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{metadata::Metadata, AppState};
+
+// --- Prometheus Metrics ---
+// Served at GET /metrics. The histograms are labeled by `model` and `backend`
+// (the replica URL the stream was first sent to). With METADATA_METRIC_LABELS,
+// finished requests are also counted by model, status, and those metadata keys.
+pub struct Metrics {
+    registry: Registry,
+    time_to_first_token: HistogramVec,
+    tokens_per_second: HistogramVec,
+    requests_in_flight: IntGaugeVec,
+    prompt_tokens: IntCounterVec,
+    cached_prompt_tokens: IntCounterVec,
+    requests_by_metadata: Option<(Vec<String>, IntCounterVec)>, // with the metadata keys, in label order
+}
+
+impl Metrics {
+    pub fn new(metadata_keys: Vec<String>) -> Result<Self> {
+        let registry = Registry::new();
+        let time_to_first_token = HistogramVec::new(
+            HistogramOpts::new("llm_gateway_time_to_first_token_seconds", "Time from request received to first streamed token.")
+                .buckets(vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 30.0]),
+            &["model", "backend"],
+        ).expect("valid histogram");
+        let tokens_per_second = HistogramVec::new(
+            HistogramOpts::new("llm_gateway_output_tokens_per_second", "Completion tokens per second after the first token, per request.")
+                .buckets(vec![1.0, 5.0, 10.0, 20.0, 30.0, 50.0, 75.0, 100.0, 150.0, 200.0, 300.0]),
+            &["model", "backend"],
+        ).expect("valid histogram");
+        let requests_in_flight = IntGaugeVec::new(
+            Opts::new("llm_gateway_requests_in_flight", "Chat requests started and not yet finished."),
+            &["model"],
+        ).expect("valid gauge");
+        let prompt_tokens = IntCounterVec::new(
+            Opts::new("llm_gateway_prompt_tokens_total", "Prompt tokens of completed chat requests."),
+            &["model", "backend"],
+        ).expect("valid counter");
+        let cached_prompt_tokens = IntCounterVec::new(
+            Opts::new("llm_gateway_cached_prompt_tokens_total", "Prompt tokens the backend reported reading from its prompt cache."),
+            &["model", "backend"],
+        ).expect("valid counter");
+        registry.register(Box::new(time_to_first_token.clone())).expect("unique metric");
+        registry.register(Box::new(tokens_per_second.clone())).expect("unique metric");
+        registry.register(Box::new(requests_in_flight.clone())).expect("unique metric");
+        registry.register(Box::new(prompt_tokens.clone())).expect("unique metric");
+        registry.register(Box::new(cached_prompt_tokens.clone())).expect("unique metric");
+        let requests_by_metadata = match metadata_keys.is_empty() {
+            true => None,
+            false => {
+                let labels: Vec<String> = ["model".to_string(), "status".to_string()].into_iter()
+                    .chain(metadata_keys.iter().map(|key| format!("metadata_{}", key.replace('-', "_"))))
+                    .collect();
+                let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+                let counter = IntCounterVec::new(
+                    Opts::new("llm_gateway_requests_by_metadata_total", "Finished requests by model, status, and METADATA_METRIC_LABELS."),
+                    &labels,
+                )?;
+                registry.register(Box::new(counter.clone())).expect("unique metric");
+                Some((metadata_keys, counter))
+            }
+        };
+        Ok(Metrics { registry, time_to_first_token, tokens_per_second, requests_in_flight, prompt_tokens, cached_prompt_tokens, requests_by_metadata })
+    }
+
+    // Counts the request as in flight until the guard is dropped.
+    pub fn start_request(&self, model: &str) -> InFlight {
+        let gauge = self.requests_in_flight.with_label_values(&[model]);
+        gauge.inc();
+        InFlight(gauge)
+    }
+
+    pub fn in_flight(&self) -> BTreeMap<String, i64> {
+        let mut counts = BTreeMap::new();
+        for family in prometheus::core::Collector::collect(&self.requests_in_flight) {
+            for metric in family.get_metric() {
+                let model = metric.get_label().iter().find(|l| l.name() == "model").map(|l| l.value().to_string());
+                if let Some(model) = model {
+                    counts.insert(model, metric.get_gauge().get_value() as i64);
+                }
+            }
+        }
+        counts
+    }
+
+    pub fn observe_ttft(&self, model: &str, backend: &str, seconds: f64) {
+        self.time_to_first_token.with_label_values(&[model, backend]).observe(seconds);
+    }
+
+    pub fn observe_tokens_per_second(&self, model: &str, backend: &str, rate: f64) {
+        self.tokens_per_second.with_label_values(&[model, backend]).observe(rate);
+    }
+
+    // The two counters together give the prompt cache hit rate.
+    pub fn observe_prompt_tokens(&self, model: &str, backend: &str, prompt: u64, cached: u64) {
+        self.prompt_tokens.with_label_values(&[model, backend]).inc_by(prompt);
+        self.cached_prompt_tokens.with_label_values(&[model, backend]).inc_by(cached);
+    }
+
+    // Keys the request doesn't set count as "".
+    pub fn observe_metadata(&self, model: &str, status: u16, metadata: &Metadata) {
+        let Some((keys, counter)) = &self.requests_by_metadata else { return };
+        let status = status.to_string();
+        let values: Vec<&str> = [model, status.as_str()].into_iter()
+            .chain(keys.iter().map(|key| metadata.get(key).map_or("", String::as_str)))
+            .collect();
+        counter.with_label_values(&values).inc();
+    }
+}
+
+pub struct InFlight(IntGauge);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+#[utoipa::path(
+    get, path = "/metrics", tag = "Operations",
+    responses((status = 200, description = "Prometheus metrics.", content_type = "text/plain", body = String)),
+)]
+pub async fn serve(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let mut body = Vec::new();
+    let encoder = TextEncoder::new();
+    let _ = encoder.encode(&state.metrics.registry.gather(), &mut body);
+    ([(CONTENT_TYPE, encoder.format_type().to_string())], body)
+}
@@ -0,0 +1,141 @@
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures::stream;
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use crate::{backend::Backend, stream::UpstreamBody, ChatRequest};
+
+pub const SCHEME: &str = "mock://";
+
+const LOREM: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore \
+    et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud exercitation ullamco laboris nisi ut aliquip ex ea \
+    commodo consequat. Duis aute irure dolor in reprehenderit in voluptate velit esse cillum dolore eu fugiat nulla \
+    pariatur. Excepteur sint occaecat cupidatat non proident, sunt in culpa qui officia deserunt mollit anim id est laborum.";
+
+// --- Mock Backend ---
+// A replica URL of the form `mock://<mode>?<options>` is served by the gateway
+// itself instead of a vLLM server, streaming one word per token:
+//   mock://lorem  lorem ipsum, `tokens` words long (capped by `max_tokens`)
+//   mock://echo   the last user message
+// Options: `ttft_ms` (default 200), `tokens_per_second` (default 50), `tokens`
+// (default 64), and `text`, a canned reply that replaces the mode's output.
+#[derive(Debug, Clone)]
+pub struct MockConfig {
+    mode: MockMode,
+    ttft: Duration,
+    tokens_per_second: f64,
+    tokens: usize,
+    text: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum MockMode {
+    Lorem,
+    Echo,
+}
+
+pub fn is_mock(url: &str) -> bool {
+    url.starts_with(SCHEME)
+}
+
+impl MockConfig {
+    pub fn parse(url: &str) -> Result<Self> {
+        let parsed = Url::parse(url).with_context(|| format!("Invalid mock backend URL '{}'", url))?;
+        let mode = match parsed.host_str().unwrap_or("lorem") {
+            "lorem" => MockMode::Lorem,
+            "echo" => MockMode::Echo,
+            other => anyhow::bail!("Unknown mock backend mode '{}' in '{}'; use lorem or echo", other, url),
+        };
+        let mut config = MockConfig { mode, ttft: Duration::from_millis(200), tokens_per_second: 50.0, tokens: 64, text: None };
+        for (name, value) in parsed.query_pairs() {
+            let invalid = || format!("Invalid value '{}' for '{}' in mock backend URL '{}'", value, name, url);
+            match name.as_ref() {
+                "ttft_ms" => config.ttft = Duration::from_millis(value.parse().with_context(invalid)?),
+                "tokens_per_second" => config.tokens_per_second = value.parse().with_context(invalid)?,
+                "tokens" => config.tokens = value.parse().with_context(invalid)?,
+                "text" => config.text = Some(value.into_owned()),
+                other => anyhow::bail!("Unknown option '{}' in mock backend URL '{}'", other, url),
+            }
+        }
+        if config.tokens_per_second.is_nan() || config.tokens_per_second <= 0.0 {
+            anyhow::bail!("tokens_per_second must be positive in mock backend URL '{}'", url);
+        }
+        Ok(config)
+    }
+
+    fn text(&self, last_user: impl FnOnce() -> String) -> String {
+        match (&self.text, self.mode) {
+            (Some(text), _) => text.clone(),
+            (None, MockMode::Echo) => last_user(),
+            (None, MockMode::Lorem) => LOREM.split(' ').cycle().take(self.tokens).collect::<Vec<_>>().join(" "),
+        }
+    }
+
+    fn words(&self, body: &ChatRequest) -> (Vec<String>, &'static str) {
+        let text = self.text(|| body.messages.iter().rev()
+            .find(|m| m.role == "user")
+            .map(|m| m.content.text().into_owned())
+            .unwrap_or_default());
+        let mut words = split_words(&text);
+        match body.max_tokens.map(|max| max as usize).filter(|max| *max < words.len()) {
+            Some(max) => {
+                words.truncate(max);
+                (words, "length")
+            }
+            None => (words, "stop"),
+        }
+    }
+}
+
+fn split_words(text: &str) -> Vec<String> {
+    text.split(' ').filter(|word| !word.is_empty()).enumerate()
+        .map(|(i, word)| if i == 0 { word.to_string() } else { format!(" {}", word) })
+        .collect()
+}
+
+// A reply outside chat (realtime sessions): its words, the delay before the
+// first, and the delay between the rest.
+pub fn reply(url: &str, last_user: &str) -> Result<(Vec<String>, Duration, Duration)> {
+    let config = MockConfig::parse(url)?;
+    let words = split_words(&config.text(|| last_user.to_string()));
+    Ok((words, config.ttft, Duration::from_secs_f64(1.0 / config.tokens_per_second)))
+}
+
+// The whole response, paced like a real generation.
+pub fn stream_response(url: &str, backend: &Backend, body: &ChatRequest) -> Result<UpstreamBody> {
+    let config = MockConfig::parse(url)?;
+    let (words, finish_reason) = config.words(body);
+    let id = format!("chatcmpl-mock-{}", uuid::Uuid::new_v4());
+    let created = chrono::Utc::now().timestamp();
+    let chunk = |delta: Value, finish_reason: Option<&str>| json!({
+        "id": id, "object": "chat.completion.chunk", "created": created, "model": body.model,
+        "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+    });
+
+    let mut payloads: Vec<(Duration, String)> = Vec::new();
+    let per_token = Duration::from_secs_f64(1.0 / config.tokens_per_second);
+    for (i, word) in words.iter().enumerate() {
+        let delta = if i == 0 { json!({ "role": "assistant", "content": word }) } else { json!({ "content": word }) };
+        payloads.push((if i == 0 { config.ttft } else { per_token }, chunk(delta, None).to_string()));
+    }
+    let first_delay = if payloads.is_empty() { config.ttft } else { Duration::ZERO };
+    payloads.push((first_delay, chunk(json!({}), Some(finish_reason)).to_string()));
+    if body.stream_options.is_some_and(|o| o.include_usage) {
+        let prompt_tokens = backend.tokenizer.count_messages(&body.messages);
+        payloads.push((Duration::ZERO, json!({
+            "id": id, "object": "chat.completion.chunk", "created": created, "model": body.model, "choices": [],
+            "usage": { "prompt_tokens": prompt_tokens, "completion_tokens": words.len(), "total_tokens": prompt_tokens + words.len() },
+        }).to_string()));
+    }
+    payloads.push((Duration::ZERO, "[DONE]".to_string()));
+
+    Ok(Box::pin(stream::unfold(payloads.into_iter(), |mut payloads| async move {
+        let (delay, data) = payloads.next()?;
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        Some((Ok(Bytes::from(format!("data: {}\n\n", data))), payloads))
+    })))
+}
@@ -0,0 +1,103 @@
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::{auth::Caller, backend::Backend, tenants, usage::Pricing, AppError, AppState};
+
+// --- Model Registry ---
+// Per-model metadata comes from the backend config and is listed by /v1/models,
+// in the OpenAI format plus the gateway's own fields.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Tools,
+    Vision,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ModelInfo {
+    id: String,
+    object: &'static str,
+    created: i64,
+    owned_by: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context_length: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    capabilities: Vec<Capability>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pricing: Option<Pricing>,
+}
+
+impl ModelInfo {
+    fn new(id: &str, backend: &Backend) -> Self {
+        let config = &backend.config;
+        ModelInfo {
+            id: id.to_string(),
+            object: "model",
+            created: backend.created,
+            owned_by: "llm-gateway",
+            context_length: config.context_length,
+            max_output_tokens: config.max_output_tokens,
+            capabilities: config.capabilities.clone(),
+            pricing: config.pricing,
+        }
+    }
+}
+
+// Only the models the caller's key may use are listed, including its own and its
+// tenant's aliases (described by their targets).
+#[utoipa::path(
+    get, path = "/v1/models", tag = "Models",
+    responses((status = 200, description = "A list of the models this key may use, including its and its tenant's aliases.", body = Object)),
+)]
+pub async fn list(State(state): State<Arc<AppState>>, Extension(caller): Extension<Caller>) -> Json<serde_json::Value> {
+    let backends = tenants::visible_backends(&state, &caller);
+    let mut data: Vec<ModelInfo> = backends.iter()
+        .filter(|(id, _)| caller.authorize_model(id).is_ok())
+        .map(|(id, backend)| ModelInfo::new(id, backend))
+        .collect();
+    for (alias, target) in tenants::aliases(&caller) {
+        if let Some(backend) = backends.get(target).filter(|_| caller.authorize_model(target).is_ok()) {
+            data.retain(|model| model.id != *alias);
+            data.push(ModelInfo::new(alias, backend));
+        }
+    }
+    let virtual_models = state.auto_router.iter().map(|auto| &auto.name)
+        .chain(state.cascades.keys().filter(|name| caller.authorize_model(name).is_ok()));
+    for name in virtual_models {
+        data.push(ModelInfo {
+            id: name.clone(),
+            object: "model",
+            created: backends.values().map(|b| b.created).min().unwrap_or(0),
+            owned_by: "llm-gateway",
+            context_length: None,
+            max_output_tokens: None,
+            capabilities: Vec::new(),
+            pricing: None,
+        });
+    }
+    data.sort_by(|a, b| a.id.cmp(&b.id));
+    Json(serde_json::json!({ "object": "list", "data": data }))
+}
+
+// Model names may contain `/` (`meta-llama/Llama-3.1-8B-Instruct`), hence the wildcard route.
+#[utoipa::path(
+    get, path = "/v1/models/{id}", tag = "Models",
+    params(("id" = String, Path, description = "The model name; may contain `/`.")),
+    responses((status = 200, description = "The model.", body = ModelInfo)),
+)]
+pub async fn get(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<String>,
+) -> Result<Json<ModelInfo>, AppError> {
+    let target = caller.resolve_alias(&id).unwrap_or(&id);
+    caller.authorize_model(target)?;
+    let backend = tenants::backend_for(&state, &caller, target).ok_or_else(|| AppError::ModelNotFound(id.clone()))?;
+    Ok(Json(ModelInfo::new(&id, &backend)))
+}
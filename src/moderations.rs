@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Result};
+use axum::{extract::State, Extension, Json};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{auth::Caller, guardrails, request_log::Recorder, usage::Usage, AppError, AppState, RequestMeta};
+
+// OpenAI's moderation categories, all present in every result.
+const CATEGORIES: [&str; 13] = [
+    "harassment", "harassment/threatening", "hate", "hate/threatening", "illicit", "illicit/violent",
+    "self-harm", "self-harm/intent", "self-harm/instructions", "sexual", "sexual/minors", "violence", "violence/graphic",
+];
+
+// Llama Guard 3's hazard categories. S5 (defamation), S6 (specialized advice),
+// S7 (privacy), S8 (intellectual property), and S13 (elections) have no OpenAI
+// equivalent: they flag the input without setting a category.
+const LLAMA_GUARD_CATEGORIES: [(&str, &[&str]); 9] = [
+    ("S1", &["violence"]),
+    ("S2", &["illicit"]),
+    ("S3", &["sexual"]),
+    ("S4", &["sexual", "sexual/minors"]),
+    ("S9", &["illicit", "illicit/violent"]),
+    ("S10", &["hate"]),
+    ("S11", &["self-harm"]),
+    ("S12", &["sexual"]),
+    ("S14", &["illicit"]),
+];
+
+// --- Moderations Endpoint ---
+// POST /v1/moderations answers in OpenAI's format using a Llama Guard style
+// classifier behind a chat completions API (the same kind of model the
+// `llama_guard` guardrail calls). Each input is classified on its own, and the
+// classifier's hazard codes are mapped to OpenAI's categories. Llama Guard gives
+// verdicts, not probabilities, so scores are 1.0 or 0.0.
+#[derive(Debug, Deserialize)]
+pub struct ModerationConfig {
+    pub url: String,
+    pub model: String,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default)]
+    pub categories: HashMap<String, Vec<String>>, // hazard code -> OpenAI categories, replacing the default
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+pub struct Moderator {
+    config: ModerationConfig,
+    client: Client,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ModerationRequest {
+    input: Value, // a string, a list of strings, or a list of text / image_url parts
+}
+
+impl Moderator {
+    pub fn new(config: ModerationConfig, client: Client) -> Result<Self> {
+        for category in config.categories.values().flatten() {
+            if !CATEGORIES.contains(&category.as_str()) {
+                anyhow::bail!("MODERATIONS maps to unknown category '{}'", category);
+            }
+        }
+        info!("Moderations -> {} ({})", config.url, config.model);
+        Ok(Moderator { config, client })
+    }
+
+    fn categories_of(&self, code: &str) -> Vec<&str> {
+        match self.config.categories.get(code) {
+            Some(categories) => categories.iter().map(String::as_str).collect(),
+            None => LLAMA_GUARD_CATEGORIES.iter()
+                .find(|(c, _)| *c == code)
+                .map(|(_, categories)| categories.to_vec())
+                .unwrap_or_default(),
+        }
+    }
+
+    // One input's result, and the classifier's token usage.
+    async fn classify(&self, content: Value) -> Result<(Value, Usage)> {
+        let payload = json!({
+            "model": self.config.model,
+            "messages": [{ "role": "user", "content": content }],
+            "max_tokens": 32,
+            "temperature": 0.0,
+            "stream": false,
+        });
+        let url = format!("{}/v1/chat/completions", self.config.url.trim_end_matches('/'));
+        let res = self.client.post(&url)
+            .timeout(Duration::from_millis(self.config.timeout_ms))
+            .json(&payload)
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            return Err(anyhow!("{} returned {}", url, res.status()));
+        }
+        let response: Value = res.json().await?;
+        let verdict = response["choices"][0]["message"]["content"].as_str()
+            .ok_or_else(|| anyhow!("classifier response has no message content"))?;
+        let codes = guardrails::llama_guard_verdict(verdict)?;
+        let flagged: Vec<&str> = codes.iter().flatten().flat_map(|code| self.categories_of(code)).collect();
+        let categories: Map<String, Value> = CATEGORIES.iter()
+            .map(|category| (category.to_string(), flagged.contains(category).into()))
+            .collect();
+        let scores: Map<String, Value> = CATEGORIES.iter()
+            .map(|category| (category.to_string(), (if flagged.contains(category) { 1.0 } else { 0.0 }).into()))
+            .collect();
+        let usage = serde_json::from_value(response["usage"].clone()).unwrap_or_default();
+        Ok((json!({ "flagged": codes.is_some(), "categories": categories, "category_scores": scores }), usage))
+    }
+}
+
+// Each string is its own input; a list of content parts is one input.
+fn inputs_of(input: Value) -> Result<Vec<Value>, AppError> {
+    match input {
+        Value::String(_) => Ok(vec![input]),
+        Value::Array(items) if items.is_empty() => Err(AppError::InvalidRequest("`input` is empty.".to_string())),
+        Value::Array(items) if items.iter().all(Value::is_string) => Ok(items),
+        Value::Array(items) if items.iter().all(Value::is_object) => Ok(vec![Value::Array(items)]),
+        _ => Err(AppError::InvalidRequest(
+            "`input` must be a string, a list of strings, or a list of content parts.".to_string(),
+        )),
+    }
+}
+
+#[utoipa::path(
+    post, path = "/v1/moderations", tag = "Moderations",
+    request_body = ModerationRequest,
+    responses(
+        (status = 200, description = "One result per input, in OpenAI's moderation format.", body = Object),
+        (status = 404, description = "MODERATIONS is not configured."),
+    ),
+)]
+pub async fn create(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    Json(request): Json<ModerationRequest>,
+) -> Result<Json<Value>, AppError> {
+    let moderator = state.moderator.as_ref()
+        .ok_or_else(|| AppError::NotFound("Moderations are not configured on this gateway.".to_string()))?;
+    let inputs = inputs_of(request.input)?;
+    if let Some(tenant) = caller.tenant() {
+        tenant.admit()?;
+    }
+    let meta = RequestMeta::new();
+    let mut recorder = Recorder::new(&state, &meta, moderator.config.model.clone(), &caller);
+    recorder.set_tenant(caller.tenant().cloned());
+    recorder.set_backend(&moderator.config.url);
+    recorder.started();
+
+    let classified = futures::future::try_join_all(inputs.into_iter().map(|input| moderator.classify(input))).await;
+    let classified = match classified {
+        Ok(classified) => classified,
+        Err(e) => {
+            let error = AppError::GuardrailUnavailable { guardrail: "moderations".to_string(), error: e.to_string() };
+            recorder.finish(503, None, None);
+            return Err(error);
+        }
+    };
+    let usage = classified.iter().fold(Usage::default(), |total, (_, usage)| Usage {
+        prompt_tokens: total.prompt_tokens + usage.prompt_tokens,
+        completion_tokens: total.completion_tokens + usage.completion_tokens,
+    });
+    recorder.finish(200, Some(usage), None);
+    let results: Vec<Value> = classified.into_iter().map(|(result, _)| result).collect();
+    Ok(Json(json!({
+        "id": format!("modr-{}", meta.id.replace('-', "")),
+        "model": moderator.config.model,
+        "results": results,
+    })))
+}
@@ -0,0 +1,128 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::stream::{ChunkFilter, Verdict};
+
+// --- Stream Normalization ---
+// Servers that are only loosely OpenAI-compatible (TGI, llama.cpp's server,
+// Ollama's `/v1` endpoints, some hosted APIs) differ in the details of their
+// chunks. With `normalize_stream`, a model's SSE `data:` chunks are rewritten into
+// OpenAI's exact shape, so clients behave the same whichever backend answered.
+// Native NDJSON streams (Ollama's `/api/chat`) are not understood. The rewrite:
+//   * `object`, `id`, `created`, and `model` are always set, and `id` stays the
+//     same for the whole stream
+//   * `role: "assistant"` is sent once per choice, on its first delta
+//   * every choice has a `delta` object and a `finish_reason`, mapped onto
+//     OpenAI's values (`eos_token` and `end_turn` become `stop`, `max_tokens`
+//     becomes `length`, and so on)
+//   * tool call deltas carry an `index`, their first fragment has `type:
+//     "function"`, and `arguments` is always a string
+pub struct Normalizer {
+    model: String,
+    id: Option<String>,
+    created: Option<i64>,
+    choices: HashMap<u64, ChoiceState>,
+}
+
+#[derive(Default)]
+struct ChoiceState {
+    role_sent: bool,
+    tool_calls: usize, // tool calls seen so far, for deltas without an `index`
+}
+
+impl Normalizer {
+    pub fn new(model: &str) -> Self {
+        Normalizer { model: model.to_string(), id: None, created: None, choices: HashMap::new() }
+    }
+}
+
+impl ChunkFilter for Normalizer {
+    fn on_chunk(&mut self, chunk: &mut Value) -> Verdict {
+        let Some(object) = chunk.as_object_mut() else { return Verdict::Continue };
+        object.insert("object".to_string(), json!("chat.completion.chunk"));
+        let id = self.id.get_or_insert_with(|| match object.get("id").and_then(Value::as_str) {
+            Some(id) if !id.is_empty() => id.to_string(),
+            _ => format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
+        });
+        object.insert("id".to_string(), json!(id));
+        let created = *self.created.get_or_insert_with(|| {
+            object.get("created").and_then(Value::as_i64).unwrap_or_else(|| chrono::Utc::now().timestamp())
+        });
+        object.insert("created".to_string(), json!(created));
+        if !object.get("model").is_some_and(Value::is_string) {
+            object.insert("model".to_string(), json!(self.model));
+        }
+
+        let Some(choices) = object.get_mut("choices").and_then(Value::as_array_mut) else {
+            object.insert("choices".to_string(), json!([]));
+            return Verdict::Continue;
+        };
+        for (position, choice) in choices.iter_mut().enumerate() {
+            let Some(choice) = choice.as_object_mut() else { continue };
+            let index = choice.get("index").and_then(Value::as_u64).unwrap_or(position as u64);
+            choice.insert("index".to_string(), json!(index));
+            let state = self.choices.entry(index).or_default();
+
+            // Some servers send the whole message instead of a delta.
+            if !choice.contains_key("delta") {
+                if let Some(message) = choice.remove("message") {
+                    choice.insert("delta".to_string(), message);
+                }
+            }
+            if !choice.get("delta").is_some_and(Value::is_object) {
+                choice.insert("delta".to_string(), json!({}));
+            }
+            let delta = choice["delta"].as_object_mut().expect("just checked");
+            match state.role_sent {
+                false => {
+                    delta.insert("role".to_string(), json!("assistant"));
+                    state.role_sent = true;
+                }
+                true => {
+                    delta.remove("role");
+                }
+            }
+            if delta.get("content").is_some_and(Value::is_null) {
+                delta.remove("content");
+            }
+            if let Some(calls) = delta.get_mut("tool_calls").and_then(Value::as_array_mut) {
+                for call in calls.iter_mut().filter_map(Value::as_object_mut) {
+                    let starts = call.get("id").is_some_and(|id| !id.is_null());
+                    if !call.get("index").is_some_and(Value::is_u64) {
+                        // A new call has an id; fragments without one continue the last.
+                        let index = if starts { state.tool_calls } else { state.tool_calls.saturating_sub(1) };
+                        call.insert("index".to_string(), json!(index));
+                    }
+                    let index = call["index"].as_u64().unwrap_or_default() as usize;
+                    state.tool_calls = state.tool_calls.max(index + 1);
+                    if starts {
+                        call.entry("type").or_insert_with(|| json!("function"));
+                    }
+                    if let Some(function) = call.get_mut("function").and_then(Value::as_object_mut) {
+                        if let Some(arguments) = function.get_mut("arguments").filter(|arguments| !arguments.is_string()) {
+                            *arguments = match arguments.is_null() {
+                                true => json!(""),
+                                false => json!(arguments.to_string()),
+                            };
+                        }
+                    }
+                }
+            }
+
+            let reason = choice.get("finish_reason").and_then(Value::as_str).filter(|reason| !reason.is_empty()).map(finish_reason);
+            choice.insert("finish_reason".to_string(), reason.map_or(Value::Null, |reason| json!(reason)));
+        }
+        Verdict::Continue
+    }
+}
+
+// Maps other servers' finish reasons onto OpenAI's.
+fn finish_reason(reason: &str) -> &str {
+    match reason {
+        "stop" | "eos" | "eos_token" | "end_turn" | "stop_sequence" | "end" | "complete" => "stop",
+        "length" | "max_tokens" | "max_length" | "model_length" => "length",
+        "tool_calls" | "tool_use" => "tool_calls",
+        "content_filter" | "safety" | "recitation" => "content_filter",
+        other => other,
+    }
+}
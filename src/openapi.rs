@@ -0,0 +1,151 @@
+use axum::Json;
+use utoipa::{
+    openapi::{
+        security::{HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme},
+        Content, OpenApi as Document, Ref, RefOr, ResponseBuilder,
+    },
+    Modify, OpenApi, ToSchema,
+};
+
+use crate::{active, admin, audio, audit, batch, credentials, drain, images, jobs, metrics, models, moderations, realtime, rerank, responses, trace, websocket};
+
+// --- OpenAPI Specification ---
+// GET /openapi.json describes every route the gateway can serve, for client
+// generators and API explorers. Routes behind BATCH_API or the admin keys are
+// listed even when those are off. /v1 routes take an API key and /admin routes
+// the admin key, as bearer tokens; errors share OpenAI's error object.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "LLM Gateway", description = "An OpenAI-compatible gateway in front of vLLM and other model backends."),
+    paths(
+        crate::proxy_chat,
+        websocket::upgrade,
+        active::client_cancel,
+        jobs::get_job,
+        jobs::cancel_job,
+        crate::token_count,
+        responses::create,
+        images::generations,
+        audio::transcriptions,
+        audio::speech,
+        moderations::create,
+        rerank::rerank,
+        realtime::connect,
+        models::list,
+        models::get,
+        batch::upload_file,
+        batch::list_files,
+        batch::get_file,
+        batch::delete_file,
+        batch::file_content,
+        batch::create_batch,
+        batch::list_batches,
+        batch::get_batch,
+        batch::cancel_batch,
+        admin::usage,
+        admin::export,
+        admin::dashboard,
+        admin::ui,
+        active::list,
+        active::admin_cancel,
+        trace::get,
+        drain::get,
+        drain::drain,
+        drain::undrain,
+        credentials::create_key,
+        credentials::list_keys,
+        credentials::delete_key,
+        credentials::rotate_key,
+        credentials::revoke_key,
+        credentials::list_credentials,
+        credentials::put_credential,
+        credentials::delete_credential,
+        audit::query,
+        crate::health_check,
+        metrics::serve,
+        serve,
+    ),
+    components(schemas(ErrorResponse, ErrorBody)),
+    tags(
+        (name = "Chat"),
+        (name = "Responses"),
+        (name = "Images"),
+        (name = "Audio"),
+        (name = "Moderations"),
+        (name = "Rerank"),
+        (name = "Realtime"),
+        (name = "Models"),
+        (name = "Batch", description = "Only served with BATCH_API set."),
+        (name = "Admin", description = "Only served with ADMIN_API_KEY or ADMIN_KEYS set. GET routes need the viewer role, cancelling a request the operator role, and everything else the admin role; /admin/keys and /admin/credentials also need CREDENTIAL_STORE."),
+        (name = "Operations"),
+    ),
+    modifiers(&Security, &Errors),
+)]
+struct ApiDoc;
+
+#[utoipa::path(get, path = "/openapi.json", tag = "Operations", responses((status = 200, description = "This document.", body = Object)))]
+pub async fn serve() -> Json<Document> {
+    let mut document = ApiDoc::openapi();
+    document.info.license = None; // the crate declares none; utoipa would list an empty one
+    Json(document)
+}
+
+#[derive(ToSchema)]
+#[allow(dead_code)] // only described
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(ToSchema)]
+#[allow(dead_code)]
+struct ErrorBody {
+    message: String,
+    #[schema(rename = "type")]
+    kind: String,
+    code: String,
+}
+
+// Bearer auth on everything but the unauthenticated routes and the admin page.
+struct Security;
+
+impl Modify for Security {
+    fn modify(&self, openapi: &mut Document) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        for name in ["api_key", "admin_key"] {
+            components.add_security_scheme(name, SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()));
+        }
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            let scheme = if path.starts_with("/v1/") {
+                "api_key"
+            } else if path.starts_with("/admin/") && path != "/admin/ui" {
+                "admin_key"
+            } else {
+                continue;
+            };
+            for operation in operations(item) {
+                operation.security = Some(vec![SecurityRequirement::new(scheme, Vec::<String>::new())]);
+            }
+        }
+    }
+}
+
+// Every operation can fail with an OpenAI error object.
+struct Errors;
+
+impl Modify for Errors {
+    fn modify(&self, openapi: &mut Document) {
+        let error = ResponseBuilder::new()
+            .description("An error, as OpenAI's error object.")
+            .content("application/json", Content::new(Some(Ref::from_schema_name("ErrorResponse"))))
+            .build();
+        for item in openapi.paths.paths.values_mut() {
+            for operation in operations(item) {
+                operation.responses.responses.entry("default".to_string()).or_insert_with(|| RefOr::T(error.clone()));
+            }
+        }
+    }
+}
+
+fn operations(item: &mut utoipa::openapi::PathItem) -> impl Iterator<Item = &mut utoipa::openapi::path::Operation> {
+    [&mut item.get, &mut item.post, &mut item.put, &mut item.patch, &mut item.delete].into_iter().flatten()
+}
@@ -0,0 +1,174 @@
+use anyhow::{Context, Result};
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Arc};
+use tracing::warn;
+
+use crate::stream::{delta_contents_mut, set_finish_reason, ChunkFilter, Verdict};
+
+// --- Output Content Policy ---
+// Loaded from OUTPUT_FILTER and applied to streamed deltas from every backend.
+#[derive(Debug, Deserialize)]
+pub struct OutputFilterConfig {
+    #[serde(default)]
+    pub blocklist: Vec<String>, // case-insensitive literal terms
+    #[serde(default)]
+    pub patterns: Vec<String>, // regexes
+    #[serde(default)]
+    pub action: FilterAction,
+    #[serde(default = "default_mask")]
+    pub mask: String,
+    // How much trailing text is held back per choice so a match split across
+    // chunks is still caught; should be at least the longest expected match.
+    #[serde(default = "default_holdback")]
+    pub holdback_chars: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    #[default]
+    Mask,
+    Terminate,
+}
+
+fn default_mask() -> String {
+    "***".to_string()
+}
+
+fn default_holdback() -> usize {
+    32
+}
+
+pub struct OutputPolicy {
+    regex: Regex,
+    action: FilterAction,
+    mask: String,
+    holdback_chars: usize,
+}
+
+impl OutputPolicy {
+    pub fn new(config: OutputFilterConfig) -> Result<Self> {
+        let mut alternatives: Vec<String> = config.blocklist.iter().map(|term| regex::escape(term)).collect();
+        for pattern in &config.patterns {
+            Regex::new(pattern).with_context(|| format!("Invalid OUTPUT_FILTER pattern '{}'", pattern))?;
+            alternatives.push(format!("(?:{})", pattern));
+        }
+        anyhow::ensure!(!alternatives.is_empty(), "OUTPUT_FILTER needs at least one blocklist term or pattern");
+
+        let regex = RegexBuilder::new(&alternatives.join("|"))
+            .case_insensitive(true)
+            .build()
+            .context("Failed to compile OUTPUT_FILTER")?;
+
+        Ok(OutputPolicy {
+            regex,
+            action: config.action,
+            mask: config.mask,
+            holdback_chars: config.holdback_chars,
+        })
+    }
+}
+
+// Per-stream state: text held back for each choice index.
+pub struct ContentFilter {
+    policy: Arc<OutputPolicy>,
+    pending: HashMap<usize, String>,
+    template: Option<Value>, // last chunk seen, used to shape flushed chunks
+}
+
+impl ContentFilter {
+    pub fn new(policy: Arc<OutputPolicy>) -> Self {
+        ContentFilter { policy, pending: HashMap::new(), template: None }
+    }
+
+    // Returns the text that is safe to emit now. On a terminate-policy match the
+    // text before the match is returned along with `true`.
+    fn release(&mut self, index: usize, incoming: &str, flush: bool) -> (String, bool) {
+        let buffer = self.pending.entry(index).or_default();
+        buffer.push_str(incoming);
+
+        if self.policy.action == FilterAction::Terminate {
+            if let Some(m) = self.policy.regex.find(buffer) {
+                let safe = buffer[..m.start()].to_string();
+                buffer.clear();
+                return (safe, true);
+            }
+        } else {
+            let masked = self.policy.regex.replace_all(buffer, self.policy.mask.as_str());
+            if let std::borrow::Cow::Owned(masked) = masked {
+                *buffer = masked;
+            }
+        }
+
+        let split = if flush { buffer.len() } else { holdback_split(buffer, self.policy.holdback_chars) };
+        let released: String = buffer.drain(..split).collect();
+        (released, false)
+    }
+}
+
+// Byte offset that leaves the last `holdback` characters in the buffer.
+fn holdback_split(buffer: &str, holdback: usize) -> usize {
+    buffer.char_indices()
+        .rev()
+        .nth(holdback.saturating_sub(1))
+        .map(|(i, _)| if holdback == 0 { buffer.len() } else { i })
+        .unwrap_or(0)
+}
+
+impl ChunkFilter for ContentFilter {
+    fn on_chunk(&mut self, chunk: &mut Value) -> Verdict {
+        let mut violated = false;
+        let mut updates = Vec::new();
+        for (index, content, finished) in delta_contents_mut(chunk) {
+            let (released, hit) = self.release(index, content, finished);
+            *content = released;
+            violated |= hit;
+            updates.push(index);
+        }
+
+        // A finish_reason without any content still has to flush held-back text.
+        if let Some(choices) = chunk.get_mut("choices").and_then(Value::as_array_mut) {
+            for choice in choices {
+                let index = choice.get("index").and_then(Value::as_u64).unwrap_or(0) as usize;
+                let finished = choice.get("finish_reason").is_some_and(|r| !r.is_null());
+                if finished && !updates.contains(&index) {
+                    let (released, hit) = self.release(index, "", true);
+                    violated |= hit;
+                    if !released.is_empty() {
+                        choice["delta"]["content"] = Value::String(released);
+                    }
+                }
+            }
+        }
+
+        self.template = Some(chunk.clone());
+        if violated {
+            warn!("Output content policy violation; terminating stream");
+            set_finish_reason(chunk, "content_filter");
+            return Verdict::Stop;
+        }
+        Verdict::Continue
+    }
+
+    fn finish(&mut self) -> Vec<Value> {
+        let mut indices: Vec<usize> = self.pending.iter()
+            .filter(|(_, text)| !text.is_empty())
+            .map(|(&i, _)| i)
+            .collect();
+        indices.sort_unstable();
+
+        let mut chunks = Vec::new();
+        for index in indices {
+            let (released, _) = self.release(index, "", true);
+            if released.is_empty() {
+                continue;
+            }
+            let mut chunk = self.template.clone().unwrap_or_else(|| json!({ "object": "chat.completion.chunk" }));
+            chunk["choices"] = json!([{ "index": index, "delta": { "content": released }, "finish_reason": null }]);
+            chunks.push(chunk);
+        }
+        chunks
+    }
+}
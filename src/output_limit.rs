@@ -0,0 +1,137 @@
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::{
+    backend::Backend,
+    events::{EventBus, LifecycleEvent},
+    stream::{delta_contents_mut, set_finish_reason, ChunkFilter, Verdict},
+};
+
+// --- Response Size Limits ---
+// A cap on how much one request may generate, whatever its `max_tokens`: on a
+// model (`response_limit`) and on an API key, with the lower limit winning.
+// Characters and tokens (counted with the model's tokenizer) are summed over
+// every choice's content and tool call arguments. The chunk that crosses the
+// limit is the last one sent, cut to fit for `max_chars`, and ends the stream
+// with `finish_reason: "length"`. Each cut is logged and published as an
+// `output_limited` lifecycle event.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ResponseLimit {
+    #[serde(default)]
+    pub max_chars: Option<usize>,
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+}
+
+impl ResponseLimit {
+    // The stricter of each limit.
+    pub fn merge(a: Option<ResponseLimit>, b: Option<ResponseLimit>) -> Option<ResponseLimit> {
+        let lower = |x: Option<usize>, y: Option<usize>| match (x, y) {
+            (Some(x), Some(y)) => Some(x.min(y)),
+            (x, y) => x.or(y),
+        };
+        match (a, b) {
+            (Some(a), Some(b)) => Some(ResponseLimit { max_chars: lower(a.max_chars, b.max_chars), max_tokens: lower(a.max_tokens, b.max_tokens) }),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+pub struct OutputLimit {
+    limit: ResponseLimit,
+    backend: Arc<Backend>,
+    chars: usize,
+    tokens: usize,
+    events: Option<EventBus>,
+    request_id: String,
+    model: String,
+    key: Option<String>,
+}
+
+impl OutputLimit {
+    pub fn new(limit: ResponseLimit, backend: Arc<Backend>, events: Option<EventBus>, request_id: &str, model: &str, key: Option<String>) -> Option<Self> {
+        (limit.max_chars.is_some() || limit.max_tokens.is_some()).then(|| OutputLimit {
+            limit,
+            backend,
+            chars: 0,
+            tokens: 0,
+            events,
+            request_id: request_id.to_string(),
+            model: model.to_string(),
+            key,
+        })
+    }
+
+    // Counts `text`. Returns how many of its bytes fit and whether a limit was reached.
+    fn take(&mut self, text: &str) -> (usize, bool) {
+        let mut fits = text.len();
+        if let Some(max) = self.limit.max_chars {
+            let remaining = max.saturating_sub(self.chars);
+            if let Some((end, _)) = text.char_indices().nth(remaining) {
+                fits = end;
+            }
+        }
+        let kept = &text[..fits];
+        self.chars += kept.chars().count();
+        if self.limit.max_tokens.is_some() && !kept.is_empty() {
+            self.tokens += self.backend.tokenizer.count_text(kept);
+        }
+        let reached = self.limit.max_chars.is_some_and(|max| self.chars >= max)
+            || self.limit.max_tokens.is_some_and(|max| self.tokens >= max);
+        (fits, reached)
+    }
+}
+
+impl ChunkFilter for OutputLimit {
+    fn on_chunk(&mut self, chunk: &mut Value) -> Verdict {
+        let mut reached = false;
+        for (_, content, _) in delta_contents_mut(chunk) {
+            if reached {
+                content.clear();
+                continue;
+            }
+            let (fits, hit) = self.take(content);
+            content.truncate(fits);
+            reached = hit;
+        }
+        let calls = chunk.get_mut("choices").and_then(Value::as_array_mut).into_iter().flatten()
+            .filter_map(|choice| choice.pointer_mut("/delta/tool_calls")?.as_array_mut())
+            .flatten()
+            .filter_map(|call| match call.pointer_mut("/function/arguments") {
+                Some(Value::String(arguments)) => Some(arguments),
+                _ => None,
+            });
+        for arguments in calls {
+            if reached {
+                arguments.clear();
+                continue;
+            }
+            let (fits, hit) = self.take(arguments);
+            arguments.truncate(fits);
+            reached = hit;
+        }
+        if !reached {
+            return Verdict::Continue;
+        }
+
+        warn!(
+            "Response for model '{}' reached its size limit ({} chars, {} tokens); ending the stream",
+            self.model, self.chars, self.tokens
+        );
+        if let Some(events) = &self.events {
+            events.publish(LifecycleEvent::OutputLimited {
+                request_id: self.request_id.clone(),
+                at: Utc::now(),
+                model: self.model.clone(),
+                key: self.key.clone(),
+                chars: self.chars,
+                tokens: self.tokens,
+            });
+        }
+        set_finish_reason(chunk, "length");
+        Verdict::Stop
+    }
+}
@@ -0,0 +1,121 @@
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+use tokio::time::Instant;
+
+use crate::{backend::Backend, stream::PayloadStream};
+
+// --- Output Pacing ---
+// A model with `pacing` sends generated text to streaming clients at no more than
+// `tokens_per_second`, so a fast backend's bursts reach a chat UI as a steady
+// flow. Chunks carrying several tokens are split at word boundaries. Up to
+// `burst` tokens go out at once after a pause. The backend is still read as fast
+// as it sends, so it is not held up; only the client's copy is delayed.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PacingConfig {
+    pub tokens_per_second: f64,
+    #[serde(default = "default_burst")]
+    pub burst: f64,
+}
+
+fn default_burst() -> f64 {
+    8.0
+}
+
+struct Pacer {
+    upstream: Option<PayloadStream>, // None once it has ended
+    pending: VecDeque<(String, f64)>, // payloads and their token counts
+    next: Instant, // when the next token may be sent, ignoring the burst allowance
+    config: PacingConfig,
+    backend: Arc<Backend>,
+}
+
+impl Pacer {
+    fn push(&mut self, payload: String) {
+        let Ok(chunk) = serde_json::from_str::<Value>(&payload) else {
+            self.pending.push_back((payload, 0.0));
+            return;
+        };
+        for piece in split(chunk) {
+            let tokens = piece["choices"][0]["delta"]["content"].as_str()
+                .map_or(0, |content| self.backend.tokenizer.count_text(content));
+            self.pending.push_back((piece.to_string(), tokens as f64));
+        }
+    }
+
+    // When the front payload may go out.
+    fn due(&self) -> Instant {
+        let earliest = Instant::now().checked_sub(self.per_tokens(self.config.burst)).unwrap_or_else(Instant::now);
+        self.next.max(earliest)
+    }
+
+    fn per_tokens(&self, tokens: f64) -> Duration {
+        Duration::from_secs_f64(tokens / self.config.tokens_per_second)
+    }
+}
+
+// One chunk per word of a single choice's content; anything else (tool calls,
+// several choices, usage) is left whole. The finish reason and usage stay on the
+// last piece.
+fn split(chunk: Value) -> Vec<Value> {
+    let choices = chunk["choices"].as_array().map_or(0, Vec::len);
+    let delta = &chunk["choices"][0]["delta"];
+    let Some(content) = delta["content"].as_str().filter(|_| choices == 1 && delta.get("tool_calls").is_none()) else {
+        return vec![chunk];
+    };
+    let words: Vec<String> = content.split_inclusive(char::is_whitespace).map(String::from).collect();
+    if words.len() < 2 {
+        return vec![chunk];
+    }
+    let last = words.len() - 1;
+    words.into_iter().enumerate().map(|(i, word)| {
+        let mut piece = chunk.clone();
+        piece["choices"][0]["delta"]["content"] = Value::String(word);
+        if i < last {
+            piece["choices"][0]["finish_reason"] = Value::Null;
+            if let Some(piece) = piece.as_object_mut() {
+                piece.remove("usage");
+            }
+        }
+        if i > 0 {
+            if let Some(delta) = piece["choices"][0]["delta"].as_object_mut() {
+                delta.remove("role");
+            }
+        }
+        piece
+    }).collect()
+}
+
+pub fn pace(upstream: PayloadStream, config: PacingConfig, backend: Arc<Backend>) -> PayloadStream {
+    let pacer = Pacer { upstream: Some(upstream), pending: VecDeque::new(), next: Instant::now(), config, backend };
+    Box::pin(futures::stream::unfold(pacer, |mut pacer| async move {
+        loop {
+            if let Some((_, tokens)) = pacer.pending.front() {
+                let tokens = *tokens;
+                let due = pacer.due();
+                if tokens == 0.0 || due <= Instant::now() {
+                    let (payload, _) = pacer.pending.pop_front().unwrap();
+                    pacer.next = due + pacer.per_tokens(tokens);
+                    return Some((payload, pacer));
+                }
+                let Some(upstream) = pacer.upstream.as_mut() else {
+                    tokio::time::sleep_until(due).await;
+                    continue;
+                };
+                tokio::select! {
+                    next = upstream.next() => match next {
+                        Some(payload) => pacer.push(payload),
+                        None => pacer.upstream = None,
+                    },
+                    _ = tokio::time::sleep_until(due) => {}
+                }
+                continue;
+            }
+            match pacer.upstream.as_mut()?.next().await {
+                Some(payload) => pacer.push(payload),
+                None => return None,
+            }
+        }
+    }))
+}
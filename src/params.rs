@@ -0,0 +1,65 @@
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::ChatRequest;
+
+// --- Per-Model Sampling Parameter Policy ---
+// Defaults fill in parameters the client left unset; limits are hard caps
+// applied afterwards, so a default can never exceed its own cap.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ParamDefaults {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ParamLimits {
+    pub max_tokens: Option<u32>,
+    pub max_temperature: Option<f32>,
+    pub max_top_p: Option<f32>,
+}
+
+pub fn apply_param_policy(body: &mut ChatRequest, defaults: &ParamDefaults, limits: &ParamLimits) {
+    if body.temperature.is_none() {
+        body.temperature = defaults.temperature;
+    }
+    if body.top_p.is_none() {
+        body.top_p = defaults.top_p;
+    }
+    // An omitted max_tokens is left unset here: the context check must not count a
+    // completion the client never asked for. `settle_omitted_max_tokens` fills it in.
+
+    if let (Some(requested), Some(cap)) = (body.max_tokens, limits.max_tokens) {
+        if requested > cap {
+            debug!("Clamping max_tokens for model '{}' from {} to {}", body.model, requested, cap);
+            body.max_tokens = Some(cap);
+        }
+    }
+    if let (Some(requested), Some(cap)) = (body.temperature, limits.max_temperature) {
+        if requested > cap {
+            debug!("Clamping temperature for model '{}' from {} to {}", body.model, requested, cap);
+            body.temperature = Some(cap);
+        }
+    }
+    if let (Some(requested), Some(cap)) = (body.top_p, limits.max_top_p) {
+        if requested > cap {
+            debug!("Clamping top_p for model '{}' from {} to {}", body.model, requested, cap);
+            body.top_p = Some(cap);
+        }
+    }
+}
+
+// For a request that left out max_tokens, once the context check has run: the
+// auto-filled value, kept under the default and the cap, or else the default,
+// falling back to the cap, rather than leaving the length up to the backend.
+pub fn settle_omitted_max_tokens(body: &mut ChatRequest, default: Option<u32>, cap: Option<u32>) {
+    let ceiling = match (default, cap) {
+        (Some(default), Some(cap)) => Some(default.min(cap)),
+        (default, cap) => default.or(cap),
+    };
+    body.max_tokens = match body.max_tokens {
+        Some(filled) => Some(ceiling.map_or(filled, |ceiling| filled.min(ceiling))),
+        None => ceiling,
+    };
+}
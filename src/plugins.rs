@@ -0,0 +1,245 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{error, info, warn};
+use wasmtime::{Config, Engine, Instance, Memory, Module, Store, TypedFunc};
+
+use crate::{stream::{delta_contents_mut, set_finish_reason, ChunkFilter, Verdict}, AppError, ChatRequest};
+
+// --- WASM Plugin Host ---
+// Plugins are core WASM modules exchanging JSON through linear memory. A module
+// exports `memory`, `alloc(len: i32) -> i32`, and any of the hooks
+// `on_request`, `on_chunk`, `on_response` with signature `(ptr: i32, len: i32) -> i64`.
+// A hook returns 0 to leave its input unchanged, or `(out_ptr << 32) | out_len`
+// pointing at a JSON `HookOutput`. Every call runs under a fuel budget so a
+// misbehaving plugin cannot stall the gateway.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginConfig {
+    pub name: String,
+    pub path: String,
+    #[serde(default = "default_fuel")]
+    pub fuel: u64,
+}
+
+fn default_fuel() -> u64 {
+    10_000_000
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum HookOutput {
+    Continue,
+    Modify { data: Value },
+    Reject { message: String },
+    Stop {
+        #[serde(default)]
+        finish_reason: Option<String>,
+    },
+}
+
+#[derive(Serialize)]
+struct ResponseEvent<'a> {
+    request: &'a Value,
+    completion: &'a str,
+}
+
+struct Plugin {
+    name: String,
+    module: Module,
+    fuel: u64,
+    on_request: bool,
+    on_chunk: bool,
+    on_response: bool,
+}
+
+pub struct PluginHost {
+    engine: Engine,
+    plugins: Vec<Plugin>,
+}
+
+impl PluginHost {
+    pub fn load(configs: Vec<PluginConfig>) -> Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|e| anyhow!("Failed to start WASM engine: {}", e))?;
+
+        let mut plugins = Vec::new();
+        for plugin in configs {
+            let module = Module::from_file(&engine, &plugin.path)
+                .map_err(|e| anyhow!("Failed to load WASM plugin '{}' from '{}': {}", plugin.name, plugin.path, e))?;
+            let exports = |name: &str| module.exports().any(|export| export.name() == name);
+            anyhow::ensure!(
+                exports("memory") && exports("alloc"),
+                "WASM plugin '{}' must export `memory` and `alloc`",
+                plugin.name
+            );
+
+            let loaded = Plugin {
+                on_request: exports("on_request"),
+                on_chunk: exports("on_chunk"),
+                on_response: exports("on_response"),
+                name: plugin.name,
+                fuel: plugin.fuel,
+                module,
+            };
+            info!(
+                "Loaded WASM plugin '{}' (on_request: {}, on_chunk: {}, on_response: {})",
+                loaded.name, loaded.on_request, loaded.on_chunk, loaded.on_response
+            );
+            plugins.push(loaded);
+        }
+
+        Ok(PluginHost { engine, plugins })
+    }
+
+    // Runs before routing, so a plugin may rewrite `model` to pick a different backend.
+    pub fn on_request(&self, body: &mut ChatRequest) -> Result<(), AppError> {
+        for plugin in self.plugins.iter().filter(|p| p.on_request) {
+            let failed = |e: anyhow::Error| AppError::PluginFailed { plugin: plugin.name.clone(), error: e.to_string() };
+
+            let mut session = Session::new(&self.engine, plugin).map_err(failed)?;
+            let input = serde_json::to_vec(&*body).map_err(|e| failed(e.into()))?;
+            match session.call("on_request", &input).map_err(failed)? {
+                None | Some(HookOutput::Continue) => {}
+                Some(HookOutput::Modify { data }) => {
+                    *body = serde_json::from_value(data)
+                        .context("on_request returned an invalid chat request")
+                        .map_err(failed)?;
+                }
+                Some(HookOutput::Reject { message }) => {
+                    return Err(AppError::PluginRejected { plugin: plugin.name.clone(), message });
+                }
+                Some(HookOutput::Stop { .. }) => {
+                    return Err(failed(anyhow!("`stop` is not a valid on_request action")));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Plugin state for one stream: each plugin keeps a single instance for every
+    // chunk of the response, so it can carry state between calls.
+    pub fn stream_filter(&self, body: &ChatRequest) -> Option<PluginFilter> {
+        let mut sessions = Vec::new();
+        for plugin in self.plugins.iter().filter(|p| p.on_chunk || p.on_response) {
+            match Session::new(&self.engine, plugin) {
+                Ok(session) => sessions.push(session),
+                Err(e) => error!("Failed to instantiate WASM plugin '{}': {}", plugin.name, e),
+            }
+        }
+        if sessions.is_empty() {
+            return None;
+        }
+        Some(PluginFilter {
+            sessions,
+            request: serde_json::to_value(body).unwrap_or(Value::Null),
+            completion: String::new(),
+        })
+    }
+}
+
+struct Session {
+    name: String,
+    fuel: u64,
+    on_chunk: bool,
+    on_response: bool,
+    store: Store<()>,
+    instance: Instance,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+}
+
+impl Session {
+    fn new(engine: &Engine, plugin: &Plugin) -> Result<Self> {
+        let mut store = Store::new(engine, ());
+        store.set_fuel(plugin.fuel).map_err(|e| anyhow!("{}", e))?;
+        let instance = Instance::new(&mut store, &plugin.module, &[])
+            .map_err(|e| anyhow!("instantiation failed: {}", e))?;
+        let memory = instance.get_memory(&mut store, "memory")
+            .ok_or_else(|| anyhow!("missing `memory` export"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")
+            .map_err(|e| anyhow!("invalid `alloc` export: {}", e))?;
+
+        Ok(Session {
+            name: plugin.name.clone(),
+            fuel: plugin.fuel,
+            on_chunk: plugin.on_chunk,
+            on_response: plugin.on_response,
+            store,
+            instance,
+            memory,
+            alloc,
+        })
+    }
+
+    fn call(&mut self, hook: &str, input: &[u8]) -> Result<Option<HookOutput>> {
+        self.store.set_fuel(self.fuel).map_err(|e| anyhow!("{}", e))?;
+
+        let func = self.instance.get_typed_func::<(i32, i32), i64>(&mut self.store, hook)
+            .map_err(|e| anyhow!("invalid `{}` export: {}", hook, e))?;
+        let len = i32::try_from(input.len()).context("hook input too large")?;
+        let ptr = self.alloc.call(&mut self.store, len).map_err(|e| anyhow!("`alloc` trapped: {}", e))?;
+        self.memory.write(&mut self.store, ptr as usize, input)
+            .map_err(|e| anyhow!("`alloc` returned an out-of-bounds pointer: {}", e))?;
+
+        let packed = func.call(&mut self.store, (ptr, len)).map_err(|e| anyhow!("`{}` trapped: {}", hook, e))?;
+        if packed == 0 {
+            return Ok(None);
+        }
+
+        let out_ptr = (packed as u64 >> 32) as usize;
+        let out_len = (packed as u64 & 0xffff_ffff) as usize;
+        let output = self.memory.data(&self.store)
+            .get(out_ptr..out_ptr + out_len)
+            .ok_or_else(|| anyhow!("`{}` returned an out-of-bounds result", hook))?;
+        let output = serde_json::from_slice(output).with_context(|| format!("`{}` returned invalid JSON", hook))?;
+        Ok(Some(output))
+    }
+}
+
+pub struct PluginFilter {
+    sessions: Vec<Session>,
+    request: Value,
+    completion: String,
+}
+
+impl ChunkFilter for PluginFilter {
+    fn on_chunk(&mut self, chunk: &mut Value) -> Verdict {
+        for session in self.sessions.iter_mut().filter(|s| s.on_chunk) {
+            let Ok(input) = serde_json::to_vec(&*chunk) else { continue };
+            // Chunk hooks fail open: a broken plugin should not kill a user's stream.
+            match session.call("on_chunk", &input) {
+                Ok(None | Some(HookOutput::Continue)) => {}
+                Ok(Some(HookOutput::Modify { data })) => *chunk = data,
+                Ok(Some(HookOutput::Stop { finish_reason })) => {
+                    set_finish_reason(chunk, finish_reason.as_deref().unwrap_or("stop"));
+                    return Verdict::Stop;
+                }
+                Ok(Some(HookOutput::Reject { message })) => {
+                    warn!("WASM plugin '{}' rejected a chunk: {}", session.name, message);
+                    set_finish_reason(chunk, "content_filter");
+                    return Verdict::Stop;
+                }
+                Err(e) => error!("WASM plugin '{}' on_chunk failed: {}", session.name, e),
+            }
+        }
+
+        for (_, content, _) in delta_contents_mut(chunk) {
+            self.completion.push_str(content);
+        }
+        Verdict::Continue
+    }
+
+    // `on_response` is a notification hook (auditing, custom accounting); its
+    // output is not applied to the stream.
+    fn finish(&mut self) -> Vec<Value> {
+        let event = ResponseEvent { request: &self.request, completion: &self.completion };
+        let input = serde_json::to_vec(&event).unwrap_or_default();
+        for session in self.sessions.iter_mut().filter(|s| s.on_response) {
+            if let Err(e) = session.call("on_response", &input) {
+                error!("WASM plugin '{}' on_response failed: {}", session.name, e);
+            }
+        }
+        Vec::new()
+    }
+}
@@ -0,0 +1,70 @@
+use axum::http::HeaderMap;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{auth::Caller, backend::Backend, AppError};
+
+pub const HEADER: &str = "x-priority";
+
+// --- Request Priority ---
+// `X-Priority: batch` marks a request that can wait, so batch jobs can use spare
+// GPU capacity without crowding out people. A batch request is only admitted
+// while its backend is below `batch_max_utilization` of its
+// `max_concurrent_requests` (counting queued requests) or else its
+// `max_concurrent_streams`; otherwise it gets a 503 to retry later. A key's
+// `priorities` lists what it may send. Without the header a request is
+// `interactive`, or `batch` for keys limited to that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Interactive,
+    Batch,
+}
+
+impl Priority {
+    fn name(self) -> &'static str {
+        match self {
+            Priority::Interactive => "interactive",
+            Priority::Batch => "batch",
+        }
+    }
+}
+
+pub fn from_headers(caller: &Caller, headers: &HeaderMap) -> Result<Priority, AppError> {
+    let allowed = caller.key().and_then(|key| key.priorities.as_deref());
+    let priority = match headers.get(HEADER) {
+        Some(value) => match value.to_str().map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            Ok("interactive") => Priority::Interactive,
+            Ok("batch") => Priority::Batch,
+            _ => return Err(AppError::InvalidRequest("X-Priority must be 'interactive' or 'batch'.".to_string())),
+        },
+        None => match allowed {
+            Some(allowed) if !allowed.contains(&Priority::Interactive) => Priority::Batch,
+            _ => Priority::Interactive,
+        },
+    };
+    if allowed.is_some_and(|allowed| !allowed.contains(&priority)) {
+        return Err(AppError::Forbidden(format!("This API key may not send {} priority requests.", priority.name())));
+    }
+    Ok(priority)
+}
+
+// Turns a batch request away from a busy backend.
+pub fn admit(priority: Priority, model: &str, backend: &Backend, retry_after_secs: u64) -> Result<(), AppError> {
+    if priority != Priority::Batch {
+        return Ok(());
+    }
+    let Some(utilization) = backend.utilization() else { return Ok(()) };
+    let threshold = backend.config.batch_max_utilization;
+    if utilization < threshold {
+        return Ok(());
+    }
+    info!("Deferring batch request for model '{}' ({:.0}% utilized)", model, utilization * 100.0);
+    Err(AppError::Overloaded {
+        message: format!(
+            "Model '{}' is {:.0}% utilized; batch priority requests are admitted below {:.0}%. Try again later.",
+            model, utilization * 100.0, threshold * 100.0
+        ),
+        retry_after_secs,
+    })
+}
@@ -0,0 +1,67 @@
+use axum::http::HeaderMap;
+use serde::Deserialize;
+
+use crate::{ChatMessage, ChatRequest};
+
+// --- System Prompt Injection ---
+// A configured template is either prepended as an extra system message or
+// replaces whatever system messages the client sent.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InjectionMode {
+    #[default]
+    Prepend,
+    Replace,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SystemPromptConfig {
+    #[serde(default)]
+    pub mode: InjectionMode,
+    pub template: String,
+}
+
+pub fn apply_system_prompt(body: &mut ChatRequest, config: &SystemPromptConfig, headers: &HeaderMap) {
+    let content = render_template(&config.template, body, headers);
+
+    if config.mode == InjectionMode::Replace {
+        body.messages.retain(|m| m.role != "system");
+    }
+
+    body.messages.insert(0, ChatMessage {
+        role: "system".to_string(),
+        content: content.into(),
+        name: None,
+        tool_calls: None,
+        tool_call_id: None,
+    });
+}
+
+// Substitutes `{{model}}` and `{{header.<name>}}` placeholders. Missing headers and
+// unknown variables render as empty strings so a template never leaks its own syntax.
+fn render_template(template: &str, body: &ChatRequest, headers: &HeaderMap) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            out.push_str(&rest[start..]);
+            return out;
+        };
+
+        let var = after[..end].trim();
+        if var == "model" {
+            out.push_str(&body.model);
+        } else if let Some(name) = var.strip_prefix("header.") {
+            if let Some(value) = headers.get(name).and_then(|v| v.to_str().ok()) {
+                out.push_str(value);
+            }
+        }
+        rest = &after[end + 2..];
+    }
+
+    out.push_str(rest);
+    out
+}
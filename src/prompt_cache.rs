@@ -0,0 +1,82 @@
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{ChatMessage, ChatRequest, MessageContent};
+
+// --- Prompt Caching ---
+// Clients written for Anthropic mark the end of a reusable prompt prefix with
+// `cache_control` on a content part or tool. A model's `cache_control` setting
+// decides what happens to those markers:
+//   * `forward` sends them upstream unchanged, for backends that understand them
+//   * `emulate` (the default) removes them and makes the prefix cheap to reuse on
+//     vLLM instead: system messages with a marker are moved ahead of the other
+//     system messages (such as an injected prompt that differs per request), and
+//     the request is routed to the replica that last served the same prefix, which
+//     is likely to still have it in its prefix cache
+// Either way, cached prompt tokens reported by the backend are counted in
+// `llm_gateway_cached_prompt_tokens_total`.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheControlMode {
+    #[default]
+    Emulate,
+    Forward,
+}
+
+const MARKER: &str = "cache_control";
+
+// Applies the mode to the request. Returns a hash of the marked prefix when it
+// should be used for routing.
+pub fn prepare(body: &mut ChatRequest, mode: CacheControlMode) -> Option<String> {
+    if mode == CacheControlMode::Forward {
+        return None;
+    }
+    let tools_marked = body.tools.as_ref().and_then(Value::as_array).is_some_and(|tools| tools.iter().any(|t| t.get(MARKER).is_some()));
+    let last_marked = body.messages.iter().rposition(marked);
+    if !tools_marked && last_marked.is_none() {
+        return None;
+    }
+
+    // Only the leading system messages are reordered; anything after the first
+    // conversation turn keeps its place.
+    let leading = body.messages.iter().take_while(|m| m.role == "system").count();
+    body.messages[..leading].sort_by_key(|m| !marked(m));
+    let last_marked = body.messages.iter().rposition(marked);
+
+    if let Some(tools) = body.tools.as_mut().and_then(Value::as_array_mut) {
+        for tool in tools.iter_mut().filter_map(Value::as_object_mut) {
+            tool.remove(MARKER);
+        }
+    }
+    for message in &mut body.messages {
+        if let MessageContent::Parts(parts) = &mut message.content {
+            for part in parts.iter_mut().filter_map(Value::as_object_mut) {
+                part.remove(MARKER);
+            }
+        }
+    }
+
+    // Tools come first in the prefix, as on Anthropic's API.
+    let mut hasher = Sha256::new();
+    hasher.update(body.tools.as_ref().map(Value::to_string).unwrap_or_default());
+    for message in &body.messages[..last_marked.map_or(0, |last| last + 1)] {
+        hasher.update(serde_json::to_string(message).unwrap_or_default());
+    }
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+fn marked(message: &ChatMessage) -> bool {
+    match &message.content {
+        MessageContent::Parts(parts) => parts.iter().any(|part| part.get(MARKER).is_some()),
+        _ => false,
+    }
+}
+
+// Prompt tokens served from the backend's cache, from OpenAI's and vLLM's
+// `prompt_tokens_details` or Anthropic's `cache_read_input_tokens`.
+pub fn cached_tokens(usage: &Value) -> Option<u64> {
+    usage.pointer("/prompt_tokens_details/cached_tokens")
+        .or_else(|| usage.get("cache_read_input_tokens"))
+        .and_then(Value::as_u64)
+}
@@ -0,0 +1,155 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::sync::oneshot;
+use tracing::debug;
+
+// A weight-1 flow's cost per request, in virtual-time units.
+const UNIT_COST: f64 = 1_000_000.0;
+
+// --- Fair Queuing ---
+// A backend with `max_concurrent_requests` admits that many requests at once
+// (across all its replicas). Beyond that, requests wait, and each free slot goes
+// to the waiting request with the smallest finish tag: weighted fair queuing, so
+// a flow (a tenant, or else an API key) sending a burst only delays its own
+// requests, and a flow with weight 2 gets twice the slots of one with weight 1.
+pub struct FairQueue {
+    limit: usize,
+    state: Mutex<QueueState>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    active: usize,
+    virtual_time: u64,
+    last_tag: HashMap<String, u64>, // per flow, while anything waits
+    waiting: BTreeMap<(u64, u64), oneshot::Sender<()>>, // (finish tag, arrival) -> waiter
+    arrivals: u64,
+}
+
+// A queued request. If it times out or its client goes away, it leaves the
+// queue, returning any slot that was handed to it in the meantime.
+struct Pending<'a> {
+    queue: &'a Arc<FairQueue>,
+    position: (u64, u64),
+    rx: oneshot::Receiver<()>,
+    granted: bool,
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        if self.granted {
+            return;
+        }
+        // Waiters are only removed by `release`, which hands them a slot.
+        let handed_over = self.queue.state.lock().unwrap().waiting.remove(&self.position).is_none();
+        if handed_over {
+            self.queue.release();
+        }
+    }
+}
+
+// Holds one of the backend's slots until dropped.
+pub struct Slot(Arc<FairQueue>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+impl FairQueue {
+    pub fn new(limit: usize) -> Arc<Self> {
+        Arc::new(FairQueue { limit: limit.max(1), state: Mutex::new(QueueState::default()) })
+    }
+
+    // None if no slot is free within `timeout`.
+    pub async fn acquire(self: &Arc<Self>, flow: &str, weight: f64, timeout: Duration) -> Option<Slot> {
+        let (tx, rx) = oneshot::channel();
+        let position = {
+            let mut state = self.state.lock().unwrap();
+            if state.active < self.limit && state.waiting.is_empty() {
+                state.active += 1;
+                return Some(Slot(self.clone()));
+            }
+            let cost = (UNIT_COST / weight.max(0.01)) as u64;
+            let start = state.last_tag.get(flow).copied().unwrap_or(0).max(state.virtual_time);
+            let tag = start + cost;
+            state.last_tag.insert(flow.to_string(), tag);
+            state.arrivals += 1;
+            let position = (tag, state.arrivals);
+            state.waiting.insert(position, tx);
+            debug!("Queued request from '{}' ({} waiting, {} active)", flow, state.waiting.len(), state.active);
+            position
+        };
+
+        let mut pending = Pending { queue: self, position, rx, granted: false };
+        pending.granted = matches!(tokio::time::timeout(timeout, &mut pending.rx).await, Ok(Ok(())));
+        pending.granted.then(|| Slot(self.clone()))
+    }
+
+    // Requests holding or waiting for a slot, over the limit.
+    pub fn utilization(&self) -> f64 {
+        let state = self.state.lock().unwrap();
+        (state.active + state.waiting.len()) as f64 / self.limit as f64
+    }
+
+    // Hands the slot to the next waiter, skipping any that gave up.
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        while let Some(((tag, _), waiter)) = state.waiting.pop_first() {
+            state.virtual_time = tag;
+            if waiter.send(()).is_ok() {
+                return;
+            }
+        }
+        state.active -= 1;
+        state.last_tag.clear();
+    }
+}
+
+// --- Stream Caps ---
+// Hard limits on concurrent streams: MAX_CONCURRENT_STREAMS for the whole gateway
+// and `max_concurrent_streams` per model. Unlike the fair queue, a request over
+// either cap is turned away at once, so a spike can't exhaust the gateway's own
+// memory and file descriptors.
+#[derive(Clone)]
+pub struct StreamLimit {
+    limit: usize,
+    active: Arc<AtomicUsize>,
+}
+
+pub struct StreamPermit(Arc<AtomicUsize>);
+
+impl Drop for StreamPermit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl StreamLimit {
+    pub fn new(limit: usize) -> Self {
+        StreamLimit { limit, active: Arc::new(AtomicUsize::new(0)) }
+    }
+
+    pub fn try_acquire(&self) -> Option<StreamPermit> {
+        if self.active.fetch_add(1, Ordering::Relaxed) >= self.limit {
+            self.active.fetch_sub(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(StreamPermit(self.active.clone()))
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn utilization(&self) -> f64 {
+        self.active.load(Ordering::Relaxed) as f64 / self.limit.max(1) as f64
+    }
+}
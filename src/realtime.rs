@@ -0,0 +1,397 @@
+use axum::{
+    extract::{
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::{HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::net::{TcpStream, UnixStream};
+use tokio_tungstenite::{
+    tungstenite::{self, client::IntoClientRequest, protocol::frame::coding::CloseCode},
+    MaybeTlsStream, WebSocketStream,
+};
+use tracing::{info, warn};
+
+use crate::{
+    auth::Caller, backend::Backend, client::{self, Transport}, headers, mock, request_log::Recorder, secrets::Secret, tenants, uds, usage::{Pricing, Usage},
+    AppError, AppState, RequestMeta,
+};
+
+type UpstreamSocket = WebSocketStream<MaybeTlsStream<Box<dyn Transport>>>;
+
+// --- Realtime API ---
+// GET /v1/realtime?model=... proxies OpenAI's Realtime protocol: the client's
+// WebSocket is relayed frame by frame to the model's backend in REALTIME_BACKENDS
+// (its own model table in the VLLM_BACKENDS format), whose `http(s)://` URLs are
+// dialed as `ws(s)://.../v1/realtime`. A hosted provider gets the backend's
+// `api_key`, else REALTIME_API_KEY, as its bearer token. Each session is one request log entry: the usage of every
+// `response.done` event, logged when either side closes.
+#[derive(Debug, Deserialize)]
+pub struct RealtimeQuery {
+    model: String,
+}
+
+#[utoipa::path(
+    get, path = "/v1/realtime", tag = "Realtime",
+    description = "Upgrades to a WebSocket relayed to the model's realtime backend, speaking OpenAI's Realtime event protocol.",
+    params(("model" = String, Query)),
+    responses((status = 101, description = "Switching to the WebSocket protocol.")),
+)]
+pub async fn connect(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    Query(query): Query<RealtimeQuery>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let meta = RequestMeta::new();
+    let (model, backend) = match tenants::route_in(&caller, &state.realtime_backends, &query.model) {
+        Ok(routed) => routed,
+        Err(e) => {
+            let mut response = e.into_response();
+            headers::stamp_metadata(response.headers_mut(), &meta, &query.model, None);
+            Recorder::new(&state, &meta, query.model, &caller).finish(response.status().as_u16(), None, None);
+            return response;
+        }
+    };
+    let mut recorder = Recorder::new(&state, &meta, model.clone(), &caller);
+    recorder.set_tenant(caller.tenant().cloned());
+    recorder.started();
+
+    let replica = backend.pick_replica(&state.latency);
+    if let Some(replica) = &replica {
+        recorder.set_backend(replica);
+    }
+    let opened = match &replica {
+        Some(replica) => open(&state, &headers, &backend, replica, &model, &mut recorder).await,
+        None => Err(AppError::NoBackendAvailable(model.clone())),
+    };
+    let mut response = match opened {
+        Ok(upstream) => {
+            let pricing = backend.config.pricing;
+            upgrade.protocols(["realtime"]).on_upgrade(move |socket| session(socket, upstream, recorder, pricing))
+        }
+        Err(e) => {
+            let response = e.into_response();
+            recorder.finish(response.status().as_u16(), None, None);
+            response
+        }
+    };
+    headers::stamp_metadata(response.headers_mut(), &meta, &model, replica.as_deref());
+    response
+}
+
+enum Upstream {
+    Socket(Box<UpstreamSocket>),
+    Mock { replica: String, model: String },
+}
+
+// Dials the backend before the client's upgrade is accepted, so a backend that
+// is down or refuses the session fails the handshake with a normal HTTP error.
+async fn open(
+    state: &AppState,
+    headers: &HeaderMap,
+    backend: &Backend,
+    replica: &str,
+    model: &str,
+    recorder: &mut Recorder,
+) -> Result<Upstream, AppError> {
+    if let Some(streams) = &backend.streams {
+        let permit = streams.try_acquire().ok_or_else(|| AppError::Overloaded {
+            message: format!("Model '{}' is at its limit of {} concurrent sessions. Try again later.", model, streams.limit()),
+            retry_after_secs: state.retry_after_secs,
+        })?;
+        recorder.hold(permit);
+    }
+    if mock::is_mock(replica) {
+        return Ok(Upstream::Mock { replica: replica.to_string(), model: model.to_string() });
+    }
+    let base = backend.base_url(replica).replacen("https://", "wss://", 1).replacen("http://", "ws://", 1);
+    let served = backend.served_model(model);
+    let url = backend.endpoint_url(&base, "realtime", served);
+    let url = format!("{}{}model={}", url, if url.contains('?') { '&' } else { '?' }, served);
+    info!("Opening realtime session for model '{}' to: {}", model, url);
+    let invalid = |e: tungstenite::Error| AppError::Internal(format!("Invalid realtime backend URL {}: {}", url, e));
+    let mut request = url.as_str().into_client_request().map_err(invalid)?;
+    state.header_policy.copy_request(headers, request.headers_mut());
+    if let Some(beta) = headers.get("openai-beta") {
+        request.headers_mut().insert("openai-beta", beta.clone());
+    }
+    let api_key = backend.api_key.as_ref().or(state.realtime_api_key.as_ref()).map(Secret::get);
+    if let Some(api_key) = api_key.filter(|key| !key.is_empty()) {
+        let bearer = HeaderValue::from_str(&format!("Bearer {}", api_key))
+            .map_err(|_| AppError::Internal("The realtime API key is not a valid header value.".to_string()))?;
+        request.headers_mut().insert("authorization", bearer);
+    }
+
+    let handshake = async {
+        let transport = dial(backend, replica, &url).await.map_err(tungstenite::Error::Io)?;
+        tokio_tungstenite::client_async_tls(request, transport).await
+    };
+    let connected = match backend.timeouts.first_byte() {
+        Some(limit) => tokio::time::timeout(limit, handshake).await.map_err(|_| AppError::UpstreamTimeout {
+            url: url.clone(),
+            message: format!("The backend did not accept the session within {}ms.", limit.as_millis()),
+        })?,
+        None => handshake.await,
+    };
+    match connected {
+        Ok((socket, _)) => Ok(Upstream::Socket(Box::new(socket))),
+        Err(tungstenite::Error::Http(response)) => {
+            let text = response.body().as_deref().map(String::from_utf8_lossy).unwrap_or_default().into_owned();
+            Err(AppError::BackendRespondedError { status: response.status(), text, url, retry_after: None })
+        }
+        Err(e) => Err(AppError::BackendConnectFailed { url, error: e.to_string() }),
+    }
+}
+
+// A `unix:` replica is reached on its socket, anything else over TCP, through the
+// backend's proxy if it has one; TLS for a `wss://` URL is added on top by the
+// handshake.
+async fn dial(backend: &Backend, replica: &str, url: &str) -> std::io::Result<Box<dyn Transport>> {
+    if let Some(path) = uds::socket_path(replica) {
+        return Ok(Box::new(UnixStream::connect(path).await?));
+    }
+    let parsed = reqwest::Url::parse(url).map_err(std::io::Error::other)?;
+    let port = parsed.port_or_known_default().unwrap_or(80);
+    let host = parsed.host_str().ok_or_else(|| std::io::Error::other(format!("{} has no host", url)))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']'); // an IPv6 literal
+    match client::proxy_for(backend.proxy.as_ref(), &parsed).map_err(std::io::Error::other)? {
+        Some(proxy) => client::connect_via(&proxy, host, port).await,
+        None => Ok(Box::new(TcpStream::connect((host, port)).await?)),
+    }
+}
+
+async fn session(client: WebSocket, upstream: Upstream, recorder: Recorder, pricing: Option<Pricing>) {
+    let cancel = recorder.cancellation();
+    let cancelled = async {
+        match &cancel {
+            Some(cancel) => cancel.notified().await,
+            None => std::future::pending().await,
+        }
+    };
+    let (status, usage) = match upstream {
+        Upstream::Socket(upstream) => relay(client, *upstream, cancelled).await,
+        Upstream::Mock { replica, model } => mock_session(client, &replica, &model, cancelled).await,
+    };
+    info!("Realtime session ended ({}): {} input + {} output tokens", status, usage.prompt_tokens, usage.completion_tokens);
+    let cost = pricing.map(|pricing| pricing.cost(&usage));
+    recorder.finish(status, Some(usage), cost);
+}
+
+// Frames pass through untouched in both directions. Ends with 200 when either
+// side closes the session, 499 if the client drops or it is cancelled, and 502
+// if the backend connection fails.
+async fn relay(client: WebSocket, upstream: UpstreamSocket, cancelled: impl std::future::Future<Output = ()>) -> (u16, Usage) {
+    let (mut client_tx, mut client_rx) = client.split();
+    let (mut upstream_tx, mut upstream_rx) = upstream.split();
+    let mut usage = Usage::default();
+    tokio::pin!(cancelled);
+    let status = loop {
+        tokio::select! {
+            message = client_rx.next() => match message {
+                Some(Ok(Message::Close(frame))) => {
+                    // The reply to the client's close is queued by the socket itself.
+                    let _ = upstream_tx.send(to_upstream(Message::Close(frame))).await;
+                    let _ = client_tx.flush().await;
+                    break 200;
+                }
+                Some(Ok(message)) => {
+                    if upstream_tx.send(to_upstream(message)).await.is_err() {
+                        let _ = client_tx.send(close(1011, "The realtime backend connection failed.")).await;
+                        break 502;
+                    }
+                }
+                None | Some(Err(_)) => {
+                    let _ = upstream_tx.close().await;
+                    break 499;
+                }
+            },
+            message = upstream_rx.next() => match message {
+                Some(Ok(tungstenite::Message::Close(frame))) => {
+                    let _ = upstream_tx.send(tungstenite::Message::Close(frame.clone())).await;
+                    let _ = client_tx.send(Message::Close(frame.map(|frame| CloseFrame { code: frame.code.into(), reason: frame.reason }))).await;
+                    break 200;
+                }
+                Some(Ok(message)) => {
+                    if let tungstenite::Message::Text(text) = &message {
+                        tally(&mut usage, text);
+                    }
+                    let Some(message) = to_client(message) else { continue };
+                    if client_tx.send(message).await.is_err() {
+                        let _ = upstream_tx.close().await;
+                        break 499;
+                    }
+                }
+                None | Some(Err(_)) => {
+                    let _ = client_tx.send(close(1011, "The realtime backend connection failed.")).await;
+                    break 502;
+                }
+            },
+            _ = &mut cancelled => {
+                let _ = upstream_tx.close().await;
+                let _ = client_tx.send(close(1000, "The session was cancelled.")).await;
+                break 499;
+            }
+        }
+    };
+    (status, usage)
+}
+
+// Adds a `response.done` event's usage (text and audio tokens alike).
+fn tally(usage: &mut Usage, text: &str) {
+    if !text.contains("response.done") {
+        return;
+    }
+    let Ok(event) = serde_json::from_str::<Value>(text) else { return };
+    if event["type"] != "response.done" {
+        return;
+    }
+    let tokens = &event["response"]["usage"];
+    usage.prompt_tokens += tokens["input_tokens"].as_u64().unwrap_or(0);
+    usage.completion_tokens += tokens["output_tokens"].as_u64().unwrap_or(0);
+}
+
+fn close(code: u16, reason: &'static str) -> Message {
+    Message::Close(Some(CloseFrame { code, reason: reason.into() }))
+}
+
+fn to_upstream(message: Message) -> tungstenite::Message {
+    match message {
+        Message::Text(text) => tungstenite::Message::Text(text),
+        Message::Binary(data) => tungstenite::Message::Binary(data),
+        Message::Ping(data) => tungstenite::Message::Ping(data),
+        Message::Pong(data) => tungstenite::Message::Pong(data),
+        Message::Close(frame) => tungstenite::Message::Close(frame.map(|frame| tungstenite::protocol::CloseFrame {
+            code: CloseCode::from(frame.code),
+            reason: frame.reason,
+        })),
+    }
+}
+
+fn to_client(message: tungstenite::Message) -> Option<Message> {
+    match message {
+        tungstenite::Message::Text(text) => Some(Message::Text(text)),
+        tungstenite::Message::Binary(data) => Some(Message::Binary(data)),
+        tungstenite::Message::Ping(data) => Some(Message::Ping(data)),
+        tungstenite::Message::Pong(data) => Some(Message::Pong(data)),
+        tungstenite::Message::Close(frame) => Some(Message::Close(frame.map(|frame| CloseFrame {
+            code: frame.code.into(),
+            reason: frame.reason,
+        }))),
+        tungstenite::Message::Frame(_) => None,
+    }
+}
+
+// --- Mock Sessions ---
+// Enough of the protocol for text-only testing: `session.created` on connect,
+// `session.update` and `conversation.item.create` acknowledged, and each
+// `response.create` answered with the mock backend's reply as text deltas.
+async fn mock_session(
+    mut client: WebSocket,
+    replica: &str,
+    model: &str,
+    cancelled: impl std::future::Future<Output = ()>,
+) -> (u16, Usage) {
+    let event_id = || format!("event_{}", uuid::Uuid::new_v4().simple());
+    let mut session = json!({ "id": format!("sess_{}", uuid::Uuid::new_v4().simple()), "object": "realtime.session", "model": model, "modalities": ["text"] });
+    let mut last_user = String::new();
+    let mut usage = Usage::default();
+    tokio::pin!(cancelled);
+    if client.send(Message::Text(json!({ "type": "session.created", "event_id": event_id(), "session": session }).to_string())).await.is_err() {
+        return (499, usage);
+    }
+    loop {
+        let message = tokio::select! {
+            message = client.recv() => message,
+            _ = &mut cancelled => {
+                let _ = client.send(close(1000, "The session was cancelled.")).await;
+                return (499, usage);
+            }
+        };
+        let text = match message {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(frame))) => {
+                let _ = client.send(Message::Close(frame)).await;
+                return (200, usage);
+            }
+            Some(Ok(_)) => continue,
+            None | Some(Err(_)) => return (499, usage),
+        };
+        let Ok(event) = serde_json::from_str::<Value>(&text) else {
+            let error = json!({ "type": "error", "event_id": event_id(), "error": { "type": "invalid_request_error", "message": "Events must be JSON." } });
+            if client.send(Message::Text(error.to_string())).await.is_err() {
+                return (499, usage);
+            }
+            continue;
+        };
+        let replies = match event["type"].as_str().unwrap_or_default() {
+            "session.update" => {
+                if let (Some(session), Some(update)) = (session.as_object_mut(), event["session"].as_object()) {
+                    session.extend(update.clone());
+                }
+                vec![json!({ "type": "session.updated", "event_id": event_id(), "session": session })]
+            }
+            "conversation.item.create" => {
+                let mut item = event["item"].clone();
+                item["id"] = item.get("id").cloned().unwrap_or_else(|| format!("item_{}", uuid::Uuid::new_v4().simple()).into());
+                if let Some(text) = item["content"].as_array().and_then(|parts| parts.iter().find_map(|part| part["text"].as_str())) {
+                    last_user = text.to_string();
+                }
+                vec![json!({ "type": "conversation.item.created", "event_id": event_id(), "item": item })]
+            }
+            "response.create" => {
+                let Ok((words, ttft, per_token)) = mock::reply(replica, &last_user) else {
+                    warn!("Invalid mock backend URL '{}'", replica);
+                    let _ = client.send(close(1011, "Invalid mock backend URL.")).await;
+                    return (502, usage);
+                };
+                let response_id = format!("resp_{}", uuid::Uuid::new_v4().simple());
+                if client.send(Message::Text(json!({
+                    "type": "response.created", "event_id": event_id(),
+                    "response": { "id": response_id, "object": "realtime.response", "status": "in_progress", "output": [] },
+                }).to_string())).await.is_err() {
+                    return (499, usage);
+                }
+                for (i, word) in words.iter().enumerate() {
+                    tokio::time::sleep(if i == 0 { ttft } else { per_token }).await;
+                    let delta = json!({ "type": "response.text.delta", "event_id": event_id(), "response_id": response_id, "delta": word });
+                    if client.send(Message::Text(delta.to_string())).await.is_err() {
+                        return (499, usage);
+                    }
+                }
+                let text = words.concat();
+                let turn = Usage { prompt_tokens: last_user.split_whitespace().count() as u64, completion_tokens: words.len() as u64 };
+                usage.prompt_tokens += turn.prompt_tokens;
+                usage.completion_tokens += turn.completion_tokens;
+                vec![
+                    json!({ "type": "response.text.done", "event_id": event_id(), "response_id": response_id, "text": text }),
+                    json!({
+                        "type": "response.done", "event_id": event_id(),
+                        "response": {
+                            "id": response_id, "object": "realtime.response", "status": "completed",
+                            "output": [{ "type": "message", "role": "assistant", "content": [{ "type": "text", "text": text }] }],
+                            "usage": {
+                                "total_tokens": turn.prompt_tokens + turn.completion_tokens,
+                                "input_tokens": turn.prompt_tokens,
+                                "output_tokens": turn.completion_tokens,
+                            },
+                        },
+                    }),
+                ]
+            }
+            _ => Vec::new(),
+        };
+        for reply in replies {
+            if client.send(Message::Text(reply.to_string())).await.is_err() {
+                return (499, usage);
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::json;
+use std::{collections::BTreeMap, time::{SystemTime, UNIX_EPOCH}};
+use tokio::{io::AsyncWriteExt, sync::mpsc};
+use tracing::{error, info};
+
+use crate::ChatRequest;
+
+// --- PII Redaction Configuration ---
+// Loaded from PII_REDACTION; the stage only runs for backends that opt in with
+// `"redact_pii": true`, typically the ones outside our own network.
+#[derive(Debug, Deserialize)]
+pub struct RedactionConfig {
+    #[serde(default = "default_detectors")]
+    pub detectors: Vec<Detector>,
+    #[serde(default)]
+    pub patterns: BTreeMap<String, String>, // label -> regex
+    #[serde(default)]
+    pub audit_log: Option<String>, // JSONL file path; records are also emitted as tracing events
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Detector {
+    Email,
+    Phone,
+    CreditCard,
+}
+
+fn default_detectors() -> Vec<Detector> {
+    vec![Detector::Email, Detector::Phone, Detector::CreditCard]
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        RedactionConfig { detectors: default_detectors(), patterns: BTreeMap::new(), audit_log: None }
+    }
+}
+
+struct Rule {
+    label: String,
+    regex: Regex,
+    luhn: bool, // only mask matches passing the Luhn checksum
+}
+
+pub struct Redactor {
+    rules: Vec<Rule>,
+    audit_tx: Option<mpsc::UnboundedSender<String>>,
+}
+
+impl Redactor {
+    pub fn new(config: RedactionConfig) -> Result<Self> {
+        let mut rules = Vec::new();
+        // Credit cards run before phone numbers so long digit runs aren't claimed as phones.
+        for detector in [Detector::CreditCard, Detector::Email, Detector::Phone] {
+            if !config.detectors.contains(&detector) {
+                continue;
+            }
+            let (label, pattern) = match detector {
+                Detector::Email => ("email", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}"),
+                Detector::Phone => ("phone", r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{3}\)|\d{3})[\s.-]?\d{3}[\s.-]?\d{4}\b"),
+                Detector::CreditCard => ("credit_card", r"\b(?:\d[ -]?){12,18}\d\b"),
+            };
+            rules.push(Rule {
+                label: label.to_string(),
+                regex: Regex::new(pattern).expect("built-in PII pattern is valid"),
+                luhn: detector == Detector::CreditCard,
+            });
+        }
+        for (label, pattern) in config.patterns {
+            let regex = Regex::new(&pattern)
+                .with_context(|| format!("Invalid PII_REDACTION pattern '{}'", label))?;
+            rules.push(Rule { label, regex, luhn: false });
+        }
+
+        let audit_tx = match config.audit_log {
+            Some(path) => Some(spawn_audit_writer(path)?),
+            None => None,
+        };
+
+        Ok(Redactor { rules, audit_tx })
+    }
+
+    // Masks every match in message content in place and records how many of each
+    // kind were replaced. The audit record never contains the redacted values.
+    pub fn redact_request(&self, body: &mut ChatRequest) {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+
+        for text in body.messages.iter_mut().flat_map(|m| m.content.texts_mut()) {
+            if let Some(masked) = self.mask(text, &mut counts) {
+                *text = masked;
+            }
+        }
+
+        if counts.is_empty() {
+            return;
+        }
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let record = json!({ "timestamp": timestamp, "model": body.model, "redactions": counts });
+        info!(target: "pii_audit", "Redacted PII before forwarding: {}", record);
+        if let Some(tx) = &self.audit_tx {
+            let _ = tx.send(record.to_string());
+        }
+    }
+
+    // Masks `text` without writing an audit record.
+    pub fn redact_text(&self, text: &str) -> String {
+        self.mask(text, &mut BTreeMap::new()).unwrap_or_else(|| text.to_string())
+    }
+
+    // The masked text, or None if nothing matched.
+    fn mask<'a>(&'a self, text: &str, counts: &mut BTreeMap<&'a str, usize>) -> Option<String> {
+        let mut masked: Option<String> = None;
+        for rule in &self.rules {
+            let current = masked.as_deref().unwrap_or(text);
+            let mut replaced = 0;
+            let next = rule.regex.replace_all(current, |caps: &regex::Captures| {
+                let matched = &caps[0];
+                if rule.luhn && !luhn_valid(matched) {
+                    return matched.to_string();
+                }
+                replaced += 1;
+                format!("[REDACTED_{}]", rule.label.to_uppercase())
+            });
+            if replaced > 0 {
+                masked = Some(next.into_owned());
+                *counts.entry(rule.label.as_str()).or_default() += replaced;
+            }
+        }
+        masked
+    }
+}
+
+// Appends audit records on a background task so request handling never waits on disk.
+fn spawn_audit_writer(path: String) -> Result<mpsc::UnboundedSender<String>> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open PII audit log '{}'", path))?;
+    let mut file = tokio::fs::File::from_std(file);
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+
+    tokio::spawn(async move {
+        while let Some(mut line) = rx.recv().await {
+            line.push('\n');
+            if let Err(e) = file.write_all(line.as_bytes()).await {
+                error!("Failed to write PII audit record to '{}': {}", path, e);
+            }
+        }
+    });
+
+    Ok(tx)
+}
+
+fn luhn_valid(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    if digits.len() < 13 {
+        return false;
+    }
+    let sum: u32 = digits.iter().rev().enumerate().map(|(i, &d)| {
+        if i % 2 == 1 {
+            let doubled = d * 2;
+            if doubled > 9 { doubled - 9 } else { doubled }
+        } else {
+            d
+        }
+    }).sum();
+    sum.is_multiple_of(10)
+}
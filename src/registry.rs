@@ -0,0 +1,212 @@
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::StreamExt;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tracing::{info, warn};
+
+use crate::{
+    backend::{Backend, BackendLoader, BackendTable, Backends},
+    config, discovery,
+};
+
+// Blocking queries and watches return at least this often, even without changes.
+const WATCH_SECS: u64 = 300;
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+// --- Backend Registry ---
+// With BACKEND_REGISTRY set, the model -> backend table is read from one key in
+// Consul KV or etcd, holding the same JSON as VLLM_BACKENDS, and watched for
+// changes, so every gateway in a fleet serves the same models. Models from
+// VLLM_BACKENDS are kept alongside the registry's (which win on a name clash).
+// An update that fails to parse or load is rejected as a whole, keeping the
+// current table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistryConfig {
+    Consul(ConsulRegistry),
+    Etcd(EtcdRegistry),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConsulRegistry {
+    #[serde(default = "default_consul_url")]
+    pub url: String,
+    pub key: String, // e.g. "llm-gateway/backends"
+    #[serde(default)]
+    pub token: Option<String>, // ACL token, sent as X-Consul-Token
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EtcdRegistry {
+    #[serde(default = "default_etcd_url")]
+    pub url: String, // etcd's v3 JSON gateway
+    pub key: String,
+}
+
+fn default_consul_url() -> String {
+    "http://127.0.0.1:8500".to_string()
+}
+
+fn default_etcd_url() -> String {
+    "http://127.0.0.1:2379".to_string()
+}
+
+impl RegistryConfig {
+    fn describe(&self) -> String {
+        match self {
+            RegistryConfig::Consul(consul) => format!("Consul key '{}' at {}", consul.key, consul.url),
+            RegistryConfig::Etcd(etcd) => format!("etcd key '{}' at {}", etcd.key, etcd.url),
+        }
+    }
+
+    // Waits until the key may have changed since `version` (not at all for 0),
+    // then reads it. Returns the new version and the value, None if the key is unset.
+    async fn read(&self, client: &Client, version: u64) -> Result<(u64, Option<String>)> {
+        match self {
+            RegistryConfig::Consul(consul) => {
+                let url = format!("{}/v1/kv/{}", consul.url.trim_end_matches('/'), consul.key.trim_start_matches('/'));
+                let mut request = client.get(url).query(&[("raw", "true")]);
+                if version > 0 {
+                    let wait = format!("{}s", WATCH_SECS);
+                    request = request.query(&[("index", version.to_string()), ("wait", wait)]);
+                }
+                if let Some(token) = &consul.token {
+                    request = request.header("X-Consul-Token", token);
+                }
+                let response = request.timeout(Duration::from_secs(WATCH_SECS + 30)).send().await?;
+                let index = response.headers().get("x-consul-index")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0);
+                if response.status() == StatusCode::NOT_FOUND {
+                    return Ok((index, None));
+                }
+                Ok((index, Some(response.error_for_status()?.text().await?)))
+            }
+            RegistryConfig::Etcd(etcd) => {
+                let base = etcd.url.trim_end_matches('/');
+                let key = BASE64.encode(&etcd.key);
+                if version > 0 {
+                    // Returns on the first event for the key, or when the watch times out.
+                    let watch = json!({ "create_request": { "key": key, "start_revision": (version + 1).to_string() } });
+                    let response = client.post(format!("{}/v3/watch", base)).json(&watch)
+                        .timeout(Duration::from_secs(WATCH_SECS))
+                        .send().await?.error_for_status()?;
+                    let mut body = response.bytes_stream();
+                    let mut buffer = Vec::new();
+                    'watch: while let Some(Ok(bytes)) = body.next().await {
+                        buffer.extend_from_slice(&bytes);
+                        while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                            let line: Vec<u8> = buffer.drain(..=end).collect();
+                            let Ok(message) = serde_json::from_slice::<Value>(&line) else { continue };
+                            if message["result"]["events"].as_array().is_some_and(|events| !events.is_empty()) {
+                                break 'watch;
+                            }
+                        }
+                    }
+                }
+                let range: Value = client.post(format!("{}/v3/kv/range", base)).json(&json!({ "key": key }))
+                    .timeout(Duration::from_secs(30))
+                    .send().await?.error_for_status()?.json().await?;
+                // int64 fields come back as strings from the JSON gateway.
+                let revision = range["header"]["revision"].as_str().and_then(|r| r.parse().ok()).unwrap_or(0);
+                let value = match range["kvs"][0]["value"].as_str() {
+                    Some(value) => Some(String::from_utf8(BASE64.decode(value)?).context("The registry value is not UTF-8")?),
+                    None => None,
+                };
+                Ok((revision, value))
+            }
+        }
+    }
+}
+
+struct Registry {
+    config: RegistryConfig,
+    loader: BackendLoader,
+    table: Arc<BackendTable>,
+    fixed: Backends, // from VLLM_BACKENDS
+    current: HashMap<String, (Value, Arc<Backend>)>, // from the registry, with the entry they were built from
+    raw: Option<String>,
+}
+
+// Loads the registry's models into `table` before the gateway starts serving,
+// then follows changes in the background.
+pub async fn start(config: RegistryConfig, loader: BackendLoader, table: Arc<BackendTable>) -> Result<()> {
+    info!("Reading backends from {}", config.describe());
+    let (version, raw) = config.read(&loader.client, 0).await
+        .with_context(|| format!("Failed to read backends from {}", config.describe()))?;
+    let raw = raw.with_context(|| format!("{} is not set", config.describe()))?;
+    let mut registry = Registry { config, loader, fixed: (*table.snapshot()).clone(), table, current: HashMap::new(), raw: None };
+    registry.apply(raw)?;
+    tokio::spawn(registry.watch(version));
+    Ok(())
+}
+
+impl Registry {
+    async fn watch(mut self, mut version: u64) {
+        loop {
+            match self.config.read(&self.loader.client, version).await {
+                // Consul asks clients to start over when its index goes backwards.
+                Ok((new_version, raw)) => {
+                    version = if new_version < version { 0 } else { new_version };
+                    match raw {
+                        Some(raw) if self.raw.as_ref() != Some(&raw) => {
+                            if let Err(e) = self.apply(raw) {
+                                warn!("Ignoring backend registry update: {:#}", e);
+                            }
+                        }
+                        None if self.raw.is_some() => {
+                            warn!("{} was deleted; keeping the current backends", self.config.describe());
+                            self.raw = None;
+                        }
+                        _ => {}
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to watch {}: {:#}; retrying", self.config.describe(), e);
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+
+    // Rebuilds only the entries that changed, so unchanged backends keep their
+    // replica counters and discovery.
+    fn apply(&mut self, raw: String) -> Result<()> {
+        let entries: HashMap<String, Value> = serde_json::from_str(&raw)
+            .context("The registry value must be a JSON object in the VLLM_BACKENDS format")?;
+        let mut next = HashMap::new();
+        let mut built = Backends::new();
+        for (model, entry) in entries {
+            let backend = match self.current.get(&model) {
+                Some((previous, backend)) if *previous == entry => backend.clone(),
+                _ => {
+                    let config = config::parse_backend(entry.clone())
+                        .with_context(|| format!("Invalid registry entry for model '{}'", model))?;
+                    let backend = Arc::new(self.loader.load(&model, config)?);
+                    built.insert(model.clone(), backend.clone());
+                    backend
+                }
+            };
+            next.insert(model, (entry, backend));
+        }
+        discovery::start(&built)?;
+
+        let removed: Vec<&String> = self.current.keys().filter(|model| !next.contains_key(*model)).collect();
+        if !built.is_empty() || !removed.is_empty() {
+            info!(
+                "Backend registry: {} model(s), {} added or changed, {} removed",
+                next.len(), built.len(), removed.len()
+            );
+        }
+        let mut backends = self.fixed.clone();
+        backends.extend(next.iter().map(|(model, (_, backend))| (model.clone(), backend.clone())));
+        self.table.replace(backends);
+        self.current = next;
+        self.raw = Some(raw);
+        Ok(())
+    }
+}
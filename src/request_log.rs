@@ -0,0 +1,401 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex},
+    time::Instant,
+};
+
+use tokio::sync::Notify;
+use tracing::info;
+
+use crate::{
+    active::{ActiveRequests, Registration},
+    alerts::Alerter,
+    archive::{ArchiveRecord, Archiver},
+    auth::{Caller, Scope},
+    capture::{CapturedChunk, Capturer, Exchange},
+    config,
+    events::{EventBus, LifecycleEvent},
+    log_content::LogContent,
+    metadata::Metadata,
+    metrics::{InFlight, Metrics},
+    routing::LatencyTracker,
+    store::Store,
+    tenants::Tenant,
+    trace::{Direction, RequestTrace, TraceEvent, TraceStore},
+    usage::Usage,
+    AppState, ChatMessage, ChatRequest, RequestMeta,
+};
+
+// --- Request Log ---
+// One record per chat request, kept in memory for the admin usage API. The oldest
+// records are dropped once REQUEST_LOG_CAPACITY is reached. With a persistent
+// STORE_URL, every record is written there too, and the most recent ones are
+// read back at startup.
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestRecord {
+    pub at: DateTime<Utc>,
+    pub model: String,
+    pub key: Option<String>, // API key name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>, // OpenAI-Organization
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>, // OpenAI-Project
+    pub status: u16, // 499 when the client went away mid-stream
+    pub prompt_tokens: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached_tokens: Option<u64>, // of the prompt tokens, those read from the backend's cache
+    pub completion_tokens: u64,
+    pub cost: Option<f64>,
+    pub latency_ms: u64, // until the last byte
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio_seconds: Option<f64>, // transcriptions
+    #[serde(skip_serializing_if = "Metadata::is_empty")]
+    pub metadata: Metadata, // the client's annotations
+}
+
+pub struct RequestLog {
+    records: Mutex<VecDeque<(u64, RequestRecord)>>, // with an ever-increasing sequence number
+    next_seq: AtomicU64,
+    capacity: usize,
+    store: Arc<dyn Store>,
+}
+
+impl RequestLog {
+    pub async fn from_env(store: Arc<dyn Store>) -> Result<Self> {
+        let capacity = config::env_parse("REQUEST_LOG_CAPACITY")?.unwrap_or(100_000);
+        let restored = store.recent_requests(capacity).await?;
+        let log = RequestLog { records: Mutex::new(VecDeque::new()), next_seq: AtomicU64::new(0), capacity, store };
+        if log.store.persistent() {
+            info!("Restored {} request records from the store", restored.len());
+        }
+        for record in restored {
+            log.keep(record);
+        }
+        Ok(log)
+    }
+
+    pub fn push(&self, record: RequestRecord) {
+        self.store.submit_request(record.clone());
+        self.keep(record);
+    }
+
+    fn keep(&self, record: RequestRecord) {
+        let mut records = self.records.lock().unwrap();
+        if records.len() >= self.capacity {
+            records.pop_front();
+        }
+        records.push_back((self.next_seq.fetch_add(1, Ordering::Relaxed), record));
+    }
+
+    // Records with `from <= at < to`, in the order they were logged.
+    pub fn range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<RequestRecord> {
+        let records = self.records.lock().unwrap();
+        records.iter().map(|(_, r)| r).filter(|r| r.at >= from && r.at < to).cloned().collect()
+    }
+
+    // Like `range`, but looks at no more than `limit` records logged after `after`
+    // (the sequence number returned by the previous page), so a large export never
+    // holds the lock for long or copies the whole log. `None` means the end.
+    pub fn page(&self, from: DateTime<Utc>, to: DateTime<Utc>, after: Option<u64>, limit: usize) -> Option<(Vec<RequestRecord>, u64)> {
+        let records = self.records.lock().unwrap();
+        let start = after.map_or(0, |after| records.partition_point(|(seq, _)| *seq <= after));
+        let end = (start + limit).min(records.len());
+        if start == end {
+            return None;
+        }
+        let last = records[end - 1].0;
+        let page = records.range(start..end).map(|(_, r)| r).filter(|r| r.at >= from && r.at < to).cloned().collect();
+        Some((page, last))
+    }
+}
+
+// Collects what is known about a request while it runs, logs it exactly once, and
+// publishes its lifecycle events. With an archive sink, the prompt and completion
+// are kept too.
+pub struct Recorder {
+    log: Arc<RequestLog>,
+    events: Option<EventBus>,
+    request_id: String,
+    at: DateTime<Utc>,
+    received: Instant,
+    model: String,
+    key: Option<String>,
+    scope: Scope,
+    metadata: Arc<Metadata>,
+    tenant: Option<Arc<Tenant>>, // charged for the request's tokens
+    backend: Option<String>, // replica URL, once one is chosen
+    first_token: Option<Instant>,
+    metrics: Arc<Metrics>,
+    latency: Arc<LatencyTracker>,
+    in_flight: Option<InFlight>, // from `started` until the recorder goes away
+    active: Arc<ActiveRequests>,
+    registration: Option<Registration>, // likewise, listing the request for cancellation
+    held: Vec<Box<dyn Send + Sync>>, // concurrency slots and permits, likewise
+    archive: Option<Archiver>,
+    alerts: Option<Arc<Alerter>>,
+    messages: Option<Vec<ChatMessage>>,
+    completion: Option<String>,
+    audio_seconds: Option<f64>,
+    cached_tokens: Option<u64>,
+    capture: Option<Capturer>,
+    sampled: bool, // content kept whatever the outcome
+    keep_errors: bool, // content kept if the request fails
+    captured: Option<(ChatRequest, Vec<CapturedChunk>)>, // request sent upstream, payloads received
+    traces: Arc<TraceStore>,
+    trace: Option<Vec<TraceEvent>>, // with X-Gateway-Trace
+}
+
+impl Recorder {
+    pub fn new(state: &AppState, meta: &RequestMeta, model: String, caller: &Caller) -> Self {
+        Recorder {
+            log: state.request_log.clone(),
+            events: state.events.clone(),
+            request_id: meta.id.clone(),
+            at: Utc::now(),
+            received: meta.received,
+            model,
+            key: caller.key().map(|key| key.name.clone()),
+            scope: caller.scope().clone(),
+            metadata: meta.metadata.clone(),
+            tenant: None,
+            backend: None,
+            first_token: None,
+            metrics: state.metrics.clone(),
+            latency: state.latency.clone(),
+            in_flight: None,
+            active: state.active.clone(),
+            registration: None,
+            held: Vec::new(),
+            archive: state.archive.clone(),
+            alerts: state.alerts.clone(),
+            messages: None,
+            completion: None,
+            audio_seconds: None,
+            cached_tokens: None,
+            capture: state.capture.clone(),
+            sampled: state.log_sampling.sample(caller.key().and_then(|key| key.log_sample_rate)),
+            keep_errors: state.log_sampling.errors,
+            captured: None,
+            traces: state.traces.clone(),
+            trace: None,
+        }
+    }
+
+    pub fn set_tenant(&mut self, tenant: Option<Arc<Tenant>>) {
+        self.tenant = tenant;
+    }
+
+    pub fn hold(&mut self, guard: impl Send + Sync + 'static) {
+        self.held.push(Box::new(guard));
+    }
+
+    // Called once the request is admitted and routed. A request rejected before then
+    // still gets its record and `request_completed`, but no `request_started`.
+    pub fn started(&mut self) {
+        self.in_flight = Some(self.metrics.start_request(&self.model));
+        let tenant = self.tenant.as_ref().map(|tenant| tenant.name.as_str());
+        self.registration = Some(self.active.register(&self.request_id, &self.model, self.key.as_deref(), tenant, self.received));
+        self.publish(|| LifecycleEvent::RequestStarted {
+            request_id: self.request_id.clone(),
+            at: self.at,
+            model: self.model.clone(),
+            key: self.key.clone(),
+            metadata: (*self.metadata).clone(),
+        });
+    }
+
+    pub fn stalled(&self, backend: &str, idle_ms: u64) {
+        self.publish(|| LifecycleEvent::StreamStalled {
+            request_id: self.request_id.clone(),
+            at: Utc::now(),
+            model: self.model.clone(),
+            backend: backend.to_string(),
+            idle_ms,
+        });
+    }
+
+    // Notified when an admin or the client cancels the request.
+    pub fn cancellation(&self) -> Option<Arc<Notify>> {
+        self.registration.as_ref().map(|registration| registration.cancel.clone())
+    }
+
+    pub fn set_backend(&mut self, backend: &str) {
+        if let Some(registration) = &self.registration {
+            registration.set_backend(backend);
+        }
+        self.backend = Some(backend.to_string());
+    }
+
+    // Called for every payload; only the first one counts.
+    pub fn first_token(&mut self) {
+        if self.first_token.is_some() {
+            return;
+        }
+        let now = Instant::now();
+        self.first_token = Some(now);
+        let ttft = now.duration_since(self.received);
+        self.metrics.observe_ttft(&self.model, self.backend.as_deref().unwrap_or(""), ttft.as_secs_f64());
+        self.publish(|| LifecycleEvent::FirstToken {
+            request_id: self.request_id.clone(),
+            at: Utc::now(),
+            model: self.model.clone(),
+            ttft_ms: ttft.as_millis() as u64,
+        });
+    }
+
+    // A sink's content mode for this request, after the tenant's.
+    fn content_for(&self, sink: LogContent) -> LogContent {
+        sink.stricter(self.tenant.as_ref().and_then(|tenant| tenant.log_content))
+    }
+
+    // Content is gathered for requests that may end up sampled.
+    pub fn wants_content(&self) -> bool {
+        (self.sampled || self.keep_errors) && self.archive.as_ref().is_some_and(|archive| self.content_for(archive.content()) != LogContent::Metadata)
+    }
+
+    pub fn set_messages(&mut self, messages: &[ChatMessage]) {
+        if self.wants_content() {
+            self.messages = Some(messages.to_vec());
+        }
+    }
+
+    pub fn set_completion(&mut self, completion: String) {
+        if self.wants_content() {
+            self.completion = Some(completion);
+        }
+    }
+
+    pub fn set_audio_seconds(&mut self, seconds: f64) {
+        self.audio_seconds = Some(seconds);
+    }
+
+    pub fn audio_seconds(&self) -> Option<f64> {
+        self.audio_seconds
+    }
+
+    pub fn set_cached_tokens(&mut self, tokens: Option<u64>) {
+        self.cached_tokens = tokens;
+    }
+
+    // The request as sent upstream, when capturing traffic.
+    pub fn set_upstream_request(&mut self, body: &ChatRequest) {
+        if (self.sampled || self.keep_errors) && self.capture.as_ref().is_some_and(|capture| self.content_for(capture.content()) != LogContent::Metadata) {
+            self.captured = Some((body.clone(), Vec::new()));
+        }
+    }
+
+    pub fn capture_chunk(&mut self, data: &str) {
+        if let Some((_, chunks)) = &mut self.captured {
+            chunks.push(CapturedChunk { ms: self.received.elapsed().as_millis() as u64, data: data.to_string() });
+        }
+    }
+
+    pub fn trace(&mut self) {
+        self.trace = Some(Vec::new());
+    }
+
+    pub fn trace_payload(&mut self, direction: Direction, data: &str) {
+        if let Some(events) = &mut self.trace {
+            events.push(TraceEvent { ms: self.received.elapsed().as_millis() as u64, direction, data: data.to_string() });
+        }
+    }
+
+    pub fn finish(self, status: u16, usage: Option<Usage>, cost: Option<f64>) {
+        let usage = usage.unwrap_or_default();
+        if let Some(tenant) = &self.tenant {
+            tenant.record_tokens(usage.prompt_tokens + usage.completion_tokens);
+        }
+        if let (Some(first_token), Some(backend), 200) = (self.first_token, &self.backend, status) {
+            let ttft_ms = first_token.duration_since(self.received).as_millis() as u64;
+            self.latency.observe(backend, ttft_ms, self.received.elapsed().as_millis() as u64);
+        }
+        // Decode speed: the tokens after the first, over the time since it arrived.
+        if let Some(first_token) = self.first_token.filter(|_| status == 200 && usage.completion_tokens > 1) {
+            let elapsed = first_token.elapsed().as_secs_f64();
+            if elapsed > 0.0 {
+                let rate = (usage.completion_tokens - 1) as f64 / elapsed;
+                self.metrics.observe_tokens_per_second(&self.model, self.backend.as_deref().unwrap_or(""), rate);
+            }
+        }
+        if status == 200 && usage.prompt_tokens > 0 {
+            let backend = self.backend.as_deref().unwrap_or("");
+            self.metrics.observe_prompt_tokens(&self.model, backend, usage.prompt_tokens, self.cached_tokens.unwrap_or(0));
+        }
+        let record = RequestRecord {
+            at: self.at,
+            model: self.model.clone(),
+            key: self.key.clone(),
+            organization: self.scope.organization.clone(),
+            project: self.scope.project.clone(),
+            status,
+            prompt_tokens: usage.prompt_tokens,
+            cached_tokens: self.cached_tokens,
+            completion_tokens: usage.completion_tokens,
+            cost,
+            latency_ms: self.received.elapsed().as_millis() as u64,
+            audio_seconds: self.audio_seconds,
+            metadata: (*self.metadata).clone(),
+        };
+        self.metrics.observe_metadata(&record.model, status, &record.metadata);
+        self.publish(|| LifecycleEvent::RequestCompleted {
+            request_id: self.request_id.clone(),
+            at: Utc::now(),
+            model: record.model.clone(),
+            key: record.key.clone(),
+            status,
+            prompt_tokens: record.prompt_tokens,
+            completion_tokens: record.completion_tokens,
+            cost,
+            latency_ms: record.latency_ms,
+            metadata: record.metadata.clone(),
+        });
+        if let Some(alerts) = &self.alerts {
+            alerts.observe(&record);
+        }
+        let capture_content = self.capture.as_ref().map(|capture| self.content_for(capture.content())).unwrap_or_default();
+        let archive_content = self.archive.as_ref().map(|archive| self.content_for(archive.content())).unwrap_or_default();
+        let trace_content = self.content_for(LogContent::Full);
+        if let Some(events) = self.trace {
+            self.traces.push(RequestTrace {
+                id: self.request_id.clone(),
+                at: self.at,
+                model: self.model.clone(),
+                key: self.key.clone(),
+                backend: self.backend.clone(),
+                status,
+                events,
+            }, trace_content);
+        }
+        let keep_content = self.sampled || (self.keep_errors && status >= 400);
+        if let (Some(capture), Some((request, chunks)), true) = (&self.capture, self.captured, keep_content) {
+            capture.submit(Exchange {
+                request_id: self.request_id.clone(),
+                at: self.at,
+                backend: self.backend.clone(),
+                status,
+                request,
+                chunks,
+                content: capture_content,
+            });
+        }
+        if let Some(archive) = &self.archive {
+            archive.submit(ArchiveRecord {
+                request_id: self.request_id,
+                record: record.clone(),
+                messages: self.messages.filter(|_| keep_content),
+                completion: self.completion.filter(|_| keep_content),
+                content: archive_content,
+            });
+        }
+        self.log.push(record);
+    }
+
+    fn publish(&self, event: impl FnOnce() -> LifecycleEvent) {
+        if let Some(events) = &self.events {
+            events.publish(event());
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::HashSet, sync::Arc};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{
+    auth::Caller, backend::Backend, config::BackendApi, headers, mock, request_log::Recorder, tenants, upstream, usage::Usage,
+    AppError, AppState, RequestMeta,
+};
+
+// --- Rerank Endpoint ---
+// POST /v1/rerank scores documents against a query for retrieval pipelines. It
+// routes by `model` to RERANK_BACKENDS, its own model table in the VLLM_BACKENDS
+// format, and takes the Jina / Cohere style request that vLLM serves. Backends
+// with `"api": "tei"` (Hugging Face text-embeddings-inference) get it translated
+// to TEI's `/rerank`. Either way, results come back sorted by relevance.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct RerankRequest {
+    model: String,
+    query: String,
+    documents: Vec<Document>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_n: Option<usize>,
+    #[serde(default = "default_return_documents")]
+    return_documents: bool,
+}
+
+fn default_return_documents() -> bool {
+    true
+}
+
+// A plain string, or `{"text": ...}`.
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+#[serde(untagged)]
+enum Document {
+    Text(String),
+    Object { text: String },
+}
+
+impl Document {
+    fn text(&self) -> &str {
+        match self {
+            Document::Text(text) | Document::Object { text } => text,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RerankResponse {
+    id: String,
+    model: String,
+    results: Vec<RerankResult>,
+    usage: RerankUsage,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+struct RerankResult {
+    index: usize,
+    relevance_score: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    document: Option<DocumentText>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+struct DocumentText {
+    text: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
+struct RerankUsage {
+    #[serde(default)]
+    total_tokens: u64,
+}
+
+// vLLM's response; only the parts the gateway passes on.
+#[derive(Debug, Deserialize)]
+struct UpstreamRerank {
+    results: Vec<RerankResult>,
+    #[serde(default)]
+    usage: RerankUsage,
+}
+
+// TEI's response: one entry per text, unsorted.
+#[derive(Debug, Deserialize)]
+struct TeiScore {
+    index: usize,
+    score: f64,
+}
+
+#[utoipa::path(
+    post, path = "/v1/rerank", tag = "Rerank",
+    request_body = RerankRequest,
+    responses((status = 200, description = "The documents by relevance, most relevant first.", body = RerankResponse)),
+)]
+pub async fn rerank(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    Json(mut request): Json<RerankRequest>,
+) -> Response {
+    let meta = RequestMeta::new();
+    let routed = match request.documents.is_empty() {
+        true => Err(AppError::InvalidRequest("`documents` is empty.".to_string())),
+        false => tenants::route_in(&caller, &state.rerank_backends, &request.model),
+    };
+    let (model, backend) = match routed {
+        Ok(routed) => routed,
+        Err(e) => {
+            let mut response = e.into_response();
+            headers::stamp_metadata(response.headers_mut(), &meta, &request.model, None);
+            Recorder::new(&state, &meta, request.model, &caller).finish(response.status().as_u16(), None, None);
+            return response;
+        }
+    };
+    request.model = backend.served_model(&model).to_string();
+    let mut recorder = Recorder::new(&state, &meta, model.clone(), &caller);
+    recorder.set_tenant(caller.tenant().cloned());
+    recorder.started();
+
+    let replica = backend.pick_replica(&state.latency);
+    if let Some(replica) = &replica {
+        recorder.set_backend(replica);
+    }
+    let scored = match &replica {
+        Some(replica) => score(&state, &headers, &backend, replica, &request).await,
+        None => Err(AppError::NoBackendAvailable(model.clone())),
+    };
+    let mut response = match scored {
+        Ok((results, usage)) => {
+            recorder.finish(200, Some(Usage { prompt_tokens: usage.total_tokens, completion_tokens: 0 }), None);
+            let results = ranked(results, &request);
+            let id = format!("rerank-{}", meta.id.replace('-', ""));
+            Json(RerankResponse { id, model: model.clone(), results, usage }).into_response()
+        }
+        Err(e) => {
+            let response = e.into_response();
+            recorder.finish(response.status().as_u16(), None, None);
+            response
+        }
+    };
+    headers::stamp_metadata(response.headers_mut(), &meta, &model, replica.as_deref());
+    response
+}
+
+// Most relevant first, cut to `top_n`, each with its document unless the client
+// said not to.
+fn ranked(mut results: Vec<RerankResult>, request: &RerankRequest) -> Vec<RerankResult> {
+    results.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+    if let Some(top_n) = request.top_n {
+        results.truncate(top_n);
+    }
+    for result in &mut results {
+        result.document = request.return_documents
+            .then(|| request.documents.get(result.index))
+            .flatten()
+            .map(|document| DocumentText { text: document.text().to_string() });
+    }
+    results
+}
+
+async fn score(
+    state: &AppState,
+    headers: &HeaderMap,
+    backend: &Backend,
+    replica: &str,
+    request: &RerankRequest,
+) -> Result<(Vec<RerankResult>, RerankUsage), AppError> {
+    if mock::is_mock(replica) {
+        return Ok((mock_scores(request), RerankUsage::default()));
+    }
+    let (url, payload) = match backend.config.api {
+        BackendApi::Tei => {
+            let texts: Vec<&str> = request.documents.iter().map(Document::text).collect();
+            (format!("{}/rerank", backend.base_url(replica)), json!({ "query": request.query, "texts": texts }))
+        }
+        _ => (backend.endpoint_url(replica, "rerank", &request.model), json!(request)),
+    };
+    info!("Routing rerank for model '{}' to: {}", request.model, url);
+    let exchange = async {
+        let outbound = backend.authorize(state.header_policy.forward_request(headers, backend.client_for(replica).post(&url))).json(&payload);
+        let res = upstream::forward(backend, outbound, url.clone()).await?;
+        res.json::<Value>().await.map_err(AppError::BackendRequestFailed)
+    };
+    let response = upstream::within_total(backend, &url, exchange).await?;
+    let unexpected = |e: serde_json::Error| AppError::Internal(format!("Unexpected rerank response from {}: {}", url, e));
+    let (results, usage) = match backend.config.api {
+        BackendApi::Tei => {
+            let scores: Vec<TeiScore> = serde_json::from_value(response).map_err(unexpected)?;
+            let results = scores.into_iter()
+                .map(|score| RerankResult { index: score.index, relevance_score: score.score, document: None })
+                .collect();
+            (results, RerankUsage::default())
+        }
+        _ => {
+            let response: UpstreamRerank = serde_json::from_value(response).map_err(unexpected)?;
+            (response.results, response.usage)
+        }
+    };
+    if let Some(result) = results.iter().find(|result| result.index >= request.documents.len()) {
+        return Err(AppError::Internal(format!("The backend at {} scored unknown document {}.", url, result.index)));
+    }
+    Ok((results, usage))
+}
+
+// The share of the query's words that appear in each document.
+fn mock_scores(request: &RerankRequest) -> Vec<RerankResult> {
+    let words = |text: &str| -> HashSet<String> { text.split_whitespace().map(str::to_lowercase).collect() };
+    let query = words(&request.query);
+    request.documents.iter().enumerate()
+        .map(|(index, document)| {
+            let overlap = query.intersection(&words(document.text())).count();
+            RerankResult { index, relevance_score: overlap as f64 / query.len().max(1) as f64, document: None }
+        })
+        .collect()
+}
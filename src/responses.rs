@@ -0,0 +1,500 @@
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{sse::Event, IntoResponse, Response, Sse},
+    Extension, Json,
+};
+use chrono::Utc;
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, sync::Arc};
+use utoipa::ToSchema;
+
+use crate::{
+    auth::Caller,
+    headers, metadata,
+    request_log::Recorder,
+    stream::{EventStream, PayloadStream},
+    usage::StreamOptions,
+    AppError, AppState, ChatMessage, ChatRequest, MessageContent, RequestMeta,
+};
+
+// --- Responses API ---
+// POST /v1/responses, which newer OpenAI SDKs use by default, translated to a
+// chat completion on the way in and back into a response (or its streaming
+// events) on the way out, so backends only need to speak chat. Stateless: the
+// gateway stores no responses, so `previous_response_id` is rejected and
+// conversations are sent in full as `input`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ResponseRequest {
+    model: String,
+    input: Value, // a string, or a list of messages and function call items
+    #[serde(default)]
+    instructions: Option<String>,
+    #[serde(default)]
+    max_output_tokens: Option<u32>,
+    #[serde(default)]
+    temperature: Option<f32>,
+    #[serde(default)]
+    top_p: Option<f32>,
+    #[serde(default)]
+    tools: Option<Vec<Value>>,
+    #[serde(default)]
+    tool_choice: Option<Value>,
+    #[serde(default)]
+    text: Option<Value>,
+    #[serde(default)]
+    stream: bool,
+    #[serde(default)]
+    previous_response_id: Option<String>,
+    #[serde(default)]
+    metadata: Option<Value>,
+}
+
+#[utoipa::path(
+    post, path = "/v1/responses", tag = "Responses",
+    description = "OpenAI's Responses API, translated to a chat completion.",
+    request_body = ResponseRequest,
+    responses((status = 200, description = "The response object, or Responses events with `stream: true`.", body = Object)),
+)]
+pub async fn create(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    Json(request): Json<ResponseRequest>,
+) -> Response {
+    let mut meta = RequestMeta::new();
+    let model = request.model.clone();
+    let result = match metadata::collect(&headers, request.metadata.as_ref()) {
+        Ok(metadata) => {
+            meta.metadata = Arc::new(metadata);
+            respond(state.clone(), caller.clone(), headers, request, meta.clone()).await
+        }
+        Err(e) => Err(e),
+    };
+    let mut response = match result {
+        Ok(response) => response,
+        Err(e) => {
+            let mut response = e.into_response();
+            headers::stamp_metadata(response.headers_mut(), &meta, &model, None);
+            Recorder::new(&state, &meta, model, &caller).finish(response.status().as_u16(), None, None);
+            return response;
+        }
+    };
+    if !response.headers().contains_key("x-request-id") {
+        headers::stamp_metadata(response.headers_mut(), &meta, &model, None);
+    }
+    response
+}
+
+async fn respond(
+    state: Arc<AppState>,
+    caller: Caller,
+    headers: HeaderMap,
+    request: ResponseRequest,
+    meta: RequestMeta,
+) -> Result<Response, AppError> {
+    let body = to_chat(&request)?;
+    let (response_headers, payloads) = crate::start_chat(state, caller, headers, body, meta).await?;
+    let translator = Translator::new(&request);
+    if !request.stream {
+        let response = translator.run(payloads).await;
+        return Ok((response_headers, Json(response)).into_response());
+    }
+    Ok((response_headers, Sse::new(translator.events(payloads))).into_response())
+}
+
+fn unsupported(what: &str) -> AppError {
+    AppError::InvalidRequest(format!("{} is not supported by this gateway's Responses API.", what))
+}
+
+// --- Request Translation ---
+fn to_chat(request: &ResponseRequest) -> Result<ChatRequest, AppError> {
+    if request.previous_response_id.is_some() {
+        return Err(AppError::InvalidRequest(
+            "This gateway does not store responses; send the whole conversation as `input` instead of previous_response_id.".to_string(),
+        ));
+    }
+    if let Some(format) = request.text.as_ref().and_then(|text| text.pointer("/format/type")).and_then(Value::as_str) {
+        if format != "text" {
+            return Err(unsupported(&format!("text.format '{}'", format)));
+        }
+    }
+
+    let mut messages = Vec::new();
+    if let Some(instructions) = &request.instructions {
+        messages.push(message("system", MessageContent::Text(instructions.clone())));
+    }
+    match &request.input {
+        Value::String(text) => messages.push(message("user", MessageContent::Text(text.clone()))),
+        Value::Array(items) => {
+            for item in items {
+                push_item(&mut messages, item)?;
+            }
+        }
+        _ => return Err(AppError::InvalidRequest("`input` must be a string or a list of items.".to_string())),
+    }
+
+    let tools = request.tools.as_ref().map(|tools| {
+        tools.iter().map(|tool| match tool["type"].as_str() {
+            Some("function") => Ok(json!({
+                "type": "function",
+                "function": { "name": tool["name"], "description": tool["description"], "parameters": tool["parameters"] },
+            })),
+            other => Err(unsupported(&format!("Tool type '{}'", other.unwrap_or("(none)")))),
+        }).collect::<Result<Vec<_>, _>>()
+    }).transpose()?;
+    let tool_choice = request.tool_choice.as_ref().map(|choice| match choice {
+        Value::Object(forced) => json!({ "type": "function", "function": { "name": forced.get("name") } }),
+        other => other.clone(),
+    });
+
+    Ok(ChatRequest {
+        model: request.model.clone(),
+        messages,
+        max_tokens: request.max_output_tokens,
+        temperature: request.temperature,
+        top_p: request.top_p,
+        presence_penalty: None,
+        frequency_penalty: None,
+        stop: None,
+        tools: tools.map(Value::Array),
+        tool_choice,
+        stream: Some(true),
+        stream_options: Some(StreamOptions { include_usage: true }),
+        logprobs: None,
+        continue_final_message: None,
+        add_generation_prompt: None,
+        metadata: None, // from `meta`
+    })
+}
+
+fn message(role: &str, content: MessageContent) -> ChatMessage {
+    ChatMessage { role: role.to_string(), content, name: None, tool_calls: None, tool_call_id: None }
+}
+
+fn push_item(messages: &mut Vec<ChatMessage>, item: &Value) -> Result<(), AppError> {
+    match item["type"].as_str() {
+        None | Some("message") => {
+            let role = match item["role"].as_str() {
+                Some("developer") => "system",
+                Some(role) => role,
+                None => return Err(AppError::InvalidRequest("Input messages need a `role`.".to_string())),
+            };
+            let content = match &item["content"] {
+                Value::String(text) => MessageContent::Text(text.clone()),
+                Value::Array(parts) => MessageContent::Parts(parts.iter().map(content_part).collect::<Result<_, _>>()?),
+                _ => MessageContent::Empty,
+            };
+            messages.push(message(role, content));
+        }
+        // Consecutive calls become one assistant message with several tool calls.
+        Some("function_call") => {
+            let call = json!({
+                "id": item["call_id"],
+                "type": "function",
+                "function": { "name": item["name"], "arguments": item["arguments"] },
+            });
+            match messages.last_mut().filter(|m| m.role == "assistant" && m.tool_calls.is_some()) {
+                Some(last) => {
+                    if let Some(Value::Array(calls)) = &mut last.tool_calls {
+                        calls.push(call);
+                    }
+                }
+                None => messages.push(ChatMessage { tool_calls: Some(json!([call])), ..message("assistant", MessageContent::Empty) }),
+            }
+        }
+        Some("function_call_output") => {
+            let output = match &item["output"] {
+                Value::String(text) => text.clone(),
+                other => other.to_string(),
+            };
+            messages.push(ChatMessage {
+                tool_call_id: item["call_id"].as_str().map(str::to_string),
+                ..message("tool", MessageContent::Text(output))
+            });
+        }
+        Some(other) => return Err(unsupported(&format!("Input item type '{}'", other))),
+    }
+    Ok(())
+}
+
+fn content_part(part: &Value) -> Result<Value, AppError> {
+    match part["type"].as_str() {
+        Some("input_text" | "output_text") => Ok(json!({ "type": "text", "text": part["text"] })),
+        Some("input_image") => {
+            let url = part["image_url"].as_str().or_else(|| part.pointer("/image_url/url").and_then(Value::as_str));
+            let url = url.ok_or_else(|| unsupported("An input_image without image_url"))?;
+            let mut image = json!({ "url": url });
+            if let Some(detail) = part.get("detail") {
+                image["detail"] = detail.clone();
+            }
+            Ok(json!({ "type": "image_url", "image_url": image }))
+        }
+        other => Err(unsupported(&format!("Content part type '{}'", other.unwrap_or("(none)")))),
+    }
+}
+
+// --- Response Translation ---
+// Builds the response from chat chunks, and the streaming events along the
+// way: each output item (the message text, each function call) is opened,
+// filled by deltas, and closed, then `response.completed` carries the whole
+// response. Non-streaming requests run the same translation and keep only the end.
+struct Translator {
+    response: Value,
+    sequence: u64,
+    output: Vec<Value>, // closed items
+    text: Option<(usize, String)>, // output index and text of the open message item
+    calls: BTreeMap<u64, usize>, // chat tool call index -> output index, while open
+    open: BTreeMap<usize, Value>, // output index -> open function call item
+    next_index: usize,
+    finish_reason: Option<String>,
+}
+
+impl Translator {
+    fn new(request: &ResponseRequest) -> Self {
+        let response = json!({
+            "id": format!("resp_{}", uuid::Uuid::new_v4().simple()),
+            "object": "response",
+            "created_at": Utc::now().timestamp(),
+            "status": "in_progress",
+            "model": request.model,
+            "output": [],
+            "instructions": request.instructions,
+            "max_output_tokens": request.max_output_tokens,
+            "temperature": request.temperature,
+            "top_p": request.top_p,
+            "tools": request.tools.clone().unwrap_or_default(),
+            "tool_choice": request.tool_choice.clone().unwrap_or_else(|| json!("auto")),
+            "parallel_tool_calls": true,
+            "previous_response_id": null,
+            "metadata": request.metadata.clone().unwrap_or_else(|| json!({})),
+            "store": false,
+            "error": null,
+            "incomplete_details": null,
+            "usage": null,
+        });
+        Translator {
+            response,
+            sequence: 0,
+            output: Vec::new(),
+            text: None,
+            calls: BTreeMap::new(),
+            open: BTreeMap::new(),
+            next_index: 0,
+            finish_reason: None,
+        }
+    }
+
+    fn event(&mut self, kind: &str, mut data: Value) -> Event {
+        data["type"] = json!(kind);
+        data["sequence_number"] = json!(self.sequence);
+        self.sequence += 1;
+        Event::default().event(kind).data(data.to_string())
+    }
+
+    fn item_id(&self, prefix: &str, index: usize) -> String {
+        let id = self.response["id"].as_str().unwrap_or_default().trim_start_matches("resp_");
+        format!("{}_{}_{}", prefix, id, index)
+    }
+
+    fn started(&mut self) -> Vec<Event> {
+        let response = self.response.clone();
+        vec![
+            self.event("response.created", json!({ "response": response })),
+            self.event("response.in_progress", json!({ "response": response })),
+        ]
+    }
+
+    fn on_chunk(&mut self, chunk: &Value) -> Vec<Event> {
+        let mut events = Vec::new();
+        if let Some(model) = chunk.get("model") {
+            self.response["model"] = model.clone();
+        }
+        if let Some(usage) = chunk.get("usage").filter(|u| u.is_object()) {
+            let input = usage["prompt_tokens"].as_u64().unwrap_or(0);
+            let output = usage["completion_tokens"].as_u64().unwrap_or(0);
+            self.response["usage"] = json!({ "input_tokens": input, "output_tokens": output, "total_tokens": input + output });
+        }
+        // The gateway never asks for n > 1; only the first choice is translated.
+        let Some(choice) = chunk.pointer("/choices/0") else { return events };
+        if let Some(reason) = choice.get("finish_reason").and_then(Value::as_str) {
+            self.finish_reason = Some(reason.to_string());
+        }
+        if let Some(text) = choice.pointer("/delta/content").and_then(Value::as_str).filter(|t| !t.is_empty()) {
+            events.extend(self.text_delta(text));
+        }
+        for call in choice.pointer("/delta/tool_calls").and_then(Value::as_array).into_iter().flatten() {
+            events.extend(self.close_text());
+            events.extend(self.call_delta(call));
+        }
+        events
+    }
+
+    fn text_delta(&mut self, delta: &str) -> Vec<Event> {
+        let mut events = Vec::new();
+        if self.text.is_none() {
+            let index = self.next_index;
+            self.next_index += 1;
+            self.text = Some((index, String::new()));
+            let item_id = self.item_id("msg", index);
+            let item = json!({ "type": "message", "id": item_id, "status": "in_progress", "role": "assistant", "content": [] });
+            events.push(self.event("response.output_item.added", json!({ "output_index": index, "item": item })));
+            let part = json!({ "type": "output_text", "text": "", "annotations": [] });
+            events.push(self.event("response.content_part.added", json!({
+                "item_id": item_id, "output_index": index, "content_index": 0, "part": part,
+            })));
+        }
+        let (index, text) = self.text.as_mut().unwrap();
+        text.push_str(delta);
+        let index = *index;
+        let item_id = self.item_id("msg", index);
+        events.push(self.event("response.output_text.delta", json!({
+            "item_id": item_id, "output_index": index, "content_index": 0, "delta": delta,
+        })));
+        events
+    }
+
+    fn close_text(&mut self) -> Vec<Event> {
+        let Some((index, text)) = self.text.take() else { return Vec::new() };
+        let item_id = self.item_id("msg", index);
+        let part = json!({ "type": "output_text", "text": text, "annotations": [] });
+        let item = json!({ "type": "message", "id": item_id, "status": "completed", "role": "assistant", "content": [part] });
+        self.output.push(item.clone());
+        vec![
+            self.event("response.output_text.done", json!({
+                "item_id": item_id, "output_index": index, "content_index": 0, "text": text,
+            })),
+            self.event("response.content_part.done", json!({
+                "item_id": item_id, "output_index": index, "content_index": 0, "part": part,
+            })),
+            self.event("response.output_item.done", json!({ "output_index": index, "item": item })),
+        ]
+    }
+
+    fn call_delta(&mut self, delta: &Value) -> Vec<Event> {
+        let mut events = Vec::new();
+        let tool_index = delta.get("index").and_then(Value::as_u64).unwrap_or(0);
+        let index = match self.calls.get(&tool_index) {
+            Some(index) => *index,
+            None => {
+                let index = self.next_index;
+                self.next_index += 1;
+                self.calls.insert(tool_index, index);
+                let item = json!({
+                    "type": "function_call",
+                    "id": self.item_id("fc", index),
+                    "call_id": delta.get("id").cloned().unwrap_or_else(|| json!(self.item_id("call", index))),
+                    "name": delta.pointer("/function/name").cloned().unwrap_or_else(|| json!("")),
+                    "arguments": "",
+                    "status": "in_progress",
+                });
+                self.open.insert(index, item.clone());
+                events.push(self.event("response.output_item.added", json!({ "output_index": index, "item": item })));
+                index
+            }
+        };
+        if let Some(arguments) = delta.pointer("/function/arguments").and_then(Value::as_str).filter(|a| !a.is_empty()) {
+            let item = self.open.get_mut(&index).unwrap();
+            let joined = item["arguments"].as_str().unwrap_or_default().to_string() + arguments;
+            item["arguments"] = json!(joined);
+            let item_id = item["id"].clone();
+            events.push(self.event("response.function_call_arguments.delta", json!({
+                "item_id": item_id, "output_index": index, "delta": arguments,
+            })));
+        }
+        events
+    }
+
+    fn close_calls(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+        self.calls.clear();
+        for (index, mut item) in std::mem::take(&mut self.open) {
+            item["status"] = json!("completed");
+            events.push(self.event("response.function_call_arguments.done", json!({
+                "item_id": item["id"], "output_index": index, "arguments": item["arguments"],
+            })));
+            events.push(self.event("response.output_item.done", json!({ "output_index": index, "item": item })));
+            self.output.push(item);
+        }
+        events
+    }
+
+    fn finish(&mut self) -> Vec<Event> {
+        let mut events = self.close_text();
+        events.extend(self.close_calls());
+        self.output.sort_by_key(|item| item["id"].as_str().and_then(|id| id.rsplit('_').next()?.parse::<usize>().ok()));
+        self.response["output"] = Value::Array(self.output.clone());
+        let incomplete = match self.finish_reason.as_deref() {
+            Some("length") => Some("max_output_tokens"),
+            Some("content_filter") => Some("content_filter"),
+            _ => None,
+        };
+        self.response["status"] = json!(if incomplete.is_some() { "incomplete" } else { "completed" });
+        if let Some(reason) = incomplete {
+            self.response["incomplete_details"] = json!({ "reason": reason });
+        }
+        let kind = if incomplete.is_some() { "response.incomplete" } else { "response.completed" };
+        let response = self.response.clone();
+        events.push(self.event(kind, json!({ "response": response })));
+        events
+    }
+
+    // A gateway error in the chat stream (its `[Gateway Error: ...]` text).
+    fn fail(&mut self, message: String) -> Vec<Event> {
+        self.response["status"] = json!("failed");
+        self.response["output"] = Value::Array(self.output.clone());
+        self.response["error"] = json!({ "code": "server_error", "message": message });
+        let response = self.response.clone();
+        vec![self.event("response.failed", json!({ "response": response }))]
+    }
+
+    // Returns false once the response has ended.
+    fn on_payload(&mut self, payload: &str, events: &mut Vec<Event>) -> bool {
+        if payload == "[DONE]" {
+            events.extend(self.finish());
+            return false;
+        }
+        match serde_json::from_str::<Value>(payload) {
+            Ok(chunk) => {
+                events.extend(self.on_chunk(&chunk));
+                true
+            }
+            Err(_) => {
+                events.extend(self.fail(payload.to_string()));
+                false
+            }
+        }
+    }
+
+    async fn run(mut self, mut payloads: PayloadStream) -> Value {
+        let mut events = Vec::new();
+        while let Some(payload) = payloads.next().await {
+            if !self.on_payload(&payload, &mut events) {
+                return self.response;
+            }
+        }
+        self.finish();
+        self.response
+    }
+
+    fn events(mut self, payloads: PayloadStream) -> EventStream {
+        let started = self.started();
+        let translated = futures::stream::unfold((self, payloads, false), |(mut translator, mut payloads, done)| async move {
+            if done {
+                return None;
+            }
+            let mut events = Vec::new();
+            let more = match payloads.next().await {
+                Some(payload) => translator.on_payload(&payload, &mut events),
+                None => {
+                    events.extend(translator.finish());
+                    false
+                }
+            };
+            Some((futures::stream::iter(events.into_iter().map(Ok)), (translator, payloads, !more)))
+        }).flatten();
+        Box::pin(futures::stream::iter(started.into_iter().map(Ok)).chain(translated))
+    }
+}
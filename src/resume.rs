@@ -0,0 +1,123 @@
+use reqwest::header::HeaderMap;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::{backend::Backend, stream::UpstreamBody, upstream, AppError, AppState, ChatMessage, ChatRequest};
+
+// --- Mid-Stream Resume ---
+// When a backend connection drops before the generation finished, the request is
+// re-issued on the next replica with the text streamed so far as a partial
+// assistant message (vLLM's `continue_final_message`), and the new stream is
+// spliced onto the old one. Only tracks choice 0; the gateway never asks for n > 1.
+pub struct Resume {
+    state: Arc<AppState>,
+    backend: Arc<Backend>,
+    headers: HeaderMap,
+    request: ChatRequest,
+    replica: String, // the replica currently streaming
+    attempts_left: u32,
+    generated: String,
+    chunk_id: Option<String>,
+    finished: bool,
+    resumed: bool,
+}
+
+impl Resume {
+    pub fn new(state: Arc<AppState>, backend: Arc<Backend>, headers: HeaderMap, request: ChatRequest, replica: String, attempts: u32) -> Self {
+        Resume {
+            state,
+            backend,
+            headers,
+            request,
+            replica,
+            attempts_left: attempts,
+            generated: String::new(),
+            chunk_id: None,
+            finished: false,
+            resumed: false,
+        }
+    }
+
+    // Sees every upstream chunk before the filters, so `generated` is exactly
+    // what the model produced. Continuation chunks are made to look like part of
+    // the original stream: same id, and no second `role` delta.
+    pub fn observe(&mut self, chunk: &mut Value) {
+        if self.resumed {
+            if let Some(id) = &self.chunk_id {
+                chunk["id"] = Value::String(id.clone());
+            }
+        } else if self.chunk_id.is_none() {
+            self.chunk_id = chunk.get("id").and_then(Value::as_str).map(String::from);
+        }
+
+        let Some(choices) = chunk.get_mut("choices").and_then(Value::as_array_mut) else { return };
+        for choice in choices {
+            if choice.get("index").and_then(Value::as_u64).unwrap_or(0) != 0 {
+                continue;
+            }
+            if choice.get("finish_reason").is_some_and(|r| !r.is_null()) {
+                self.finished = true;
+            }
+            if let Some(delta) = choice.get_mut("delta").and_then(Value::as_object_mut) {
+                if self.resumed {
+                    delta.remove("role");
+                }
+                if let Some(content) = delta.get("content").and_then(Value::as_str) {
+                    self.generated.push_str(content);
+                }
+            }
+        }
+    }
+
+    pub fn replica(&self) -> &str {
+        &self.replica
+    }
+
+    pub fn can_resume(&self) -> bool {
+        !self.finished && self.attempts_left > 0
+    }
+
+    pub async fn reconnect(&mut self, reason: &str) -> Option<UpstreamBody> {
+        let backend = self.backend.clone();
+
+        let mut body = self.request.clone();
+        if !self.generated.is_empty() {
+            body.messages.push(ChatMessage {
+                role: "assistant".to_string(),
+                content: self.generated.clone().into(),
+                name: None,
+                tool_calls: None,
+                tool_call_id: None,
+            });
+            body.continue_final_message = Some(true);
+            body.add_generation_prompt = Some(false);
+            if let Some(max_tokens) = body.max_tokens {
+                let used = backend.tokenizer.count_text(&self.generated) as u32;
+                body.max_tokens = Some(max_tokens.saturating_sub(used).max(1));
+            }
+        }
+
+        while self.attempts_left > 0 {
+            self.attempts_left -= 1;
+            self.replica = backend.replica_after(&self.replica)?;
+            warn!(
+                "Stream for model '{}' dropped ({}); resuming on {} after {} chars",
+                self.request.model, reason, self.replica, self.generated.len()
+            );
+            let build = |request| backend.authorize(self.state.header_policy.forward_request(&self.headers, request));
+            match upstream::connect(&backend, &self.replica, backend.config.chaos.as_ref(), &body, &build).await {
+                Ok(upstream) => {
+                    self.resumed = true;
+                    return Some(upstream.body);
+                }
+                Err(AppError::BackendRequestFailed(e)) => warn!("Resume attempt failed: {}", e),
+                Err(AppError::BackendRespondedError { status, text, .. }) => {
+                    warn!("Resume attempt failed: {} {}", status, text)
+                }
+                Err(_) => warn!("Resume attempt failed"),
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,342 @@
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+use tracing::info;
+
+use crate::{
+    auth::{glob_match, Caller},
+    backend::Backend,
+    models::Capability,
+    tenants,
+    tokenizer::Tokenizer,
+    AppError, AppState, ChatRequest,
+};
+
+pub const MAX_LATENCY_HEADER: &str = "x-gateway-max-latency-ms";
+pub const ROUTE_HEADER: &str = "x-gateway-route";
+pub const BACKEND_HEADER: &str = "x-gateway-backend";
+// Recent successful requests kept per replica.
+const LATENCY_SAMPLES: usize = 200;
+
+// --- Routing Overrides ---
+// For debugging: `X-Gateway-Route` sends the request to the named model as-is,
+// skipping routing rules, `auto`, and capability fallbacks. `X-Gateway-Backend`
+// pins it to one replica, by URL (as reported in the response's
+// `x-gateway-backend`) or by index, with no balancing, hedging, or resume.
+// Only keys with `routing_overrides` may send them.
+#[derive(Debug)]
+pub struct RoutingOverride {
+    pub route: Option<String>,
+    pub backend: Option<String>,
+}
+
+impl RoutingOverride {
+    pub fn from_headers(caller: &Caller, headers: &HeaderMap) -> Result<Self, AppError> {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.trim().to_string());
+        let pinned = RoutingOverride { route: header(ROUTE_HEADER), backend: header(BACKEND_HEADER) };
+        if pinned.is_set() && caller.key().is_some_and(|key| !key.routing_overrides) {
+            return Err(AppError::Forbidden(
+                "This API key may not use the X-Gateway-Route or X-Gateway-Backend headers.".to_string(),
+            ));
+        }
+        Ok(pinned)
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.route.is_some() || self.backend.is_some()
+    }
+
+    pub fn replica(&self, backend: &Backend, model: &str) -> Result<Option<String>, AppError> {
+        let Some(wanted) = &self.backend else { return Ok(None) };
+        let replicas = backend.replicas.get();
+        let url = wanted.trim_end_matches('/');
+        let found = replicas.iter().find(|replica| *replica == url)
+            .or_else(|| wanted.parse::<usize>().ok().and_then(|i| replicas.get(i)));
+        match found {
+            Some(replica) => {
+                info!("Request for model '{}' pinned to {}", model, replica);
+                Ok(Some(replica.clone()))
+            }
+            None => Err(AppError::InvalidRequest(format!(
+                "Model '{}' has no backend '{}'. Use one of its replica URLs or an index below {}.",
+                model, wanted, replicas.len()
+            ))),
+        }
+    }
+}
+
+// --- Routing Rules ---
+// Loaded from ROUTING_RULES and evaluated in order before anything else looks at
+// the model; the first rule whose conditions all hold rewrites the model name.
+// Prompt tokens are counted with the requested model's tokenizer (cl100k_base for
+// names that aren't a backend, such as `auto`).
+#[derive(Debug, Deserialize)]
+pub struct RoutingRule {
+    #[serde(default)]
+    pub model: Option<String>, // glob on the requested model; any model when omitted
+    #[serde(default)]
+    pub min_prompt_tokens: Option<usize>,
+    #[serde(default)]
+    pub max_prompt_tokens: Option<usize>,
+    #[serde(default)]
+    pub max_messages: Option<usize>,
+    pub route_to: String,
+}
+
+pub fn apply_routing_rules(state: &AppState, rules: &[RoutingRule], body: &mut ChatRequest) {
+    let mut prompt_tokens = None;
+    for rule in rules {
+        if rule.model.as_deref().is_some_and(|pattern| !glob_match(pattern, &body.model)) {
+            continue;
+        }
+        if rule.max_messages.is_some_and(|max| body.messages.len() > max) {
+            continue;
+        }
+        if rule.min_prompt_tokens.is_some() || rule.max_prompt_tokens.is_some() {
+            let tokens = *prompt_tokens.get_or_insert_with(|| match state.vllm_backends.get(&body.model) {
+                Some(backend) => backend.tokenizer.count_messages(&body.messages),
+                None => Tokenizer::load(None).map_or(0, |t| t.count_messages(&body.messages)),
+            });
+            if rule.min_prompt_tokens.is_some_and(|min| tokens < min) || rule.max_prompt_tokens.is_some_and(|max| tokens > max) {
+                continue;
+            }
+        }
+        if rule.route_to != body.model {
+            info!("Routing rule sends '{}' request to '{}'", body.model, rule.route_to);
+            body.model = rule.route_to.clone();
+        }
+        return;
+    }
+}
+
+// --- Capability Routing ---
+// With CAPABILITY_ROUTING=true, requests that use tools or image inputs only go to
+// models whose `capabilities` cover them. If the requested model falls short, its
+// `capability_fallbacks` are tried in order; if none fits, the client gets a 400.
+pub fn required_capabilities(body: &ChatRequest) -> Vec<Capability> {
+    let mut required = Vec::new();
+    let tools = body.tools.as_ref().is_some_and(|t| t.as_array().is_none_or(|a| !a.is_empty()))
+        || body.messages.iter().any(|m| m.role == "tool" || m.tool_calls.is_some());
+    if tools {
+        required.push(Capability::Tools);
+    }
+    if body.messages.iter().any(|m| m.content.has_image()) {
+        required.push(Capability::Vision);
+    }
+    required
+}
+
+fn missing(backend: &Backend, required: &[Capability]) -> Vec<Capability> {
+    required.iter().copied().filter(|c| !backend.config.capabilities.contains(c)).collect()
+}
+
+// Returns the backend to use, rewriting `body.model` when falling back.
+pub fn route_by_capability(
+    state: &AppState,
+    caller: &Caller,
+    body: &mut ChatRequest,
+    backend: Arc<Backend>,
+) -> Result<Arc<Backend>, AppError> {
+    let required = required_capabilities(body);
+    let lacking = missing(&backend, &required);
+    if lacking.is_empty() {
+        return Ok(backend);
+    }
+
+    for fallback in &backend.config.capability_fallbacks {
+        let Some(candidate) = tenants::backend_for(state, caller, fallback) else { continue };
+        if caller.authorize_model(fallback).is_ok() && missing(&candidate, &required).is_empty() {
+            info!("Model '{}' lacks {:?}; routing to '{}'", body.model, lacking, fallback);
+            body.model = fallback.clone();
+            return Ok(candidate);
+        }
+    }
+    Err(AppError::UnsupportedCapability { model: body.model.clone(), missing: lacking })
+}
+
+// --- Latency Tracking ---
+// Time to first token and total latency of recent successful streams, per
+// replica URL.
+#[derive(Default)]
+pub struct LatencyTracker {
+    samples: Mutex<HashMap<String, VecDeque<Sample>>>,
+}
+
+#[derive(Clone, Copy)]
+struct Sample {
+    ttft_ms: u64,
+    total_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LatencyStats {
+    pub samples: usize,
+    pub p95_ttft_ms: u64,
+    pub p95_latency_ms: u64,
+}
+
+impl LatencyTracker {
+    pub fn observe(&self, replica: &str, ttft_ms: u64, total_ms: u64) {
+        let mut samples = self.samples.lock().unwrap();
+        let window = samples.entry(replica.to_string()).or_default();
+        if window.len() >= LATENCY_SAMPLES {
+            window.pop_front();
+        }
+        window.push_back(Sample { ttft_ms, total_ms });
+    }
+
+    // p95 TTFT over the recent samples of all the given replicas; None without data.
+    pub fn p95_ttft(&self, replicas: &[String]) -> Option<u64> {
+        let samples = self.samples.lock().unwrap();
+        p95(replicas.iter().filter_map(|r| samples.get(r)).flatten().map(|s| s.ttft_ms).collect())
+    }
+
+    // Only replicas with at least `min_samples` samples are included.
+    fn p95_ttft_each(&self, replicas: &[String], min_samples: usize) -> Vec<Option<u64>> {
+        let samples = self.samples.lock().unwrap();
+        replicas.iter()
+            .map(|r| samples.get(r)
+                .filter(|w| w.len() >= min_samples)
+                .and_then(|w| p95(w.iter().map(|s| s.ttft_ms).collect())))
+            .collect()
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, LatencyStats> {
+        let samples = self.samples.lock().unwrap();
+        samples.iter()
+            .map(|(replica, window)| (replica.clone(), LatencyStats {
+                samples: window.len(),
+                p95_ttft_ms: p95(window.iter().map(|s| s.ttft_ms).collect()).unwrap_or(0),
+                p95_latency_ms: p95(window.iter().map(|s| s.total_ms).collect()).unwrap_or(0),
+            }))
+            .collect()
+    }
+}
+
+fn p95(mut values: Vec<u64>) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let rank = (0.95 * values.len() as f64).ceil() as usize;
+    Some(values[rank.clamp(1, values.len()) - 1])
+}
+
+// --- Replica Balancing ---
+// `round_robin` (the default) spreads requests evenly. `latency` sends each
+// request to the replica with the lowest recent p95 time to first token, except
+// that every `explore_every`-th request still goes round-robin, so a slow replica
+// is re-measured and wins traffic back once it recovers. Replicas with too few
+// samples are only reached by exploring, and until two replicas have enough to
+// compare, everything stays round-robin.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Balance {
+    #[default]
+    RoundRobin,
+    Latency,
+}
+
+const MIN_LATENCY_SAMPLES: usize = 5;
+
+// Returns an index into `replicas`.
+pub fn pick_fastest(backend: &Backend, replicas: &[String], latency: &LatencyTracker, request_number: usize) -> Option<usize> {
+    if replicas.len() < 2 || request_number.is_multiple_of(backend.config.explore_every.max(1) as usize) {
+        return None;
+    }
+    let measured: Vec<(u64, usize)> = latency.p95_ttft_each(replicas, MIN_LATENCY_SAMPLES)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, p95)| Some((p95?, i)))
+        .collect();
+    // One measured replica has nothing to be compared with yet.
+    if measured.len() < 2 {
+        return None;
+    }
+    measured.into_iter().min().map(|(_, i)| i)
+}
+
+// --- Cost-Optimized Routing ---
+// Loaded from AUTO_ROUTER. Requests for the virtual model `name` go to the
+// cheapest candidate that the key may use and that fits the request: its
+// capabilities, context window, output limit, and, with an
+// `X-Gateway-Max-Latency-Ms` header, its recent p95 time to first token.
+#[derive(Debug, Deserialize)]
+pub struct AutoRouterConfig {
+    #[serde(default = "default_auto_name")]
+    pub name: String,
+    // Candidates; by default every model with `pricing`.
+    #[serde(default)]
+    pub models: Option<Vec<String>>,
+    // Used to estimate cost when the request has no max_tokens.
+    #[serde(default = "default_expected_completion")]
+    pub expected_completion_tokens: u32,
+}
+
+fn default_auto_name() -> String {
+    "auto".to_string()
+}
+
+fn default_expected_completion() -> u32 {
+    256
+}
+
+pub fn route_auto(state: &AppState, config: &AutoRouterConfig, caller: &Caller, headers: &HeaderMap, body: &mut ChatRequest) -> Result<(), AppError> {
+    let max_latency: Option<u64> = match headers.get(MAX_LATENCY_HEADER) {
+        Some(value) => Some(value.to_str().ok().and_then(|v| v.trim().parse().ok()).ok_or_else(|| {
+            AppError::InvalidRequest(format!("{} must be a whole number of milliseconds.", MAX_LATENCY_HEADER))
+        })?),
+        None => None,
+    };
+    let required = required_capabilities(body);
+    let completion_tokens = body.max_tokens;
+
+    let backends = tenants::visible_backends(state, caller);
+    let mut best: Option<(f64, &String)> = None;
+    for (model, backend) in backends.iter() {
+        let candidate = match &config.models {
+            Some(models) => models.contains(model),
+            None => backend.config.pricing.is_some(),
+        };
+        if !candidate || caller.authorize_model(model).is_err() || !missing(backend, &required).is_empty() {
+            continue;
+        }
+        if let (Some(requested), Some(max)) = (completion_tokens, backend.config.max_output_tokens) {
+            if requested > max {
+                continue;
+            }
+        }
+        let prompt_tokens = backend.tokenizer.count_messages(&body.messages) as u64;
+        if let Some(context_length) = backend.config.context_length {
+            if prompt_tokens + completion_tokens.unwrap_or(0) as u64 > context_length as u64 {
+                continue;
+            }
+        }
+        if let Some(max_latency) = max_latency {
+            if state.latency.p95_ttft(&backend.replicas.get()).is_some_and(|p95| p95 > max_latency) {
+                continue;
+            }
+        }
+
+        let cost = backend.config.pricing.map_or(0.0, |pricing| pricing.cost(&crate::usage::Usage {
+            prompt_tokens,
+            completion_tokens: completion_tokens.unwrap_or(config.expected_completion_tokens) as u64,
+        }));
+        // Ties go to the alphabetically first model, so the choice is stable.
+        if best.is_none_or(|(best_cost, best_model)| cost < best_cost || (cost == best_cost && model < best_model)) {
+            best = Some((cost, model));
+        }
+    }
+
+    let Some((cost, model)) = best else {
+        return Err(AppError::InvalidRequest(format!(
+            "No model available to '{}' can serve this request (capabilities, context length, or latency).", config.name
+        )));
+    };
+    info!("Routing '{}' request to '{}' (estimated cost ${:.6})", config.name, model, cost);
+    body.model = model.clone();
+    Ok(())
+}
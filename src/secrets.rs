@@ -0,0 +1,272 @@
+use anyhow::{Context, Result};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock, RwLock,
+    },
+    time::Duration,
+};
+use tracing::{info, warn};
+
+use crate::{config, credentials::CredentialStore};
+
+const DEFAULT_REFRESH_SECS: u64 = 300;
+const VAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// --- Secret Files ---
+// Any of the gateway's settings can be given as NAME_FILE instead, naming a file
+// that holds the value (Docker and Kubernetes secrets are mounted this way). A
+// trailing newline is dropped. Setting both is an error. Other variables ending
+// in _FILE belong to other programs and are left alone; an `env:NAME` reference
+// falls back to NAME_FILE when NAME isn't set.
+const SETTINGS: &[&str] = &[
+    "ADMIN_API_KEY", "ADMIN_KEYS", "ALERTS", "ARCHIVE_SINK", "ASYNC_JOB_LIMIT", "ASYNC_JOB_TTL_SECS", "AUDIO_BACKENDS",
+    "AUDIO_MAX_UPLOAD_BYTES", "AUDIT_LOG", "AUTO_ROUTER", "AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY", "AWS_SESSION_TOKEN",
+    "BACKEND_REGISTRY", "BATCH_API", "CAPABILITY_ROUTING", "CAPTURE_CONTENT", "CAPTURE_DIR", "CASCADES",
+    "CORS_ALLOWED_HEADERS", "CORS_ALLOWED_METHODS", "CORS_ALLOWED_ORIGINS", "CORS_ALLOW_CREDENTIALS", "CORS_EXPOSE_HEADERS",
+    "CORS_MAX_AGE_SECS", "CREDENTIAL_STORE", "DEFAULT_SYSTEM_PROMPT", "EVENT_SINK", "FLEET_CHANNEL", "FLEET_REDIS_URL",
+    "FORWARD_REQUEST_HEADERS", "FORWARD_RESPONSE_HEADERS", "GATEWAY_API_KEYS", "GATEWAY_CONFIG", "GATEWAY_LISTEN_ADDR",
+    "GATEWAY_SOCKET_MODE", "GRPC_LISTEN_ADDR", "GUARDRAILS", "HEALTH_CHECK_INTERVAL_SECS", "IDEMPOTENCY_MAX_ENTRIES",
+    "IDEMPOTENCY_TTL_SECS", "IMAGE_BACKENDS", "IP_ACCESS", "KUBERNETES_API_URL", "KUBERNETES_TOKEN", "LOG_SAMPLING",
+    "MAX_CONCURRENT_STREAMS", "METADATA_METRIC_LABELS", "MODERATIONS", "OUTPUT_FILTER", "OVERLOAD_RETRY_AFTER_SECS",
+    "PII_REDACTION", "REALTIME_API_KEY", "REALTIME_BACKENDS", "REQUEST_LOG_CAPACITY", "RERANK_BACKENDS",
+    "RESPONSE_COMPRESSION", "ROUTING_RULES", "SECRET_REFRESH_SECS", "STICKY_SESSIONS", "STORE_MAX_CONNECTIONS", "STORE_URL",
+    "STREAM_RESUME_SECS", "TENANTS", "TLS_CERT_PATH", "TLS_KEY_PATH", "TLS_RELOAD_INTERVAL_SECS", "TRACE_CAPACITY",
+    "UPSTREAM_POOL", "UPSTREAM_PROXY", "UPSTREAM_TIMEOUTS", "UPSTREAM_TLS", "VAULT", "VAULT_TOKEN", "VERIFY_BACKENDS",
+    "VLLM_BACKENDS", "WASM_PLUGINS",
+];
+
+pub fn load_files() -> Result<()> {
+    for name in SETTINGS {
+        if let Some(value) = from_file(name)? {
+            std::env::set_var(name, value);
+        }
+    }
+    Ok(())
+}
+
+// NAME_FILE's contents, if it is set.
+fn from_file(name: &str) -> Result<Option<String>> {
+    let Ok(path) = std::env::var(format!("{}_FILE", name)) else { return Ok(None) };
+    if std::env::var_os(name).is_some() {
+        anyhow::bail!("Both {} and {}_FILE are set; use one", name, name);
+    }
+    let value = std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}_FILE ({})", name, path))?;
+    Ok(Some(value.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+// --- Credential References ---
+// Upstream credentials (a backend's `api_key`, REALTIME_API_KEY) are either the
+// value itself or a reference:
+//   env:NAME                  another setting (so NAME_FILE works too)
+//   file:/path                a file, re-read on every refresh
+//   vault:<path>#<field>      a HashiCorp Vault secret, e.g. vault:secret/data/openai#api_key
+//   store:<name>              a credential in CREDENTIAL_STORE, updated when it's PUT
+// File and Vault references are fetched again every SECRET_REFRESH_SECS (300 by
+// default), so rotated credentials are picked up without a restart. Vault needs
+// VAULT (`{"address": ..., "namespace": ...}`) and VAULT_TOKEN, which is re-read
+// from VAULT_TOKEN_FILE on each refresh when that is set.
+#[derive(Clone)]
+pub struct Secret(Arc<RwLock<String>>);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Secret(Arc::new(RwLock::new(value)))
+    }
+
+    // Empty until a Vault secret's first fetch.
+    pub fn get(&self) -> String {
+        self.0.read().unwrap().clone()
+    }
+
+    // True if the value changed.
+    pub fn set(&self, value: String) -> bool {
+        let mut current = self.0.write().unwrap();
+        let changed = *current != value;
+        *current = value;
+        changed
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VaultConfig {
+    pub address: String,
+    #[serde(default)]
+    pub namespace: Option<String>, // Vault Enterprise namespace
+}
+
+#[derive(Debug, Clone)]
+enum Source {
+    File(PathBuf),
+    Vault { path: String, field: String },
+}
+
+impl Source {
+    fn describe(&self) -> String {
+        match self {
+            Source::File(path) => format!("file:{}", path.display()),
+            Source::Vault { path, field } => format!("vault:{}#{}", path, field),
+        }
+    }
+}
+
+pub struct Secrets {
+    vault: Option<VaultConfig>,
+    client: Client,
+    refresh: Option<Duration>,
+    tracked: Mutex<Vec<(Source, Secret)>>,
+    started: AtomicBool,
+    store: OnceLock<Arc<CredentialStore>>,
+}
+
+enum Resolved {
+    Value(String),
+    Source(Source),
+}
+
+impl Secrets {
+    pub fn from_env(client: Client) -> Result<Arc<Self>> {
+        let refresh = config::env_parse::<u64>("SECRET_REFRESH_SECS")?.unwrap_or(DEFAULT_REFRESH_SECS);
+        Ok(Arc::new(Secrets {
+            vault: config::env_json("VAULT")?,
+            client,
+            refresh: (refresh > 0).then(|| Duration::from_secs(refresh)),
+            tracked: Mutex::new(Vec::new()),
+            started: AtomicBool::new(false),
+            store: OnceLock::new(),
+        }))
+    }
+
+    // Enables `store:` references; set before any backend is loaded.
+    pub fn use_store(&self, store: Arc<CredentialStore>) {
+        let _ = self.store.set(store);
+    }
+
+    fn resolve(&self, reference: &str) -> Result<Resolved> {
+        if let Some(name) = reference.strip_prefix("env:") {
+            let value = match std::env::var(name) {
+                Ok(value) => value,
+                Err(_) => from_file(name).with_context(|| format!("Credential reference '{}'", reference))?
+                    .with_context(|| format!("Credential reference '{}': {} is not set", reference, name))?,
+            };
+            Ok(Resolved::Value(value))
+        } else if let Some(path) = reference.strip_prefix("file:") {
+            Ok(Resolved::Source(Source::File(PathBuf::from(path))))
+        } else if let Some(location) = reference.strip_prefix("vault:") {
+            if self.vault.is_none() {
+                anyhow::bail!("Credential reference '{}' needs VAULT to be configured", reference);
+            }
+            let (path, field) = location.rsplit_once('#')
+                .with_context(|| format!("Credential reference '{}' needs a field, as vault:<path>#<field>", reference))?;
+            Ok(Resolved::Source(Source::Vault { path: path.trim_matches('/').to_string(), field: field.to_string() }))
+        } else {
+            Ok(Resolved::Value(reference.to_string()))
+        }
+    }
+
+    // Resolves a reference once, for values that are only read at startup.
+    pub async fn read(&self, reference: &str) -> Result<String> {
+        match self.resolve(reference)? {
+            Resolved::Value(value) => Ok(value),
+            Resolved::Source(source) => self.fetch(&source).await.with_context(|| format!("Failed to fetch {}", source.describe())),
+        }
+    }
+
+    // Resolves a credential setting. Vault secrets are fetched by `start`, or
+    // right away for backends loaded after it (from the backend registry).
+    pub fn reference(self: &Arc<Self>, reference: &str) -> Result<Secret> {
+        if let Some(name) = reference.strip_prefix("store:") {
+            let store = self.store.get().with_context(|| format!("Credential reference '{}' needs CREDENTIAL_STORE to be configured", reference))?;
+            return Ok(store.credential(name));
+        }
+        let source = match self.resolve(reference)? {
+            Resolved::Value(value) => return Ok(Secret::new(value)),
+            Resolved::Source(source) => source,
+        };
+
+        let secret = Secret::new(String::new());
+        if let Source::File(path) = &source {
+            secret.set(read_file(path)?);
+        } else if self.started.load(Ordering::Relaxed) {
+            let (this, source, secret) = (self.clone(), source.clone(), secret.clone());
+            tokio::spawn(async move {
+                match this.fetch(&source).await {
+                    Ok(value) => {
+                        secret.set(value);
+                    }
+                    Err(e) => warn!("Failed to fetch {}: {:#}", source.describe(), e),
+                }
+            });
+        }
+        self.tracked.lock().unwrap().push((source, secret.clone()));
+        Ok(secret)
+    }
+
+    // Fetches every Vault secret referenced so far, failing startup if one can't
+    // be, then keeps all references fresh.
+    pub async fn start(self: &Arc<Self>) -> Result<()> {
+        let tracked = self.tracked.lock().unwrap().clone();
+        for (source, secret) in tracked.iter().filter(|(source, _)| matches!(source, Source::Vault { .. })) {
+            secret.set(self.fetch(source).await.with_context(|| format!("Failed to fetch {}", source.describe()))?);
+        }
+        self.started.store(true, Ordering::Relaxed);
+        if !tracked.is_empty() {
+            info!("{} credential reference(s) loaded", tracked.len());
+        }
+        let Some(refresh) = self.refresh else { return Ok(()) };
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(refresh);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let tracked = this.tracked.lock().unwrap().clone();
+                for (source, secret) in tracked {
+                    match this.fetch(&source).await {
+                        Ok(value) if value.is_empty() => warn!("{} is empty; keeping the previous value", source.describe()),
+                        Ok(value) => {
+                            if secret.set(value) {
+                                info!("Credential {} was rotated", source.describe());
+                            }
+                        }
+                        Err(e) => warn!("Failed to refresh {}; keeping the previous value: {:#}", source.describe(), e),
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    async fn fetch(&self, source: &Source) -> Result<String> {
+        let (path, field) = match source {
+            Source::File(path) => return read_file(path),
+            Source::Vault { path, field } => (path, field),
+        };
+        let vault = self.vault.as_ref().context("VAULT is not configured")?;
+        let token = match std::env::var("VAULT_TOKEN_FILE") {
+            Ok(file) => read_file(&PathBuf::from(file))?,
+            Err(_) => std::env::var("VAULT_TOKEN").context("VAULT_TOKEN is not set")?,
+        };
+        let url = format!("{}/v1/{}", vault.address.trim_end_matches('/'), path);
+        let mut request = self.client.get(&url).header("x-vault-token", token).timeout(VAULT_TIMEOUT);
+        if let Some(namespace) = &vault.namespace {
+            request = request.header("x-vault-namespace", namespace);
+        }
+        let body: Value = request.send().await?.error_for_status()?.json().await?;
+        // KV v2 nests the secret under data.data; KV v1 and other engines don't.
+        let data = &body["data"];
+        let value = data["data"].get(field).or_else(|| data.get(field))
+            .and_then(Value::as_str)
+            .with_context(|| format!("{} has no string field '{}'", url, field))?;
+        Ok(value.to_string())
+    }
+}
+
+fn read_file(path: &PathBuf) -> Result<String> {
+    let value = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(value.trim_end_matches(['\r', '\n']).to_string())
+}
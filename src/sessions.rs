@@ -0,0 +1,122 @@
+use axum::http::{header::COOKIE, HeaderMap};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+use crate::{auth::Caller, backend::Backend, health::HealthMonitor, routing::LatencyTracker};
+
+pub const HEADER: &str = "x-session-id";
+const MAX_SESSION_ID_LEN: usize = 255;
+
+// --- Sticky Sessions ---
+// With STICKY_SESSIONS set, requests carrying an `X-Session-ID` header (or the
+// configured cookie) go to the replica that served the session's last turn, so
+// vLLM can reuse the conversation's prefix cache. A session moves to another
+// replica, picked by the usual balancing, when its replica fails a health check
+// or a request to it fails. Sessions are scoped per API key and model.
+#[derive(Debug, Deserialize)]
+pub struct StickyConfig {
+    #[serde(default)]
+    pub cookie: Option<String>,
+    #[serde(default = "default_ttl")]
+    pub ttl_secs: u64, // idle time before a session is forgotten
+    #[serde(default = "default_max_sessions")]
+    pub max_sessions: usize, // beyond this, new sessions are not pinned
+}
+
+fn default_ttl() -> u64 {
+    1800
+}
+
+fn default_max_sessions() -> usize {
+    100_000
+}
+
+pub struct SessionStore {
+    config: StickyConfig,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+struct Session {
+    replica: String,
+    last_used: Instant,
+}
+
+impl SessionStore {
+    pub fn new(config: StickyConfig) -> Self {
+        info!(
+            "Sticky sessions on {}{} (idle timeout {}s)",
+            HEADER, config.cookie.as_ref().map_or(String::new(), |c| format!(" or cookie '{}'", c)), config.ttl_secs
+        );
+        SessionStore { config, sessions: Mutex::new(HashMap::new()) }
+    }
+
+    // The store key for this request's session, if it names one.
+    pub fn key(&self, caller: &Caller, headers: &HeaderMap, model: &str) -> Option<String> {
+        let from_header = headers.get(HEADER).and_then(|v| v.to_str().ok()).map(str::trim);
+        let id = from_header.or_else(|| {
+            let name = self.config.cookie.as_deref()?;
+            headers.get_all(COOKIE).iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(';'))
+                .find_map(|pair| pair.trim().strip_prefix(name)?.strip_prefix('='))
+        })?;
+        if id.is_empty() || id.len() > MAX_SESSION_ID_LEN {
+            return None;
+        }
+        let scope = caller.key().map_or("", |key| key.name.as_str());
+        Some(format!("{}\n{}\n{}", scope, model, id))
+    }
+
+    // Requests sharing a cached prompt prefix, whichever key sent them. The NUL
+    // keeps these apart from named sessions.
+    pub fn prefix_key(&self, model: &str, prefix: &str) -> String {
+        format!("\0prefix\n{}\n{}", model, prefix)
+    }
+
+    // The session's replica while it is known and healthy; otherwise a fresh pick
+    // that avoids replicas the health monitor has marked down.
+    pub fn pick(&self, key: &str, model: &str, backend: &Backend, latency: &LatencyTracker, health: Option<&HealthMonitor>) -> Option<String> {
+        let healthy = |url: &str| !backend.is_drained(url) && health.is_none_or(|h| h.is_healthy(model, url));
+        let replicas = backend.replicas.get();
+        let ttl = Duration::from_secs(self.config.ttl_secs);
+        let stored = self.sessions.lock().unwrap().get(key)
+            .filter(|session| session.last_used.elapsed() < ttl && replicas.contains(&session.replica))
+            .map(|session| session.replica.clone());
+        if let Some(replica) = stored {
+            if healthy(&replica) {
+                return Some(replica);
+            }
+            info!("Session replica {} for model '{}' is unhealthy or drained; reassigning", replica, model);
+        }
+
+        let first = backend.pick_replica(latency)?;
+        let start = replicas.iter().position(|url| *url == first).unwrap_or(0);
+        let count = replicas.len();
+        (0..count).map(|offset| &replicas[(start + offset) % count])
+            .find(|url| healthy(url))
+            .cloned()
+            .or(Some(first))
+    }
+
+    pub fn assign(&self, key: &str, replica: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Instant::now();
+        if !sessions.contains_key(key) && sessions.len() >= self.config.max_sessions {
+            let ttl = Duration::from_secs(self.config.ttl_secs);
+            sessions.retain(|_, session| now.duration_since(session.last_used) < ttl);
+            if sessions.len() >= self.config.max_sessions {
+                return;
+            }
+        }
+        sessions.insert(key.to_string(), Session { replica: replica.to_string(), last_used: now });
+    }
+
+    pub fn forget(&self, key: &str) {
+        self.sessions.lock().unwrap().remove(key);
+    }
+}
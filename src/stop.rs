@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{
+    config::BackendConfig,
+    stream::{ChunkFilter, Verdict},
+    AppError, ChatRequest,
+};
+
+// As on OpenAI's API.
+const MAX_STOP_SEQUENCES: usize = 4;
+const MAX_STOP_CHARS: usize = 256;
+
+// --- Request `stop` ---
+// One string or a list of them. Any other shape is refused when the body is
+// parsed, and `normalize_stop` checks the limits before anything is sent, since
+// backends answer bad stop values with errors that are hard to read. Upstream
+// always gets a list with empty and repeated sequences removed, and no `stop`
+// at all if nothing is left.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(untagged, try_from = "Value")]
+pub enum StringOrVec {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl TryFrom<Value> for StringOrVec {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let invalid = || "`stop` must be a string or an array of strings".to_string();
+        match value {
+            Value::String(stop) => Ok(StringOrVec::One(stop)),
+            Value::Array(stops) => stops.into_iter()
+                .map(|stop| match stop {
+                    Value::String(stop) => Ok(stop),
+                    _ => Err(invalid()),
+                })
+                .collect::<Result<_, _>>()
+                .map(StringOrVec::Many),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl StringOrVec {
+    pub fn sequences(&self) -> &[String] {
+        match self {
+            StringOrVec::One(stop) => std::slice::from_ref(stop),
+            StringOrVec::Many(stops) => stops,
+        }
+    }
+}
+
+pub fn normalize_stop(body: &mut ChatRequest) -> Result<(), AppError> {
+    let Some(stop) = body.stop.take() else { return Ok(()) };
+    let mut sequences: Vec<String> = Vec::new();
+    for sequence in stop.sequences() {
+        if !sequence.is_empty() && !sequences.contains(sequence) {
+            sequences.push(sequence.clone());
+        }
+    }
+    if sequences.len() > MAX_STOP_SEQUENCES {
+        return Err(AppError::InvalidRequest(format!(
+            "Too many stop sequences: {}. At most {} are allowed.", sequences.len(), MAX_STOP_SEQUENCES
+        )));
+    }
+    if let Some(long) = sequences.iter().find(|sequence| sequence.chars().count() > MAX_STOP_CHARS) {
+        return Err(AppError::InvalidRequest(format!(
+            "Stop sequence is too long: {} characters. Each may be at most {}.", long.chars().count(), MAX_STOP_CHARS
+        )));
+    }
+    body.stop = (!sequences.is_empty()).then_some(StringOrVec::Many(sequences));
+    Ok(())
+}
+
+// --- Stop Sequences ---
+// For backends that ignore `stop` or don't support some sequences, the gateway
+// watches the streamed text itself. A model's `stop_sequences` always apply, and
+// with `enforce_stop` so do the request's own `stop` strings. A choice's output
+// is cut just before its first match and the choice ends with `finish_reason:
+// "stop"`; the stream ends once every choice has finished. Each choice holds back
+// one character less than the longest sequence, so a sequence split across chunks
+// is still caught.
+pub struct StopFilter {
+    sequences: Vec<String>,
+    holdback_chars: usize,
+    pending: HashMap<usize, String>,
+    finished: HashMap<usize, bool>, // every choice seen, and whether it has finished
+    stopped: HashSet<usize>, // choices cut at a stop sequence, whose later chunks are dropped
+    template: Option<Value>, // last chunk seen, used to shape flushed chunks
+}
+
+impl StopFilter {
+    pub fn new(config: &BackendConfig, request: &ChatRequest) -> Option<Self> {
+        let mut sequences = config.stop_sequences.clone();
+        if let Some(stop) = request.stop.as_ref().filter(|_| config.enforce_stop) {
+            sequences.extend_from_slice(stop.sequences());
+        }
+        sequences.retain(|sequence| !sequence.is_empty());
+        let longest = sequences.iter().map(|sequence| sequence.chars().count()).max()?;
+        Some(StopFilter {
+            sequences,
+            holdback_chars: longest - 1,
+            pending: HashMap::new(),
+            finished: HashMap::new(),
+            stopped: HashSet::new(),
+            template: None,
+        })
+    }
+
+    // Returns the text that can be sent now, and whether a sequence was found (in
+    // which case the text is everything before it).
+    fn release(&mut self, index: usize, incoming: &str, flush: bool) -> (String, bool) {
+        let buffer = self.pending.entry(index).or_default();
+        buffer.push_str(incoming);
+
+        if let Some(start) = self.sequences.iter().filter_map(|sequence| buffer.find(sequence.as_str())).min() {
+            let released = buffer[..start].to_string();
+            buffer.clear();
+            return (released, true);
+        }
+        let split = match flush || self.holdback_chars == 0 {
+            true => buffer.len(),
+            false => buffer.char_indices().rev().nth(self.holdback_chars - 1).map_or(0, |(i, _)| i),
+        };
+        (buffer.drain(..split).collect(), false)
+    }
+}
+
+impl ChunkFilter for StopFilter {
+    fn on_chunk(&mut self, chunk: &mut Value) -> Verdict {
+        let mut stopped = false;
+        if let Some(choices) = chunk.get_mut("choices").and_then(Value::as_array_mut) {
+            let index_of = |choice: &Value| choice.get("index").and_then(Value::as_u64).unwrap_or(0) as usize;
+            choices.retain(|choice| !self.stopped.contains(&index_of(choice)));
+            for choice in choices {
+                let index = index_of(choice);
+                let finished = choice.get("finish_reason").is_some_and(|r| !r.is_null());
+                let hit = match choice.pointer_mut("/delta/content") {
+                    Some(Value::String(content)) => {
+                        let (released, hit) = self.release(index, content, finished);
+                        *content = released;
+                        hit
+                    }
+                    // A finish_reason without any content still has to flush held-back text.
+                    _ if finished => {
+                        let (released, hit) = self.release(index, "", true);
+                        if !released.is_empty() {
+                            choice["delta"]["content"] = Value::String(released);
+                        }
+                        hit
+                    }
+                    _ => false,
+                };
+                if hit {
+                    info!("Stop sequence found in the output of choice {}", index);
+                    choice["finish_reason"] = Value::String("stop".to_string());
+                    self.stopped.insert(index);
+                }
+                stopped |= hit;
+                *self.finished.entry(index).or_default() |= finished || hit;
+            }
+        }
+
+        self.template = Some(chunk.clone());
+        if stopped && self.finished.values().all(|&finished| finished) {
+            info!("Every choice has finished; ending the stream");
+            return Verdict::Stop;
+        }
+        Verdict::Continue
+    }
+
+    fn finish(&mut self) -> Vec<Value> {
+        let mut indices: Vec<usize> = self.pending.iter()
+            .filter(|(_, text)| !text.is_empty())
+            .map(|(&i, _)| i)
+            .collect();
+        indices.sort_unstable();
+
+        let mut chunks = Vec::new();
+        for index in indices {
+            let (released, _) = self.release(index, "", true);
+            if released.is_empty() {
+                continue;
+            }
+            let mut chunk = self.template.clone().unwrap_or_else(|| json!({ "object": "chat.completion.chunk" }));
+            chunk["choices"] = json!([{ "index": index, "delta": { "content": released }, "finish_reason": null }]);
+            chunks.push(chunk);
+        }
+        chunks
+    }
+}
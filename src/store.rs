@@ -0,0 +1,644 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use sqlx::{
+    any::{AnyPoolOptions, AnyRow},
+    migrate::{Migrate, Migrator},
+    AnyPool, Row,
+};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::{
+    audit::{self, AuditEntry, AuditFilter},
+    config,
+    request_log::RequestRecord,
+};
+
+static SQLITE: Migrator = sqlx::migrate!("migrations/sqlite");
+static POSTGRES: Migrator = sqlx::migrate!("migrations/postgres");
+
+const STORE_BUFFER: usize = 10_000; // queued writes; more are dropped while the database is unreachable
+const BATCH_SIZE: usize = 500;
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+// --- Storage Backends ---
+// What outlives the process goes through a `Store`, picked by STORE_URL:
+// `memory:` (the default) keeps nothing beyond the in-memory request log, audit
+// log, and key store, so a small deployment needs no database; `sqlite:<file>`
+// and `postgres://...` persist finished requests, audit entries, async jobs, and
+// CREDENTIAL_STORE's keys and credentials, read back at startup. Several
+// instances can share a Postgres store: budget spend is summed across them,
+// stored keys are picked up from each other, and any of them answers for a job.
+#[async_trait]
+pub trait Store: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // False for `memory:`, whose writes go nowhere.
+    fn persistent(&self) -> bool {
+        true
+    }
+
+    // Migrations left unapplied, outside `serve`.
+    fn pending(&self) -> &[String] {
+        &[]
+    }
+
+    // Request log
+    fn submit_request(&self, record: RequestRecord);
+
+    // The last `limit` finished requests, oldest first.
+    async fn recent_requests(&self, limit: usize) -> Result<Vec<RequestRecord>>;
+
+    // Budgets: each API key's cost since `since`, across every instance, or None
+    // when the store doesn't know.
+    async fn spend_since(&self, since: DateTime<Utc>) -> Result<Option<HashMap<String, f64>>>;
+
+    // Keys: CREDENTIAL_STORE's document, when it has no `path`. Saving replaces
+    // the document only if it is still at revision `expected`, and returns false
+    // if another instance changed it first.
+    async fn load_keys(&self) -> Result<Option<String>>;
+    async fn save_keys(&self, document: String, revision: u64, expected: u64) -> Result<bool>;
+
+    // Async jobs: each job's JSON under its id, with the name of the key that owns
+    // it. Finishing replaces a job only while it is still in progress, and returns
+    // false once it was cancelled or finished elsewhere.
+    async fn insert_job(&self, id: &str, owner: Option<&str>, job: String) -> Result<()>;
+    async fn finish_job(&self, id: &str, job: String) -> Result<bool>;
+    async fn load_job(&self, id: &str) -> Result<Option<(Option<String>, String)>>;
+    async fn forget_jobs(&self, finished_before: DateTime<Utc>) -> Result<()>;
+
+    // Audit log: each entry with the line hashed into the chain. An entry is
+    // appended, with `hash` as the new head, only if its `prev_hash` is still the
+    // head; false if another instance appended first.
+    async fn audit_head(&self) -> Result<String>;
+    async fn append_audit(&self, entry: &AuditEntry, line: &str, hash: &str) -> Result<bool>;
+
+    // Every audit entry's line, in the order they were recorded.
+    async fn audit_lines(&self) -> Result<Vec<String>>;
+
+    // The lines of the entries matching `filter`, newest first.
+    async fn query_audit(&self, filter: &AuditFilter) -> Result<Vec<String>>;
+
+    // For /admin/dashboard.
+    async fn health(&self) -> Value;
+}
+
+pub async fn from_env(migrate: bool) -> Result<Arc<dyn Store>> {
+    let url = std::env::var("STORE_URL").ok().map(|url| url.trim().to_string()).filter(|url| !url.is_empty());
+    match url.as_deref() {
+        None | Some("memory:") => Ok(Arc::new(MemoryStore)),
+        Some(url) => {
+            let max_connections = config::env_parse("STORE_MAX_CONNECTIONS")?.unwrap_or(10);
+            Ok(Arc::new(SqlStore::open(url, max_connections, migrate).await?))
+        }
+    }
+}
+
+// --- Memory Store ---
+// Persists nothing. Every write is dropped and every read finds nothing: no
+// requests, spend, keys, or jobs, and an empty audit chain. The request log,
+// audit log, key store, and jobs keep their own data in memory, so a single
+// instance works as before, and it all ends with the process.
+pub struct MemoryStore;
+
+#[async_trait]
+impl Store for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn persistent(&self) -> bool {
+        false
+    }
+
+    fn submit_request(&self, _record: RequestRecord) {}
+
+    async fn recent_requests(&self, _limit: usize) -> Result<Vec<RequestRecord>> {
+        Ok(Vec::new())
+    }
+
+    async fn spend_since(&self, _since: DateTime<Utc>) -> Result<Option<HashMap<String, f64>>> {
+        Ok(None)
+    }
+
+    async fn load_keys(&self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    async fn save_keys(&self, _document: String, _revision: u64, _expected: u64) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn insert_job(&self, _id: &str, _owner: Option<&str>, _job: String) -> Result<()> {
+        Ok(())
+    }
+
+    async fn finish_job(&self, _id: &str, _job: String) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn load_job(&self, _id: &str) -> Result<Option<(Option<String>, String)>> {
+        Ok(None)
+    }
+
+    async fn forget_jobs(&self, _finished_before: DateTime<Utc>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn audit_head(&self) -> Result<String> {
+        Ok(String::new())
+    }
+
+    async fn append_audit(&self, _entry: &AuditEntry, _line: &str, _hash: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn audit_lines(&self) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    async fn query_audit(&self, _filter: &AuditFilter) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    async fn health(&self) -> Value {
+        json!({ "kind": self.name(), "healthy": true, "persistent": false })
+    }
+}
+
+// --- SQL Store ---
+// SQLite and Postgres, through one pool. The schema is versioned by the
+// migrations under migrations/: `serve` applies the ones the database lacks,
+// each in a transaction, after copying a SQLite file aside, and refuses to start
+// on a database migrated by a newer gateway or whose applied migrations differ
+// from this build's. Request records are queued and batched off the request path,
+// and retried while the database is unreachable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Sqlite,
+    Postgres,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Sqlite => "sqlite",
+            Kind::Postgres => "postgres",
+        }
+    }
+
+    fn migrator(self) -> &'static Migrator {
+        match self {
+            Kind::Sqlite => &SQLITE,
+            Kind::Postgres => &POSTGRES,
+        }
+    }
+}
+
+enum Write {
+    Request(RequestRecord),
+}
+
+const KEYS_DOCUMENT: &str = "credential_store";
+
+pub struct SqlStore {
+    pool: AnyPool,
+    kind: Kind,
+    version: i64, // the schema version the database is at
+    pending: Vec<String>,
+    tx: mpsc::Sender<Write>,
+}
+
+impl SqlStore {
+    // `migrate` is false for the one-shot commands, which only report pending
+    // migrations and leave the database as it is.
+    async fn open(url: &str, max_connections: u32, migrate: bool) -> Result<Self> {
+        sqlx::any::install_default_drivers();
+        let (kind, url) = match url.split(':').next() {
+            Some("sqlite") if url.contains("mode=") => (Kind::Sqlite, url.to_string()),
+            Some("sqlite") => (Kind::Sqlite, format!("{}{}mode=rwc", url, if url.contains('?') { '&' } else { '?' })),
+            Some("postgres" | "postgresql") => (Kind::Postgres, url.to_string()),
+            _ => anyhow::bail!("Unsupported STORE_URL '{}'; use memory:, sqlite:<file>, or postgres://", display(url)),
+        };
+        let pool = AnyPoolOptions::new()
+            .max_connections(max_connections)
+            .acquire_timeout(Duration::from_secs(5))
+            .connect(&url).await
+            .with_context(|| format!("Failed to connect to STORE_URL {}", display(&url)))?;
+
+        let migrator = kind.migrator();
+        let latest = migrator.iter().map(|m| m.version).max().unwrap_or_default();
+        let mut conn = pool.acquire().await?;
+        conn.ensure_migrations_table().await?;
+        let applied = conn.list_applied_migrations().await?;
+        let mut version = applied.iter().map(|m| m.version).max().unwrap_or_default();
+        if version > latest {
+            anyhow::bail!(
+                "The store at {} has schema version {}, but this gateway only knows up to {}; run a newer gateway, \
+                or restore the backup taken before the upgrade",
+                display(&url), version, latest
+            );
+        }
+        let mut pending: Vec<String> = migrator.iter()
+            .filter(|m| !applied.iter().any(|a| a.version == m.version))
+            .map(|m| format!("{} ({})", m.version, m.description))
+            .collect();
+        if migrate && !pending.is_empty() {
+            if kind == Kind::Sqlite && version > 0 {
+                backup(&mut conn, &url, version).await?;
+            }
+            migrator.run_direct(&mut *conn).await
+                .with_context(|| format!("Failed to migrate the store at {}", display(&url)))?;
+            info!("Migrated the store from schema version {} to {}", version, latest);
+            (version, pending) = (latest, Vec::new());
+        }
+        drop(conn);
+        info!("Store: {} {} at schema version {}", kind.name(), display(&url), version);
+
+        let (tx, rx) = mpsc::channel(STORE_BUFFER);
+        tokio::spawn(write_batches(pool.clone(), rx));
+        Ok(SqlStore { pool, kind, version, pending, tx })
+    }
+
+    fn submit(&self, write: Write) {
+        if let Err(mpsc::error::TrySendError::Full(_)) = self.tx.try_send(write) {
+            warn!("Store write queue is full; dropping a record");
+        }
+    }
+}
+
+#[async_trait]
+impl Store for SqlStore {
+    fn name(&self) -> &'static str {
+        self.kind.name()
+    }
+
+    fn pending(&self) -> &[String] {
+        &self.pending
+    }
+
+    fn submit_request(&self, record: RequestRecord) {
+        self.submit(Write::Request(record));
+    }
+
+    async fn recent_requests(&self, limit: usize) -> Result<Vec<RequestRecord>> {
+        if !self.pending.is_empty() {
+            return Ok(Vec::new());
+        }
+        let rows = sqlx::query(
+            "SELECT at_ms, model, key_name, organization, project, status, prompt_tokens, cached_tokens, \
+            completion_tokens, cost, latency_ms, audio_seconds, metadata FROM requests ORDER BY id DESC LIMIT $1",
+        )
+        .bind(limit.min(i64::MAX as usize) as i64)
+        .fetch_all(&self.pool).await
+        .context("Failed to read requests from the store")?;
+        let mut records = rows.iter().map(request_from_row).collect::<Result<Vec<_>, _>>()
+            .context("Invalid request row in the store")?;
+        records.reverse();
+        Ok(records)
+    }
+
+    async fn spend_since(&self, since: DateTime<Utc>) -> Result<Option<HashMap<String, f64>>> {
+        if !self.pending.is_empty() {
+            return Ok(None);
+        }
+        let rows = sqlx::query(
+            "SELECT key_name, SUM(cost) AS usd FROM requests \
+            WHERE at_ms >= $1 AND key_name IS NOT NULL AND cost IS NOT NULL GROUP BY key_name",
+        )
+        .bind(since.timestamp_millis())
+        .fetch_all(&self.pool).await
+        .context("Failed to sum spend in the store")?;
+        let spend = rows.iter().map(|row| Ok((row.try_get("key_name")?, row.try_get("usd")?)))
+            .collect::<Result<_, sqlx::Error>>()
+            .context("Invalid spend row in the store")?;
+        Ok(Some(spend))
+    }
+
+    async fn load_keys(&self) -> Result<Option<String>> {
+        if !self.pending.is_empty() {
+            return Ok(None);
+        }
+        let row = sqlx::query("SELECT body FROM documents WHERE name = $1").bind(KEYS_DOCUMENT)
+            .fetch_optional(&self.pool).await
+            .context("Failed to read the credential store from the store")?;
+        row.map(|row| row.try_get("body")).transpose().context("Invalid credential store row in the store")
+    }
+
+    // Written at once rather than queued, so an admin change isn't reported as
+    // made before it is.
+    async fn save_keys(&self, document: String, revision: u64, expected: u64) -> Result<bool> {
+        if !self.pending.is_empty() {
+            anyhow::bail!("The store has unapplied migrations");
+        }
+        let now = Utc::now().timestamp_millis();
+        let updated = sqlx::query("UPDATE documents SET body = $1, revision = $2, updated_at_ms = $3 WHERE name = $4 AND revision = $5")
+            .bind(&document)
+            .bind(revision as i64)
+            .bind(now)
+            .bind(KEYS_DOCUMENT)
+            .bind(expected as i64)
+            .execute(&self.pool).await
+            .context("Failed to save the credential store to the store")?;
+        if updated.rows_affected() > 0 {
+            return Ok(true);
+        }
+        let inserted = sqlx::query(
+            "INSERT INTO documents (name, body, updated_at_ms, revision) VALUES ($1, $2, $3, $4) ON CONFLICT (name) DO NOTHING",
+        )
+        .bind(KEYS_DOCUMENT)
+        .bind(&document)
+        .bind(now)
+        .bind(revision as i64)
+        .execute(&self.pool).await
+        .context("Failed to save the credential store to the store")?;
+        Ok(inserted.rows_affected() > 0)
+    }
+
+    // Jobs are written at once too, so a poll on another instance finds them.
+    async fn insert_job(&self, id: &str, owner: Option<&str>, job: String) -> Result<()> {
+        if !self.pending.is_empty() {
+            anyhow::bail!("The store has unapplied migrations");
+        }
+        sqlx::query("INSERT INTO jobs (id, owner, body) VALUES ($1, $2, $3)")
+            .bind(id)
+            .bind(owner)
+            .bind(job)
+            .execute(&self.pool).await
+            .context("Failed to save the job to the store")?;
+        Ok(())
+    }
+
+    async fn finish_job(&self, id: &str, job: String) -> Result<bool> {
+        if !self.pending.is_empty() {
+            anyhow::bail!("The store has unapplied migrations");
+        }
+        let updated = sqlx::query("UPDATE jobs SET body = $1, finished_at_ms = $2 WHERE id = $3 AND finished_at_ms IS NULL")
+            .bind(job)
+            .bind(Utc::now().timestamp_millis())
+            .bind(id)
+            .execute(&self.pool).await
+            .context("Failed to save the job to the store")?;
+        Ok(updated.rows_affected() > 0)
+    }
+
+    async fn load_job(&self, id: &str) -> Result<Option<(Option<String>, String)>> {
+        if !self.pending.is_empty() {
+            return Ok(None);
+        }
+        let row = sqlx::query("SELECT owner, body FROM jobs WHERE id = $1").bind(id)
+            .fetch_optional(&self.pool).await
+            .context("Failed to read the job from the store")?;
+        row.map(|row| -> Result<_, sqlx::Error> { Ok((row.try_get("owner")?, row.try_get("body")?)) })
+            .transpose()
+            .context("Invalid job row in the store")
+    }
+
+    async fn forget_jobs(&self, finished_before: DateTime<Utc>) -> Result<()> {
+        if !self.pending.is_empty() {
+            return Ok(());
+        }
+        sqlx::query("DELETE FROM jobs WHERE finished_at_ms < $1")
+            .bind(finished_before.timestamp_millis())
+            .execute(&self.pool).await
+            .context("Failed to forget finished jobs in the store")?;
+        Ok(())
+    }
+
+    async fn audit_head(&self) -> Result<String> {
+        if !self.pending.is_empty() {
+            anyhow::bail!("The store has unapplied migrations");
+        }
+        let head = sqlx::query("SELECT hash FROM audit_head WHERE id = 1").fetch_optional(&self.pool).await
+            .context("Failed to read the audit head from the store")?;
+        if let Some(row) = head {
+            return row.try_get("hash").context("Invalid audit head row in the store");
+        }
+        let last = sqlx::query("SELECT line FROM audit_log ORDER BY seq DESC LIMIT 1").fetch_optional(&self.pool).await
+            .context("Failed to read the audit log from the store")?;
+        let line: Option<String> = last.map(|row| row.try_get("line")).transpose().context("Invalid audit row in the store")?;
+        Ok(line.as_deref().map(audit::hash).unwrap_or_default())
+    }
+
+    // Written at once rather than queued, so the entry joins the chain the other
+    // instances see. Moving the head first holds it for the rest of the
+    // transaction.
+    async fn append_audit(&self, entry: &AuditEntry, line: &str, hash: &str) -> Result<bool> {
+        if !self.pending.is_empty() {
+            anyhow::bail!("The store has unapplied migrations");
+        }
+        let mut tx = self.pool.begin().await.context("Failed to append to the audit log in the store")?;
+        let moved = sqlx::query("UPDATE audit_head SET hash = $1 WHERE id = 1 AND hash = $2")
+            .bind(hash)
+            .bind(&entry.prev_hash)
+            .execute(&mut *tx).await
+            .context("Failed to append to the audit log in the store")?;
+        if moved.rows_affected() == 0 {
+            let created = sqlx::query("INSERT INTO audit_head (id, hash) VALUES (1, $1) ON CONFLICT (id) DO NOTHING")
+                .bind(hash)
+                .execute(&mut *tx).await
+                .context("Failed to append to the audit log in the store")?;
+            if created.rows_affected() == 0 {
+                return Ok(false);
+            }
+        }
+        sqlx::query("INSERT INTO audit_log (id, at_ms, actor, action, target, line) VALUES ($1, $2, $3, $4, $5, $6)")
+            .bind(&entry.id)
+            .bind(entry.at.timestamp_millis())
+            .bind(&entry.actor)
+            .bind(&entry.action)
+            .bind(&entry.target)
+            .bind(line)
+            .execute(&mut *tx).await
+            .context("Failed to append to the audit log in the store")?;
+        tx.commit().await.context("Failed to append to the audit log in the store")?;
+        Ok(true)
+    }
+
+    async fn audit_lines(&self) -> Result<Vec<String>> {
+        if !self.pending.is_empty() {
+            return Ok(Vec::new());
+        }
+        let rows = sqlx::query("SELECT line FROM audit_log ORDER BY seq").fetch_all(&self.pool).await
+            .context("Failed to read the audit log from the store")?;
+        rows.iter().map(|row| row.try_get("line")).collect::<Result<_, _>>().context("Invalid audit row in the store")
+    }
+
+    async fn query_audit(&self, filter: &AuditFilter) -> Result<Vec<String>> {
+        if !self.pending.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut sql = "SELECT line FROM audit_log WHERE at_ms >= $1 AND at_ms < $2".to_string();
+        let mut texts = Vec::new();
+        let mut condition = |sql: &mut String, column: &str, value: &str| {
+            texts.push(value.to_string());
+            sql.push_str(&format!(" AND {} = ${}", column, texts.len() + 2));
+        };
+        if let Some(actor) = &filter.actor {
+            condition(&mut sql, "actor", actor);
+        }
+        if let Some(target) = &filter.target {
+            condition(&mut sql, "target", target);
+        }
+        match &filter.action {
+            Some(prefix) if prefix.ends_with('.') => condition(&mut sql, &format!("substr(action, 1, {})", prefix.chars().count()), prefix),
+            Some(action) => condition(&mut sql, "action", action),
+            None => {}
+        }
+        sql.push_str(&format!(" ORDER BY seq DESC LIMIT {}", filter.limit.min(i64::MAX as usize)));
+        let mut query = sqlx::query(&sql).bind(filter.from.timestamp_millis()).bind(filter.to.timestamp_millis());
+        for text in &texts {
+            query = query.bind(text);
+        }
+        let rows = query.fetch_all(&self.pool).await.context("Failed to query the audit log in the store")?;
+        rows.iter().map(|row| row.try_get("line")).collect::<Result<_, _>>().context("Invalid audit row in the store")
+    }
+
+    async fn health(&self) -> Value {
+        let error = match tokio::time::timeout(PING_TIMEOUT, sqlx::query("SELECT 1").execute(&self.pool)).await {
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(_) => Some(format!("no answer within {}s", PING_TIMEOUT.as_secs())),
+        };
+        json!({
+            "kind": self.kind.name(),
+            "healthy": error.is_none(),
+            "error": error,
+            "schema_version": self.version,
+            "connections": self.pool.size(),
+            "idle_connections": self.pool.num_idle(),
+            "queued_writes": STORE_BUFFER - self.tx.capacity(),
+        })
+    }
+}
+
+// The URL without its password, for messages.
+fn display(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(mut parsed) if parsed.password().is_some() => {
+            let _ = parsed.set_password(Some("***"));
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
+
+// Copies a SQLite file to `<file>.v<version>-<time>.bak` before migrating it.
+async fn backup(conn: &mut sqlx::AnyConnection, url: &str, version: i64) -> Result<()> {
+    let path = url.trim_start_matches("sqlite:").trim_start_matches("//");
+    let path = path.split('?').next().unwrap_or(path);
+    if path.is_empty() || path.starts_with(':') {
+        return Ok(()); // in memory
+    }
+    let target = format!("{}.v{}-{}.bak", path, version, Utc::now().format("%Y%m%d%H%M%S"));
+    sqlx::query(&format!("VACUUM INTO '{}'", target.replace('\'', "''"))).execute(&mut *conn).await
+        .with_context(|| format!("Failed to back up the store to '{}' before migrating it", target))?;
+    info!("Backed up the store to '{}' before migrating it", target);
+    Ok(())
+}
+
+fn request_from_row(row: &AnyRow) -> Result<RequestRecord, sqlx::Error> {
+    let at_ms: i64 = row.try_get("at_ms")?;
+    let unsigned = |column: &str| row.try_get::<i64, _>(column).map(|value| value.max(0) as u64);
+    Ok(RequestRecord {
+        at: DateTime::from_timestamp_millis(at_ms).unwrap_or_default(),
+        model: row.try_get("model")?,
+        key: row.try_get("key_name")?,
+        organization: row.try_get("organization")?,
+        project: row.try_get("project")?,
+        status: unsigned("status")? as u16,
+        prompt_tokens: unsigned("prompt_tokens")?,
+        cached_tokens: row.try_get::<Option<i64>, _>("cached_tokens")?.map(|value| value.max(0) as u64),
+        completion_tokens: unsigned("completion_tokens")?,
+        cost: row.try_get("cost")?,
+        latency_ms: unsigned("latency_ms")?,
+        audio_seconds: row.try_get("audio_seconds")?,
+        metadata: row.try_get::<Option<String>, _>("metadata")?
+            .map(|json| serde_json::from_str(&json).map_err(|e| sqlx::Error::Decode(e.into())))
+            .transpose()?
+            .unwrap_or_default(),
+    })
+}
+
+async fn write_batches(pool: AnyPool, mut rx: mpsc::Receiver<Write>) {
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while let Some(write) = rx.recv().await {
+        batch.push(write);
+        while batch.len() < BATCH_SIZE {
+            match rx.try_recv() {
+                Ok(write) => batch.push(write),
+                Err(_) => break,
+            }
+        }
+        // One record the database rejects would fail the whole batch, so the
+        // batch is then written a record at a time and only that record is lost.
+        if let Err(e) = persist(&pool, &batch).await {
+            warn!("The store rejected a batch of {} record(s): {}; writing them one at a time", batch.len(), e);
+            for write in &batch {
+                if let Err(e) = persist(&pool, std::slice::from_ref(write)).await {
+                    error!("The store rejected {}: {}; it is not persisted", write.describe(), e);
+                }
+            }
+        }
+        batch.clear();
+    }
+}
+
+// Retries while the database is unreachable; an error is the database's own
+// rejection, which retrying can't help.
+async fn persist(pool: &AnyPool, batch: &[Write]) -> Result<(), sqlx::Error> {
+    let mut delay = Duration::from_secs(1);
+    loop {
+        match write_batch(pool, batch).await {
+            Ok(()) => return Ok(()),
+            Err(e @ sqlx::Error::Database(_)) => return Err(e),
+            Err(e) => {
+                warn!("Failed to write {} record(s) to the store: {}; retrying in {}s", batch.len(), e, delay.as_secs());
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(Duration::from_secs(30));
+            }
+        }
+    }
+}
+
+impl Write {
+    // For the log line when it's lost.
+    fn describe(&self) -> String {
+        match self {
+            Write::Request(record) => format!("the request record for model '{}' at {}", record.model, record.at.to_rfc3339()),
+        }
+    }
+}
+
+async fn write_batch(pool: &AnyPool, batch: &[Write]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for write in batch {
+        match write {
+            Write::Request(record) => {
+                sqlx::query(
+                    "INSERT INTO requests (at_ms, model, key_name, organization, project, status, prompt_tokens, \
+                    cached_tokens, completion_tokens, cost, latency_ms, audio_seconds, metadata) \
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
+                )
+                .bind(record.at.timestamp_millis())
+                .bind(&record.model)
+                .bind(&record.key)
+                .bind(&record.organization)
+                .bind(&record.project)
+                .bind(record.status as i64)
+                .bind(record.prompt_tokens as i64)
+                .bind(record.cached_tokens.map(|tokens| tokens as i64))
+                .bind(record.completion_tokens as i64)
+                .bind(record.cost)
+                .bind(record.latency_ms as i64)
+                .bind(record.audio_seconds)
+                .bind((!record.metadata.is_empty()).then(|| serde_json::to_string(&record.metadata).unwrap_or_default()))
+                .execute(&mut *tx).await?;
+            }
+        }
+    }
+    tx.commit().await
+}
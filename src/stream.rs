@@ -0,0 +1,709 @@
+use axum::response::{sse::Event, Sse};
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+use futures_core::stream::Stream;
+use serde_json::{json, Value};
+use std::{collections::VecDeque, convert::Infallible, io, pin::Pin, sync::Arc, time::Duration};
+use tokio::{sync::Notify, time::Instant};
+use tracing::{error, info, warn};
+
+use crate::{
+    backend::Backend,
+    client::Timeouts,
+    guardrails::{Decision, Guardrail},
+    request_log::Recorder,
+    resume::Resume,
+    trace::Direction,
+    usage::{self, UsageTap},
+    ChatRequest,
+};
+
+// The pipeline yields raw SSE `data:` payloads (chunk JSON, `[DONE]`, or gateway
+// error text); framing them as events happens at the edge, in `sse_response`.
+pub type PayloadStream = Pin<Box<dyn Stream<Item = String> + Send>>;
+pub type UpstreamBody = Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>;
+pub type EventStream = Pin<Box<dyn Stream<Item = Result<Event, Infallible>> + Send>>;
+
+// Events are numbered from 1 (after `skip`, for a resumed stream), so a client
+// can resume with `Last-Event-ID`; see `idempotency::StreamResumes`.
+pub fn sse_response(payloads: PayloadStream, skip: usize) -> Sse<EventStream> {
+    let events: EventStream = Box::pin(payloads.zip(stream::iter(skip + 1..))
+        .map(|(data, id)| Ok(Event::default().id(id.to_string()).data(data))));
+    Sse::new(events)
+}
+
+// --- Chunk Filters ---
+// Filters see every parsed `chat.completion.chunk` in order and may rewrite it in
+// place. Returning `Verdict::Stop` ends the stream after the (modified) chunk, which
+// the later filters still see, is sent.
+pub enum Verdict {
+    Continue,
+    Stop,
+}
+
+pub trait ChunkFilter: Send {
+    fn on_chunk(&mut self, chunk: &mut Value) -> Verdict;
+
+    // Called once when the upstream signals completion, for filters that hold back
+    // text; any returned chunks are sent before the final `[DONE]`.
+    fn finish(&mut self) -> Vec<Value> {
+        Vec::new()
+    }
+}
+
+// Mutable access to each choice's `delta.content` string, for filters that
+// operate on generated text.
+pub fn delta_contents_mut(chunk: &mut Value) -> impl Iterator<Item = (usize, &mut String, bool)> {
+    chunk.get_mut("choices")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(|choice| {
+            let index = choice.get("index").and_then(Value::as_u64).unwrap_or(0) as usize;
+            let finished = choice.get("finish_reason").is_some_and(|r| !r.is_null());
+            match choice.get_mut("delta").and_then(|d| d.get_mut("content")) {
+                Some(Value::String(content)) => Some((index, content, finished)),
+                _ => None,
+            }
+        })
+}
+
+// Each choice's tool call argument pieces.
+fn tool_arguments(chunk: &Value) -> impl Iterator<Item = &str> {
+    chunk.get("choices")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|choice| choice.pointer("/delta/tool_calls")?.as_array())
+        .flatten()
+        .filter_map(|call| call.pointer("/function/arguments")?.as_str())
+}
+
+pub fn set_finish_reason(chunk: &mut Value, reason: &str) {
+    if let Some(choices) = chunk.get_mut("choices").and_then(Value::as_array_mut) {
+        for choice in choices {
+            choice["finish_reason"] = Value::String(reason.to_string());
+        }
+    }
+}
+
+// Post-response guardrails need the original request and the full completion text.
+pub struct ResponseCheck {
+    pub guardrails: Vec<Arc<dyn Guardrail>>,
+    pub request: ChatRequest,
+}
+
+// Limits and injected faults for one stream.
+#[derive(Clone, Default)]
+pub struct StreamControl {
+    pub max_duration: Option<Duration>, // then the stream ends as if it hit max_tokens
+    pub drop_after: Option<usize>, // chaos: break the stream after this many upstream chunks
+    pub idle: Option<Duration>, // longest wait for the next upstream chunk
+    pub deadline: Option<Instant>, // for the whole upstream request
+    pub source: Option<(Arc<Backend>, String)>, // backend and replica streaming, marked suspect on a stall
+    pub model: Option<String>, // the name the client asked for, set on every chunk
+    pub prompt_tokens: Option<usize>, // counted, for the usage of a stream the gateway ends early
+}
+
+// --- Model Name Rewriting ---
+// Backends name the model in their chunks by whatever they serve it under: an
+// `upstream_model`, a weights path, or the model a routing rule picked. Every
+// chunk's `model` is set back to the name the client asked for, so logs and SDKs
+// that compare it see what they sent; X-Gateway-Model says which model answered.
+struct ModelName {
+    value: Value,
+    field: String, // `"model":"<name>"`, as compact JSON has it
+}
+
+impl ModelName {
+    fn new(name: String) -> Self {
+        let value = Value::String(name);
+        ModelName { field: format!("\"model\":{}", value), value }
+    }
+
+    // Whether a payload can be passed through without parsing it.
+    fn unchanged(&self, data: &str) -> bool {
+        !data.contains("\"model\"") || data.contains(&self.field)
+    }
+}
+
+// --- Stream Response Function ---
+// Bounds `line_buf`, so a backend that never sends a newline can't grow it forever.
+const MAX_LINE_BYTES: usize = 4 * 1024 * 1024;
+
+fn line_end(bytes: &[u8]) -> Option<usize> {
+    bytes.iter().position(|&b| b == b'\n')
+}
+
+struct StreamState {
+    upstream: UpstreamBody,
+    filters: Vec<Box<dyn ChunkFilter>>,
+    response_check: Option<ResponseCheck>,
+    resume: Option<Resume>,
+    drop_after: Option<usize>, // chaos: upstream chunks left before the stream breaks
+    max_duration: Option<Duration>,
+    deadline: Option<Instant>, // from max_duration
+    idle: Option<Duration>,
+    total_deadline: Option<Instant>,
+    source: Option<(Arc<Backend>, String)>,
+    model: Option<ModelName>,
+    cancel: Option<Arc<Notify>>,
+    usage: UsageTap,
+    prompt_tokens: Option<usize>,
+    recorder: Option<Recorder>,
+    collect_completion: bool, // for the response check, the archive, or a usage estimate
+    completion: String,
+    arguments: String, // tool call arguments sent, for a usage estimate
+    last_chunk: Option<Value>,
+    queue: VecDeque<String>,
+    line_buf: Vec<u8>, // a partial line waiting for the rest of it
+    passthrough: bool,
+    upstream_done: bool,
+    stopped_by: Option<usize>, // the filter that ended the stream
+    ended_early: bool, // before the backend's usage chunk
+    finished: bool,
+    failed: bool,
+    cancelled: bool,
+}
+
+impl StreamState {
+    // Lines are cut straight out of the upstream `Bytes` without copying; only a
+    // line split across two chunks goes through `line_buf`, whose allocation is
+    // reused. UTF-8 is validated per payload rather than per network chunk, so a
+    // multi-byte character split across chunks is handled correctly.
+    fn handle_bytes(&mut self, mut chunk: Bytes) {
+        if !self.line_buf.is_empty() {
+            let Some(end) = line_end(&chunk) else {
+                self.line_buf.extend_from_slice(&chunk);
+                if self.line_buf.len() > MAX_LINE_BYTES {
+                    self.fail(format!("[Gateway Error: Backend sent a line over {} bytes]", MAX_LINE_BYTES));
+                }
+                return;
+            };
+            let mut line_buf = std::mem::take(&mut self.line_buf);
+            line_buf.extend_from_slice(&chunk.split_to(end + 1));
+            self.handle_line(&line_buf);
+            line_buf.clear();
+            self.line_buf = line_buf;
+        }
+
+        while !(self.upstream_done || self.finished) {
+            let Some(end) = line_end(&chunk) else { break };
+            let line = chunk.split_to(end + 1);
+            self.handle_line(&line);
+        }
+
+        if !(self.upstream_done || self.finished) {
+            self.line_buf.extend_from_slice(&chunk);
+        }
+    }
+
+    fn handle_line(&mut self, line: &[u8]) {
+        let line = line.trim_ascii_end();
+        let Some(data) = line.strip_prefix(b"data:") else { return };
+        match std::str::from_utf8(data) {
+            Ok(data) => self.handle_data(data.trim()),
+            Err(e) => {
+                let err_msg = format!("[Gateway Error: Non-UTF8 data received: {}]", e);
+                error!("{}", err_msg);
+                self.queue.push_back(err_msg);
+            }
+        }
+    }
+
+    fn fail(&mut self, err_msg: String) {
+        error!("{}", err_msg);
+        self.queue.push_back(err_msg);
+        self.finished = true;
+        self.failed = true;
+    }
+
+    fn handle_data(&mut self, data: &str) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.capture_chunk(data);
+            recorder.trace_payload(Direction::Upstream, data);
+        }
+        if data == "[DONE]" {
+            self.upstream_done = true;
+            return;
+        }
+        // vLLM sends nothing until the first token is ready.
+        if let Some(recorder) = &mut self.recorder {
+            recorder.first_token();
+        }
+
+        // With nobody looking at chunks there is no need to parse and re-encode them.
+        if self.passthrough && !usage::mentions_usage(data) && self.model.as_ref().is_none_or(|m| m.unchanged(data)) {
+            self.queue.push_back(data.to_string());
+            return;
+        }
+
+        // Anything that isn't a JSON chunk is passed through untouched.
+        let Ok(mut chunk) = serde_json::from_str::<Value>(data) else {
+            self.queue.push_back(data.to_string());
+            return;
+        };
+        if let (Some(name), Some(model)) = (&self.model, chunk.get_mut("model")) {
+            *model = name.value.clone();
+        }
+
+        if !self.usage.observe(&mut chunk) {
+            return;
+        }
+        if let Some(resume) = &mut self.resume {
+            resume.observe(&mut chunk);
+        }
+
+        // The filters after one that stops the stream still see its last chunk,
+        // whose finish_reason makes them release what they hold back.
+        let mut stop = None;
+        for (i, filter) in self.filters.iter_mut().enumerate() {
+            if let Verdict::Stop = filter.on_chunk(&mut chunk) {
+                stop = stop.or(Some(i));
+            }
+        }
+
+        self.collect(&mut chunk);
+
+        self.queue.push_back(chunk.to_string());
+        self.last_chunk = Some(chunk);
+        if stop.is_some() {
+            self.stopped_by = stop;
+            self.end_early();
+        }
+    }
+
+    fn collect(&mut self, chunk: &mut Value) {
+        if !self.collect_completion {
+            return;
+        }
+        for (_, content, _) in delta_contents_mut(chunk) {
+            self.completion.push_str(content);
+        }
+        if self.prompt_tokens.is_some() {
+            self.arguments.extend(tool_arguments(chunk));
+        }
+    }
+
+    // Closes the backend connection; the stream then ends through `finish` as if
+    // the backend had sent `[DONE]`, with usage counted by the gateway.
+    fn end_early(&mut self) {
+        self.upstream = Box::pin(stream::empty());
+        self.line_buf.clear();
+        self.upstream_done = true;
+        self.ended_early = true;
+    }
+
+    // Ends a stream that ran past `max_duration`: the backend connection is closed
+    // and the client gets a final `length` chunk saying why.
+    fn cut_off(&mut self) {
+        let limit = self.max_duration.unwrap_or_default().as_secs();
+        warn!("Stream for model '{}' exceeded its {}s limit; ending it", self.usage.model, limit);
+        self.end_early();
+        let mut chunk = self.last_chunk.clone()
+            .unwrap_or_else(|| json!({ "object": "chat.completion.chunk", "model": self.usage.model }));
+        chunk["choices"] = json!([{ "index": 0, "delta": {}, "finish_reason": "length" }]);
+        chunk["gateway"] = json!({
+            "event": "max_stream_duration",
+            "message": format!("The gateway ended this generation after {} seconds.", limit),
+        });
+        self.queue.push_back(chunk.to_string());
+    }
+
+    // Closes the backend connection and ends the client's stream.
+    fn cancel(&mut self) {
+        info!("Stream for model '{}' cancelled", self.usage.model);
+        self.upstream = Box::pin(stream::empty());
+        self.line_buf.clear();
+        self.queue.push_back("[Gateway Error: Request cancelled]".to_string());
+        self.finished = true;
+        self.cancelled = true;
+    }
+
+    // The backend kept the connection open but stopped sending (e.g. a hung
+    // engine). Its replica is avoided for a while, and the stream is resumed
+    // elsewhere if possible.
+    async fn stalled(&mut self) {
+        let idle_ms = self.idle.unwrap_or_default().as_millis() as u64;
+        if let Some((backend, replica)) = &self.source {
+            let replica = self.resume.as_ref().map_or(replica.as_str(), Resume::replica);
+            warn!("Stream for model '{}' from {} stalled for {}ms", self.usage.model, replica, idle_ms);
+            backend.mark_suspect(replica);
+            if let Some(recorder) = &self.recorder {
+                recorder.stalled(replica, idle_ms);
+            }
+        }
+        self.upstream = Box::pin(stream::empty());
+        let reason = format!("no chunk for {}ms", idle_ms);
+        if self.resume(&reason).await {
+            return;
+        }
+        self.fail(format!("[Gateway Error: Backend stalled: {}]", reason));
+    }
+
+    // Swaps in a continuation stream if resuming is enabled and still possible.
+    async fn resume(&mut self, reason: &str) -> bool {
+        let Some(resume) = self.resume.as_mut().filter(|r| r.can_resume()) else { return false };
+        match resume.reconnect(reason).await {
+            Some(upstream) => {
+                self.upstream = upstream;
+                self.line_buf.clear();
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn finish(&mut self) {
+        // Flushed text still goes through the filters after the one holding it. If
+        // one of them stops the stream, nothing more is flushed. Text held before
+        // the filter that stopped the stream came after the stop, so it is dropped.
+        let mut stopped = false;
+        for i in self.stopped_by.map_or(0, |i| i + 1)..self.filters.len() {
+            for mut chunk in self.filters[i].finish() {
+                if stopped {
+                    break;
+                }
+                for later in &mut self.filters[i + 1..] {
+                    if let Verdict::Stop = later.on_chunk(&mut chunk) {
+                        stopped = true;
+                        break;
+                    }
+                }
+                self.collect(&mut chunk);
+                self.queue.push_back(chunk.to_string());
+            }
+        }
+
+        if let Some(check) = &self.response_check {
+            let results = futures::future::join_all(
+                check.guardrails.iter().map(|g| g.check_response(&check.request, &self.completion)),
+            ).await;
+            let mut flagged = false;
+            for (guardrail, result) in check.guardrails.iter().zip(results) {
+                match result {
+                    Ok(Decision::Block { reason }) => {
+                        warn!(
+                            "Completion for model '{}' flagged by guardrail '{}': {}",
+                            check.request.model, guardrail.name(), reason
+                        );
+                        flagged = true;
+                    }
+                    Ok(Decision::Allow) => {}
+                    Err(e) => error!("Post-response guardrail '{}' failed: {}", guardrail.name(), e),
+                }
+            }
+            if flagged {
+                let mut chunk = self.last_chunk.clone().unwrap_or_else(|| json!({ "object": "chat.completion.chunk" }));
+                chunk["choices"] = json!([{ "index": 0, "delta": {}, "finish_reason": "content_filter" }]);
+                self.queue.push_back(chunk.to_string());
+            }
+        }
+
+        // The backend's usage chunk never came, so the prompt (as counted before
+        // sending) and the text actually sent are counted instead.
+        if self.ended_early && self.usage.usage.is_none() {
+            if let (Some(prompt_tokens), Some((backend, _))) = (self.prompt_tokens, &self.source) {
+                let completion_tokens = backend.tokenizer.count_text(&self.completion) + backend.tokenizer.count_text(&self.arguments);
+                if let Some(chunk) = self.usage.estimate(prompt_tokens as u64, completion_tokens as u64, self.last_chunk.as_ref()) {
+                    self.queue.push_back(chunk.to_string());
+                }
+            }
+        }
+
+        if let Some(usage) = &self.usage.usage {
+            info!(
+                "Stream for model '{}' completed: {} prompt{} + {} completion tokens{}",
+                self.usage.model, usage.prompt_tokens,
+                self.usage.cached_tokens.filter(|&n| n > 0).map(|n| format!(" ({} cached)", n)).unwrap_or_default(),
+                usage.completion_tokens,
+                self.usage.cost().map(|c| format!(", cost ${:.6}", c)).unwrap_or_default()
+            );
+        }
+
+        self.queue.push_back("[DONE]".to_string());
+        self.finished = true;
+    }
+}
+
+// The request is logged when the stream goes away, however that happens.
+impl Drop for StreamState {
+    fn drop(&mut self) {
+        if let Some(mut recorder) = self.recorder.take() {
+            recorder.set_completion(std::mem::take(&mut self.completion));
+            recorder.set_cached_tokens(self.usage.cached_tokens);
+            let status = match (self.failed, self.finished) {
+                _ if self.cancelled => 499,
+                (true, _) => 502,
+                (false, true) => 200,
+                (false, false) => 499,
+            };
+            recorder.finish(status, self.usage.usage, self.usage.cost());
+        }
+    }
+}
+
+// Never resolves without a deadline.
+async fn until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+pub fn stream_response(
+    upstream: UpstreamBody,
+    filters: Vec<Box<dyn ChunkFilter>>,
+    response_check: Option<ResponseCheck>,
+    resume: Option<Resume>,
+    control: StreamControl,
+    usage: UsageTap,
+    recorder: Recorder,
+) -> PayloadStream {
+    let collect_completion = response_check.is_some() || recorder.wants_content() || control.prompt_tokens.is_some();
+    // A stream that may be cut off keeps its last chunk, to base the final one on.
+    let passthrough = filters.is_empty() && !collect_completion && resume.is_none() && control.max_duration.is_none();
+    let state = StreamState {
+        upstream,
+        filters,
+        response_check,
+        resume,
+        drop_after: control.drop_after,
+        max_duration: control.max_duration,
+        deadline: control.max_duration.map(|limit| Instant::now() + limit),
+        idle: control.idle,
+        total_deadline: control.deadline,
+        source: control.source,
+        model: control.model.map(ModelName::new),
+        cancel: recorder.cancellation(),
+        usage,
+        prompt_tokens: control.prompt_tokens,
+        recorder: Some(recorder),
+        collect_completion,
+        completion: String::new(),
+        arguments: String::new(),
+        last_chunk: None,
+        queue: VecDeque::new(),
+        line_buf: Vec::new(),
+        passthrough,
+        upstream_done: false,
+        stopped_by: None,
+        ended_early: false,
+        finished: false,
+        failed: false,
+        cancelled: false,
+    };
+
+    // Dropping the state (after a filter stops the stream, or when the client goes
+    // away) drops the upstream body, which closes the backend connection.
+    let stream = stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(payload) = state.queue.pop_front() {
+                if let Some(recorder) = &mut state.recorder {
+                    recorder.trace_payload(Direction::Client, &payload);
+                }
+                return Some((payload, state));
+            }
+            if state.finished {
+                return None;
+            }
+            if state.upstream_done {
+                state.finish().await;
+                continue;
+            }
+
+            // Injected the same way a broken backend connection shows up.
+            if state.drop_after == Some(0) {
+                state.drop_after = None;
+                warn!("Chaos: dropping stream for model '{}'", state.usage.model);
+                if state.resume("stream dropped (chaos)").await {
+                    continue;
+                }
+                state.fail("[Gateway Error: Could not read chunk from backend: stream dropped (chaos)]".to_string());
+                continue;
+            }
+
+            let cancel = state.cancel.clone();
+            let idle = state.idle.map(|idle| Instant::now() + idle);
+            let next = tokio::select! {
+                next = state.upstream.next() => next,
+                _ = until(state.deadline) => {
+                    state.cut_off();
+                    continue;
+                }
+                _ = until(state.total_deadline) => {
+                    warn!("Upstream request for model '{}' passed its total timeout", state.usage.model);
+                    state.fail("[Gateway Error: Backend request exceeded its total timeout]".to_string());
+                    continue;
+                }
+                _ = until(idle) => {
+                    state.stalled().await;
+                    continue;
+                }
+                _ = async { cancel.as_ref().unwrap().notified().await }, if cancel.is_some() => {
+                    state.cancel();
+                    continue;
+                }
+            };
+            match next {
+                Some(Ok(chunk)) => {
+                    if let Some(left) = &mut state.drop_after {
+                        *left -= 1;
+                    }
+                    state.handle_bytes(chunk);
+                }
+                Some(Err(e)) => {
+                    if state.resume(&e.to_string()).await {
+                        continue;
+                    }
+                    state.fail(format!("[Gateway Error: Could not read chunk from backend: {}]", e));
+                }
+                // The backend closed without `[DONE]`; unless that can be resumed,
+                // still flush held-back text.
+                None => {
+                    if !state.line_buf.is_empty() {
+                        let line = std::mem::take(&mut state.line_buf);
+                        state.handle_line(&line);
+                    }
+                    if !(state.upstream_done || state.finished || state.resume("connection closed before [DONE]").await) {
+                        state.upstream_done = true;
+                    }
+                }
+            }
+        }
+    });
+
+    Box::pin(stream)
+}
+
+// --- Byte Passthrough ---
+// For non-chat endpoints whose responses are forwarded as is (speech audio, image
+// events, ...). The body is cut off if the backend goes quiet for longer than its
+// idle timeout or runs past its total timeout. The request is logged when the
+// body ends, or as 499 if the client goes away first.
+pub fn passthrough(body: UpstreamBody, timeouts: &Timeouts, recorder: Recorder) -> impl Stream<Item = io::Result<Bytes>> {
+    let idle = timeouts.idle();
+    let deadline = timeouts.total().map(|total| Instant::now() + total);
+    let log = LogOnDrop { recorder: Some(recorder), status: 499 };
+    stream::unfold(Some((body, log)), move |state| async move {
+        let (mut body, mut log) = state?;
+        let limit = idle.map(|idle| Instant::now() + idle).into_iter().chain(deadline).min();
+        let next = async { body.next().await.map(|chunk| chunk.map_err(io::Error::other)) };
+        let next = match limit {
+            Some(limit) => tokio::time::timeout_at(limit, next).await
+                .unwrap_or_else(|_| Some(Err(io::Error::new(io::ErrorKind::TimedOut, "backend timed out")))),
+            None => next.await,
+        };
+        match next {
+            Some(Ok(chunk)) => {
+                if let Some(recorder) = &mut log.recorder {
+                    recorder.first_token();
+                }
+                Some((Ok(chunk), Some((body, log))))
+            }
+            Some(Err(e)) => {
+                log.status = if e.kind() == io::ErrorKind::TimedOut { 504 } else { 502 };
+                Some((Err(e), None))
+            }
+            None => {
+                log.status = 200;
+                None
+            }
+        }
+    })
+}
+
+struct LogOnDrop {
+    recorder: Option<Recorder>,
+    status: u16,
+}
+
+impl Drop for LogOnDrop {
+    fn drop(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish(self.status, None, None);
+        }
+    }
+}
+
+// --- Non-Streaming Responses ---
+// Folds a finished stream into the `chat.completion` object a non-streaming
+// request would have returned. A gateway error anywhere in the stream fails the
+// whole response with its text.
+pub async fn collect(mut payloads: PayloadStream) -> Result<Value, String> {
+    let mut completion = json!({ "object": "chat.completion", "choices": [] });
+    let mut choices: Vec<Value> = Vec::new();
+    while let Some(payload) = payloads.next().await {
+        if payload == "[DONE]" {
+            break;
+        }
+        let Ok(chunk) = serde_json::from_str::<Value>(&payload) else { return Err(payload) };
+        for field in ["id", "created", "model", "system_fingerprint"] {
+            if let Some(value) = chunk.get(field).filter(|_| completion.get(field).is_none()) {
+                completion[field] = value.clone();
+            }
+        }
+        if let Some(usage) = chunk.get("usage").filter(|u| u.is_object()) {
+            completion["usage"] = usage.clone();
+        }
+        for delta_choice in chunk.get("choices").and_then(Value::as_array).into_iter().flatten() {
+            let index = delta_choice.get("index").and_then(Value::as_u64).unwrap_or(0) as usize;
+            while choices.len() <= index {
+                let index = choices.len();
+                choices.push(json!({
+                    "index": index,
+                    "message": { "role": "assistant", "content": null },
+                    "finish_reason": null,
+                }));
+            }
+            let choice = &mut choices[index];
+            if let Some(reason) = delta_choice.get("finish_reason").filter(|r| !r.is_null()) {
+                choice["finish_reason"] = reason.clone();
+            }
+            let Some(delta) = delta_choice.get("delta") else { continue };
+            let message = &mut choice["message"];
+            if let Some(content) = delta.get("content").and_then(Value::as_str) {
+                let text = message["content"].as_str().unwrap_or_default().to_string() + content;
+                message["content"] = Value::String(text);
+            }
+            for call in delta.get("tool_calls").and_then(Value::as_array).into_iter().flatten() {
+                merge_tool_call(message, call);
+            }
+        }
+    }
+    // Deltas are matched up by `index`, which complete tool calls don't have.
+    for call in choices.iter_mut().filter_map(|c| c["message"].get_mut("tool_calls")?.as_array_mut()).flatten() {
+        if let Some(call) = call.as_object_mut() {
+            call.remove("index");
+        }
+    }
+    completion["choices"] = Value::Array(choices);
+    Ok(completion)
+}
+
+// Tool call deltas carry the id and name once, then the arguments in pieces.
+fn merge_tool_call(message: &mut Value, delta: &Value) {
+    let index = delta.get("index").and_then(Value::as_u64).unwrap_or(0);
+    if !message["tool_calls"].is_array() {
+        message["tool_calls"] = json!([]);
+    }
+    let calls = message["tool_calls"].as_array_mut().unwrap();
+    let position = match calls.iter().position(|call| call["index"] == index) {
+        Some(position) => position,
+        None => {
+            calls.push(json!({ "index": index, "type": "function", "function": { "name": "", "arguments": "" } }));
+            calls.len() - 1
+        }
+    };
+    let call = &mut calls[position];
+    if let Some(id) = delta.get("id") {
+        call["id"] = id.clone();
+    }
+    let function = &mut call["function"];
+    for field in ["name", "arguments"] {
+        if let Some(piece) = delta.pointer(&format!("/function/{}", field)).and_then(Value::as_str) {
+            let text = function[field].as_str().unwrap_or_default().to_string() + piece;
+            function[field] = Value::String(text);
+        }
+    }
+}
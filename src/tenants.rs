@@ -0,0 +1,212 @@
+use anyhow::{Context, Result};
+use axum::http::HeaderMap;
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tracing::info;
+
+use crate::{
+    auth::Caller,
+    backend::{Backend, BackendLoader, Backends},
+    config, discovery,
+    log_content::LogContent,
+    params::ParamDefaults,
+    AppError, AppState,
+};
+
+pub const HEADER: &str = "x-tenant-id";
+
+// --- Tenants ---
+// TENANTS maps a tenant name to its own models, so several teams can share one
+// gateway. A request's tenant is its API key's `tenant`, or, with authentication
+// disabled, the X-Tenant-ID header. For that tenant, `aliases` rename requested
+// models first (e.g. "gpt-4o" -> "llama-3-70b"), then the tenant's `backends`
+// (same format as VLLM_BACKENDS) are searched before the gateway's, which an
+// `isolated` tenant cannot see at all.
+#[derive(Debug, Deserialize)]
+pub struct TenantConfig {
+    #[serde(default)]
+    pub backends: HashMap<String, Value>,
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+    #[serde(default)]
+    pub defaults: ParamDefaults, // applied before the model's own defaults
+    #[serde(default)]
+    pub isolated: bool,
+    #[serde(default)]
+    pub quota: TenantQuota,
+    #[serde(default = "crate::auth::default_weight")]
+    pub weight: f64, // share of a saturated backend's slots
+    #[serde(default)]
+    pub log_content: Option<LogContent>, // for the archive and capture; stricter than theirs only
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TenantQuota {
+    #[serde(default)]
+    pub requests_per_minute: Option<u64>,
+    #[serde(default)]
+    pub tokens_per_day: Option<u64>, // prompt + completion, UTC days
+}
+
+pub struct Tenant {
+    pub name: String,
+    pub backends: Backends,
+    pub aliases: HashMap<String, String>,
+    pub defaults: ParamDefaults,
+    pub isolated: bool,
+    quota: TenantQuota,
+    pub weight: f64,
+    pub log_content: Option<LogContent>,
+    usage: Mutex<QuotaUsage>,
+}
+
+#[derive(Default)]
+struct QuotaUsage {
+    minute: i64,
+    requests: u64,
+    day: Option<NaiveDate>,
+    tokens: u64,
+}
+
+impl Tenant {
+    pub fn resolve_alias(&self, model: &str) -> Option<&String> {
+        self.aliases.get(model)
+    }
+
+    // Counts the request against the per-minute quota, unless either quota is used up.
+    pub fn admit(&self) -> Result<(), AppError> {
+        let now = Utc::now();
+        let mut usage = self.usage.lock().unwrap();
+        if usage.day != Some(now.date_naive()) {
+            usage.day = Some(now.date_naive());
+            usage.tokens = 0;
+        }
+        if let Some(limit) = self.quota.tokens_per_day.filter(|limit| usage.tokens >= *limit) {
+            return Err(AppError::QuotaExceeded {
+                message: format!("Tenant '{}' has used its {} tokens for today.", self.name, limit),
+                resets_soon: false,
+            });
+        }
+        let minute = now.timestamp() / 60;
+        if usage.minute != minute {
+            usage.minute = minute;
+            usage.requests = 0;
+        }
+        if let Some(limit) = self.quota.requests_per_minute.filter(|limit| usage.requests >= *limit) {
+            return Err(AppError::QuotaExceeded {
+                message: format!("Tenant '{}' is limited to {} requests per minute.", self.name, limit),
+                resets_soon: true,
+            });
+        }
+        usage.requests += 1;
+        Ok(())
+    }
+
+    pub fn record_tokens(&self, tokens: u64) {
+        let today = Utc::now().date_naive();
+        let mut usage = self.usage.lock().unwrap();
+        if usage.day != Some(today) {
+            usage.day = Some(today);
+            usage.tokens = 0;
+        }
+        usage.tokens += tokens;
+    }
+}
+
+#[derive(Default)]
+pub struct Tenants(HashMap<String, Arc<Tenant>>);
+
+impl Tenants {
+    pub fn from_env(loader: &BackendLoader) -> Result<Self> {
+        let configs: HashMap<String, TenantConfig> = config::env_json("TENANTS")?.unwrap_or_default();
+        let mut tenants = HashMap::new();
+        for (name, tenant) in configs {
+            info!("Tenant '{}': {} model(s), {} alias(es)", name, tenant.backends.len(), tenant.aliases.len());
+            let mut backends = Backends::new();
+            for (model, entry) in tenant.backends {
+                let config = config::parse_backend(entry)
+                    .with_context(|| format!("Invalid backend '{}' for tenant '{}'", model, name))?;
+                backends.insert(model.clone(), Arc::new(loader.load(&model, config)?));
+            }
+            discovery::start(&backends)?;
+            tenants.insert(name.clone(), Arc::new(Tenant {
+                name,
+                backends,
+                aliases: tenant.aliases,
+                defaults: tenant.defaults,
+                isolated: tenant.isolated,
+                quota: tenant.quota,
+                weight: tenant.weight,
+                log_content: tenant.log_content,
+                usage: Mutex::new(QuotaUsage::default()),
+            }));
+        }
+        Ok(Tenants(tenants))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<Tenant>> {
+        self.0.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Tenant>> {
+        self.0.values()
+    }
+
+    // The header only counts without API keys; a key's tenant is fixed by its config.
+    pub fn named_in(&self, headers: &HeaderMap) -> Result<Option<Arc<Tenant>>, AppError> {
+        let Some(name) = headers.get(HEADER).and_then(|v| v.to_str().ok()).map(str::trim) else { return Ok(None) };
+        self.get(name).cloned().map(Some)
+            .ok_or_else(|| AppError::InvalidRequest(format!("Unknown tenant '{}' in X-Tenant-ID.", name)))
+    }
+}
+
+// The backend serving `model` for this caller.
+pub fn backend_for(state: &AppState, caller: &Caller, model: &str) -> Option<Arc<Backend>> {
+    match caller.tenant() {
+        Some(tenant) => tenant.backends.get(model).cloned()
+            .or_else(|| (!tenant.isolated).then(|| state.vllm_backends.get(model)).flatten()),
+        None => state.vllm_backends.get(model),
+    }
+}
+
+// The backend serving `model` from one of the per-endpoint tables (AUDIO_BACKENDS,
+// ...), after the key's and tenant's aliases and the key's model restrictions. Isolated
+// tenants can't reach these tables. The request counts against the tenant's quota.
+pub fn route_in(caller: &Caller, backends: &Backends, model: &str) -> Result<(String, Arc<Backend>), AppError> {
+    let model = caller.resolve_alias(model).map_or(model, String::as_str).to_string();
+    caller.authorize_model(&model)?;
+    let backend = backends.get(&model)
+        .filter(|_| !caller.tenant().is_some_and(|tenant| tenant.isolated))
+        .cloned()
+        .ok_or_else(|| AppError::ModelNotFound(model.clone()))?;
+    if let Some(tenant) = caller.tenant() {
+        tenant.admit()?;
+    }
+    Ok((model, backend))
+}
+
+// Every model this caller's tenant can reach, its own shadowing the gateway's.
+pub fn visible_backends(state: &AppState, caller: &Caller) -> Backends {
+    let mut backends = match caller.tenant() {
+        Some(tenant) if tenant.isolated => Backends::new(),
+        _ => (*state.vllm_backends.snapshot()).clone(),
+    };
+    if let Some(tenant) = caller.tenant() {
+        backends.extend(tenant.backends.iter().map(|(model, backend)| (model.clone(), backend.clone())));
+    }
+    backends
+}
+
+// The caller's key and tenant aliases, as (alias, target); the key's win.
+pub fn aliases(caller: &Caller) -> Vec<(&String, &String)> {
+    let own = caller.key().map(|key| &key.aliases);
+    let inherited = caller.tenant().into_iter()
+        .flat_map(|tenant| tenant.aliases.iter())
+        .filter(|(alias, _)| !own.is_some_and(|own| own.contains_key(*alias)));
+    own.into_iter().flatten().chain(inherited).collect()
+}
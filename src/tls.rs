@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use axum_server::tls_rustls::RustlsConfig;
+use std::{path::PathBuf, time::{Duration, SystemTime}};
+use tracing::{error, info};
+
+// --- TLS Listener Configuration ---
+// Enabled when both TLS_CERT_PATH and TLS_KEY_PATH are set. With
+// TLS_RELOAD_INTERVAL_SECS, the files are polled and reloaded in place when either
+// changes, so rotated certificates are picked up without dropping connections.
+pub struct TlsSettings {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    pub reload_interval: Option<Duration>,
+}
+
+impl TlsSettings {
+    pub fn from_env() -> Result<Option<Self>> {
+        let cert = std::env::var("TLS_CERT_PATH").ok().filter(|s| !s.is_empty());
+        let key = std::env::var("TLS_KEY_PATH").ok().filter(|s| !s.is_empty());
+        let (cert_path, key_path) = match (cert, key) {
+            (Some(cert), Some(key)) => (PathBuf::from(cert), PathBuf::from(key)),
+            (None, None) => return Ok(None),
+            _ => anyhow::bail!("TLS_CERT_PATH and TLS_KEY_PATH must be set together"),
+        };
+
+        let reload_interval = match std::env::var("TLS_RELOAD_INTERVAL_SECS") {
+            Ok(raw) => {
+                let secs: u64 = raw.parse().context("Invalid TLS_RELOAD_INTERVAL_SECS")?;
+                (secs > 0).then(|| Duration::from_secs(secs))
+            }
+            Err(_) => None,
+        };
+
+        Ok(Some(TlsSettings { cert_path, key_path, reload_interval }))
+    }
+
+    pub async fn load(&self) -> Result<RustlsConfig> {
+        // rustls needs a process-wide crypto provider; ring avoids a C/cmake toolchain.
+        let _ = rustls::crypto::ring::default_provider().install_default();
+
+        let config = RustlsConfig::from_pem_file(&self.cert_path, &self.key_path)
+            .await
+            .with_context(|| format!(
+                "Failed to load TLS certificate '{}' / key '{}'",
+                self.cert_path.display(), self.key_path.display()
+            ))?;
+
+        if let Some(interval) = self.reload_interval {
+            spawn_reload_watcher(config.clone(), self.cert_path.clone(), self.key_path.clone(), interval);
+        }
+        Ok(config)
+    }
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn spawn_reload_watcher(config: RustlsConfig, cert_path: PathBuf, key_path: PathBuf, interval: Duration) {
+    tokio::spawn(async move {
+        let mut last_seen = (modified(&cert_path), modified(&key_path));
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            let current = (modified(&cert_path), modified(&key_path));
+            if current == last_seen {
+                continue;
+            }
+
+            // A failed reload (e.g. cert written before its key) keeps serving the
+            // previous certificate and is retried on the next tick.
+            match config.reload_from_pem_file(&cert_path, &key_path).await {
+                Ok(()) => {
+                    info!("Reloaded TLS certificate from '{}'", cert_path.display());
+                    last_seen = current;
+                }
+                Err(e) => error!("Failed to reload TLS certificate, keeping the previous one: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,81 @@
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use tiktoken_rs::CoreBPE;
+
+use crate::ChatMessage;
+
+// --- Tokenizer Configuration ---
+// Models without an explicit tokenizer are counted with tiktoken's cl100k_base,
+// which is close enough for budgeting but not exact for non-OpenAI vocabularies.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TokenizerConfig {
+    Tiktoken {
+        #[serde(default = "default_encoding")]
+        encoding: String,
+    },
+    Huggingface {
+        path: String,
+    },
+}
+
+fn default_encoding() -> String {
+    "cl100k_base".to_string()
+}
+
+pub enum Tokenizer {
+    Tiktoken(&'static CoreBPE),
+    HuggingFace(Box<tokenizers::Tokenizer>),
+}
+
+// Chat formatting overhead, following OpenAI's published accounting: each message
+// is wrapped in a few control tokens and the reply is primed with a few more.
+const TOKENS_PER_MESSAGE: usize = 3;
+const TOKENS_PER_NAME: usize = 1;
+const TOKENS_FOR_REPLY_PRIMING: usize = 3;
+
+impl Tokenizer {
+    pub fn load(config: Option<&TokenizerConfig>) -> Result<Self> {
+        match config {
+            None => Ok(Tokenizer::Tiktoken(tiktoken_rs::cl100k_base_singleton())),
+            Some(TokenizerConfig::Tiktoken { encoding }) => {
+                let bpe = match encoding.as_str() {
+                    "cl100k_base" => tiktoken_rs::cl100k_base_singleton(),
+                    "o200k_base" => tiktoken_rs::o200k_base_singleton(),
+                    "p50k_base" => tiktoken_rs::p50k_base_singleton(),
+                    "r50k_base" => tiktoken_rs::r50k_base_singleton(),
+                    other => bail!("Unknown tiktoken encoding '{}'", other),
+                };
+                Ok(Tokenizer::Tiktoken(bpe))
+            }
+            Some(TokenizerConfig::Huggingface { path }) => {
+                let tokenizer = tokenizers::Tokenizer::from_file(path)
+                    .map_err(|e| anyhow!("Failed to load tokenizer from '{}': {}", path, e))?;
+                Ok(Tokenizer::HuggingFace(Box::new(tokenizer)))
+            }
+        }
+    }
+
+    pub fn count_text(&self, text: &str) -> usize {
+        match self {
+            Tokenizer::Tiktoken(bpe) => bpe.count_ordinary(text),
+            // Encoding only fails on pathological inputs; fall back to a rough estimate
+            // rather than letting a counting problem reject the request.
+            Tokenizer::HuggingFace(tokenizer) => tokenizer
+                .encode_fast(text, false)
+                .map(|encoding| encoding.len())
+                .unwrap_or(text.len() / 4),
+        }
+    }
+
+    pub fn count_messages(&self, messages: &[ChatMessage]) -> usize {
+        let mut total = TOKENS_FOR_REPLY_PRIMING;
+        for message in messages {
+            total += TOKENS_PER_MESSAGE + self.count_text(&message.role) + self.count_text(&message.content.text());
+            if let Some(name) = &message.name {
+                total += TOKENS_PER_NAME + self.count_text(name);
+            }
+        }
+        total
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use crate::{auth::Caller, config, log_content::LogContent, redaction::Redactor, AppError, AppState};
+
+pub const HEADER: &str = "x-gateway-trace";
+
+// --- Stream Traces ---
+// A request sent with `X-Gateway-Trace: true` keeps every payload of its stream,
+// both as the backend sent it and as the client received it, with the time in ms
+// since the gateway received the request. The last TRACE_CAPACITY traces are
+// kept in memory for GET /admin/requests/{id}/trace. Only keys with `trace` may
+// send the header, and a tenant's `log_content` other than `full` applies to the
+// payloads (`metadata` refuses the header).
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Upstream, // from the backend, before any filter
+    Client,   // to the client
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceEvent {
+    pub ms: u64,
+    pub direction: Direction,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestTrace {
+    pub id: String,
+    pub at: DateTime<Utc>,
+    pub model: String,
+    pub key: Option<String>,
+    pub backend: Option<String>,
+    pub status: u16,
+    pub events: Vec<TraceEvent>,
+}
+
+pub struct TraceStore {
+    traces: Mutex<VecDeque<RequestTrace>>,
+    capacity: usize,
+    redactor: Redactor,
+}
+
+impl TraceStore {
+    pub fn from_env() -> Result<Self> {
+        let capacity = config::env_parse("TRACE_CAPACITY")?.unwrap_or(100);
+        Ok(TraceStore { traces: Mutex::new(VecDeque::new()), capacity, redactor: Redactor::new(Default::default())? })
+    }
+
+    pub fn push(&self, mut trace: RequestTrace, content: LogContent) {
+        for event in &mut trace.events {
+            content.apply_payload(&self.redactor, &mut event.data);
+        }
+        let mut traces = self.traces.lock().unwrap();
+        if traces.len() >= self.capacity {
+            traces.pop_front();
+        }
+        traces.push_back(trace);
+    }
+
+    fn get(&self, id: &str) -> Option<RequestTrace> {
+        self.traces.lock().unwrap().iter().rev().find(|trace| trace.id == id).cloned()
+    }
+}
+
+// Whether to trace this request.
+pub fn requested(caller: &Caller, headers: &HeaderMap) -> Result<bool, AppError> {
+    let Some(value) = headers.get(HEADER) else { return Ok(false) };
+    if value.to_str().map(str::trim).ok() != Some("true") {
+        return Err(AppError::InvalidRequest("X-Gateway-Trace must be 'true'.".to_string()));
+    }
+    if caller.key().is_some_and(|key| !key.trace) {
+        return Err(AppError::Forbidden("This API key may not use the X-Gateway-Trace header.".to_string()));
+    }
+    if caller.tenant().is_some_and(|tenant| tenant.log_content == Some(LogContent::Metadata)) {
+        return Err(AppError::Forbidden("This tenant's requests may not be traced.".to_string()));
+    }
+    Ok(true)
+}
+
+// GET /admin/requests/:id/trace
+#[utoipa::path(
+    get, path = "/admin/requests/{id}/trace", tag = "Admin",
+    params(("id" = String, Path, description = "The request's `x-request-id`.")),
+    responses((status = 200, description = "The request's streamed payloads, in order.", body = Object)),
+)]
+pub async fn get(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Result<Json<RequestTrace>, AppError> {
+    state.traces.get(&id).map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("No trace is kept for request '{}'.", id)))
+}
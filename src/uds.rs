@@ -0,0 +1,78 @@
+use anyhow::{Context, Result};
+use axum::{extract::ConnectInfo, Extension, Router};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder,
+    service::TowerToHyperService,
+};
+use std::{
+    net::SocketAddr,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+use tokio::net::UnixListener;
+use tracing::{info, warn};
+
+// --- Unix Sockets ---
+// GATEWAY_LISTEN_ADDR=unix:/path/to/gateway.sock serves the gateway on a Unix
+// socket instead of TCP, for a sidecar next to an app server; file permissions
+// (GATEWAY_SOCKET_MODE, octal, e.g. 660) then decide who may connect. A stale
+// socket file from an earlier run is replaced. Clients on the socket count as
+// 127.0.0.1 for IP_ACCESS. A replica URL of `unix:/path/to/vllm.sock` reaches a
+// backend listening on that socket: its requests go straight to the socket, so
+// the socket's permissions keep deciding who may reach the backend.
+pub const SCHEME: &str = "unix:";
+
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl ListenAddr {
+    pub fn parse(addr: &str) -> Result<Self> {
+        if let Some(path) = socket_path(addr) {
+            return Ok(ListenAddr::Unix(path));
+        }
+        let addr = addr.parse().with_context(|| format!("Invalid GATEWAY_LISTEN_ADDR format: {}", addr))?;
+        Ok(ListenAddr::Tcp(addr))
+    }
+}
+
+// The path in `unix:/path` or `unix:///path`.
+pub fn socket_path(url: &str) -> Option<PathBuf> {
+    let path = url.strip_prefix(SCHEME)?;
+    let path = path.strip_prefix("//").unwrap_or(path);
+    Some(PathBuf::from(path))
+}
+
+pub async fn serve(path: &Path, app: Router) -> Result<()> {
+    if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        std::fs::remove_file(path).with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path).with_context(|| format!("Failed to bind to socket {}", path.display()))?;
+    if let Ok(mode) = std::env::var("GATEWAY_SOCKET_MODE") {
+        let mode = u32::from_str_radix(mode.trim(), 8).with_context(|| format!("Invalid GATEWAY_SOCKET_MODE '{}'; use octal, e.g. 660", mode))?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to set permissions on {}", path.display()))?;
+    }
+    info!("🚀 Gateway listening on {}{}", SCHEME, path.display());
+
+    let app = app.layer(Extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0)))));
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Failed to accept a connection on {}: {}", path.display(), e);
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            // Upgrades carry the WebSocket and Realtime routes.
+            if let Err(e) = Builder::new(TokioExecutor::new()).serve_connection_with_upgrades(TokioIo::new(stream), service).await {
+                warn!("Unix socket connection failed: {}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,185 @@
+use futures::{stream, StreamExt};
+use reqwest::{header::{HeaderMap, CONTENT_TYPE, RETRY_AFTER}, RequestBuilder, StatusCode};
+use std::{future::Future, time::Duration};
+use tracing::{info, warn};
+
+use crate::{backend::Backend, chaos::ChaosConfig, headers::HeaderPolicy, mock, stream::UpstreamBody, AppError, ChatRequest};
+
+// --- Upstream Connection ---
+// An accepted (2xx) streaming response from one replica.
+pub struct Upstream {
+    pub replica: String, // base URL of the replica that answered
+    pub headers: HeaderMap,
+    pub body: UpstreamBody,
+}
+
+// Opens the chat stream, starting on replica `first`. `build` adds the headers
+// to each attempt's outbound request, so every attempt carries the same ones.
+//
+// With `hedge_after_ms` and more than one replica, a second replica is raced if
+// the first hasn't sent anything by then. Whichever streams first wins and the
+// other request is dropped, which closes its connection and aborts the generation.
+pub async fn open(
+    backend: &Backend,
+    first: String,
+    chaos: Option<&ChaosConfig>,
+    body: &ChatRequest,
+    build: impl Fn(RequestBuilder) -> RequestBuilder,
+) -> Result<Upstream, AppError> {
+    let second = backend.replica_after(&first).filter(|second| *second != first);
+    let (Some(hedge_after), Some(second)) = (backend.config.hedge_after_ms, second) else {
+        return connect(backend, &first, chaos, body, &build).await;
+    };
+
+    let primary = first_chunk(connect(backend, &first, chaos, body, &build));
+    tokio::pin!(primary);
+    if let Ok(result) = tokio::time::timeout(Duration::from_millis(hedge_after), &mut primary).await {
+        return result;
+    }
+
+    info!("No first token from {} after {}ms; hedging to {}", first, hedge_after, second);
+    let secondary = first_chunk(connect(backend, &second, chaos, body, &build));
+    tokio::pin!(secondary);
+
+    // A failure only decides the race once the other attempt has failed too.
+    tokio::select! {
+        result = &mut primary => match result {
+            Ok(upstream) => Ok(upstream),
+            Err(_) => {
+                warn!("Primary attempt on {} failed; waiting for hedge", first);
+                secondary.await
+            }
+        },
+        result = &mut secondary => match result {
+            Ok(upstream) => Ok(upstream),
+            Err(_) => {
+                warn!("Hedged attempt on {} failed; waiting for primary", second);
+                primary.await
+            }
+        },
+    }
+}
+
+// With `first_byte_ms`, the attempt fails unless the replica's first chunk
+// arrives in time.
+pub async fn connect(
+    backend: &Backend,
+    replica: &str,
+    chaos: Option<&ChaosConfig>,
+    body: &ChatRequest,
+    build: &impl Fn(RequestBuilder) -> RequestBuilder,
+) -> Result<Upstream, AppError> {
+    let Some(limit) = backend.timeouts.first_byte() else {
+        return send(backend, replica, chaos, body, build).await;
+    };
+    match tokio::time::timeout(limit, first_chunk(send(backend, replica, chaos, body, build))).await {
+        Ok(result) => result,
+        Err(_) => {
+            warn!("No first chunk from {} within {}ms", replica, limit.as_millis());
+            Err(AppError::UpstreamTimeout {
+                url: replica.to_string(),
+                message: format!("The backend sent nothing within {}ms.", limit.as_millis()),
+            })
+        }
+    }
+}
+
+async fn send(
+    backend: &Backend,
+    replica: &str,
+    chaos: Option<&ChaosConfig>,
+    body: &ChatRequest,
+    build: &impl Fn(RequestBuilder) -> RequestBuilder,
+) -> Result<Upstream, AppError> {
+    if let Some(chaos) = chaos {
+        chaos.before_connect(replica).await?;
+    }
+    if mock::is_mock(replica) {
+        let body = mock::stream_response(replica, backend, body).map_err(|e| AppError::BackendRespondedError {
+            status: StatusCode::INTERNAL_SERVER_ERROR, text: e.to_string(), url: replica.to_string(), retry_after: None,
+        })?;
+        return Ok(Upstream { replica: replica.to_string(), headers: HeaderMap::new(), body });
+    }
+    let renamed;
+    let body = match &backend.config.upstream_model {
+        Some(served) => {
+            renamed = ChatRequest { model: served.clone(), ..body.clone() };
+            &renamed
+        }
+        None => body,
+    };
+    let url = backend.endpoint_url(replica, "chat/completions", &body.model);
+    info!("Routing request for model '{}' to: {}", body.model, url);
+
+    let res = match build(backend.client_for(replica).post(&url)).json(body).send().await {
+        Ok(res) => res,
+        Err(e) => {
+            if e.is_connect() {
+                backend.mark_suspect(replica);
+            }
+            return Err(AppError::BackendRequestFailed(e));
+        }
+    };
+    if !res.status().is_success() {
+        let status = res.status();
+        let retry_after = res.headers().get(RETRY_AFTER).cloned();
+        let text = res.text().await.unwrap_or_else(|_| "No response body".to_string());
+        return Err(AppError::BackendRespondedError { status, text, url, retry_after });
+    }
+
+    let headers = res.headers().clone();
+    Ok(Upstream { replica: replica.to_string(), headers, body: Box::pin(res.bytes_stream()) })
+}
+
+// Resolves once the body has produced its first chunk (vLLM sends nothing until
+// the first token is ready), then puts that chunk back in front of the stream.
+async fn first_chunk(connecting: impl std::future::Future<Output = Result<Upstream, AppError>>) -> Result<Upstream, AppError> {
+    let mut upstream = connecting.await?;
+    if let Some(first) = upstream.body.next().await {
+        let first = first.map_err(AppError::BackendRequestFailed)?;
+        upstream.body = Box::pin(stream::once(async move { Ok(first) }).chain(upstream.body));
+    }
+    Ok(upstream)
+}
+
+// --- Other Endpoints ---
+// Sends a request to one of the non-chat endpoints (speech, images, ...). The
+// backend's `first_byte_ms` bounds the wait for its response headers, and error
+// statuses become AppErrors as for chat.
+pub async fn forward(backend: &Backend, request: RequestBuilder, url: String) -> Result<reqwest::Response, AppError> {
+    let sending = request.send();
+    let res = match backend.timeouts.first_byte() {
+        Some(first_byte) => tokio::time::timeout(first_byte, sending).await.map_err(|_| AppError::UpstreamTimeout {
+            url: url.clone(),
+            message: format!("The backend did not respond within {}ms.", backend.timeouts.first_byte_ms.unwrap_or_default()),
+        })?,
+        None => sending.await,
+    }.map_err(AppError::BackendRequestFailed)?;
+    if !res.status().is_success() {
+        let status = res.status();
+        let retry_after = res.headers().get(RETRY_AFTER).cloned();
+        let text = res.text().await.unwrap_or_else(|_| "No response body".to_string());
+        return Err(AppError::BackendRespondedError { status, text, url, retry_after });
+    }
+    Ok(res)
+}
+
+// Bounds a whole exchange (request and buffered response) by the backend's `total_ms`.
+pub async fn within_total<T>(backend: &Backend, url: &str, exchange: impl Future<Output = Result<T, AppError>>) -> Result<T, AppError> {
+    match backend.timeouts.total() {
+        Some(total) => tokio::time::timeout(total, exchange).await.unwrap_or_else(|_| Err(AppError::UpstreamTimeout {
+            url: url.to_string(),
+            message: format!("The backend request did not complete within {}ms.", backend.timeouts.total_ms.unwrap_or_default()),
+        })),
+        None => exchange.await,
+    }
+}
+
+// The allowlisted response headers, plus the backend's Content-Type.
+pub fn response_headers(policy: &HeaderPolicy, res: &reqwest::Response) -> HeaderMap {
+    let mut headers = policy.forward_response(res.headers());
+    if let Some(content_type) = res.headers().get(CONTENT_TYPE) {
+        headers.insert(CONTENT_TYPE, content_type.clone());
+    }
+    headers
+}
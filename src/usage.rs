@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+// --- Pricing ---
+// Per-backend list prices in USD per million tokens, or per minute of audio for
+// transcription backends.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, ToSchema)]
+pub struct Pricing {
+    #[serde(default)]
+    pub input_per_million: f64,
+    #[serde(default)]
+    pub output_per_million: f64,
+    #[serde(default)]
+    pub per_audio_minute: f64,
+}
+
+impl Pricing {
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        (usage.prompt_tokens as f64 * self.input_per_million
+            + usage.completion_tokens as f64 * self.output_per_million) / 1_000_000.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, ToSchema)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, ToSchema)]
+pub struct StreamOptions {
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+// --- Usage Tap ---
+// The gateway always asks the backend for the final usage chunk so it can account
+// for every stream. It is passed on only if the client asked for it, with the
+// computed `cost` added when the backend has pricing.
+pub struct UsageTap {
+    pub model: String,
+    client_wants_usage: bool,
+    pricing: Option<Pricing>,
+    pub usage: Option<Usage>,
+    pub cached_tokens: Option<u64>, // prompt tokens the backend served from its cache
+}
+
+impl UsageTap {
+    pub fn new(model: String, client_wants_usage: bool, pricing: Option<Pricing>) -> Self {
+        UsageTap { model, client_wants_usage, pricing, usage: None, cached_tokens: None }
+    }
+
+    pub fn cost(&self) -> Option<f64> {
+        Some(self.pricing?.cost(self.usage.as_ref()?))
+    }
+
+    // Returns false if the chunk should not be sent to the client.
+    pub fn observe(&mut self, chunk: &mut Value) -> bool {
+        let Some(raw) = chunk.get_mut("usage").filter(|u| u.is_object()) else { return true };
+        let Ok(usage) = serde_json::from_value::<Usage>(raw.clone()) else { return true };
+        self.usage = Some(usage);
+        self.cached_tokens = crate::prompt_cache::cached_tokens(raw);
+        if let Some(pricing) = &self.pricing {
+            raw["cost"] = Value::from(pricing.cost(&usage));
+        }
+
+        let usage_only = chunk.get("choices").and_then(Value::as_array).is_none_or(|c| c.is_empty());
+        self.client_wants_usage || !usage_only
+    }
+
+    // For a stream the gateway ended before the backend's usage chunk: records the
+    // given counts and returns the usage chunk to send, if the client asked for one.
+    pub fn estimate(&mut self, prompt_tokens: u64, completion_tokens: u64, template: Option<&Value>) -> Option<Value> {
+        let usage = Usage { prompt_tokens, completion_tokens };
+        self.usage = Some(usage);
+        let mut raw = json!({
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "total_tokens": prompt_tokens + completion_tokens,
+        });
+        if let Some(pricing) = &self.pricing {
+            raw["cost"] = Value::from(pricing.cost(&usage));
+        }
+        let mut chunk = template.cloned().unwrap_or_else(|| json!({ "object": "chat.completion.chunk", "model": self.model }));
+        if let Some(fields) = chunk.as_object_mut() {
+            fields.remove("gateway");
+        }
+        chunk["choices"] = json!([]);
+        chunk["usage"] = raw;
+        self.client_wants_usage.then_some(chunk)
+    }
+}
+
+// Cheap pre-check for the passthrough path, which skips JSON parsing otherwise.
+pub fn mentions_usage(data: &str) -> bool {
+    data.contains("\"usage\":{") || data.contains("\"usage\": {")
+}
@@ -0,0 +1,121 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::{backend::Backend, check, mock, uds, AppState};
+
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// --- Startup Verification ---
+// With VERIFY_BACKENDS=warn (or `--verify-backends`), `serve` asks every replica
+// for its `/v1/models` before it starts listening and warns about each model the
+// replica doesn't serve under the name the gateway sends (the model's own name,
+// or its `upstream_model`), so a typo shows up in the log instead of as the
+// first user's 404. `strict` (or `--strict`) refuses to start on a mismatch.
+// Unreachable replicas and ones that don't list their models are only warned
+// about, since pods may still be starting. `check-config --ping` reports the
+// same findings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    #[default]
+    Off,
+    Warn,
+    Strict,
+}
+
+impl Mode {
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("VERIFY_BACKENDS").ok().map(|raw| raw.trim().to_ascii_lowercase()).as_deref() {
+            None | Some("") | Some("off") => Ok(Mode::Off),
+            Some("warn") => Ok(Mode::Warn),
+            Some("strict") => Ok(Mode::Strict),
+            Some(other) => anyhow::bail!("Invalid VERIFY_BACKENDS '{}'; use off, warn, or strict", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    Mismatch,    // the replica answered but doesn't serve the model
+    Unreachable,
+    Unlisted,    // the replica answered /models with an error or something unexpected
+}
+
+pub struct Finding {
+    pub problem: Problem,
+    pub message: String,
+}
+
+pub async fn run(state: &AppState, mode: Mode) -> Result<()> {
+    if mode == Mode::Off {
+        return Ok(());
+    }
+    let findings = verify(state).await;
+    for finding in &findings {
+        warn!("Backend verification: {}", finding.message);
+    }
+    let mismatches = findings.iter().filter(|finding| finding.problem == Problem::Mismatch).count();
+    if mode == Mode::Strict && mismatches > 0 {
+        anyhow::bail!("{} backend(s) don't serve the model they are routed for (VERIFY_BACKENDS=strict)", mismatches);
+    }
+    info!("Backend verification finished with {} warning(s)", findings.len());
+    Ok(())
+}
+
+pub async fn verify(state: &AppState) -> Vec<Finding> {
+    let mut probes = Vec::new();
+    for (table, backends) in check::tables(state) {
+        let mut backends: Vec<_> = backends.into_iter().collect();
+        backends.sort_by(|a, b| a.0.cmp(&b.0));
+        for (model, backend) in backends {
+            for url in backend.replicas.get().iter().filter(|url| !mock::is_mock(url)) {
+                if uds::socket_path(url).is_none() && reqwest::Url::parse(url).is_err() {
+                    continue; // reported by check-config
+                }
+                let (table, model, backend, url) = (table.clone(), model.clone(), backend.clone(), url.clone());
+                probes.push(async move {
+                    let served = backend.served_model(&model).to_string();
+                    let result = list_models(&backend, &url, &served).await;
+                    let label = format!("{}: '{}' replica {}", table, model, url);
+                    match result {
+                        Ok(models) if models.contains(&served) => None,
+                        Ok(models) => {
+                            let close = models.iter().find(|id| id.eq_ignore_ascii_case(&served))
+                                .map(|id| format!(" (did you mean '{}'?)", id))
+                                .unwrap_or_default();
+                            let listed = match models.len() {
+                                0 => "no models".to_string(),
+                                n if n > 5 => format!("{}, and {} more", models[..5].join(", "), n - 5),
+                                _ => models.join(", "),
+                            };
+                            Some(Finding {
+                                problem: Problem::Mismatch,
+                                message: format!("{} doesn't serve '{}'{}; it lists {}", label, served, close, listed),
+                            })
+                        }
+                        Err((problem, message)) => Some(Finding { problem, message: format!("{} {}", label, message) }),
+                    }
+                });
+            }
+        }
+    }
+    futures::future::join_all(probes).await.into_iter().flatten().collect()
+}
+
+// The model IDs a replica lists.
+async fn list_models(backend: &Backend, url: &str, served: &str) -> Result<Vec<String>, (Problem, String)> {
+    let request = backend.client_for(url).get(backend.endpoint_url(url, "models", served)).timeout(VERIFY_TIMEOUT);
+    let response = backend.authorize(request).send().await
+        .map_err(|e| (Problem::Unreachable, format!("is unreachable: {}", crate::error::chain(&e))))?;
+    if !response.status().is_success() {
+        return Err((Problem::Unlisted, format!("answered /models with {}", response.status())));
+    }
+    let body: Value = response.json().await
+        .map_err(|e| (Problem::Unlisted, format!("answered /models with invalid JSON: {}", e)))?;
+    let data = body["data"].as_array()
+        .ok_or_else(|| (Problem::Unlisted, "answered /models without a `data` list".to_string()))?;
+    Ok(data.iter().filter_map(|entry| entry["id"].as_str()).map(String::from).collect())
+}
@@ -0,0 +1,118 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use futures::StreamExt;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::{auth::Caller, metadata, request_log::Recorder, AppError, AppState, ChatRequest, RequestMeta};
+
+// --- WebSocket Streaming ---
+// GET /v1/chat/completions/ws upgrades to a WebSocket for clients that handle it
+// better than SSE. Each text frame the client sends is a chat completion request;
+// the gateway answers with one text frame per `chat.completion.chunk` (exactly
+// the SSE `data:` payloads) and a final `[DONE]`, after which the connection
+// takes the next request. Requests go through `start_chat` like the SSE route,
+// so routing, quotas, guardrails, and logging are shared. Errors arrive as an
+// OpenAI error object frame and leave the connection open.
+#[utoipa::path(
+    get, path = "/v1/chat/completions/ws", tag = "Chat",
+    description = "Upgrades to a WebSocket: each text frame sent is a chat completion request, answered with one frame per chunk and a final `[DONE]`.",
+    responses((status = 101, description = "Switching to the WebSocket protocol.")),
+)]
+pub async fn upgrade(
+    State(state): State<Arc<AppState>>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    upgrade.on_upgrade(move |socket| serve(socket, state, caller, headers))
+}
+
+async fn serve(mut socket: WebSocket, state: Arc<AppState>, caller: Caller, headers: HeaderMap) {
+    while let Some(Ok(message)) = socket.recv().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => return,
+            Message::Binary(_) => {
+                let error = AppError::InvalidRequest("Send requests as text frames.".to_string());
+                if send_error(&mut socket, error.into_response()).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+            Message::Ping(_) | Message::Pong(_) => continue,
+        };
+        let body = match serde_json::from_str::<ChatRequest>(&text) {
+            Ok(body) => body,
+            Err(e) => {
+                let error = AppError::InvalidRequest(format!("Invalid chat completion request: {}", e));
+                if send_error(&mut socket, error.into_response()).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+        if !stream_chat(&mut socket, &state, &caller, &headers, body).await {
+            return;
+        }
+    }
+}
+
+// Streams one request's chunks. False if the client went away, which drops the
+// stream and logs the request as cancelled.
+async fn stream_chat(socket: &mut WebSocket, state: &Arc<AppState>, caller: &Caller, headers: &HeaderMap, body: ChatRequest) -> bool {
+    let mut meta = RequestMeta::new();
+    let model = body.model.clone();
+    let started = match metadata::collect(headers, body.metadata.as_ref()) {
+        Ok(metadata) => {
+            meta.metadata = Arc::new(metadata);
+            crate::start_chat(state.clone(), caller.clone(), headers.clone(), body, meta.clone()).await
+        }
+        Err(e) => Err(e),
+    };
+    let mut payloads = match started {
+        Ok((_, payloads)) => payloads,
+        Err(e) => {
+            let response = e.into_response();
+            Recorder::new(state, &meta, model, caller).finish(response.status().as_u16(), None, None);
+            return send_error(socket, response).await.is_ok();
+        }
+    };
+    info!("Streaming request {} over WebSocket", meta.id);
+    loop {
+        tokio::select! {
+            payload = payloads.next() => {
+                let Some(payload) = payload else { return true };
+                let done = payload == "[DONE]";
+                if socket.send(Message::Text(payload)).await.is_err() {
+                    return false;
+                }
+                if done {
+                    return true;
+                }
+            }
+            message = socket.recv() => match message {
+                None | Some(Err(_)) | Some(Ok(Message::Close(_))) => return false,
+                Some(Ok(Message::Ping(_) | Message::Pong(_))) => {}
+                Some(Ok(_)) => {
+                    warn!("Ignoring a WebSocket frame sent while request {} is streaming", meta.id);
+                }
+            },
+        }
+    }
+}
+
+// The error response's JSON body, as a frame.
+async fn send_error(socket: &mut WebSocket, response: Response) -> Result<(), axum::Error> {
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap_or_default();
+    let error = serde_json::from_slice::<Value>(&body).unwrap_or_default();
+    socket.send(Message::Text(error.to_string())).await
+}